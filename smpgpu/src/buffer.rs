@@ -9,6 +9,7 @@ use crate::{
 
 pub struct Buffer {
     inner: wgpu::Buffer,
+    read_only: bool,
 }
 
 impl Buffer {
@@ -21,6 +22,23 @@ impl Buffer {
     pub fn copy_to_buf_op<'a>(&'a self, buf: &'a Self) -> impl EncoderOp + 'a {
         CopyOp::BufBuf(self, 0, buf, 0, self.size())
     }
+
+    #[inline]
+    pub fn copy_to_buf_at_op<'a>(&'a self, buf: &'a Self, offset: u64) -> impl EncoderOp + 'a {
+        CopyOp::BufBuf(self, 0, buf, offset, self.size())
+    }
+
+    /// Copies `size` bytes from `src_offset` in this buffer to `dst_offset` in `buf`.
+    #[inline]
+    pub fn copy_range_to_buf_op<'a>(
+        &'a self,
+        src_offset: u64,
+        buf: &'a Self,
+        dst_offset: u64,
+        size: u64,
+    ) -> impl EncoderOp + 'a {
+        CopyOp::BufBuf(self, src_offset, buf, dst_offset, size)
+    }
 }
 
 impl<'a> Bindable<'a> for &'a Buffer {
@@ -29,7 +47,7 @@ impl<'a> Bindable<'a> for &'a Buffer {
     fn into_binding(self) -> (wgpu::BindingType, BindResource<'a>) {
         let ty = if self.usage().contains(wgpu::BufferUsages::STORAGE) {
             wgpu::BufferBindingType::Storage {
-                read_only: !self.usage().contains(wgpu::BufferUsages::COPY_SRC),
+                read_only: self.read_only,
             }
         } else if self.usage().contains(wgpu::BufferUsages::UNIFORM) {
             wgpu::BufferBindingType::Uniform
@@ -68,6 +86,7 @@ pub struct BufferBuilder<'a> {
     label: Option<&'a str>,
     size: u64,
     usage: wgpu::BufferUsages,
+    copyable: bool,
 }
 
 impl<'a> BufferBuilder<'a> {
@@ -79,6 +98,7 @@ impl<'a> BufferBuilder<'a> {
             label: None,
             size: 0,
             usage: wgpu::BufferUsages::empty(),
+            copyable: false,
         }
     }
 
@@ -119,7 +139,9 @@ impl<'a> BufferBuilder<'a> {
         if (self.usage.contains(wgpu::BufferUsages::MAP_READ)
             || self.usage.contains(wgpu::BufferUsages::MAP_WRITE))
             && (self.usage.contains(wgpu::BufferUsages::UNIFORM)
-                || self.usage.contains(wgpu::BufferUsages::STORAGE))
+                || self.usage.contains(wgpu::BufferUsages::STORAGE)
+                || self.usage.contains(wgpu::BufferUsages::VERTEX)
+                || self.usage.contains(wgpu::BufferUsages::INDIRECT))
         {
             self.usage
                 .remove(wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE);
@@ -139,6 +161,14 @@ impl<'a> BufferBuilder<'a> {
         self.with_usage(wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ)
     }
 
+    /// Allows the buffer to be the source of copies while staying read only in shaders.
+    #[must_use]
+    #[inline]
+    pub fn copyable(mut self) -> Self {
+        self.copyable = true;
+        self.with_usage(wgpu::BufferUsages::COPY_SRC)
+    }
+
     #[must_use]
     #[inline]
     pub fn storage(self) -> Self {
@@ -157,6 +187,19 @@ impl<'a> BufferBuilder<'a> {
         self.with_usage(wgpu::BufferUsages::VERTEX)
     }
 
+    /// Lets the buffer hold the arguments of indirect draws, given to the encoder of a
+    /// [`crate::RenderCheckpoint`].
+    #[must_use]
+    #[inline]
+    pub fn indirect(self) -> Self {
+        self.with_usage(wgpu::BufferUsages::INDIRECT)
+    }
+
+    #[inline]
+    const fn read_only(&self) -> bool {
+        self.copyable || !self.usage.contains(wgpu::BufferUsages::COPY_SRC)
+    }
+
     #[must_use]
     #[inline]
     pub fn build(self) -> Buffer {
@@ -167,7 +210,10 @@ impl<'a> BufferBuilder<'a> {
             mapped_at_creation: false,
         });
 
-        Buffer { inner }
+        Buffer {
+            inner,
+            read_only: self.read_only(),
+        }
     }
 
     #[must_use]
@@ -180,7 +226,10 @@ impl<'a> BufferBuilder<'a> {
                 contents,
                 usage: self.usage,
             });
-        Buffer { inner }
+        Buffer {
+            inner,
+            read_only: self.read_only(),
+        }
     }
 
     /// SAFETY: T must be safe to transmute to bytes (likely true for any type you would want to put in a buffer).
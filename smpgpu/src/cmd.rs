@@ -1,22 +1,29 @@
 #![allow(dead_code)]
 
-use std::ops::Range;
+use std::{cell::RefCell, ops::Range};
 
 use encase::ShaderSize;
 use wgpu::ComputePassDescriptor;
 
-use crate::{bind::IntoBindGroup, shader::CompiledRenderShader, Buffer, OntoDevice};
+use crate::{
+    bind::IntoBindGroup,
+    shader::{CompiledRenderShader, CompiledShader, ShaderWatch},
+    texture::{padded_row_bytes, pixel_bytes},
+    Buffer, Context, OntoDevice, Result, Texture,
+};
 
 pub struct ComputeCheckpoint {
-    groups: Vec<wgpu::BindGroup>,
-    pipeline: wgpu::ComputePipeline,
+    groups: Box<[wgpu::BindGroup]>,
+    pipeline: RefCell<wgpu::ComputePipeline>,
     work_groups: [u32; 3],
+    /// Set when the shader's file is watched, see [`Self::reload_changed`]
+    reload: Option<(ShaderWatch, ComputePipelineDesc)>,
 }
 
 impl ComputeCheckpoint {
     #[inline]
-    pub fn builder(dev: &impl AsRef<wgpu::Device>) -> ComputeCheckpointBuilder<'_> {
-        ComputeCheckpointBuilder::new(dev.as_ref())
+    pub fn builder(ctx: &Context) -> ComputeCheckpointBuilder<'_> {
+        ComputeCheckpointBuilder::new(ctx)
     }
 
     #[must_use]
@@ -26,104 +33,138 @@ impl ComputeCheckpoint {
         self
     }
 
+    /// Commands starting with this pass, which more can be chained onto.
+    #[inline]
     pub fn encoder(&self, dev: &impl AsRef<wgpu::Device>) -> CommandBuilder {
-        let dev = dev.as_ref();
-        let mut encoder =
-            dev.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        CommandBuilder::new(dev).then(self)
+    }
+
+    /// Like [`RenderCheckpoint::reload_changed`].
+    ///
+    /// # Errors
+    /// the file can't be read or its shader doesn't fit the pipeline, keeping the old one
+    pub fn reload_changed(&self, ctx: &Context) -> Result<bool> {
+        let Some((watch, desc)) = &self.reload else {
+            return Ok(false);
+        };
+        let Some(pipeline) = watch.reload(ctx.as_ref(), |m| desc.create(ctx, m))? else {
+            return Ok(false);
+        };
+        self.pipeline.replace(pipeline);
+        Ok(true)
+    }
+}
+
+/// Everything a compute pipeline is made of besides its shader.
+struct ComputePipelineDesc {
+    layout: wgpu::PipelineLayout,
+    entry: Option<String>,
+}
+
+impl ComputePipelineDesc {
+    fn create(&self, ctx: &Context, module: &wgpu::ShaderModule) -> wgpu::ComputePipeline {
+        let dev: &wgpu::Device = ctx.as_ref();
+        dev.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&self.layout),
+            module,
+            entry_point: self.entry.as_deref(),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: ctx.pipeline_cache(),
+        })
+    }
+}
 
-        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+/// Dispatches the pass, so it can be chained onto other commands with [`CommandBuilder::then`].
+impl EncoderOp for &ComputeCheckpoint {
+    fn encoder_op(self, enc: &mut wgpu::CommandEncoder) {
+        let mut pass = enc.begin_compute_pass(&ComputePassDescriptor {
             label: None,
             timestamp_writes: None,
         });
+        pass.set_pipeline(&self.pipeline.borrow());
         for (i, g) in self.groups.iter().enumerate() {
-            compute_pass.set_bind_group(i as _, g, &[]);
+            pass.set_bind_group(i as _, g, &[]);
         }
-        compute_pass.set_pipeline(&self.pipeline);
-        compute_pass.dispatch_workgroups(
-            self.work_groups[0],
-            self.work_groups[1],
-            self.work_groups[2],
-        );
-
-        CommandBuilder { encoder }
+        let [x, y, z] = self.work_groups;
+        pass.dispatch_workgroups(x, y, z);
     }
 }
 
 pub struct ComputeCheckpointBuilder<'a> {
-    dev: &'a wgpu::Device,
+    ctx: &'a Context,
     groups: Vec<(wgpu::BindGroupLayout, wgpu::BindGroup)>,
-    shader: Option<wgpu::ShaderModule>,
-    entry: Option<&'a str>,
+    shader: Option<CompiledShader<'a>>,
 }
 
 impl<'a> ComputeCheckpointBuilder<'a> {
     #[inline]
-    pub const fn new(dev: &'a wgpu::Device) -> Self {
+    pub fn new(ctx: &'a Context) -> Self {
         Self {
-            dev,
+            ctx,
             groups: Vec::new(),
             shader: None,
-            entry: None,
         }
     }
 
     pub fn group(mut self, b: impl IntoBindGroup) -> Self {
-        let (layout, group) = b.into_wgpu_bind_group(self.dev);
+        let (layout, group) = b.into_wgpu_bind_group(self.ctx.as_ref());
         self.groups.push((layout, group));
         self
     }
 
-    pub fn shader(
-        mut self,
-        desc: wgpu::ShaderModuleDescriptor,
-        entry: impl Into<Option<&'a str>>,
-    ) -> Self {
-        self.shader = Some(self.dev.create_shader_module(desc));
-        self.entry = entry.into();
+    /// Like [`RenderCheckpointBuilder::shader`], with the entry point of the compute shader.
+    pub fn shader(mut self, shader: impl OntoDevice<CompiledShader<'a>>) -> Self {
+        self.shader = Some(shader.onto_device(self.ctx));
         self
     }
 
     pub fn build(self) -> ComputeCheckpoint {
-        let pipeline_layout = self
-            .dev
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &self.groups.iter().map(|(l, _)| l).collect::<Vec<_>>(),
-                push_constant_ranges: &[],
-            });
+        let dev: &wgpu::Device = self.ctx.as_ref();
+        let pipeline_layout = dev.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &self.groups.iter().map(|(l, _)| l).collect::<Vec<_>>(),
+            push_constant_ranges: &[],
+        });
 
-        let pipeline = self
-            .dev
-            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                module: &self.shader.expect("no shader provided to compute builder"),
-                entry_point: self.entry,
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                cache: None,
-            });
+        let shader = self.shader.expect("no shader provided to compute builder");
+        let desc = ComputePipelineDesc {
+            layout: pipeline_layout,
+            entry: shader.entry.map(str::to_owned),
+        };
+        let pipeline = desc.create(
+            self.ctx,
+            shader
+                .module
+                .as_ref()
+                .expect("no shader module in compute shader"),
+        );
+        let reload = shader.source.map(|p| (ShaderWatch::new(&p), desc));
 
         let groups = self.groups.into_iter().map(|(_, g)| g).collect();
 
         ComputeCheckpoint {
             groups,
-            pipeline,
+            pipeline: RefCell::new(pipeline),
             work_groups: [0; 3],
+            reload,
         }
     }
 }
 
 pub struct RenderCheckpoint {
     groups: Box<[wgpu::BindGroup]>,
-    pipeline: wgpu::RenderPipeline,
+    pipeline: RefCell<wgpu::RenderPipeline>,
     vert_range: Range<u32>,
     insts_range: Range<u32>,
+    /// Set when the shader's file is watched, see [`Self::reload_changed`]
+    reload: Option<(ShaderWatch, RenderPipelineDesc)>,
 }
 
 impl RenderCheckpoint {
     #[inline]
-    pub fn builder(dev: &impl AsRef<wgpu::Device>) -> RenderCheckpointBuilder {
-        RenderCheckpointBuilder::new(dev)
+    pub fn builder(ctx: &Context) -> RenderCheckpointBuilder<'_> {
+        RenderCheckpointBuilder::new(ctx)
     }
 
     #[inline]
@@ -134,6 +175,8 @@ impl RenderCheckpoint {
             color_attachs: Vec::new(),
             vert_bufs: Vec::new(),
             index_buf: None,
+            insts_range: self.insts_range.clone(),
+            indirect: None,
         }
     }
 
@@ -150,47 +193,137 @@ impl RenderCheckpoint {
         self.insts_range = range;
         self
     }
+
+    /// Rebuilds the pipeline from the WGSL file its shader was made from when the file changed
+    /// since it was read, returning whether it did. Files are only watched in debug builds,
+    /// see [`crate::include_shader!`].
+    ///
+    /// # Errors
+    /// the file can't be read or its shader doesn't fit the pipeline, keeping the old one
+    pub fn reload_changed(&self, ctx: &Context) -> Result<bool> {
+        let Some((watch, desc)) = &self.reload else {
+            return Ok(false);
+        };
+        let Some(pipeline) = watch.reload(ctx.as_ref(), |m| desc.create(ctx, m, m))? else {
+            return Ok(false);
+        };
+        self.pipeline.replace(pipeline);
+        Ok(true)
+    }
+}
+
+/// Stride, step and attributes of a vertex buffer.
+type VertBuffer = (
+    wgpu::BufferAddress,
+    wgpu::VertexStepMode,
+    Vec<wgpu::VertexAttribute>,
+);
+
+/// Everything a render pipeline is made of besides its shaders.
+struct RenderPipelineDesc {
+    layout: wgpu::PipelineLayout,
+    vert_buffers: Vec<VertBuffer>,
+    frag_targets: Vec<Option<wgpu::ColorTargetState>>,
+    samples: u32,
+    vert_entry: Option<String>,
+    frag_entry: Option<String>,
+}
+
+impl RenderPipelineDesc {
+    fn create(
+        &self,
+        ctx: &Context,
+        vert: &wgpu::ShaderModule,
+        frag: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        let buffers = self
+            .vert_buffers
+            .iter()
+            .map(
+                |(array_stride, step_mode, attributes)| wgpu::VertexBufferLayout {
+                    array_stride: *array_stride,
+                    step_mode: *step_mode,
+                    attributes,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let dev: &wgpu::Device = ctx.as_ref();
+        dev.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: vert,
+                entry_point: self.vert_entry.as_deref(),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &buffers,
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: self.samples,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: frag,
+                entry_point: self.frag_entry.as_deref(),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &self.frag_targets,
+            }),
+            multiview: None,
+            cache: ctx.pipeline_cache(),
+        })
+    }
 }
 
 pub struct RenderCheckpointBuilder<'a> {
-    dev: &'a wgpu::Device,
+    ctx: &'a Context,
     groups: Vec<(wgpu::BindGroupLayout, wgpu::BindGroup)>,
     shader: Option<CompiledRenderShader<'a>>,
-    vert_buffers: Vec<wgpu::VertexBufferLayout<'a>>,
+    vert_buffers: Vec<VertBuffer>,
     frag_targets: Vec<Option<wgpu::ColorTargetState>>,
+    samples: u32,
 }
 
 impl<'a> RenderCheckpointBuilder<'a> {
-    pub fn new(dev: &'a impl AsRef<wgpu::Device>) -> Self {
+    pub fn new(ctx: &'a Context) -> Self {
         Self {
-            dev: dev.as_ref(),
+            ctx,
             groups: Vec::new(),
             shader: None,
             vert_buffers: Vec::new(),
             frag_targets: Vec::new(),
+            samples: 1,
         }
     }
 
     pub fn group(mut self, b: impl IntoBindGroup) -> Self {
-        let (layout, group) = b.into_wgpu_bind_group(self.dev);
+        let (layout, group) = b.into_wgpu_bind_group(self.ctx.as_ref());
         self.groups.push((layout, group));
         self
     }
 
     pub fn shader(mut self, shader: impl OntoDevice<CompiledRenderShader<'a>>) -> Self {
-        self.shader = Some(shader.onto_device(self.dev));
+        self.shader = Some(shader.onto_device(self.ctx));
         self
     }
 
-    pub fn vert_buffer_of<T: ShaderSize>(
-        mut self,
-        attributes: &'a [wgpu::VertexAttribute],
-    ) -> Self {
-        self.vert_buffers.push(wgpu::VertexBufferLayout {
-            array_stride: T::SHADER_SIZE.into(),
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes,
-        });
+    pub fn vert_buffer_of<T: ShaderSize>(mut self, attributes: &[wgpu::VertexAttribute]) -> Self {
+        self.vert_buffers.push((
+            T::SHADER_SIZE.into(),
+            wgpu::VertexStepMode::Vertex,
+            attributes.to_vec(),
+        ));
+        self
+    }
+
+    /// Like [`Self::vert_buffer_of`], stepping once per instance instead of per vertex.
+    pub fn inst_buffer_of<T: ShaderSize>(mut self, attributes: &[wgpu::VertexAttribute]) -> Self {
+        self.vert_buffers.push((
+            T::SHADER_SIZE.into(),
+            wgpu::VertexStepMode::Instance,
+            attributes.to_vec(),
+        ));
         self
     }
 
@@ -199,53 +332,54 @@ impl<'a> RenderCheckpointBuilder<'a> {
         self
     }
 
+    /// Like [`Self::frag_target`], blending over what's already there by the fragment's alpha.
+    pub fn frag_target_alpha(mut self, format: wgpu::TextureFormat) -> Self {
+        self.frag_targets.push(Some(wgpu::ColorTargetState {
+            format,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        }));
+        self
+    }
+
+    /// Renders into targets with `count` samples per pixel, see
+    /// [`crate::TextureBuilder::samples`].
+    pub fn multisample(mut self, count: u32) -> Self {
+        self.samples = count;
+        self
+    }
+
     pub fn build(self) -> RenderCheckpoint {
-        let pipeline_layout = self
-            .dev
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &self.groups.iter().map(|(l, _)| l).collect::<Vec<_>>(),
-                push_constant_ranges: &[],
-            });
-
-        let (vert_module, vert_entry, frag_module, frag_entry) = self
-            .shader
-            .as_ref()
-            .expect("no shader provided")
-            .split()
-            .expect("no shader module in RenderShader");
+        let dev: &wgpu::Device = self.ctx.as_ref();
+        let pipeline_layout = dev.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &self.groups.iter().map(|(l, _)| l).collect::<Vec<_>>(),
+            push_constant_ranges: &[],
+        });
 
-        let pipeline = self
-            .dev
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: vert_module,
-                    entry_point: vert_entry,
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    buffers: &self.vert_buffers,
-                },
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                fragment: Some(wgpu::FragmentState {
-                    module: frag_module,
-                    entry_point: frag_entry,
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    targets: &self.frag_targets,
-                }),
-                multiview: None,
-                cache: None,
-            });
+        let shader = self.shader.as_ref().expect("no shader provided");
+        let (vert_module, vert_entry, frag_module, frag_entry) =
+            shader.split().expect("no shader module in RenderShader");
+
+        let desc = RenderPipelineDesc {
+            layout: pipeline_layout,
+            vert_buffers: self.vert_buffers,
+            frag_targets: self.frag_targets,
+            samples: self.samples,
+            vert_entry: vert_entry.map(str::to_owned),
+            frag_entry: frag_entry.map(str::to_owned),
+        };
+        let pipeline = desc.create(self.ctx, vert_module, frag_module);
+        let reload = shader.source().map(|p| (ShaderWatch::new(p), desc));
 
         let groups = self.groups.into_iter().map(|(_, g)| g).collect();
 
         RenderCheckpoint {
             groups,
-            pipeline,
+            pipeline: RefCell::new(pipeline),
             vert_range: 0..0,
             insts_range: 0..1,
+            reload,
         }
     }
 }
@@ -256,6 +390,9 @@ pub struct RenderCommandBuilder<'a> {
     color_attachs: Vec<Option<wgpu::RenderPassColorAttachment<'a>>>,
     vert_bufs: Vec<wgpu::BufferSlice<'a>>,
     index_buf: Option<(wgpu::BufferSlice<'a>, wgpu::IndexFormat, Range<u32>)>,
+    insts_range: Range<u32>,
+    /// Buffer and offset of the draw's arguments
+    indirect: Option<(&'a Buffer, wgpu::BufferAddress)>,
 }
 
 impl<'a> RenderCommandBuilder<'a> {
@@ -277,6 +414,24 @@ impl<'a> RenderCommandBuilder<'a> {
         self
     }
 
+    /// Draws `range` of the instances instead of the checkpoint's, like only the slots in use
+    /// this frame.
+    #[inline]
+    pub const fn instances(mut self, range: Range<u32>) -> Self {
+        self.insts_range = range;
+        self
+    }
+
+    /// Takes the counts and offsets of the draw from `buf` at `offset`, which the GPU can
+    /// write, instead of the ranges given. They're laid out like [`wgpu::util::DrawIndirectArgs`],
+    /// or [`wgpu::util::DrawIndexedIndirectArgs`] with an index buffer, see
+    /// [`crate::BufferBuilder::indirect`].
+    #[inline]
+    pub const fn indirect(mut self, buf: &'a Buffer, offset: wgpu::BufferAddress) -> Self {
+        self.indirect = Some((buf, offset));
+        self
+    }
+
     #[inline]
     pub fn then(self, op: impl EncoderOp) -> CommandBuilder {
         self.build().then(op)
@@ -295,7 +450,7 @@ impl<'a> RenderCommandBuilder<'a> {
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        pass.set_pipeline(&self.cp.pipeline);
+        pass.set_pipeline(&self.cp.pipeline.borrow());
 
         for (i, g) in self.cp.groups.iter().enumerate() {
             pass.set_bind_group(i as _, g, &[]);
@@ -305,11 +460,17 @@ impl<'a> RenderCommandBuilder<'a> {
             pass.set_vertex_buffer(i as _, s);
         }
 
-        if let Some((b, f, indices)) = self.index_buf {
-            pass.set_index_buffer(b, f);
-            pass.draw_indexed(indices, 0, self.cp.insts_range.clone());
-        } else {
-            pass.draw(self.cp.vert_range.clone(), self.cp.insts_range.clone());
+        match (self.index_buf, self.indirect) {
+            (Some((b, f, _)), Some((args, offset))) => {
+                pass.set_index_buffer(b, f);
+                pass.draw_indexed_indirect(args, offset);
+            }
+            (Some((b, f, indices)), None) => {
+                pass.set_index_buffer(b, f);
+                pass.draw_indexed(indices, 0, self.insts_range);
+            }
+            (None, Some((args, offset))) => pass.draw_indirect(args, offset),
+            (None, None) => pass.draw(self.cp.vert_range.clone(), self.insts_range),
         }
 
         CommandBuilder { encoder }
@@ -322,6 +483,7 @@ pub trait RenderAttachOp {
 
 pub struct RenderAttachment {
     view: wgpu::TextureView,
+    resolve: Option<wgpu::TextureView>,
     ops: wgpu::Operations<wgpu::Color>,
 }
 
@@ -330,16 +492,30 @@ impl RenderAttachment {
     pub fn new(view: wgpu::TextureView) -> Self {
         Self {
             view,
+            resolve: None,
             ops: wgpu::Operations::default(),
         }
     }
 
+    /// Averages the samples of a multisampled attachment into `target` at the end of the pass.
+    #[inline]
+    pub fn resolve(mut self, target: &Texture) -> Self {
+        self.resolve = Some(target.view());
+        self
+    }
+
     #[inline]
     pub const fn load_clear(mut self, [r, g, b, a]: [f64; 4]) -> Self {
         self.ops.load = wgpu::LoadOp::Clear(wgpu::Color { r, g, b, a });
         self
     }
 
+    #[inline]
+    pub const fn load(mut self) -> Self {
+        self.ops.load = wgpu::LoadOp::Load;
+        self
+    }
+
     #[inline]
     pub const fn store(mut self) -> Self {
         self.ops.store = wgpu::StoreOp::Store;
@@ -359,7 +535,7 @@ impl<'a> From<&'a RenderAttachment> for wgpu::RenderPassColorAttachment<'a> {
     fn from(v: &'a RenderAttachment) -> Self {
         wgpu::RenderPassColorAttachment {
             view: &v.view,
-            resolve_target: None,
+            resolve_target: v.resolve.as_ref(),
             ops: v.ops,
         }
     }
@@ -370,6 +546,14 @@ pub struct CommandBuilder {
 }
 
 impl CommandBuilder {
+    /// Commands without a pass, for only copying.
+    pub fn new(dev: &impl AsRef<wgpu::Device>) -> Self {
+        let encoder = dev
+            .as_ref()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        Self { encoder }
+    }
+
     #[inline]
     pub fn then(mut self, op: impl EncoderOp) -> Self {
         op.encoder_op(&mut self.encoder);
@@ -408,6 +592,14 @@ pub enum CopyOp<'a> {
         wgpu::BufferAddress,
         wgpu::BufferAddress,
     ),
+    /// Source and destination with the first layer copied from and to.
+    TextText(
+        &'a wgpu::Texture,
+        u32,
+        &'a wgpu::Texture,
+        u32,
+        wgpu::Extent3d,
+    ),
 }
 
 impl<'a> EncoderOp for CopyOp<'a> {
@@ -424,7 +616,7 @@ impl<'a> EncoderOp for CopyOp<'a> {
                     buffer,
                     layout: wgpu::ImageDataLayout {
                         offset: 0,
-                        bytes_per_row: Some(ext.width * 4),
+                        bytes_per_row: Some(ext.width * pixel_bytes(texture.format())),
                         rows_per_image: Some(ext.height),
                     },
                 },
@@ -435,8 +627,8 @@ impl<'a> EncoderOp for CopyOp<'a> {
                     buffer,
                     layout: wgpu::ImageDataLayout {
                         offset: 0,
-                        bytes_per_row: Some(ext.width * 4),
-                        rows_per_image: Some(ext.height * 4),
+                        bytes_per_row: Some(padded_row_bytes(ext.width, texture.format())),
+                        rows_per_image: Some(ext.height),
                     },
                 },
                 wgpu::ImageCopyTexture {
@@ -450,6 +642,15 @@ impl<'a> EncoderOp for CopyOp<'a> {
             CopyOp::BufBuf(src, src_off, dst, dst_off, size) => {
                 enc.copy_buffer_to_buffer(src, src_off, dst, dst_off, size);
             }
+            CopyOp::TextText(src, src_layer, dst, dst_layer, ext) => {
+                let layer = |texture, z| wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z },
+                    aspect: wgpu::TextureAspect::All,
+                };
+                enc.copy_texture_to_texture(layer(src, src_layer), layer(dst, dst_layer), ext);
+            }
         }
     }
 }
@@ -1,16 +1,24 @@
 use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
     num::NonZero,
-    sync::{Arc, Weak},
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
 };
 
 use encase::{internal::WriteInto, ShaderType};
 
-use crate::{Buffer, DirectWritableBufferView, Error, Result};
+use crate::{Buffer, DirectWritableBufferView, Error, Result, Texture};
 
 pub struct Context {
     dev: wgpu::Device,
     queue: wgpu::Queue,
+    info: wgpu::AdapterInfo,
     wake_poll: kanal::Sender<()>,
+    /// Driver cache pipelines are built with, and the file it's saved to
+    pipeline_cache: Option<(wgpu::PipelineCache, PathBuf)>,
+    /// Every shader module made, by the hash of its WGSL
+    modules: Mutex<HashMap<u64, Arc<wgpu::ShaderModule>>>,
 }
 
 impl Context {
@@ -23,6 +31,61 @@ impl Context {
         }
     }
 
+    #[must_use]
+    #[inline]
+    pub const fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.info
+    }
+
+    /// See [`ContextDeviceBuilder::pipeline_cache_dir`].
+    #[must_use]
+    #[inline]
+    pub fn pipeline_cache(&self) -> Option<&wgpu::PipelineCache> {
+        self.pipeline_cache.as_ref().map(|(cache, _)| cache)
+    }
+
+    /// Writes every pipeline the driver compiled so far to the pipeline cache's file, returning
+    /// whether there was a cache to save, see [`ContextDeviceBuilder::pipeline_cache_dir`].
+    ///
+    /// # Errors
+    /// the file can't be written
+    pub fn save_pipeline_cache(&self) -> Result<bool> {
+        let Some((cache, path)) = &self.pipeline_cache else {
+            return Ok(false);
+        };
+        let Some(data) = cache.get_data() else {
+            return Ok(false);
+        };
+
+        // moved over the old file once it's whole, so it's never left half written
+        let tmp = path.with_extension("tmp");
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(Error::PipelineCache)?;
+        }
+        std::fs::write(&tmp, data).map_err(Error::PipelineCache)?;
+        std::fs::rename(&tmp, path).map_err(Error::PipelineCache)?;
+        Ok(true)
+    }
+
+    /// Module made from `desc`, shared with every other shader made from the same WGSL.
+    pub(crate) fn shader_module(
+        &self,
+        desc: wgpu::ShaderModuleDescriptor,
+    ) -> Arc<wgpu::ShaderModule> {
+        let wgpu::ShaderSource::Wgsl(src) = &desc.source else {
+            return Arc::new(self.dev.create_shader_module(desc));
+        };
+        let mut hasher = DefaultHasher::new();
+        src.hash(&mut hasher);
+
+        self.modules
+            .lock()
+            .unwrap()
+            .entry(hasher.finish())
+            .or_insert_with(|| Arc::new(self.dev.create_shader_module(desc)))
+            .clone()
+    }
+
     #[inline]
     pub fn signal_wake(&self) {
         self.wake_poll.send(()).expect("poller has died");
@@ -43,6 +106,12 @@ impl Context {
         self.queue.write_buffer_with(buffer, offset, size).unwrap()
     }
 
+    /// Writes `data`, tightly packed rows, to `layer` of `texture`.
+    #[inline]
+    pub fn write_texture_layer(&self, texture: &Texture, data: &[u8], layer: u32) {
+        texture.write_to_layer(&self.queue, data, layer);
+    }
+
     #[inline]
     pub fn write_uniform<T: ShaderType + WriteInto>(&self, buffer: &Buffer, v: &T) {
         let mut data = self.write_with(buffer, 0, buffer.size().try_into().unwrap());
@@ -100,6 +169,7 @@ impl<'a, 'b> ContextAdapterBuilder<'a, 'b> {
                     | wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER,
                 limits: wgpu::Limits::downlevel_defaults(),
                 hints: wgpu::MemoryHints::Performance,
+                pipeline_cache_dir: None,
             })
     }
 }
@@ -109,16 +179,43 @@ pub struct ContextDeviceBuilder {
     features: wgpu::Features,
     limits: wgpu::Limits,
     hints: wgpu::MemoryHints,
+    pipeline_cache_dir: Option<PathBuf>,
 }
 
 impl ContextDeviceBuilder {
+    /// Builds pipelines with a driver cache loaded from a file in `dir` named after the GPU,
+    /// which [`Context::save_pipeline_cache`] writes back, so they're compiled again only when
+    /// they change. Only Vulkan drivers support it, the rest build pipelines without one.
+    #[must_use]
+    pub fn pipeline_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.pipeline_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// # Errors
+    /// no device could be made from the adapter, or the pipeline cache can't be read
     pub async fn request_build(self) -> Result<Arc<Context>> {
+        let info = self.adapter.get_info();
+        let cache_path = self
+            .pipeline_cache_dir
+            .filter(|_| {
+                self.adapter
+                    .features()
+                    .contains(wgpu::Features::PIPELINE_CACHE)
+            })
+            .zip(wgpu::util::pipeline_cache_key(&info))
+            .map(|(dir, key)| dir.join(key));
+        let features = match cache_path {
+            Some(_) => self.features | wgpu::Features::PIPELINE_CACHE,
+            None => self.features,
+        };
+
         let (dev, queue) = self
             .adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: self.features,
+                    required_features: features,
                     required_limits: self.limits,
                     memory_hints: self.hints,
                 },
@@ -127,12 +224,36 @@ impl ContextDeviceBuilder {
             .await
             .map_err(Error::from)?;
 
+        let pipeline_cache = match cache_path {
+            Some(path) => {
+                let data = match std::fs::read(&path) {
+                    Ok(data) => Some(data),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(err) => return Err(Error::PipelineCache(err)),
+                };
+                // SAFETY: the data was saved from a cache of an adapter with the same key, and
+                // with `fallback` a cache that doesn't match the driver starts out empty
+                let cache = unsafe {
+                    dev.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                        label: Some("pipeline_cache"),
+                        data: data.as_deref(),
+                        fallback: true,
+                    })
+                };
+                Some((cache, path))
+            }
+            None => None,
+        };
+
         let (wake_poll, wake_recv) = kanal::unbounded();
 
         let out = Arc::new(Context {
             dev,
             queue,
+            info,
             wake_poll,
+            pipeline_cache,
+            modules: Mutex::new(HashMap::new()),
         });
 
         spawn_poller(wake_recv, Arc::downgrade(&out));
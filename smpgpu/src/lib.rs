@@ -7,7 +7,7 @@ mod buffer;
 pub use buffer::{Buffer, BufferBuilder};
 
 mod cmd;
-pub use cmd::{ComputeCheckpoint, RenderCheckpoint};
+pub use cmd::{CommandBuilder, ComputeCheckpoint, RenderCheckpoint};
 
 pub mod ctx;
 pub use ctx::Context;
@@ -15,6 +15,9 @@ pub use ctx::Context;
 mod mem;
 pub use mem::MemMapper;
 
+mod mip;
+pub use mip::MipGenerator;
+
 mod sampler;
 pub use sampler::{Sampler, SamplerBuilder};
 
@@ -30,6 +33,12 @@ pub enum Error {
     FailedToGetAdapater,
     #[error(transparent)]
     RequestDeviceError(#[from] wgpu::RequestDeviceError),
+    #[error("failed to reload shader {0:?}: {1}")]
+    ShaderReload(std::path::PathBuf, String),
+    #[error("pipeline cache io error: {0}")]
+    PipelineCache(std::io::Error),
+    #[error("failed to map buffer: {0}")]
+    BufferMap(#[from] wgpu::BufferAsyncError),
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -37,9 +46,10 @@ pub type Result<T> = ::std::result::Result<T, Error>;
 pub type DirectWritableBufferView<'a> = wgpu::QueueWriteBufferView<'a>;
 
 pub trait OntoDevice<T> {
-    fn onto_device(self, dev: &wgpu::Device) -> T;
+    fn onto_device(self, ctx: &Context) -> T;
 }
 
 pub mod reexport {
-    pub use wgpu::include_wgsl;
+    pub use crate::shader::source_path;
+    pub use wgpu::{include_wgsl, AdapterInfo, FilterMode, TextureFormat};
 }
@@ -1,3 +1,5 @@
+use crate::Result;
+
 type MapperCallback<'a> = Box<dyn FnOnce(wgpu::BufferView<'a>) + 'a>;
 
 #[derive(Default)]
@@ -23,22 +25,35 @@ impl<'a> MemMapper<'a> {
         self
     }
 
+    /// Maps every buffer and hands it to its callback, skipping the callbacks of buffers that
+    /// fail to map.
+    ///
+    /// # Errors
+    /// a buffer failed to map, or the device was lost before it did
     #[cfg(feature = "tokio")]
-    pub async fn run_all(self) {
+    pub async fn run_all(self) -> Result<()> {
         let chans = self.slices.into_iter().map(|(b, cb)| {
             let (res_send, res_recv) = kanal::bounded(1);
             let bs = b.slice(..);
-            bs.map_async(wgpu::MapMode::Read, move |v| res_send.send(v).unwrap());
+            bs.map_async(wgpu::MapMode::Read, move |v| _ = res_send.send(v));
             (b, bs, cb, res_recv)
         });
 
         futures::future::join_all(chans.map(|(b, bs, cb, res_recv)| async move {
-            res_recv.to_async().recv().await.unwrap().unwrap();
+            // a dropped callback means the device was lost with the buffer unmapped
+            res_recv
+                .to_async()
+                .recv()
+                .await
+                .unwrap_or(Err(wgpu::BufferAsyncError))?;
             let data = bs.get_mapped_range();
             cb(data);
             b.unmap();
+            Ok(())
         }))
-        .await;
+        .await
+        .into_iter()
+        .collect()
     }
 
     pub fn block_all(self) {
@@ -0,0 +1,153 @@
+use crate::{cmd::EncoderOp, CommandBuilder, Texture};
+
+/// Fills the mip levels of textures in one format from their first level, see
+/// [`crate::TextureBuilder::mip_levels`].
+pub struct MipGenerator {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl MipGenerator {
+    /// Generator for textures in `format`, which has to be filterable and renderable.
+    pub fn new(dev: &impl AsRef<wgpu::Device>, format: wgpu::TextureFormat) -> Self {
+        let dev = dev.as_ref();
+        let layout = dev.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mip_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = dev.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let module = dev.create_shader_module(wgpu::include_wgsl!("shaders/mip.wgsl"));
+        let pipeline = dev.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("mip_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(format.into())],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let sampler = dev.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mip_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            layout,
+            sampler,
+        }
+    }
+
+    /// Commands drawing every mip level past the first of every layer of `texture` from the
+    /// level above it.
+    pub fn encoder(&self, dev: &impl AsRef<wgpu::Device>, texture: &Texture) -> CommandBuilder {
+        CommandBuilder::new(dev).then(self.op(dev, texture))
+    }
+
+    /// Like [`Self::encoder`], to chain onto other commands.
+    #[must_use]
+    pub fn op(&self, dev: &impl AsRef<wgpu::Device>, texture: &Texture) -> impl EncoderOp + '_ {
+        let view = |layer, level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let levels = (0..texture.depth_or_array_layers())
+            .flat_map(|layer| (1..texture.mip_level_count()).map(move |level| (layer, level)))
+            .map(|(layer, level)| {
+                let src = view(layer, level - 1);
+                let group = dev.as_ref().create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&src),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+                (view(layer, level), group)
+            })
+            .collect();
+
+        MipOp {
+            pipeline: &self.pipeline,
+            levels,
+        }
+    }
+}
+
+/// Target and source of every level drawn by a [`MipGenerator`], in order.
+struct MipOp<'a> {
+    pipeline: &'a wgpu::RenderPipeline,
+    levels: Vec<(wgpu::TextureView, wgpu::BindGroup)>,
+}
+
+impl EncoderOp for MipOp<'_> {
+    fn encoder_op(self, enc: &mut wgpu::CommandEncoder) {
+        for (target, group) in &self.levels {
+            let mut pass = enc.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations::default(),
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(self.pipeline);
+            pass.set_bind_group(0, group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
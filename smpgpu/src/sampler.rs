@@ -45,13 +45,24 @@ impl<'a> Bindable<'a> for &'a Sampler {
 pub struct SamplerBuilder<'a> {
     dev: &'a wgpu::Device,
     label: Option<&'a str>,
+    mag_filter: wgpu::FilterMode,
+    min_filter: wgpu::FilterMode,
+    mipmap_filter: wgpu::FilterMode,
+    anisotropy: u16,
 }
 
 impl<'a> SamplerBuilder<'a> {
     #[must_use]
     #[inline]
     pub const fn new(dev: &'a wgpu::Device) -> Self {
-        Self { dev, label: None }
+        Self {
+            dev,
+            label: None,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            anisotropy: 1,
+        }
     }
 
     #[must_use]
@@ -61,6 +72,39 @@ impl<'a> SamplerBuilder<'a> {
         self
     }
 
+    /// Filter of textures drawn larger than they are, defaults to linear.
+    #[must_use]
+    #[inline]
+    pub const fn mag_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mag_filter = filter;
+        self
+    }
+
+    /// Filter of textures drawn smaller than they are, defaults to linear.
+    #[must_use]
+    #[inline]
+    pub const fn min_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.min_filter = filter;
+        self
+    }
+
+    /// Filter between mip levels, defaults to nearest.
+    #[must_use]
+    #[inline]
+    pub const fn mipmap_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mipmap_filter = filter;
+        self
+    }
+
+    /// Takes up to `n` samples along the slope of textures seen at an angle, from 1 to 16.
+    /// Every filter has to be linear for it to be more than 1.
+    #[must_use]
+    #[inline]
+    pub const fn anisotropy(mut self, n: u16) -> Self {
+        self.anisotropy = n;
+        self
+    }
+
     #[must_use]
     #[inline]
     pub fn build(self) -> Sampler {
@@ -69,19 +113,25 @@ impl<'a> SamplerBuilder<'a> {
             address_mode_u: wgpu::AddressMode::ClampToBorder,
             address_mode_v: wgpu::AddressMode::ClampToBorder,
             address_mode_w: wgpu::AddressMode::ClampToBorder,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
             lod_min_clamp: 0.0,
             lod_max_clamp: 32.0,
             compare: None,
-            anisotropy_clamp: 1,
+            anisotropy_clamp: self.anisotropy,
             border_color: Some(wgpu::SamplerBorderColor::TransparentBlack),
         });
 
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .contains(&wgpu::FilterMode::Linear);
         Sampler {
             inner,
-            ty: wgpu::SamplerBindingType::Filtering,
+            ty: if linear {
+                wgpu::SamplerBindingType::Filtering
+            } else {
+                wgpu::SamplerBindingType::NonFiltering
+            },
         }
     }
 }
@@ -1,10 +1,28 @@
-use crate::OntoDevice;
+use std::{
+    cell::Cell,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::SystemTime,
+};
 
+use crate::{Error, OntoDevice, Result};
+
+/// Shader compiled into the binary from a WGSL file next to the calling source file. In debug
+/// builds the file is also watched, so checkpoints built from it can be rebuilt as it's
+/// edited, see [`crate::RenderCheckpoint::reload_changed`].
 #[macro_export]
 macro_rules! include_shader {
     ($vp: literal $(=> $ve: literal)? $(& $fe: literal)?) => {
         ::smpgpu::Shader::new()
             .module(::smpgpu::reexport::include_wgsl!($vp))
+            .source(if cfg!(debug_assertions) {
+                ::smpgpu::reexport::source_path(env!("CARGO_MANIFEST_DIR"), file!(), $vp)
+            } else {
+                None
+            })
             $(.entry($ve))?
             $(.frag_entry($fe))?
     };
@@ -14,6 +32,7 @@ macro_rules! include_shader {
 pub struct Shader<'a, 'b> {
     desc: Option<wgpu::ShaderModuleDescriptor<'a>>,
     entry: Option<&'b str>,
+    source: Option<PathBuf>,
 }
 
 impl<'a, 'b> Shader<'a, 'b> {
@@ -30,6 +49,14 @@ impl<'a, 'b> Shader<'a, 'b> {
         self
     }
 
+    /// WGSL file the module was made from, watched for changes once it's built.
+    #[must_use]
+    #[inline]
+    pub fn source(mut self, path: impl Into<Option<PathBuf>>) -> Self {
+        self.source = path.into();
+        self
+    }
+
     #[must_use]
     #[inline]
     pub fn entry(mut self, entry: impl Into<Option<&'b str>>) -> Self {
@@ -58,15 +85,17 @@ impl<'a, 'b> From<Shader<'a, 'b>> for RenderShader<'a, 'b> {
 
 #[derive(Debug, Default)]
 pub struct CompiledShader<'b> {
-    pub module: Option<wgpu::ShaderModule>,
+    pub module: Option<Arc<wgpu::ShaderModule>>,
     pub entry: Option<&'b str>,
+    pub source: Option<PathBuf>,
 }
 
 impl<'a, 'b> OntoDevice<CompiledShader<'b>> for Shader<'a, 'b> {
-    fn onto_device(self, dev: &wgpu::Device) -> CompiledShader<'b> {
+    fn onto_device(self, ctx: &crate::Context) -> CompiledShader<'b> {
         CompiledShader {
-            module: self.desc.map(|desc| dev.create_shader_module(desc)),
+            module: self.desc.map(|desc| ctx.shader_module(desc)),
             entry: self.entry,
+            source: self.source,
         }
     }
 }
@@ -80,16 +109,16 @@ pub struct RenderShader<'a, 'b> {
 impl<'a, 'b> RenderShader<'a, 'b> {}
 
 impl<'a, 'b> OntoDevice<CompiledRenderShader<'b>> for Shader<'a, 'b> {
-    fn onto_device(self, dev: &wgpu::Device) -> CompiledRenderShader<'b> {
-        RenderShader::from(self).onto_device(dev)
+    fn onto_device(self, ctx: &crate::Context) -> CompiledRenderShader<'b> {
+        RenderShader::from(self).onto_device(ctx)
     }
 }
 
 impl<'a, 'b> OntoDevice<CompiledRenderShader<'b>> for RenderShader<'a, 'b> {
-    fn onto_device(self, dev: &wgpu::Device) -> CompiledRenderShader<'b> {
+    fn onto_device(self, ctx: &crate::Context) -> CompiledRenderShader<'b> {
         CompiledRenderShader {
-            vert: self.vert.onto_device(dev),
-            frag: self.frag.onto_device(dev),
+            vert: self.vert.onto_device(ctx),
+            frag: self.frag.onto_device(ctx),
         }
     }
 }
@@ -102,7 +131,7 @@ pub struct CompiledRenderShader<'b> {
 
 impl<'b> CompiledRenderShader<'b> {
     #[inline]
-    pub(crate) const fn split(
+    pub(crate) fn split(
         &self,
     ) -> Option<(
         &wgpu::ShaderModule,
@@ -110,10 +139,94 @@ impl<'b> CompiledRenderShader<'b> {
         &wgpu::ShaderModule,
         Option<&'b str>,
     )> {
-        match (self.vert.module.as_ref(), self.frag.module.as_ref()) {
+        match (self.vert.module.as_deref(), self.frag.module.as_deref()) {
             (Some(vm), Some(fm)) => Some((vm, self.vert.entry, fm, self.frag.entry)),
             (Some(m), _) | (_, Some(m)) => Some((m, self.vert.entry, m, self.frag.entry)),
             _ => None,
         }
     }
+
+    /// File the shader was made from, when both stages come from the same module.
+    #[inline]
+    pub(crate) fn source(&self) -> Option<&Path> {
+        match (&self.vert.module, &self.frag.module) {
+            (Some(_), None) => self.vert.source.as_deref(),
+            (None, Some(_)) => self.frag.source.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Path of `path`, relative to the source `file` of the crate in `manifest_dir`, when the
+/// source is still on this machine.
+#[doc(hidden)]
+#[must_use]
+pub fn source_path(manifest_dir: &str, file: &str, path: &str) -> Option<PathBuf> {
+    // `file!()` is relative to the workspace, somewhere above the crate
+    Path::new(manifest_dir)
+        .ancestors()
+        .map(|dir| dir.join(file))
+        .find(|f| f.is_file())
+        .and_then(|f| Some(f.parent()?.join(path)))
+}
+
+/// WGSL file a pipeline's shader was made from and when it was last read.
+#[derive(Debug)]
+pub(crate) struct ShaderWatch {
+    path: PathBuf,
+    read: Cell<Option<SystemTime>>,
+}
+
+impl ShaderWatch {
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            read: Cell::new(modified(path)),
+        }
+    }
+
+    /// Makes a new module from the file when it changed since it was last read and passes it
+    /// to `f`, which builds the pipeline that replaces the old one.
+    ///
+    /// # Errors
+    /// the file can't be read, or the module or anything `f` makes from it isn't valid. The
+    /// file isn't read again until it changes.
+    pub(crate) fn reload<T>(
+        &self,
+        dev: &wgpu::Device,
+        f: impl FnOnce(&wgpu::ShaderModule) -> T,
+    ) -> Result<Option<T>> {
+        let time = modified(&self.path);
+        if time == self.read.get() {
+            return Ok(None);
+        }
+        self.read.set(time);
+
+        let err = |msg: String| Error::ShaderReload(self.path.clone(), msg);
+        let src = std::fs::read_to_string(&self.path).map_err(|e| err(e.to_string()))?;
+
+        // errors go to the device's handler, which panics, unless they're caught by a scope
+        dev.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = dev.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: self.path.to_str(),
+            source: wgpu::ShaderSource::Wgsl(src.into()),
+        });
+        let out = f(&module);
+        match pop_error_scope(dev) {
+            Some(e) => Err(err(e.to_string())),
+            None => Ok(Some(out)),
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Error caught by the innermost error scope, which native devices have ready right away.
+fn pop_error_scope(dev: &wgpu::Device) -> Option<wgpu::Error> {
+    match pin!(dev.pop_error_scope()).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(err) => err,
+        Poll::Pending => None,
+    }
 }
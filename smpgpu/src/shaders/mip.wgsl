@@ -0,0 +1,24 @@
+// Draws a mip level from the one above it, each pixel a linear blend of the 4 it covers.
+
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var src_sampler: sampler;
+
+struct VertOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the whole level
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VertOut {
+    let uv = vec2(f32((i << 1u) & 2u), f32(i & 2u));
+    var out: VertOut;
+    out.pos = vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertOut) -> @location(0) vec4<f32> {
+    return textureSample(src, src_sampler, in.uv);
+}
@@ -6,6 +6,8 @@ use crate::{
 
 pub struct Texture {
     inner: wgpu::Texture,
+    /// Bound as an array, even with a single layer
+    array: bool,
 }
 
 impl Texture {
@@ -20,12 +22,25 @@ impl Texture {
         self.inner.format()
     }
 
+    /// Bytes each pixel takes in a buffer it's copied to or from.
+    #[must_use]
+    #[inline]
+    pub fn pixel_bytes(&self) -> u32 {
+        pixel_bytes(self.format())
+    }
+
+    /// Bytes in a row of a buffer copied to this texture, see [`Self::copy_from_buf_op`].
+    #[inline]
+    pub fn padded_row_bytes(&self) -> u32 {
+        padded_row_bytes(self.width(), self.format())
+    }
+
     #[inline]
     pub(crate) fn texture_view_dimension(&self) -> wgpu::TextureViewDimension {
-        if self.size().depth_or_array_layers == 1 {
-            wgpu::TextureViewDimension::D2
-        } else {
+        if self.array {
             wgpu::TextureViewDimension::D2Array
+        } else {
+            wgpu::TextureViewDimension::D2
         }
     }
 
@@ -33,7 +48,7 @@ impl Texture {
     pub(crate) fn view(&self) -> wgpu::TextureView {
         self.inner.create_view(&wgpu::TextureViewDescriptor {
             label: None,
-            format: Some(wgpu::TextureFormat::Rgba8Unorm),
+            format: None,
             dimension: Some(self.texture_view_dimension()),
             aspect: wgpu::TextureAspect::All,
             base_mip_level: 0,
@@ -60,7 +75,7 @@ impl Texture {
             data,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(size.width * 4),
+                bytes_per_row: Some(size.width * self.pixel_bytes()),
                 rows_per_image: Some(size.height),
             },
             wgpu::Extent3d {
@@ -76,11 +91,53 @@ impl Texture {
         let size = self.size();
         Buffer::builder(dev)
             .label("texture_staging_buf")
-            .size((size.width * size.height * size.depth_or_array_layers * 4) as _)
+            .size((size.width * size.height * size.depth_or_array_layers * self.pixel_bytes()) as _)
             .writable()
             .build()
     }
 
+    /// Copies all of `buf` into `layer`, with its rows padded to a multiple of 256 bytes.
+    #[inline]
+    pub fn copy_from_buf_op<'a>(&'a self, buf: &'a Buffer, layer: u32) -> impl EncoderOp + 'a {
+        let size = self.size();
+        CopyOp::BufText(
+            buf,
+            self,
+            wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+            wgpu::TextureAspect::All,
+            wgpu::Extent3d {
+                depth_or_array_layers: 1,
+                ..size
+            },
+        )
+    }
+
+    /// Copies `count` layers starting at `first` to `texture`, starting at `dst_layer`.
+    #[inline]
+    pub fn copy_layers_to_op<'a>(
+        &'a self,
+        first: u32,
+        count: u32,
+        texture: &'a Self,
+        dst_layer: u32,
+    ) -> impl EncoderOp + 'a {
+        let size = self.size();
+        CopyOp::TextText(
+            self,
+            first,
+            texture,
+            dst_layer,
+            wgpu::Extent3d {
+                depth_or_array_layers: count,
+                ..size
+            },
+        )
+    }
+
     #[inline]
     pub fn copy_to_buf_op<'a>(&'a self, buf: &'a Buffer) -> impl EncoderOp + 'a {
         let size = self.size();
@@ -104,24 +161,30 @@ impl<'a> Bindable<'a> for &'a Texture {
     type VisBind = Self;
 
     fn into_binding(self) -> (wgpu::BindingType, BindResource<'a>) {
-        let access: wgpu::StorageTextureAccess = match (
-            self.usage().contains(wgpu::TextureUsages::COPY_SRC),
-            self.usage().contains(wgpu::TextureUsages::COPY_DST),
-        ) {
-            (true, true) => wgpu::StorageTextureAccess::ReadWrite,
-            (true, false) => wgpu::StorageTextureAccess::WriteOnly,
-            (false, true) => wgpu::StorageTextureAccess::ReadOnly,
-            (false, false) => panic!("attempted to add a texture with read or write flags"),
-        };
-
         let ty = if self.usage().contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            let access = match (
+                self.usage().contains(wgpu::TextureUsages::COPY_SRC),
+                self.usage().contains(wgpu::TextureUsages::COPY_DST),
+            ) {
+                (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                (true, false) => wgpu::StorageTextureAccess::WriteOnly,
+                (false, true) => wgpu::StorageTextureAccess::ReadOnly,
+                (false, false) => panic!("attempted to add a texture with read or write flags"),
+            };
             wgpu::BindingType::StorageTexture {
                 access,
                 format: self.format(),
                 view_dimension: self.texture_view_dimension(),
             }
         } else {
-            todo!("non storage textures");
+            let multisampled = self.sample_count() > 1;
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float {
+                    filterable: !multisampled,
+                },
+                view_dimension: self.texture_view_dimension(),
+                multisampled,
+            }
         };
 
         (ty, BindResource::TextureView(self.view()))
@@ -149,6 +212,10 @@ pub struct TextureBuilder<'a> {
     width: u32,
     height: u32,
     layers: u32,
+    array: bool,
+    mip_levels: u32,
+    samples: u32,
+    format: wgpu::TextureFormat,
     usage: wgpu::TextureUsages,
 }
 
@@ -162,6 +229,10 @@ impl<'a> TextureBuilder<'a> {
             width: 0,
             height: 0,
             layers: 1,
+            array: false,
+            mip_levels: 1,
+            samples: 1,
+            format: wgpu::TextureFormat::Rgba8Unorm,
             usage: wgpu::TextureUsages::TEXTURE_BINDING,
         }
     }
@@ -181,10 +252,39 @@ impl<'a> TextureBuilder<'a> {
         self
     }
 
+    /// Makes the texture an array of `layers`, bound as one even when there is only one.
     #[must_use]
     #[inline]
     pub const fn layers(mut self, layers: usize) -> Self {
         self.layers = layers as _;
+        self.array = true;
+        self
+    }
+
+    /// Gives the texture `n` mip levels, each half the size of the one before, or as many as
+    /// its size allows when `n` is 0. They are drawn from the first level by a
+    /// [`crate::MipGenerator`], so the texture can also be rendered to.
+    #[must_use]
+    #[inline]
+    pub const fn mip_levels(mut self, n: u32) -> Self {
+        self.mip_levels = n;
+        self
+    }
+
+    /// Gives every pixel `n` samples to render into, which are averaged into a texture with one
+    /// by a [`RenderAttachment::resolve`]. Only 1 and 4 work with every format and GPU.
+    #[must_use]
+    #[inline]
+    pub const fn samples(mut self, n: u32) -> Self {
+        self.samples = n;
+        self
+    }
+
+    /// Defaults to `Rgba8Unorm`.
+    #[must_use]
+    #[inline]
+    pub const fn format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = format;
         self
     }
 
@@ -224,25 +324,53 @@ impl<'a> TextureBuilder<'a> {
     #[must_use]
     #[inline]
     pub fn build(self) -> Texture {
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: self.layers,
+        };
+        let mip_level_count = match self.mip_levels {
+            0 => size.max_mips(wgpu::TextureDimension::D2),
+            n => n,
+        };
+        let usage = if mip_level_count > 1 {
+            self.usage | wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            self.usage
+        };
+
         let inner = self.dev.create_texture(&wgpu::TextureDescriptor {
             label: None,
-            size: wgpu::Extent3d {
-                width: self.width,
-                height: self.height,
-                depth_or_array_layers: self.layers,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
+            size,
+            mip_level_count,
+            sample_count: self.samples,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: self.usage,
+            format: self.format,
+            usage,
             view_formats: &[],
         });
 
-        Texture { inner }
+        Texture {
+            inner,
+            array: self.array,
+        }
     }
 }
 
+/// Bytes a pixel of `format` takes in a buffer, for formats without multiple aspects.
+#[inline]
+pub(crate) fn pixel_bytes(format: wgpu::TextureFormat) -> u32 {
+    format
+        .block_copy_size(None)
+        .expect("texture format has no single copy size")
+}
+
+/// Bytes in a row of `width` pixels of `format` copied from a buffer, which has to be a
+/// multiple of [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`].
+pub(crate) fn padded_row_bytes(width: u32, format: wgpu::TextureFormat) -> u32 {
+    (width * pixel_bytes(format)).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
 impl std::ops::Deref for Texture {
     type Target = wgpu::Texture;
 
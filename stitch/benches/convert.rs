@@ -0,0 +1,37 @@
+//! Pixel format conversion of camera frames to RGBA, run on every frame before projection.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use stitch::convert;
+
+const RESOLUTIONS: [(usize, usize); 3] = [(640, 480), (1280, 720), (1920, 1080)];
+
+fn yuyv(c: &mut Criterion) {
+    let mut group = c.benchmark_group("yuyv_to_rgba");
+    for (w, h) in RESOLUTIONS {
+        let src = (0..w * h * 2).map(|i| i as u8).collect::<Vec<_>>();
+        let mut dst = vec![0; w * h * 4];
+
+        group.throughput(Throughput::Elements((w * h) as u64));
+        group.bench_function(BenchmarkId::from_parameter(format!("{w}x{h}")), |b| {
+            b.iter(|| convert::yuyv_to_rgba(&src, &mut dst, w, h).unwrap());
+        });
+    }
+    group.finish();
+}
+
+fn nv12(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nv12_to_rgba");
+    for (w, h) in RESOLUTIONS {
+        let src = (0..w * h * 3 / 2).map(|i| i as u8).collect::<Vec<_>>();
+        let mut dst = vec![0; w * h * 4];
+
+        group.throughput(Throughput::Elements((w * h) as u64));
+        group.bench_function(BenchmarkId::from_parameter(format!("{w}x{h}")), |b| {
+            b.iter(|| convert::nv12_to_rgba(&src, &mut dst, w, h).unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, yuyv, nv12);
+criterion_main!(benches);
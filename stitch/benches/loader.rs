@@ -0,0 +1,70 @@
+//! Handing a buffer to a camera's loader thread and taking it back filled, which every frame of
+//! every camera goes through.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use stitch::{
+    buf::FrameSize,
+    loader::{Loader, Ticket},
+};
+
+const RESOLUTIONS: [(u32, u32); 3] = [(640, 480), (1280, 720), (1920, 1080)];
+
+/// Loader that fills every frame like a camera copying out its latest capture.
+fn copy_loader(w: u32, h: u32) -> Loader<Box<[u8]>> {
+    let frame = vec![0x80; (w * h * 4) as usize];
+    Loader::new_blocking(w, h, 4, move |buf| buf.copy_from_slice(&frame))
+}
+
+fn round_trip(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _rt = rt.enter();
+
+    let mut group = c.benchmark_group("loader_round_trip");
+    for (w, h) in RESOLUTIONS {
+        let loader = copy_loader(w, h);
+        let mut buf = Some(vec![0; loader.num_bytes()].into_boxed_slice());
+
+        group.throughput(Throughput::Bytes(loader.num_bytes() as u64));
+        group.bench_function(BenchmarkId::from_parameter(format!("{w}x{h}")), |b| {
+            b.iter(|| {
+                let ticket = loader.give(buf.take().unwrap()).unwrap();
+                buf = Some(Ticket::block_take(ticket).unwrap());
+            });
+        });
+    }
+    group.finish();
+}
+
+/// A camera shared between the projector and raw feeds, so every frame is copied out to each
+/// subscriber.
+fn shared(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _rt = rt.enter();
+    let (w, h) = (1280, 720);
+
+    let mut group = c.benchmark_group("shared_loader_1280x720");
+    for subs in [1, 2, 4] {
+        let loaders = copy_loader(w, h).tee::<Box<[u8]>>(subs);
+        let mut bufs = loaders
+            .iter()
+            .map(|l| Some(vec![0; l.num_bytes()].into_boxed_slice()))
+            .collect::<Vec<_>>();
+
+        group.bench_function(BenchmarkId::new("subscribers", subs), |b| {
+            b.iter(|| {
+                let tickets = loaders
+                    .iter()
+                    .zip(&mut bufs)
+                    .map(|(l, buf)| l.give(buf.take().unwrap()).unwrap())
+                    .collect::<Vec<_>>();
+                for (ticket, buf) in tickets.into_iter().zip(&mut bufs) {
+                    *buf = Some(ticket.block_take().unwrap());
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, round_trip, shared);
+criterion_main!(benches);
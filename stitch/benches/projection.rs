@@ -0,0 +1,179 @@
+//! Frame latency of both projectors, from loading the camera frames to reading back the output,
+//! and generating the camera masks. Without a GPU adapter only the CPU projector is measured.
+
+use std::ops::{Deref, DerefMut};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use stitch::{
+    buf::FrameSize,
+    camera::{Camera, Config, Fov},
+    loader::{self, Loader, OwnedWriteBuffer},
+    proj::{CpuProjector, GpuProjector, ProjectionStyle},
+};
+
+const INPUT_SIZE: (u32, u32) = (1280, 720);
+
+const STYLE: ProjectionStyle = ProjectionStyle::Hemisphere {
+    pos: [0.0, 0.0, 0.0],
+    radius: 30.0,
+};
+
+/// `n` cameras in a ring around the origin, each filled with a flat color every frame.
+fn ring_cams<B: OwnedWriteBuffer + 'static>(n: u32) -> Vec<Camera<Loader<B>>> {
+    let (w, h) = INPUT_SIZE;
+    (0..n)
+        .map(|i| {
+            let azimuth = 360.0 * i as f32 / n as f32;
+            let loader = Loader::new_blocking(w, h, 4, move |buf| buf.fill(0x40 * i as u8 + 0x3f));
+            Config::builder((), Fov::D(150.0))
+                .pos(0.0, 0.0, 8.0)
+                .pitch(35.0)
+                .azimuth(azimuth)
+                .build()
+                .with_dims(w as f32, h as f32)
+                .with_buffer(loader)
+        })
+        .collect()
+}
+
+fn gpu_frame(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _rt = rt.enter();
+    let (w, h) = INPUT_SIZE;
+
+    let mut group = c.benchmark_group("gpu_frame");
+    for n in [2, 4] {
+        for (out_w, out_h) in [(1280, 720), (1920, 1080), (3840, 2160)] {
+            let proj = match rt.block_on(GpuProjector::builder_auto()) {
+                Ok(b) => b
+                    .input_size(w, h, n)
+                    .out_size(out_w, out_h)
+                    .flat_bound()
+                    .build()
+                    .unwrap(),
+                Err(err) => {
+                    eprintln!("skipping GPU projection, no adapter: {err}");
+                    return;
+                }
+            };
+            let cams = ring_cams(n);
+            proj.update_cam_specs(&cams).unwrap();
+            proj.update_proj_view(STYLE);
+            let mut out = Output::new((out_w, out_h));
+
+            let id = BenchmarkId::new(format!("{n}_cams"), format!("{out_w}x{out_h}"));
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    loader::block_discard_tickets(proj.take_input_buffers(&cams).unwrap());
+                    proj.update_render();
+                    proj.block_copy_render_to(&mut out).unwrap();
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+fn cpu_frame(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _rt = rt.enter();
+    let (w, h) = INPUT_SIZE;
+
+    let mut group = c.benchmark_group("cpu_frame");
+    group.sample_size(10);
+    for n in [2, 4] {
+        for (out_w, out_h) in [(640, 480), (1280, 720)] {
+            let mut proj = CpuProjector::builder()
+                .input_size(w, h, n)
+                .out_size(out_w, out_h)
+                .build()
+                .unwrap();
+            let cams = ring_cams(n);
+            proj.update_cam_specs(&cams).unwrap();
+            proj.update_proj_view(STYLE);
+            let mut out = Output::new((out_w, out_h));
+
+            let id = BenchmarkId::new(format!("{n}_cams"), format!("{out_w}x{out_h}"));
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    proj.block_load_inputs(&cams).unwrap();
+                    proj.update_render();
+                    proj.copy_render_to(&mut out);
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+fn masks(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _rt = rt.enter();
+    let (w, h) = INPUT_SIZE;
+
+    let mut proj = CpuProjector::builder()
+        .input_size(w, h, 4)
+        .out_size(640, 480)
+        .build()
+        .unwrap();
+    proj.update_cam_specs(&ring_cams::<Box<[u8]>>(4)).unwrap();
+    proj.update_proj_view(STYLE);
+
+    let mut group = c.benchmark_group("masks");
+    group.sample_size(10);
+    group.bench_function("auto_4_cams", |b| {
+        b.iter(|| proj.auto_masks(75f32.to_radians()));
+    });
+
+    let mask = image::GrayImage::from_fn(w / 2, h / 2, |x, _| image::Luma([(x % 256) as u8]));
+    group.bench_function("rescale_from_half", |b| {
+        b.iter(|| proj.update_mask(0, mask.clone()).unwrap());
+    });
+    group.finish();
+}
+
+/// RGBA output of the main view.
+struct Output {
+    data: Vec<u8>,
+    size: (usize, usize),
+}
+
+impl Output {
+    fn new(size: (usize, usize)) -> Self {
+        Self {
+            data: vec![0; size.0 * size.1 * 4],
+            size,
+        }
+    }
+}
+
+impl FrameSize for Output {
+    fn width(&self) -> usize {
+        self.size.0
+    }
+
+    fn height(&self) -> usize {
+        self.size.1
+    }
+
+    fn chans(&self) -> usize {
+        4
+    }
+}
+
+impl Deref for Output {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl DerefMut for Output {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+criterion_group!(benches, gpu_frame, cpu_frame, masks);
+criterion_main!(benches);
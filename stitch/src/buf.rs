@@ -3,17 +3,87 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
+use serde::{Deserialize, Serialize};
+
+/// Layout of a frame's pixels in memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelFormat {
+    #[default]
+    Rgba8,
+    Rgb8,
+    /// Full resolution luma plane followed by interleaved half resolution U and V
+    Nv12,
+    /// Y0 U Y1 V for every pair of pixels
+    Yuyv,
+    Gray8,
+    /// 16 bit float channels
+    Rgba16F,
+}
+
+impl PixelFormat {
+    /// Packed format with `chans` channels of `bytes_per_chan` bytes each, RGBA8 when no
+    /// format matches.
+    #[must_use]
+    pub const fn packed(chans: usize, bytes_per_chan: usize) -> Self {
+        match (chans, bytes_per_chan) {
+            (1, 1) => Self::Gray8,
+            (3, 1) => Self::Rgb8,
+            (4, 2) => Self::Rgba16F,
+            _ => Self::Rgba8,
+        }
+    }
+
+    /// Channels of a pixel once decoded.
+    #[must_use]
+    pub const fn chans(self) -> usize {
+        match self {
+            Self::Rgba8 | Self::Rgba16F => 4,
+            Self::Rgb8 | Self::Nv12 | Self::Yuyv => 3,
+            Self::Gray8 => 1,
+        }
+    }
+
+    #[must_use]
+    pub const fn bytes_per_chan(self) -> usize {
+        match self {
+            Self::Rgba16F => 2,
+            _ => 1,
+        }
+    }
+
+    /// Bytes a `width` by `height` frame takes up.
+    #[must_use]
+    pub const fn frame_bytes(self, width: usize, height: usize) -> usize {
+        match self {
+            Self::Nv12 => width * height + width.div_ceil(2) * 2 * height.div_ceil(2),
+            Self::Yuyv => width.div_ceil(2) * 4 * height,
+            _ => width * height * self.chans() * self.bytes_per_chan(),
+        }
+    }
+}
+
 pub trait FrameSize {
     fn width(&self) -> usize;
     fn height(&self) -> usize;
     fn chans(&self) -> usize;
 
+    /// Bytes in each channel of a pixel, like 2 for RGBA16F frames.
+    fn bytes_per_chan(&self) -> usize {
+        1
+    }
+
+    /// Layout of the pixels, packed in [`Self::chans`] channels unless told otherwise.
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::packed(self.chans(), self.bytes_per_chan())
+    }
+
     fn frame_size(&self) -> (usize, usize, usize) {
         (self.width(), self.height(), self.chans())
     }
 
     fn num_bytes(&self) -> usize {
-        self.width() * self.height() * self.chans()
+        self.width() * self.height() * self.chans() * self.bytes_per_chan()
     }
 
     fn as_empty_view(&self) -> FrameBufferView<'static> {
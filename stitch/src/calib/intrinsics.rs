@@ -0,0 +1,377 @@
+//! Fits a camera's lens to frames of a chessboard.
+
+use std::ops::Deref;
+
+use glam::{Mat3, Quat, Vec2, Vec3};
+use rayon::prelude::*;
+
+use super::{levenberg_marquardt, pixel_ray, targets::Chessboard};
+use crate::{
+    buf::FrameSize,
+    camera::{Distortion, Fov, LensKind, SensorParams, ViewParams},
+    Error, Result,
+};
+
+/// Parameters per board: rotation vector and position in the camera's frame.
+const POSE: usize = 6;
+/// Frames the board has to be found in before the focal distance can be told apart from how far
+/// away it is.
+const MIN_BOARDS: usize = 3;
+
+/// Fits the focal distance, image offset and [`Distortion`] of a camera's lens to frames of a
+/// [`Chessboard`] held at different angles and distances, filling as much of the frame between
+/// them as possible.
+#[derive(Clone, Copy, Debug)]
+pub struct LensCalibrator {
+    board: Chessboard,
+    lens: LensKind,
+    fov: Fov,
+    radial_terms: usize,
+    tangential: bool,
+    iterations: usize,
+}
+
+/// Summary of a [`LensCalibrator`] run.
+#[derive(Clone, Copy, Debug)]
+pub struct LensFit {
+    /// Fitted sensor, with the focal distance of frames the size of the ones calibrated with
+    pub sensor: SensorParams,
+    /// Frames the board was found in
+    pub boards: usize,
+    /// RMS distance in pixels between the corners found and where the fit projects them
+    pub rms: f32,
+}
+
+impl LensCalibrator {
+    /// Fits a `lens` with roughly `fov` to frames of `board`.
+    #[must_use]
+    pub const fn new(board: Chessboard, lens: LensKind, fov: Fov) -> Self {
+        Self {
+            board,
+            lens,
+            fov,
+            radial_terms: 2,
+            tangential: false,
+            iterations: 200,
+        }
+    }
+
+    /// Radial terms fitted, from `k1` up to all 4. Wider lenses need more, and more frames to
+    /// pin them down.
+    #[must_use]
+    pub const fn radial_terms(mut self, n: usize) -> Self {
+        self.radial_terms = if n > 4 { 4 } else { n };
+        self
+    }
+
+    /// Whether `p1` and `p2` are fitted, for rectilinear lenses that aren't square to the
+    /// sensor.
+    #[must_use]
+    pub const fn tangential(mut self, fit: bool) -> Self {
+        self.tangential = fit;
+        self
+    }
+
+    /// Levenberg-Marquardt iterations of each stage of the fit.
+    #[must_use]
+    pub const fn iterations(mut self, n: usize) -> Self {
+        self.iterations = n;
+        self
+    }
+
+    /// Finds the board in every frame and fits the lens to the ones it's found in.
+    ///
+    /// # Errors
+    /// the frames aren't all the same size, or the board is found in fewer than 3 of them
+    pub fn calibrate<F>(&self, frames: &[F]) -> Result<LensFit>
+    where
+        F: Deref<Target = [u8]> + FrameSize + Sync,
+    {
+        let Some(first) = frames.first() else {
+            return Err(Error::Calibration("no frames to find the board in"));
+        };
+        let (w, h, _) = first.frame_size();
+        if frames.iter().any(|f| (f.width(), f.height()) != (w, h)) {
+            return Err(Error::Calibration("frames aren't all the same size"));
+        }
+
+        let boards = frames
+            .par_iter()
+            .filter_map(|f| self.board.detect(f))
+            .collect::<Vec<_>>();
+        #[allow(clippy::cast_precision_loss)]
+        let size = Vec2::new(w as f32, h as f32);
+        self.fit(size, &boards)
+    }
+
+    /// Fits the lens to the corners of boards found in frames of `size`, each laid out like
+    /// [`Chessboard::detect`] returns them.
+    ///
+    /// # Errors
+    /// there are fewer than 3 boards, or one has the wrong number of corners
+    pub fn fit(&self, size: Vec2, boards: &[Vec<Vec2>]) -> Result<LensFit> {
+        let model = self.model();
+        if boards.len() < MIN_BOARDS {
+            return Err(Error::Calibration(
+                "the board has to be found in at least 3 frames",
+            ));
+        }
+        if boards.iter().any(|b| b.len() != model.len()) {
+            return Err(Error::Calibration(
+                "a board has the wrong number of corners",
+            ));
+        }
+
+        let guess = ViewParams {
+            pos: [0.; 3],
+            pitch: 0.,
+            azimuth: 0.,
+            roll: 0.,
+            sensor: SensorParams {
+                img_off: [0.; 2],
+                fov: self.fov.with_dims(self.lens, size.x, size.y),
+                distortion: Distortion::NONE,
+            },
+            lens: self.lens,
+        };
+        let poses = boards
+            .iter()
+            .map(|corners| initial_pose(&guess, size, &model, corners))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::Calibration(
+                "a board is outside the guessed field of view",
+            ))?;
+
+        // boards are placed with the guessed lens first, the lens is too far off to fit it
+        // to boards that are nowhere near where they're seen
+        let intr = self.params(&guess.sensor);
+        let residuals = |poses: &[f32]| self.residuals(size, &model, boards, &intr, poses);
+        let poses = levenberg_marquardt(poses.concat(), self.iterations, residuals);
+
+        let n = intr.len();
+        let residuals = |x: &[f32]| self.residuals(size, &model, boards, &x[..n], &x[n..]);
+        let x = levenberg_marquardt([intr, poses].concat(), self.iterations, residuals);
+
+        #[allow(clippy::cast_precision_loss)]
+        let corners = (boards.len() * model.len()) as f32;
+        let rms = (residuals(&x).iter().map(|v| v * v).sum::<f32>() / corners).sqrt();
+        Ok(LensFit {
+            sensor: self.sensor(&x[..n]),
+            boards: boards.len(),
+            rms,
+        })
+    }
+
+    /// Corners of the board in its own plane, a square apart and in the order found.
+    fn model(&self) -> Vec<Vec3> {
+        let (cols, rows) = self.board.size();
+        #[allow(clippy::cast_precision_loss)]
+        (0..rows)
+            .flat_map(|r| (0..cols).map(move |c| Vec3::new(c as f32, r as f32, 0.)))
+            .collect()
+    }
+
+    /// Focal distance, image offset and the distortion terms fitted.
+    fn params(&self, sensor: &SensorParams) -> Vec<f32> {
+        let d = sensor.distortion;
+        let [ox, oy] = sensor.img_off;
+        let mut x = vec![sensor.fov.assume_focal_dist().unwrap_or(1.), ox, oy];
+        x.extend([d.k1, d.k2, d.k3, d.k4].into_iter().take(self.radial_terms));
+        if self.tangential {
+            x.extend([d.p1, d.p2]);
+        }
+        x
+    }
+
+    /// Inverse of [`Self::params`], with the terms not fitted left at 0.
+    fn sensor(&self, x: &[f32]) -> SensorParams {
+        let mut k = [0.; 4];
+        k[..self.radial_terms].copy_from_slice(&x[3..3 + self.radial_terms]);
+        let p = x.get(3 + self.radial_terms..).filter(|_| self.tangential);
+        SensorParams {
+            img_off: [x[1], x[2]],
+            fov: Fov::FocalDist(x[0]),
+            distortion: Distortion {
+                k1: k[0],
+                k2: k[1],
+                k3: k[2],
+                k4: k[3],
+                p1: p.map_or(0., |p| p[0]),
+                p2: p.map_or(0., |p| p[1]),
+            },
+        }
+    }
+
+    /// Pixel distances between every corner found and where `intr` and `poses` project it.
+    fn residuals(
+        &self,
+        size: Vec2,
+        model: &[Vec3],
+        boards: &[Vec<Vec2>],
+        intr: &[f32],
+        poses: &[f32],
+    ) -> Vec<f32> {
+        let sensor = self.sensor(intr);
+        let mut out = Vec::with_capacity(boards.len() * model.len() * 2);
+        for (corners, pose) in boards.iter().zip(poses.chunks_exact(POSE)) {
+            let rot = Quat::from_scaled_axis(Vec3::new(pose[0], pose[1], pose[2]));
+            let pos = Vec3::new(pose[3], pose[4], pose[5]);
+            for (p, c) in model.iter().zip(corners) {
+                let d = project(&sensor, self.lens, size, rot * *p + pos) - *c;
+                out.extend([d.x, d.y]);
+            }
+        }
+        out
+    }
+}
+
+/// Pixel of a frame of `size` that `p`, in the frame of a camera looking along y with z up, is
+/// seen at through `sensor` and `lens`, the projection in `proj/shaders/render.wgsl`.
+fn project(sensor: &SensorParams, lens: LensKind, size: Vec2, p: Vec3) -> Vec2 {
+    let ang = (p.y / p.length()).clamp(-1., 1.).acos();
+    let r = match lens {
+        LensKind::Rectilinear => ang.tan(),
+        LensKind::Equidistant => ang,
+        LensKind::Equisolid => 2. * (ang / 2.).sin(),
+    };
+    let u = Vec2::new(p.x, p.z).normalize_or_zero() * r;
+
+    let f = sensor.fov.assume_focal_dist().unwrap_or(1.);
+    let img = Vec2::from(sensor.distortion.apply(u.into())) * f;
+    (img * Vec2::new(1., -1.) * size.length() + size) / 2. + Vec2::from(sensor.img_off)
+}
+
+/// Rotation vector and position of a board with `corners` seen through the lens of `guess`,
+/// facing the camera at the distance its corners are apart.
+fn initial_pose(
+    guess: &ViewParams,
+    size: Vec2,
+    model: &[Vec3],
+    corners: &[Vec2],
+) -> Option<Vec<f32>> {
+    let rays = corners
+        .iter()
+        .map(|&c| pixel_ray(guess, size, c))
+        .collect::<Option<Vec<_>>>()?;
+
+    // the model is a square apart along x and y, so the board's axes are the directions the
+    // rays move along between its corners
+    let (mut along_x, mut along_y) = (Vec3::ZERO, Vec3::ZERO);
+    let (mut apart, mut pairs) = (0., 0u16);
+    for (i, (p, ray)) in model.iter().zip(&rays).enumerate() {
+        if let Some((q, next)) = model.get(i + 1).zip(rays.get(i + 1)) {
+            if q.y == p.y {
+                along_x += *next - *ray;
+                apart += next.angle_between(*ray);
+                pairs += 1;
+            }
+        }
+        if let Some(j) = model.iter().position(|q| *q == *p + Vec3::Y) {
+            along_y += rays[j] - *ray;
+        }
+    }
+    let x = along_x.try_normalize()?;
+    let z = x.cross(along_y).try_normalize()?;
+    let rot = Quat::from_mat3(&Mat3::from_cols(x, z.cross(x), z));
+
+    let dist = 1. / (apart / f32::from(pairs)).tan();
+    #[allow(clippy::cast_precision_loss)]
+    let center = model.iter().sum::<Vec3>() / model.len() as f32;
+    let seen = rays.iter().sum::<Vec3>().normalize();
+    let pos = seen * dist - rot * center;
+
+    let axis = rot.to_scaled_axis();
+    Some(vec![axis.x, axis.y, axis.z, pos.x, pos.y, pos.z])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Vec2 = Vec2::new(640., 480.);
+
+    /// Corners of a board held `dist` squares away and tilted by `tilt`, as seen through
+    /// `sensor`.
+    fn seen(calib: &LensCalibrator, sensor: &SensorParams, tilt: Vec3, dist: f32) -> Vec<Vec2> {
+        let model = calib.model();
+        #[allow(clippy::cast_precision_loss)]
+        let center = model.iter().sum::<Vec3>() / model.len() as f32;
+        // facing the camera, with the board's rows along x and its columns down z
+        let rot =
+            Quat::from_scaled_axis(tilt) * Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+        model
+            .iter()
+            .map(|p| {
+                project(
+                    sensor,
+                    calib.lens,
+                    SIZE,
+                    rot * (*p - center) + Vec3::Y * dist,
+                )
+            })
+            .collect()
+    }
+
+    /// Boards at a spread of angles and distances, like the frames taken to calibrate with.
+    fn boards(calib: &LensCalibrator, sensor: &SensorParams) -> Vec<Vec<Vec2>> {
+        [
+            (Vec3::ZERO, 9.),
+            (Vec3::new(0.4, 0., 0.), 8.),
+            (Vec3::new(-0.3, 0.2, 0.), 10.),
+            (Vec3::new(0., 0., 0.5), 7.),
+            (Vec3::new(0.2, -0.3, -0.4), 12.),
+            (Vec3::new(-0.5, 0.1, 0.3), 6.),
+        ]
+        .into_iter()
+        .map(|(tilt, dist)| seen(calib, sensor, tilt, dist))
+        .collect()
+    }
+
+    #[test]
+    fn fits_known_lens() {
+        let calib = LensCalibrator::new(Chessboard::new(7, 5), LensKind::Rectilinear, Fov::D(80.));
+        let truth = SensorParams {
+            img_off: [6., -4.],
+            fov: Fov::D(70.).with_dims(LensKind::Rectilinear, SIZE.x, SIZE.y),
+            distortion: Distortion {
+                k1: -0.12,
+                k2: 0.03,
+                ..Distortion::NONE
+            },
+        };
+
+        let fit = calib.fit(SIZE, &boards(&calib, &truth)).unwrap();
+        assert_eq!(fit.boards, 6);
+        assert!(fit.rms < 0.05, "rms of {}", fit.rms);
+
+        let f = fit.sensor.fov.assume_focal_dist().unwrap();
+        let want = truth.fov.assume_focal_dist().unwrap();
+        assert!(
+            (f - want).abs() < want * 2e-3,
+            "focal distance {f} isn't {want}"
+        );
+        // the offset trades off against the tilt of the boards, so it's the loosest
+        let off = Vec2::from(fit.sensor.img_off).distance(truth.img_off.into());
+        assert!(off < 1., "image offset {:?}", fit.sensor.img_off);
+        let d = fit.sensor.distortion;
+        assert!((d.k1 - truth.distortion.k1).abs() < 5e-3, "{d:?}");
+        assert!((d.k2 - truth.distortion.k2).abs() < 5e-3, "{d:?}");
+    }
+
+    #[test]
+    fn rejects_too_few_boards_or_corners() {
+        let calib = LensCalibrator::new(Chessboard::new(7, 5), LensKind::Rectilinear, Fov::D(80.));
+        let sensor = SensorParams {
+            img_off: [0.; 2],
+            fov: Fov::D(80.).with_dims(LensKind::Rectilinear, SIZE.x, SIZE.y),
+            distortion: Distortion::NONE,
+        };
+        let mut boards = boards(&calib, &sensor);
+
+        boards.truncate(2);
+        assert!(calib.fit(SIZE, &boards).is_err());
+        boards[1].pop();
+        boards.push(boards[0].clone());
+        assert!(calib.fit(SIZE, &boards).is_err());
+    }
+}
@@ -0,0 +1,585 @@
+//! Refines camera extrinsics by matching features where their views of the ground overlap,
+//! and fits their lenses to frames of a chessboard in [`intrinsics`].
+
+use std::ops::Deref;
+
+use glam::{Vec2, Vec3};
+use rayon::prelude::*;
+
+pub mod intrinsics;
+pub mod targets;
+
+use crate::{
+    buf::FrameSize,
+    camera::{Camera, LensKind, ViewParams},
+    linalg, Error, Result,
+};
+
+/// Half the side of the patch a descriptor is sampled from, in pixels.
+const PATCH_RADIUS: usize = 8;
+/// Descriptor samples per side, spread evenly over the patch.
+const DESC_SIDE: usize = 9;
+const DESC_LEN: usize = DESC_SIDE * DESC_SIDE;
+
+/// Parameters per camera: position, pitch, azimuth and roll.
+const PARAMS: usize = 6;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Calibrator {
+    max_features: usize,
+    search_radius: f32,
+    min_score: f32,
+    iterations: usize,
+    prior_weight: f32,
+    max_range: f32,
+}
+
+/// Summary of a [`Calibrator::refine`] run, errors are RMS distances on the ground.
+#[derive(Clone, Copy, Debug)]
+pub struct Refinement {
+    pub matches: usize,
+    pub initial_err: f32,
+    pub final_err: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Match {
+    a: usize,
+    b: usize,
+    pa: Vec2,
+    pb: Vec2,
+}
+
+struct Feature {
+    pos: Vec2,
+    desc: [f32; DESC_LEN],
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Calibrator {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_features: 500,
+            search_radius: 50.,
+            min_score: 0.8,
+            iterations: 50,
+            prior_weight: 0.1,
+            max_range: 1000.,
+        }
+    }
+
+    /// Strongest corners kept per frame.
+    #[must_use]
+    pub const fn max_features(mut self, n: usize) -> Self {
+        self.max_features = n;
+        self
+    }
+
+    /// How far apart, in world units, two features may land on the ground using the initial
+    /// guess and still be considered a match.
+    #[must_use]
+    pub const fn search_radius(mut self, r: f32) -> Self {
+        self.search_radius = r;
+        self
+    }
+
+    /// Lowest normalized cross correlation between two features for them to match.
+    #[must_use]
+    pub const fn min_score(mut self, s: f32) -> Self {
+        self.min_score = s;
+        self
+    }
+
+    #[must_use]
+    pub const fn iterations(mut self, n: usize) -> Self {
+        self.iterations = n;
+        self
+    }
+
+    /// How strongly every parameter is pulled back towards its initial guess.
+    #[must_use]
+    pub const fn prior_weight(mut self, w: f32) -> Self {
+        self.prior_weight = w;
+        self
+    }
+
+    /// Ignore ground points further than this from the camera that sees them.
+    #[must_use]
+    pub const fn max_range(mut self, r: f32) -> Self {
+        self.max_range = r;
+        self
+    }
+
+    /// Refines the position and angles of every camera except the first, which anchors the
+    /// rig. `frames` holds one frame per camera, all taken at the same time.
+    ///
+    /// # Errors
+    /// `frames` doesn't have one frame per camera, or no features match between cameras
+    pub fn refine<T, F>(&self, cams: &mut [Camera<T>], frames: &[F]) -> Result<Refinement>
+    where
+        F: Deref<Target = [u8]> + FrameSize + Sync,
+    {
+        if frames.len() != cams.len() {
+            return Err(Error::Calibration("a frame is needed for every camera"));
+        }
+
+        let features = frames
+            .par_iter()
+            .map(|f| self.features(f))
+            .collect::<Vec<_>>();
+        let sizes = frames
+            .iter()
+            .map(|f| {
+                #[allow(clippy::cast_precision_loss)]
+                Vec2::new(f.width() as f32, f.height() as f32)
+            })
+            .collect::<Vec<_>>();
+
+        let views = cams.iter().map(|c| c.view).collect::<Vec<_>>();
+        let matches = self.match_features(&views, &sizes, &features);
+        if matches.is_empty() {
+            return Err(Error::Calibration("no features matched between cameras"));
+        }
+
+        let init = views.iter().skip(1).flat_map(to_params).collect::<Vec<_>>();
+        let residuals =
+            |x: &[f32]| self.residuals(&with_params(&views, x), &sizes, &matches, &init, x);
+
+        let initial_err = ground_rms(&residuals(&init), matches.len());
+        let x = levenberg_marquardt(init.clone(), self.iterations, residuals);
+        let final_err = ground_rms(&residuals(&x), matches.len());
+
+        for (c, v) in cams.iter_mut().zip(with_params(&views, &x)) {
+            c.view = v;
+        }
+
+        Ok(Refinement {
+            matches: matches.len(),
+            initial_err,
+            final_err,
+        })
+    }
+
+    fn features<F: Deref<Target = [u8]> + FrameSize>(&self, frame: &F) -> Vec<Feature> {
+        let (w, h, _) = frame.frame_size();
+        let gray = gray(frame);
+
+        corners(&gray, w, h, self.max_features)
+            .into_iter()
+            .filter_map(|(x, y)| {
+                #[allow(clippy::cast_precision_loss)]
+                let pos = Vec2::new(x as f32, y as f32);
+                Some(Feature {
+                    pos,
+                    desc: descriptor(&gray, w, x, y)?,
+                })
+            })
+            .collect()
+    }
+
+    fn match_features(
+        &self,
+        views: &[ViewParams],
+        sizes: &[Vec2],
+        features: &[Vec<Feature>],
+    ) -> Vec<Match> {
+        let grounds = features
+            .iter()
+            .zip(views.iter().zip(sizes))
+            .map(|(fs, (v, s))| {
+                fs.iter()
+                    .map(|f| ground_hit(v, *s, f.pos, self.max_range))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut out = Vec::new();
+        for a in 0..features.len() {
+            for b in a + 1..features.len() {
+                for (fa, ga) in features[a].iter().zip(&grounds[a]) {
+                    let Some(ga) = ga else { continue };
+
+                    let mut best = (self.min_score, None);
+                    for (fb, gb) in features[b].iter().zip(&grounds[b]) {
+                        if !gb.is_some_and(|gb| gb.distance(*ga) <= self.search_radius) {
+                            continue;
+                        }
+
+                        let score = ncc(&fa.desc, &fb.desc);
+                        if score > best.0 {
+                            best = (score, Some(fb.pos));
+                        }
+                    }
+
+                    if let (_, Some(pb)) = best {
+                        out.push(Match {
+                            a,
+                            b,
+                            pa: fa.pos,
+                            pb,
+                        });
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Ground distances between every match, followed by the weighted drift from `init`.
+    fn residuals(
+        &self,
+        views: &[ViewParams],
+        sizes: &[Vec2],
+        matches: &[Match],
+        init: &[f32],
+        x: &[f32],
+    ) -> Vec<f32> {
+        let mut out = Vec::with_capacity(matches.len() * 2 + x.len());
+        for m in matches {
+            let ga = ground_hit(&views[m.a], sizes[m.a], m.pa, self.max_range);
+            let gb = ground_hit(&views[m.b], sizes[m.b], m.pb, self.max_range);
+            let d = ga.zip(gb).map_or(Vec2::ZERO, |(ga, gb)| ga - gb);
+            out.extend([d.x, d.y]);
+        }
+
+        out.extend(x.iter().zip(init).map(|(x, i)| (x - i) * self.prior_weight));
+        out
+    }
+}
+
+/// Minimizes the squared sum of `f(x)`, with a forward difference Jacobian.
+fn levenberg_marquardt(
+    mut x: Vec<f32>,
+    iterations: usize,
+    f: impl Fn(&[f32]) -> Vec<f32>,
+) -> Vec<f32> {
+    const EPS: f32 = 1e-4;
+
+    let m = x.len();
+    let mut lambda = 1e-3;
+    let mut r = f(&x);
+    let mut cost = r.iter().map(|v| v * v).sum::<f32>();
+
+    for _ in 0..iterations {
+        let jac = (0..m)
+            .map(|k| {
+                let mut xk = x.clone();
+                xk[k] += EPS;
+                f(&xk)
+                    .iter()
+                    .zip(&r)
+                    .map(|(rk, r)| (rk - r) / EPS)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut jtj = vec![0f32; m * m];
+        let mut jtr = vec![0f32; m];
+        for i in 0..m {
+            for j in 0..m {
+                jtj[i * m + j] = jac[i].iter().zip(&jac[j]).map(|(a, b)| a * b).sum();
+            }
+            jtr[i] = -jac[i].iter().zip(&r).map(|(a, b)| a * b).sum::<f32>();
+        }
+
+        loop {
+            let mut a = jtj.clone();
+            for i in 0..m {
+                a[i * m + i] += lambda * jtj[i * m + i].max(1e-6);
+            }
+
+            let step = linalg::solve(&mut a, &mut jtr.clone(), m);
+            let next = step.map(|s| x.iter().zip(s).map(|(x, s)| x + s).collect::<Vec<_>>());
+            let next_r = next.as_deref().map(&f);
+            let next_cost = next_r
+                .as_ref()
+                .map_or(f32::INFINITY, |r| r.iter().map(|v| v * v).sum());
+
+            if next_cost < cost {
+                x = next.unwrap();
+                r = next_r.unwrap();
+                cost = next_cost;
+                lambda = (lambda / 10.).max(1e-7);
+                break;
+            }
+
+            lambda *= 10.;
+            if lambda > 1e7 {
+                return x;
+            }
+        }
+    }
+
+    x
+}
+
+fn ground_rms(residuals: &[f32], matches: usize) -> f32 {
+    #[allow(clippy::cast_precision_loss)]
+    let n = matches.max(1) as f32;
+    (residuals[..matches * 2].iter().map(|v| v * v).sum::<f32>() / n).sqrt()
+}
+
+fn to_params(v: &ViewParams) -> [f32; PARAMS] {
+    let [x, y, z] = v.pos;
+    [x, y, z, v.pitch, v.azimuth, v.roll]
+}
+
+/// `views` with every camera but the first replaced by the parameters in `x`.
+fn with_params(views: &[ViewParams], x: &[f32]) -> Vec<ViewParams> {
+    let mut out = views.to_vec();
+    for (v, p) in out.iter_mut().skip(1).zip(x.chunks_exact(PARAMS)) {
+        v.pos = [p[0], p[1], p[2]];
+        v.pitch = p[3];
+        v.azimuth = p[4];
+        v.roll = p[5];
+    }
+    out
+}
+
+/// World direction seen through pixel `px` of an image of `size`, the inverse of the
+/// projection in `proj/shaders/render.wgsl`.
+#[must_use]
+pub fn pixel_ray(view: &ViewParams, size: Vec2, px: Vec2) -> Option<Vec3> {
+    let diag = size.length();
+    let img = (2. * (px - Vec2::from(view.sensor.img_off)) - size) / diag * Vec2::new(1., -1.);
+    let f = view.focal_dist(size.x, size.y);
+    let img = Vec2::from(view.sensor.distortion.remove((img / f).into())) * f;
+
+    let r = img.length();
+    let ang = match view.lens {
+        LensKind::Rectilinear => (r / f).atan(),
+        LensKind::Equidistant => r / f,
+        LensKind::Equisolid => 2. * (r / (2. * f)).asin(),
+    };
+    if !ang.is_finite() {
+        return None;
+    }
+
+    let (s, c) = ang.sin_cos();
+    let dir = if r > 0. {
+        Vec3::new(s * img.x / r, c, s * img.y / r)
+    } else {
+        Vec3::Y
+    };
+
+    let rev_mat = glam::Mat3::from_euler(glam::EulerRot::ZXY, view.azimuth, view.pitch, view.roll);
+    Some(rev_mat.transpose() * dir)
+}
+
+/// Where pixel `px` lands on the ground plane, if it does within `max_range` of the camera.
+#[must_use]
+pub fn ground_hit(view: &ViewParams, size: Vec2, px: Vec2, max_range: f32) -> Option<Vec2> {
+    let d = pixel_ray(view, size, px)?;
+    if d.z >= -1e-3 {
+        return None;
+    }
+
+    let o = Vec3::from(view.pos);
+    let g = o - d * (o.z / d.z);
+    (g.truncate().distance(o.truncate()) <= max_range).then(|| g.truncate())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn gray<F: Deref<Target = [u8]> + FrameSize>(frame: &F) -> Vec<f32> {
+    let chans = frame.chans();
+    let c = chans.min(3);
+    frame
+        .chunks_exact(chans)
+        .map(|p| p[..c].iter().map(|&v| f32::from(v)).sum::<f32>() / c as f32)
+        .collect()
+}
+
+/// Strongest Harris corners, at most `max` of them, at least a patch away from the edges.
+fn corners(gray: &[f32], w: usize, h: usize, max: usize) -> Vec<(usize, usize)> {
+    const K: f32 = 0.04;
+    const WIN: usize = 2;
+    const NMS: usize = 3;
+
+    let margin = PATCH_RADIUS + WIN + 1;
+    if w <= margin * 2 || h <= margin * 2 {
+        return Vec::new();
+    }
+
+    let grad = |x: usize, y: usize| {
+        let i = y * w + x;
+        (
+            (gray[i + 1] - gray[i - 1]) / 2.,
+            (gray[i + w] - gray[i - w]) / 2.,
+        )
+    };
+
+    let mut resp = vec![0f32; w * h];
+    resp.par_chunks_exact_mut(w)
+        .enumerate()
+        .skip(margin)
+        .take(h - margin * 2)
+        .for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate().take(w - margin).skip(margin) {
+                let (mut xx, mut yy, mut xy) = (0., 0., 0.);
+                for wy in y - WIN..=y + WIN {
+                    for wx in x - WIN..=x + WIN {
+                        let (gx, gy) = grad(wx, wy);
+                        xx += gx * gx;
+                        yy += gy * gy;
+                        xy += gx * gy;
+                    }
+                }
+                *out = (xx * yy - xy * xy) - K * (xx + yy) * (xx + yy);
+            }
+        });
+
+    let max_resp = resp.iter().copied().fold(0., f32::max);
+    let mut found = (margin..h - margin)
+        .flat_map(|y| (margin..w - margin).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            let r = resp[y * w + x];
+            r > max_resp * 0.01
+                && (y.saturating_sub(NMS)..=(y + NMS).min(h - 1)).all(|ny| {
+                    (x.saturating_sub(NMS)..=(x + NMS).min(w - 1))
+                        .all(|nx| (nx, ny) == (x, y) || resp[ny * w + nx] < r)
+                })
+        })
+        .collect::<Vec<_>>();
+
+    found.sort_unstable_by(|a, b| resp[b.1 * w + b.0].total_cmp(&resp[a.1 * w + a.0]));
+    found.truncate(max);
+    found
+}
+
+/// Zero mean, unit length patch around `(x, y)`, or `None` for a flat patch.
+fn descriptor(gray: &[f32], w: usize, x: usize, y: usize) -> Option<[f32; DESC_LEN]> {
+    let step = PATCH_RADIUS * 2 / (DESC_SIDE - 1);
+
+    let mut desc = [0f32; DESC_LEN];
+    for (i, d) in desc.iter_mut().enumerate() {
+        let px = x + (i % DESC_SIDE) * step - PATCH_RADIUS;
+        let py = y + (i / DESC_SIDE) * step - PATCH_RADIUS;
+        *d = gray[py * w + px];
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let mean = desc.iter().sum::<f32>() / DESC_LEN as f32;
+    desc.iter_mut().for_each(|d| *d -= mean);
+
+    let norm = desc.iter().map(|d| d * d).sum::<f32>().sqrt();
+    if norm < 1e-3 {
+        return None;
+    }
+    desc.iter_mut().for_each(|d| *d /= norm);
+
+    Some(desc)
+}
+
+fn ncc(a: &[f32; DESC_LEN], b: &[f32; DESC_LEN]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Distortion, Fov, SensorParams};
+
+    const SIZE: Vec2 = Vec2::new(640., 480.);
+
+    fn view(pos: [f32; 3], pitch: f32, azimuth: f32, roll: f32) -> ViewParams {
+        ViewParams {
+            pos,
+            pitch,
+            azimuth,
+            roll,
+            sensor: SensorParams {
+                img_off: [3., -2.],
+                fov: Fov::D(90.).with_dims(LensKind::Rectilinear, SIZE.x, SIZE.y),
+                distortion: Distortion::NONE,
+            },
+            lens: LensKind::Rectilinear,
+        }
+    }
+
+    /// Pixel `view` sees the ground point `p` at, if it's in front of it and in frame.
+    fn pixel_of(view: &ViewParams, p: Vec2) -> Option<Vec2> {
+        let rev_mat =
+            glam::Mat3::from_euler(glam::EulerRot::ZXY, view.azimuth, view.pitch, view.roll);
+        let d = rev_mat * (p.extend(0.) - Vec3::from(view.pos));
+        if d.y <= 0. {
+            return None;
+        }
+
+        let img = Vec2::new(d.x, d.z) / d.y * view.focal_dist(SIZE.x, SIZE.y);
+        let px = (img * Vec2::new(1., -1.) * SIZE.length() + SIZE) / 2.
+            + Vec2::from(view.sensor.img_off);
+        (px.cmpge(Vec2::ZERO).all() && px.cmplt(SIZE).all()).then_some(px)
+    }
+
+    /// Points on the ground every 2 units, from 6 left of the origin to 6 right and 6 ahead.
+    #[allow(clippy::cast_precision_loss)]
+    fn ground() -> impl Iterator<Item = Vec2> {
+        (-3..=3).flat_map(|x| (-1..=3).map(move |y| Vec2::new(x as f32, y as f32) * 2.))
+    }
+
+    #[test]
+    fn ground_hit_inverts_projection() {
+        let v = view([-2., -12., 10.], 0.7, 0.1, -0.05);
+        let mut seen = 0;
+        for p in ground() {
+            let Some(px) = pixel_of(&v, p) else { continue };
+            let hit = ground_hit(&v, SIZE, px, 100.).unwrap();
+            assert!(hit.distance(p) < 1e-3, "{p} came back as {hit}");
+            seen += 1;
+        }
+        assert!(seen > 20, "only {seen} points are in frame");
+
+        // the top of the frame lands far off, past a shorter range
+        let top = Vec2::new(320., 0.);
+        assert!(ground_hit(&v, SIZE, top, 100.).is_some());
+        assert_eq!(ground_hit(&v, SIZE, top, 30.), None);
+
+        // and a level camera never sees the ground above the middle of the frame
+        let level = view([0., 0., 10.], 0., 0., 0.);
+        assert_eq!(ground_hit(&level, SIZE, Vec2::new(320., 100.), 1000.), None);
+    }
+
+    #[test]
+    fn refines_perturbed_camera() {
+        let truth = [
+            view([-6., -12., 10.], 0.7, 0., 0.),
+            view([6., -12., 10.], 0.75, 0.1, 0.05),
+        ];
+        let matches = ground()
+            .filter_map(|p| Some((pixel_of(&truth[0], p)?, pixel_of(&truth[1], p)?)))
+            .map(|(pa, pb)| Match { a: 0, b: 1, pa, pb })
+            .collect::<Vec<_>>();
+        assert!(matches.len() > 15, "only {} matches", matches.len());
+
+        let mut guess = truth;
+        guess[1].pos = [6.4, -12.3, 10.3];
+        guess[1].pitch += 0.03;
+        guess[1].azimuth -= 0.03;
+        guess[1].roll += 0.02;
+
+        // the same solve as `Calibrator::refine`, without finding the matches in frames
+        let calib = Calibrator::new().prior_weight(0.).iterations(100);
+        let sizes = [SIZE; 2];
+        let init = to_params(&guess[1]).to_vec();
+        let residuals =
+            |x: &[f32]| calib.residuals(&with_params(&guess, x), &sizes, &matches, &init, x);
+        let initial_err = ground_rms(&residuals(&init), matches.len());
+        let x = levenberg_marquardt(init.clone(), calib.iterations, residuals);
+        let final_err = ground_rms(&residuals(&x), matches.len());
+        assert!(initial_err > 0.1, "the guess is already {initial_err} off");
+        assert!(final_err < 1e-2, "still {final_err} off");
+
+        let refined = with_params(&guess, &x);
+        assert_eq!(refined[0], truth[0], "the first camera moved");
+        for (got, want) in to_params(&refined[1]).iter().zip(to_params(&truth[1])) {
+            assert!((got - want).abs() < 1e-2, "{:?}", refined[1]);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Calibration target detection in captured frames.
+
+use std::{collections::HashMap, ops::Deref};
+
+use glam::{IVec2, Mat2, Vec2};
+use rayon::prelude::*;
+
+use crate::buf::FrameSize;
+
+/// Most saddle points considered when assembling a board.
+const MAX_CANDIDATES: usize = 1000;
+/// Strongest saddle points tried as the first corner of a board.
+const MAX_SEEDS: usize = 64;
+/// Fraction of the expected spacing a corner may stray from where its neighbors predict.
+const GRID_TOLERANCE: f32 = 0.35;
+
+/// Chessboard with `cols` by `rows` inner corners, where four squares meet.
+#[derive(Clone, Copy, Debug)]
+pub struct Chessboard {
+    cols: usize,
+    rows: usize,
+    blur_radius: usize,
+    refine_radius: usize,
+}
+
+impl Chessboard {
+    #[must_use]
+    pub const fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            blur_radius: 2,
+            refine_radius: 5,
+        }
+    }
+
+    /// Inner corners to a row, and rows of them.
+    #[must_use]
+    pub const fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Box blur applied before looking for corners, raise it for noisy or large frames.
+    #[must_use]
+    pub const fn blur_radius(mut self, r: usize) -> Self {
+        self.blur_radius = r;
+        self
+    }
+
+    /// Half the side of the window used to find each corner to subpixel accuracy.
+    #[must_use]
+    pub const fn refine_radius(mut self, r: usize) -> Self {
+        self.refine_radius = r;
+        self
+    }
+
+    /// Finds every inner corner of the board, `cols` to a row and row by row. A board has no
+    /// marked origin, so the order may start from either end.
+    pub fn detect<F: Deref<Target = [u8]> + FrameSize>(&self, frame: &F) -> Option<Vec<Vec2>> {
+        let (w, h, _) = frame.frame_size();
+        let gray = super::gray(frame);
+        let smooth = box_blur(&gray, w, h, self.blur_radius);
+
+        let mut cands = Vec::<Vec2>::new();
+        for p in saddle_points(&smooth, w, h, self.refine_radius + 1) {
+            let p = refine_corner(&gray, w, h, p, self.refine_radius);
+            if is_x_junction(&smooth, w, p, self.refine_radius)
+                && cands.iter().all(|c| c.distance(p) > 1.)
+            {
+                cands.push(p);
+            }
+        }
+
+        let grid = (0..cands.len().min(MAX_SEEDS)).find_map(|seed| self.grow_grid(&cands, seed))?;
+        Some(grid.into_iter().map(|i| cands[i]).collect())
+    }
+
+    /// Grows a grid of corners out from `seed`, returning candidate indices row by row if it
+    /// ends up exactly the size of the board.
+    fn grow_grid(&self, cands: &[Vec2], seed: usize) -> Option<Vec<usize>> {
+        let p = cands[seed];
+        let mut near = (0..cands.len()).filter(|&i| i != seed).collect::<Vec<_>>();
+        near.sort_unstable_by(|&a, &b| p.distance(cands[a]).total_cmp(&p.distance(cands[b])));
+
+        let &first = near.first()?;
+        let u = cands[first] - p;
+        let &second = near.iter().skip(1).take(3).find(|&&i| {
+            let v = cands[i] - p;
+            u.normalize().dot(v.normalize()).abs() < 0.5
+        })?;
+        let v = cands[second] - p;
+
+        let mut grid = HashMap::from([(IVec2::ZERO, seed), (IVec2::X, first), (IVec2::Y, second)]);
+        let mut used = vec![false; cands.len()];
+        for i in [seed, first, second] {
+            used[i] = true;
+        }
+        let max_side = self.cols.max(self.rows);
+
+        loop {
+            let mut added = false;
+            let cells = grid.keys().copied().collect::<Vec<_>>();
+            for cell in cells {
+                for dir in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                    let next = cell + dir;
+                    if grid.contains_key(&next) {
+                        continue;
+                    }
+
+                    let here = cands[grid[&cell]];
+                    let step = grid.get(&(cell - dir)).map_or_else(
+                        || {
+                            if dir.x == 0 {
+                                v * dir.y as f32
+                            } else {
+                                u * dir.x as f32
+                            }
+                        },
+                        |&back| here - cands[back],
+                    );
+                    let pred = here + step;
+                    let tol = step.length() * GRID_TOLERANCE;
+
+                    let found = (0..cands.len())
+                        .filter(|&i| !used[i])
+                        .map(|i| (i, cands[i].distance(pred)))
+                        .filter(|&(_, d)| d <= tol)
+                        .min_by(|a, b| a.1.total_cmp(&b.1));
+
+                    if let Some((i, _)) = found {
+                        grid.insert(next, i);
+                        used[i] = true;
+                        added = true;
+                    }
+                }
+            }
+
+            let (min, max) = grid.keys().fold((IVec2::MAX, IVec2::MIN), |(lo, hi), c| {
+                (lo.min(*c), hi.max(*c))
+            });
+            let ext = (max - min + IVec2::ONE).as_uvec2();
+            if ext.max_element() as usize > max_side {
+                return None;
+            }
+
+            if !added {
+                let (cols, rows) = (ext.x as usize, ext.y as usize);
+                let transposed = match (cols, rows) {
+                    _ if grid.len() != cols * rows => return None,
+                    (c, r) if (c, r) == (self.cols, self.rows) => false,
+                    (c, r) if (r, c) == (self.cols, self.rows) => true,
+                    _ => return None,
+                };
+
+                let mut out = Vec::with_capacity(grid.len());
+                for r in 0..self.rows {
+                    for c in 0..self.cols {
+                        let (x, y) = if transposed { (r, c) } else { (c, r) };
+                        #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+                        out.push(grid[&(min + IVec2::new(x as i32, y as i32))]);
+                    }
+                }
+                return Some(out);
+            }
+        }
+    }
+}
+
+/// Strongest saddle points, where the image curves up along one axis and down along the other
+/// as it does where the squares of a board meet, at least `margin` pixels from the edges.
+#[allow(clippy::cast_precision_loss)]
+#[must_use]
+pub fn saddle_points(gray: &[f32], w: usize, h: usize, margin: usize) -> Vec<Vec2> {
+    const NMS: usize = 2;
+
+    let margin = margin.max(NMS + 1);
+    if w <= margin * 2 || h <= margin * 2 {
+        return Vec::new();
+    }
+
+    let mut resp = vec![0f32; w * h];
+    resp.par_chunks_exact_mut(w)
+        .enumerate()
+        .skip(margin)
+        .take(h - margin * 2)
+        .for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate().take(w - margin).skip(margin) {
+                let i = y * w + x;
+                let xx = gray[i + 1] - 2. * gray[i] + gray[i - 1];
+                let yy = gray[i + w] - 2. * gray[i] + gray[i - w];
+                let xy =
+                    (gray[i + w + 1] - gray[i + w - 1] - gray[i - w + 1] + gray[i - w - 1]) / 4.;
+                *out = (xy * xy - xx * yy).max(0.);
+            }
+        });
+
+    let max_resp = resp.iter().copied().fold(0., f32::max);
+    let mut found = (margin..h - margin)
+        .flat_map(|y| (margin..w - margin).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            let r = resp[y * w + x];
+            r > max_resp * 0.1
+                && (y - NMS..=y + NMS).all(|ny| {
+                    // ties go to the first pixel, so flat peaks still get one point
+                    (x - NMS..=x + NMS).all(|nx| {
+                        let n = resp[ny * w + nx];
+                        n < r || (n == r && (ny, nx) >= (y, x))
+                    })
+                })
+        })
+        .collect::<Vec<_>>();
+
+    found.sort_unstable_by(|a, b| resp[b.1 * w + b.0].total_cmp(&resp[a.1 * w + a.0]));
+    found.truncate(MAX_CANDIDATES);
+
+    found
+        .into_iter()
+        .map(|(x, y)| Vec2::new(x as f32, y as f32))
+        .collect()
+}
+
+/// Whether the ring of `radius` around `p` alternates between dark and light 4 times, as it
+/// does where the squares of a board meet but not at the outer corners of the board.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn is_x_junction(gray: &[f32], w: usize, p: Vec2, radius: usize) -> bool {
+    const SAMPLES: usize = 16;
+
+    let ring = (0..SAMPLES)
+        .map(|i| {
+            let ang = i as f32 / SAMPLES as f32 * std::f32::consts::TAU;
+            let at = p + Vec2::from_angle(ang) * radius as f32;
+            gray[at.y.round() as usize * w + at.x.round() as usize]
+        })
+        .collect::<Vec<_>>();
+
+    let mean = ring.iter().sum::<f32>() / SAMPLES as f32;
+    let changes = (0..SAMPLES)
+        .filter(|&i| (ring[i] > mean) != (ring[(i + 1) % SAMPLES] > mean))
+        .count();
+    changes == 4
+}
+
+/// Moves `p` to the point every gradient in the surrounding window points away from, which is
+/// the corner to subpixel accuracy. Returns `p` if that wanders out of the window.
+#[allow(
+    clippy::cast_possible_wrap,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+#[must_use]
+pub fn refine_corner(gray: &[f32], w: usize, h: usize, p: Vec2, radius: usize) -> Vec2 {
+    let r = radius as isize;
+    let max_shift = radius as f32;
+
+    let mut q = p;
+    for _ in 0..10 {
+        let (cx, cy) = (q.x.round() as isize, q.y.round() as isize);
+        if cx - r < 1 || cy - r < 1 || cx + r + 1 >= w as isize || cy + r + 1 >= h as isize {
+            return p;
+        }
+
+        let mut a = Mat2::ZERO;
+        let mut b = Vec2::ZERO;
+        for y in cy - r..=cy + r {
+            for x in cx - r..=cx + r {
+                let i = y as usize * w + x as usize;
+                let g = Vec2::new(
+                    (gray[i + 1] - gray[i - 1]) / 2.,
+                    (gray[i + w] - gray[i - w]) / 2.,
+                );
+                let gg = Mat2::from_cols(g * g.x, g * g.y);
+                a += gg;
+                let at = Vec2::new(x as f32, y as f32);
+                b += gg * at;
+            }
+        }
+
+        if a.determinant().abs() < f32::EPSILON {
+            return q;
+        }
+
+        let next = a.inverse() * b;
+        if next.distance(p) > max_shift {
+            return p;
+        }
+
+        let moved = next.distance(q);
+        q = next;
+        if moved < 0.01 {
+            break;
+        }
+    }
+
+    q
+}
+
+/// Separable box blur with a window of `2 * r + 1` pixels, clamped at the edges.
+#[allow(
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn box_blur(src: &[f32], w: usize, h: usize, r: usize) -> Vec<f32> {
+    if r == 0 {
+        return src.to_vec();
+    }
+
+    let norm = (2 * r + 1) as f32;
+
+    let mut rows = vec![0f32; w * h];
+    rows.par_chunks_exact_mut(w)
+        .zip(src.par_chunks_exact(w))
+        .for_each(|(out, row)| {
+            for (x, o) in out.iter_mut().enumerate() {
+                *o = (x as isize - r as isize..=(x + r) as isize)
+                    .map(|sx| row[sx.clamp(0, w as isize - 1) as usize])
+                    .sum::<f32>()
+                    / norm;
+            }
+        });
+
+    let mut out = vec![0f32; w * h];
+    out.par_chunks_exact_mut(w).enumerate().for_each(|(y, o)| {
+        for (x, o) in o.iter_mut().enumerate() {
+            *o = (y as isize - r as isize..=(y + r) as isize)
+                .map(|sy| rows[sy.clamp(0, h as isize - 1) as usize * w + x])
+                .sum::<f32>()
+                / norm;
+        }
+    });
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buf::FrameBufferView;
+
+    const SQUARE: f32 = 16.;
+
+    /// Gray frame of a board with `squares` of [`SQUARE`] pixels on a white margin, its first
+    /// square's corner at `origin` and turned by `angle` around it, sampled at pixel centers 4
+    /// times a side. Returns the frame and the inner corners of the board row by row.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn board(
+        (w, h): (usize, usize),
+        squares: (i32, i32),
+        origin: Vec2,
+        angle: f32,
+    ) -> (Vec<u8>, Vec<Vec2>) {
+        const SAMPLES: usize = 4;
+
+        let rot = Vec2::from_angle(angle);
+        let back = Vec2::from_angle(-angle);
+        let square = |p: Vec2| {
+            let local = back.rotate(p - origin) / SQUARE;
+            let cell = local.floor().as_ivec2();
+            let inside = cell.cmpge(IVec2::ZERO).all() && cell.cmplt(squares.into()).all();
+            inside && (cell.x + cell.y) % 2 == 0
+        };
+
+        let mut frame = vec![0u8; w * h];
+        for (i, px) in frame.iter_mut().enumerate() {
+            let at = Vec2::new((i % w) as f32, (i / w) as f32);
+            let dark = (0..SAMPLES * SAMPLES)
+                .filter(|s| {
+                    let off = Vec2::new((s % SAMPLES) as f32, (s / SAMPLES) as f32);
+                    square(at + (off + 0.5) / SAMPLES as f32 - 0.5)
+                })
+                .count();
+            *px = (255 - dark * 255 / (SAMPLES * SAMPLES)) as u8;
+        }
+
+        let corners = (1..squares.1)
+            .flat_map(|r| (1..squares.0).map(move |c| Vec2::new(c as f32, r as f32)))
+            .map(|c| origin + rot.rotate(c * SQUARE))
+            .collect();
+        (frame, corners)
+    }
+
+    /// Checks `found` holds every corner of `corners` once, in rows of `cols`.
+    fn assert_corners(found: &[Vec2], corners: &[Vec2], cols: usize) {
+        assert_eq!(found.len(), corners.len());
+        for c in corners {
+            let near = found.iter().filter(|f| f.distance(*c) < 0.3).count();
+            assert_eq!(near, 1, "{c} found {near} times in {found:?}");
+        }
+        for row in found.chunks_exact(cols) {
+            for pair in row.windows(2) {
+                assert!((pair[0].distance(pair[1]) - SQUARE).abs() < 0.5, "{row:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn detects_square_board() {
+        let (frame, corners) = board((200, 160), (8, 6), Vec2::new(30.5, 25.5), 0.);
+        let found = Chessboard::new(7, 5)
+            .detect(&FrameBufferView::new((200, 160, 1), &frame))
+            .expect("board isn't found");
+        assert_corners(&found, &corners, 7);
+    }
+
+    #[test]
+    fn detects_turned_board() {
+        let (frame, corners) = board((240, 200), (6, 5), Vec2::new(80.3, 20.8), 0.4);
+        let found = Chessboard::new(5, 4)
+            .detect(&FrameBufferView::new((240, 200, 1), &frame))
+            .expect("board isn't found");
+        assert_corners(&found, &corners, 5);
+    }
+
+    #[test]
+    fn rejects_board_of_another_size() {
+        let (frame, _) = board((200, 160), (8, 6), Vec2::new(30.5, 25.5), 0.);
+        let frame = FrameBufferView::new((200, 160, 1), &frame);
+        assert!(Chessboard::new(6, 5).detect(&frame).is_none());
+        assert!(Chessboard::new(5, 7).detect(&frame).is_some());
+    }
+}
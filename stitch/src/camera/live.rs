@@ -1,9 +1,14 @@
-use std::path::PathBuf;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use nokhwa::{
     pixel_format::RgbAFormat,
     utils::{
-        CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType, Resolution,
+        ApiBackend, CameraFormat, CameraIndex, ControlValueSetter, FrameFormat, KnownCameraControl,
+        RequestedFormat, RequestedFormatType, Resolution,
     },
     FormatDecoder,
 };
@@ -11,26 +16,246 @@ use nokhwa::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    buf::PixelFormat,
+    convert,
+    fault::Faults,
     loader::{Loader, OwnedWriteBuffer},
-    Error, Result,
+    proj::Preprocess,
+    transform::Transform,
+    DimErrorKind, Error, Result,
 };
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
     pub live_index: u32,
+    /// Device opened instead of camera `live_index`, like a link under */dev/v4l/by-id* that
+    /// stays the same when cameras are renumbered. `live_index` then only names the camera.
+    #[serde(default)]
+    pub device: Option<PathBuf>,
     pub mask_path: Option<PathBuf>,
     pub resolution: Option<[u32; 2]>,
     pub frame_rate: Option<u32>,
+    pub format: Option<LiveFormat>,
+    #[serde(default)]
+    pub controls: Controls,
+    #[serde(default)]
+    pub transform: Transform,
+    #[serde(default)]
+    pub preprocess: Preprocess,
+    /// Faults injected into the camera's frames once they're transformed, for testing
+    #[serde(default)]
+    pub faults: Faults,
+}
+
+/// Imaging controls set when the camera opens, in the units its driver uses. The ones left
+/// unset keep whatever the camera has, and a camera adjusting its exposure or white balance
+/// on its own may ignore those.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Controls {
+    pub exposure: Option<i64>,
+    pub gain: Option<i64>,
+    pub white_balance: Option<i64>,
+    pub saturation: Option<i64>,
+    pub brightness: Option<i64>,
+    pub contrast: Option<i64>,
+}
+
+impl Controls {
+    pub const NONE: Self = Self {
+        exposure: None,
+        gain: None,
+        white_balance: None,
+        saturation: None,
+        brightness: None,
+        contrast: None,
+    };
+
+    /// These controls, with the ones set in `other` changed to its values.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            exposure: other.exposure.or(self.exposure),
+            gain: other.gain.or(self.gain),
+            white_balance: other.white_balance.or(self.white_balance),
+            saturation: other.saturation.or(self.saturation),
+            brightness: other.brightness.or(self.brightness),
+            contrast: other.contrast.or(self.contrast),
+        }
+    }
+
+    /// Every control that is set, with its value.
+    fn set(&self) -> impl Iterator<Item = (KnownCameraControl, i64)> {
+        [
+            (KnownCameraControl::Exposure, self.exposure),
+            (KnownCameraControl::Gain, self.gain),
+            (KnownCameraControl::WhiteBalance, self.white_balance),
+            (KnownCameraControl::Saturation, self.saturation),
+            (KnownCameraControl::Brightness, self.brightness),
+            (KnownCameraControl::Contrast, self.contrast),
+        ]
+        .into_iter()
+        .filter_map(|(c, v)| Some((c, v?)))
+    }
+}
+
+/// Pixel format requested from the camera, frames are always converted to RGBA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveFormat {
+    Mjpeg,
+    Yuyv,
+    Nv12,
+}
+
+impl TryFrom<FrameFormat> for LiveFormat {
+    type Error = FrameFormat;
+
+    fn try_from(f: FrameFormat) -> std::result::Result<Self, FrameFormat> {
+        match f {
+            FrameFormat::MJPEG => Ok(Self::Mjpeg),
+            FrameFormat::YUYV => Ok(Self::Yuyv),
+            FrameFormat::NV12 => Ok(Self::Nv12),
+            f => Err(f),
+        }
+    }
+}
+
+impl From<LiveFormat> for FrameFormat {
+    fn from(f: LiveFormat) -> Self {
+        match f {
+            LiveFormat::Mjpeg => Self::MJPEG,
+            LiveFormat::Yuyv => Self::YUYV,
+            LiveFormat::Nv12 => Self::NV12,
+        }
+    }
+}
+
+/// Changes the [`Controls`] of an open camera, each change taking effect before its next frame.
+#[derive(Clone, Debug)]
+pub struct ControlHandle(Arc<Mutex<ControlState>>);
+
+#[derive(Debug)]
+struct ControlState {
+    current: Controls,
+    changed: Option<Controls>,
+}
+
+impl ControlHandle {
+    fn new(current: Controls) -> Self {
+        Self(Arc::new(Mutex::new(ControlState {
+            current,
+            changed: None,
+        })))
+    }
+
+    /// Controls the camera was set to, including changes waiting for its next frame.
+    #[must_use]
+    pub fn get(&self) -> Controls {
+        let state = self.0.lock().unwrap();
+        state.changed.unwrap_or(state.current)
+    }
+
+    /// Changes the controls set in `controls`, keeping the rest as they are.
+    pub fn set(&self, controls: Controls) {
+        let mut state = self.0.lock().unwrap();
+        state.changed = Some(state.changed.unwrap_or(state.current).merge(controls));
+    }
+
+    /// Controls changed since the last call.
+    fn take_changed(&self) -> Option<Controls> {
+        let mut state = self.0.lock().unwrap();
+        let changed = state.changed.take()?;
+        state.current = changed;
+        Some(changed)
+    }
 }
 
 impl Config {
+    /// Camera `live_index` at its highest resolution, without a mask.
+    pub const fn new(live_index: u32) -> Self {
+        Self {
+            live_index,
+            device: None,
+            mask_path: None,
+            resolution: None,
+            frame_rate: None,
+            format: None,
+            controls: Controls::NONE,
+            transform: Transform::NONE,
+            preprocess: Preprocess::NONE,
+            faults: Faults::NONE,
+        }
+    }
+
+    /// Size of the frames projected from the camera, its resolution once transformed and
+    /// turned by its preprocessing, if the resolution is set.
+    #[must_use]
+    pub fn frame_resolution(&self) -> Option<[u32; 2]> {
+        self.resolution
+            .map(|res| self.preprocess.rotate.turn(self.transform.out_size(res)))
+    }
+
+    #[must_use]
+    pub const fn with_resolution(mut self, w: u32, h: u32) -> Self {
+        self.resolution = Some([w, h]);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_frame_rate(mut self, fps: u32) -> Self {
+        self.frame_rate = Some(fps);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_format(mut self, format: LiveFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_controls(mut self, controls: Controls) -> Self {
+        self.controls = controls;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_faults(mut self, faults: Faults) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    #[must_use]
+    pub fn with_preprocess(mut self, preprocess: Preprocess) -> Self {
+        self.preprocess = preprocess;
+        self
+    }
+
+    #[must_use]
+    pub fn with_device(mut self, p: impl Into<PathBuf>) -> Self {
+        self.device = Some(p.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_mask(mut self, p: impl Into<PathBuf>) -> Self {
+        self.mask_path = Some(p.into());
+        self
+    }
+
     #[must_use]
     #[inline]
     fn camera_format(&self) -> RequestedFormatType {
         match (self.resolution, self.frame_rate) {
             (Some([w, h]), Some(fr)) => RequestedFormatType::Closest(CameraFormat::new(
                 Resolution::new(w, h),
-                FrameFormat::MJPEG,
+                self.format.map_or(FrameFormat::MJPEG, Into::into),
                 fr,
             )),
             (Some([w, h]), None) => RequestedFormatType::HighestResolution(Resolution::new(w, h)),
@@ -38,37 +263,230 @@ impl Config {
             (None, None) => RequestedFormatType::AbsoluteHighestResolution,
         }
     }
+
+    #[must_use]
+    #[inline]
+    fn requested_format<F: FormatDecoder>(&self) -> RequestedFormat<'static> {
+        match self.format {
+            Some(LiveFormat::Mjpeg) => {
+                RequestedFormat::with_formats(self.camera_format(), &[FrameFormat::MJPEG])
+            }
+            Some(LiveFormat::Yuyv) => {
+                RequestedFormat::with_formats(self.camera_format(), &[FrameFormat::YUYV])
+            }
+            Some(LiveFormat::Nv12) => {
+                RequestedFormat::with_formats(self.camera_format(), &[FrameFormat::NV12])
+            }
+            None => RequestedFormat::new::<F>(self.camera_format()),
+        }
+    }
 }
 
-impl<B: OwnedWriteBuffer + 'static> TryFrom<Config> for Loader<B> {
-    type Error = Error;
+fn decode_frame<F: FormatDecoder>(
+    ff: FrameFormat,
+    res: Resolution,
+    data: &[u8],
+    out: &mut [u8],
+) -> Result<()> {
+    let (w, h) = (res.width() as usize, res.height() as usize);
+    match ff {
+        FrameFormat::YUYV => convert::to_rgba(PixelFormat::Yuyv, data, out, w, h),
+        FrameFormat::NV12 => convert::to_rgba(PixelFormat::Nv12, data, out, w, h),
+        FrameFormat::GRAY => convert::to_rgba(PixelFormat::Gray8, data, out, w, h),
+        FrameFormat::RAWRGB => convert::to_rgba(PixelFormat::Rgb8, data, out, w, h),
+        _ => F::write_output_buffer(ff, res, data, out).map_err(Error::from),
+    }
+}
 
-    fn try_from(spec: Config) -> Result<Self> {
+fn with_format<B: OwnedWriteBuffer + 'static>(
+    loader: Loader<B>,
+    format: Option<PixelFormat>,
+) -> Loader<B> {
+    match format {
+        Some(format) => loader.with_pixel_format(format),
+        None => loader,
+    }
+}
+
+/// Copies a frame as the camera delivered it to the start of `out`, which can be longer.
+fn copy_frame(data: &[u8], out: &mut [u8]) -> Result<()> {
+    let out_len = out.len();
+    out.get_mut(..data.len())
+        .ok_or_else(|| DimErrorKind::Bytes.err(out_len, data.len()))?
+        .copy_from_slice(data);
+    Ok(())
+}
+
+impl Config {
+    /// Opens the camera, along with a handle to change its controls while it's open.
+    ///
+    /// # Errors
+    /// the camera can't be opened
+    pub fn open<B: OwnedWriteBuffer + 'static>(self) -> Result<(Loader<B>, ControlHandle)> {
         type Format = RgbAFormat;
         const CHANS: u32 = 4;
 
-        let live_index = spec.live_index;
-        let mut raw = nokhwa::Camera::new(
-            CameraIndex::Index(live_index),
-            RequestedFormat::new::<Format>(spec.camera_format()),
-        )?;
+        let live_index = self.live_index;
+        let index = match &self.device {
+            Some(dev) => device_index(dev)?,
+            None => live_index,
+        };
+        let init_err = |source| Error::AdapterInit {
+            camera: live_index,
+            source,
+        };
+        let mut raw =
+            nokhwa::Camera::new(CameraIndex::Index(index), self.requested_format::<Format>())
+                .map_err(init_err)?;
 
-        raw.open_stream()?;
+        set_controls(&mut raw, live_index, &self.controls);
+        let controls = ControlHandle::new(self.controls);
+
+        raw.open_stream().map_err(init_err)?;
         let res = raw.resolution();
         let ff = raw.frame_format();
+        // left for the projector to decode
+        let undecoded = match ff {
+            FrameFormat::YUYV if self.preprocess.decode => Some(PixelFormat::Yuyv),
+            FrameFormat::NV12 if self.preprocess.decode => Some(PixelFormat::Nv12),
+            _ => None,
+        };
+
+        let handle = controls.clone();
+        let capture = move |buf: &mut [u8]| {
+            if let Some(changed) = handle.take_changed() {
+                set_controls(&mut raw, live_index, &changed);
+            }
+            raw.frame_raw()
+                .map_err(Error::from)
+                .and_then(|raw_frame| match undecoded {
+                    Some(_) => copy_frame(&raw_frame, buf),
+                    None => decode_frame::<Format>(ff, res, &raw_frame, buf),
+                })
+                .inspect_err(|err| {
+                    tracing::warn!("failed to read from camera {}: {err}", live_index);
+                })
+        };
+
+        let (w, h) = (res.width(), res.height());
+        let loader = if self.transform.is_none() && self.faults.is_none() {
+            with_format(Loader::new_fallible(w, h, CHANS, capture), undecoded)
+        } else if self.faults.is_none() {
+            let src = Loader::<Box<[u8]>>::new_fallible(w, h, CHANS, capture);
+            self.transform.apply(with_format(src, undecoded))?
+        } else {
+            let mut src = with_format(Loader::new_fallible(w, h, CHANS, capture), undecoded);
+            if !self.transform.is_none() {
+                src = self.transform.apply(src)?;
+            }
+            self.faults.apply(src)
+        };
+        Ok((loader, controls))
+    }
+}
 
-        Ok(Self::new_blocking(
-            res.width(),
-            res.height(),
-            CHANS as _,
-            move |buf| {
-                _ = raw
-                    .frame_raw()
-                    .and_then(|raw_frame| Format::write_output_buffer(ff, res, &raw_frame, buf))
-                    .inspect_err(|err| {
-                        tracing::warn!("failed to read from camera {}: {err}", live_index);
-                    });
-            },
-        ))
+/// Index of the `/dev/videoN` that `dev` is or links to.
+fn device_index(dev: &Path) -> Result<u32> {
+    let path = std::fs::canonicalize(dev).map_err(Error::io_ctx(format!("resolving {dev:?}")))?;
+    path.file_name()
+        .and_then(|n| n.to_str()?.strip_prefix("video")?.parse().ok())
+        .ok_or_else(|| {
+            let err = io::Error::new(io::ErrorKind::InvalidInput, "not a video device");
+            Error::IO(err, format!("opening {path:?}"))
+        })
+}
+
+/// Links under */dev/v4l* that lead to camera `index`, which keep leading to it when cameras
+/// are renumbered.
+#[must_use]
+pub fn stable_paths(index: u32) -> Vec<PathBuf> {
+    ["/dev/v4l/by-id", "/dev/v4l/by-path"]
+        .into_iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|p| device_index(p).is_ok_and(|i| i == index))
+        .collect()
+}
+
+/// Camera found by [`probe`].
+#[derive(Clone, Debug)]
+pub struct Probed {
+    pub index: u32,
+    pub name: String,
+    /// See [`stable_paths`].
+    pub paths: Vec<PathBuf>,
+    /// Every mode the camera can be opened in, largest first.
+    pub modes: Vec<Mode>,
+}
+
+/// Resolution, frame rate and format a camera captures in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mode {
+    pub resolution: [u32; 2],
+    pub frame_rate: u32,
+    pub format: LiveFormat,
+}
+
+/// Finds every camera `backend` can open, along with the modes it captures in. Cameras that
+/// fail to open are left out with a warning.
+///
+/// # Errors
+/// the cameras can't be listed
+pub fn probe(backend: ApiBackend) -> Result<Vec<Probed>> {
+    let found = nokhwa::query(backend)?
+        .into_iter()
+        .filter_map(|info| {
+            let index = info.index().as_index().ok()?;
+            let modes = probe_modes(info.index())
+                .inspect_err(|err| tracing::warn!("failed to probe camera {index}: {err}"))
+                .ok()?;
+            Some(Probed {
+                index,
+                name: info.human_name(),
+                paths: stable_paths(index),
+                modes,
+            })
+        })
+        .collect();
+    Ok(found)
+}
+
+fn probe_modes(index: &CameraIndex) -> Result<Vec<Mode>> {
+    let req = RequestedFormat::new::<RgbAFormat>(RequestedFormatType::None);
+    let mut modes = nokhwa::Camera::new(index.clone(), req)?
+        .compatible_camera_formats()?
+        .into_iter()
+        .filter_map(|f| {
+            let res = f.resolution();
+            Some(Mode {
+                resolution: [res.width(), res.height()],
+                frame_rate: f.frame_rate(),
+                format: f.format().try_into().ok()?,
+            })
+        })
+        .collect::<Vec<_>>();
+    modes.sort_by_key(|m| {
+        let [w, h] = m.resolution;
+        std::cmp::Reverse((u64::from(w) * u64::from(h), m.frame_rate))
+    });
+    modes.dedup();
+    Ok(modes)
+}
+
+/// Sets every control set in `controls`, warning about the ones the camera doesn't take.
+fn set_controls(raw: &mut nokhwa::Camera, live_index: u32, controls: &Controls) {
+    for (control, value) in controls.set() {
+        if let Err(err) = raw.set_camera_control(control, ControlValueSetter::Integer(value)) {
+            tracing::warn!("failed to set {control:?} of camera {live_index}: {err}");
+        }
+    }
+}
+
+impl<B: OwnedWriteBuffer + 'static> TryFrom<Config> for Loader<B> {
+    type Error = Error;
+
+    fn try_from(spec: Config) -> Result<Self> {
+        spec.open().map(|(loader, _)| loader)
     }
 }
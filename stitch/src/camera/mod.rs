@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "live")]
 pub mod live;
+#[cfg(feature = "tokio")]
+pub mod replay;
 
 use crate::{
     buf::FrameSize,
@@ -58,7 +60,7 @@ impl<T: FrameSize> Camera<T> {
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Config<K> {
     #[serde(flatten)]
     pub view: ViewParams,
@@ -67,6 +69,11 @@ pub struct Config<K> {
 }
 
 impl<K> Config<K> {
+    /// See [`ConfigBuilder::new`].
+    pub const fn builder(meta: K, fov: Fov) -> ConfigBuilder<K> {
+        ConfigBuilder::new(meta, fov)
+    }
+
     #[must_use]
     pub fn with_dims(mut self, w: f32, h: f32) -> Self {
         self.view = self.view.with_dims(w, h);
@@ -78,6 +85,78 @@ impl<K> Config<K> {
     }
 }
 
+/// Builds a camera [`Config`] in code, taking angles in degrees like the config file does.
+#[derive(Clone, Copy, Debug)]
+pub struct ConfigBuilder<K>(Config<K>);
+
+impl<K> ConfigBuilder<K> {
+    /// Camera at the origin with no pitch, azimuth or roll, a centered rectilinear lens and
+    /// field of view `fov`.
+    pub const fn new(meta: K, fov: Fov) -> Self {
+        Self(Config {
+            view: ViewParams {
+                pos: [0.; 3],
+                pitch: 0.,
+                azimuth: 0.,
+                roll: 0.,
+                sensor: SensorParams {
+                    img_off: [0.; 2],
+                    fov,
+                    distortion: Distortion::NONE,
+                },
+                lens: LensKind::Rectilinear,
+            },
+            meta,
+        })
+    }
+
+    pub const fn pos(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.0.view.pos = [x, y, z];
+        self
+    }
+
+    pub const fn pitch(mut self, deg: f32) -> Self {
+        self.0.view.pitch = deg.to_radians();
+        self
+    }
+
+    pub const fn azimuth(mut self, deg: f32) -> Self {
+        self.0.view.azimuth = deg.to_radians();
+        self
+    }
+
+    pub const fn roll(mut self, deg: f32) -> Self {
+        self.0.view.roll = deg.to_radians();
+        self
+    }
+
+    /// Offset of the image center from the middle of the sensor.
+    pub const fn img_off(mut self, x: f32, y: f32) -> Self {
+        self.0.view.sensor.img_off = [x, y];
+        self
+    }
+
+    pub const fn lens(mut self, lens: LensKind) -> Self {
+        self.0.view.lens = lens;
+        self
+    }
+
+    pub const fn distortion(mut self, distortion: Distortion) -> Self {
+        self.0.view.sensor.distortion = distortion;
+        self
+    }
+
+    pub fn build(self) -> Config<K> {
+        self.0
+    }
+}
+
+impl<K> From<ConfigBuilder<K>> for Config<K> {
+    fn from(b: ConfigBuilder<K>) -> Self {
+        b.build()
+    }
+}
+
 #[cfg(feature = "live")]
 impl<T> Config<T> {
     /// # Errors
@@ -95,7 +174,7 @@ impl<T> Config<T> {
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ViewParams {
     pub pos: [f32; 3],
     #[serde(with = "conv_deg_rad")]
@@ -148,14 +227,88 @@ impl ViewParams {
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SensorParams {
     #[serde(default)]
     pub img_off: [f32; 2],
     pub fov: Fov,
+    #[serde(default, skip_serializing_if = "Distortion::is_none")]
+    pub distortion: Distortion,
+}
+
+/// How far a real lens strays from its ideal [`LensKind`], as radial terms `k1`..`k4` of
+/// `r^2`..`r^8` and tangential terms `p1` and `p2`. They apply to where the ideal lens puts
+/// a point, in focal distances from the optical center, so with a rectilinear lens `k1`..`k3`,
+/// `p1` and `p2` are OpenCV's pinhole coefficients, and with an equidistant lens `k1`..`k4` are
+/// its fisheye model's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Distortion {
+    pub k1: f32,
+    pub k2: f32,
+    pub k3: f32,
+    pub k4: f32,
+    pub p1: f32,
+    pub p2: f32,
+}
+
+impl Distortion {
+    pub const NONE: Self = Self {
+        k1: 0.,
+        k2: 0.,
+        k3: 0.,
+        k4: 0.,
+        p1: 0.,
+        p2: 0.,
+    };
+
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Where the lens moves `[x, y]`, a point the ideal lens puts that many focal distances
+    /// right of and above the optical center.
+    #[must_use]
+    #[inline]
+    pub fn apply(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let r2 = x.mul_add(x, y * y);
+        let radial = 1. + r2 * (self.k1 + r2 * (self.k2 + r2 * (self.k3 + r2 * self.k4)));
+        let [tx, ty] = self.tangential([x, y], r2);
+        [x.mul_add(radial, tx), y.mul_add(radial, ty)]
+    }
+
+    /// Inverse of [`Self::apply`], found by fixed point iteration like OpenCV's
+    /// `undistortPoints`.
+    #[must_use]
+    #[inline]
+    pub fn remove(&self, [dx, dy]: [f32; 2]) -> [f32; 2] {
+        const ITERATIONS: usize = 10;
+
+        if self.is_none() {
+            return [dx, dy];
+        }
+        let (mut x, mut y) = (dx, dy);
+        for _ in 0..ITERATIONS {
+            let r2 = x.mul_add(x, y * y);
+            let radial = 1. + r2 * (self.k1 + r2 * (self.k2 + r2 * (self.k3 + r2 * self.k4)));
+            let [tx, ty] = self.tangential([x, y], r2);
+            (x, y) = ((dx - tx) / radial, (dy - ty) / radial);
+        }
+        [x, y]
+    }
+
+    /// OpenCV's tangential terms, which take y as pointing down.
+    fn tangential(&self, [x, y]: [f32; 2], r2: f32) -> [f32; 2] {
+        let xy = x * y;
+        [
+            (-2. * self.p1).mul_add(xy, self.p2 * 2f32.mul_add(x * x, r2)),
+            (2. * self.p2).mul_add(xy, -self.p1 * 2f32.mul_add(y * y, r2)),
+        ]
+    }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Fov {
     W(f32),
     H(f32),
@@ -194,7 +347,7 @@ impl Fov {
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
 #[repr(u8)]
 pub enum LensKind {
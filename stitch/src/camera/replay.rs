@@ -0,0 +1,283 @@
+//! Camera frames recorded as they were loaded, and played back in place of the camera.
+//!
+//! Every camera is recorded to two files named after it: `cam<N>.rgba` with the frames back to
+//! back, and `cam<N>.idx` with the width, height and channels of the frames as `u32`s followed
+//! by the `u64` microseconds each frame was loaded at, all little endian.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    loader::{Loader, OwnedWriteBuffer},
+    Error, Result,
+};
+
+/// Bytes of the frame size at the start of an index.
+const HEADER_BYTES: usize = 12;
+
+/// Recording of camera `camera` in `dir`, played back at the pace it was recorded and from the
+/// start again once it ends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub dir: PathBuf,
+    pub camera: u32,
+}
+
+impl Config {
+    pub fn new(dir: impl Into<PathBuf>, camera: u32) -> Self {
+        Self {
+            dir: dir.into(),
+            camera,
+        }
+    }
+
+    /// Loader of the recording that plays back the latest frame recorded by the time `clock`
+    /// is set to, instead of keeping the pace it was recorded at. Returns it with the time the
+    /// last frame was recorded at, from when the recording started.
+    ///
+    /// # Errors
+    /// the recording can't be opened
+    pub fn load_stepped<B: OwnedWriteBuffer + 'static>(
+        self,
+        clock: ReplayClock,
+    ) -> Result<(Loader<B>, Duration)> {
+        let (mut reader, (w, h, chans)) = StreamReader::open(&self)?;
+        let end = reader.end();
+        let loader = Loader::new_blocking(w, h, chans, move |buf| {
+            if let Err(err) = reader.load_at(clock.get(), buf) {
+                tracing::warn!("failed to replay camera {}: {err}", self.camera);
+            }
+        });
+        Ok((loader, end))
+    }
+
+    fn frames_path(&self) -> PathBuf {
+        self.dir.join(format!("cam{}.rgba", self.camera))
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join(format!("cam{}.idx", self.camera))
+    }
+}
+
+/// Time from the start of a recording that the loaders from [`Config::load_stepped`] play
+/// back, shared by every clone.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayClock(Arc<AtomicU64>);
+
+impl ReplayClock {
+    /// Moves every loader on to `at`, from their next frame on.
+    pub fn set(&self, at: Duration) {
+        let micros = u64::try_from(at.as_micros()).unwrap_or(u64::MAX);
+        self.0.store(micros, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> Duration {
+        Duration::from_micros(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Records the frames of one camera, see [`Config`].
+pub struct StreamWriter {
+    frames: BufWriter<File>,
+    index: BufWriter<File>,
+    started: Instant,
+}
+
+impl StreamWriter {
+    /// Starts the recording of `cfg` with frames of `w` by `h` with `chans` channels, timing
+    /// them from `started`.
+    ///
+    /// # Errors
+    /// the files can't be created
+    pub fn create(
+        cfg: &Config,
+        (w, h, chans): (usize, usize, usize),
+        started: Instant,
+    ) -> Result<Self> {
+        let create = |p: PathBuf| {
+            File::create(&p)
+                .map(BufWriter::new)
+                .map_err(Error::io_ctx(format!("creating {p:?}")))
+        };
+        let frames = create(cfg.frames_path())?;
+        let mut index = create(cfg.index_path())?;
+
+        for n in [w, h, chans] {
+            index
+                .write_all(&u32::try_from(n)?.to_le_bytes())
+                .map_err(Error::io_ctx("writing replay index".to_string()))?;
+        }
+        Ok(Self {
+            frames,
+            index,
+            started,
+        })
+    }
+
+    /// Adds `frame`, loaded at `at`.
+    ///
+    /// # Errors
+    /// the frame can't be written
+    pub fn push(&mut self, frame: &[u8], at: Instant) -> Result<()> {
+        let micros = u64::try_from(at.saturating_duration_since(self.started).as_micros())
+            .unwrap_or(u64::MAX);
+        self.frames
+            .write_all(frame)
+            .and_then(|()| self.index.write_all(&micros.to_le_bytes()))
+            .map_err(Error::io_ctx("writing replay frame".to_string()))
+    }
+
+    /// Flushes both files.
+    ///
+    /// # Errors
+    /// the files can't be written
+    pub fn finish(mut self) -> Result<()> {
+        self.frames
+            .flush()
+            .and_then(|()| self.index.flush())
+            .map_err(Error::io_ctx("finishing replay".to_string()))
+    }
+}
+
+/// Plays back the frames of a recording, see [`Config`].
+struct StreamReader {
+    frames: File,
+    frame_bytes: u64,
+    /// Time of every frame from the first
+    times: Vec<Duration>,
+    /// Time of the first frame from when the recording started
+    first: Duration,
+    /// When the first frame was played
+    started: Instant,
+    /// Frame last loaded
+    loaded: Option<usize>,
+}
+
+impl StreamReader {
+    /// Opens the recording of `cfg`, returning it with the size of its frames.
+    fn open(cfg: &Config) -> Result<(Self, (u32, u32, u32))> {
+        let index_path = cfg.index_path();
+        let mut index = Vec::new();
+        File::open(&index_path)
+            .and_then(|mut f| f.read_to_end(&mut index))
+            .map_err(Error::io_ctx(format!("reading {index_path:?}")))?;
+        if index.len() < HEADER_BYTES {
+            return Err(invalid(format!("{index_path:?} is missing its header")));
+        }
+
+        let header = index[..HEADER_BYTES]
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let (w, h, chans) = (header[0], header[1], header[2]);
+        let frame_bytes = u64::from(w) * u64::from(h) * u64::from(chans);
+
+        let frames_path = cfg.frames_path();
+        let frames =
+            File::open(&frames_path).map_err(Error::io_ctx(format!("opening {frames_path:?}")))?;
+        let recorded = frames
+            .metadata()
+            .map_err(Error::io_ctx(format!("reading {frames_path:?}")))?
+            .len()
+            / frame_bytes.max(1);
+
+        // a recording that was cut short can have a frame without its time or the other way round
+        let mut times = index[HEADER_BYTES..]
+            .chunks_exact(8)
+            .map(|b| Duration::from_micros(u64::from_le_bytes(b.try_into().unwrap())))
+            .take(recorded.try_into()?)
+            .collect::<Vec<_>>();
+        let Some(&first) = times.first() else {
+            return Err(invalid(format!("{frames_path:?} has no frames")));
+        };
+        for t in &mut times {
+            *t -= first;
+        }
+
+        let reader = Self {
+            frames,
+            frame_bytes,
+            times,
+            first,
+            started: Instant::now(),
+            loaded: None,
+        };
+        Ok((reader, (w, h, chans)))
+    }
+
+    /// Loads the latest frame that is due into `out`, waiting for the next one if the last
+    /// frame loaded is still the latest. Like a live camera, frames that weren't asked for in
+    /// time are skipped.
+    fn load(&mut self, out: &mut [u8]) -> io::Result<()> {
+        let mut next = self.loaded.map_or(0, |i| i + 1);
+        if next >= self.times.len() {
+            self.started = Instant::now();
+            next = 0;
+        }
+
+        let due = self.started + self.times[next];
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+
+        let elapsed = self.started.elapsed();
+        let latest = next + self.times[next..].partition_point(|&t| t <= elapsed).max(1) - 1;
+        self.read(latest, out)
+    }
+
+    /// Loads the latest frame recorded by `at` from when the recording started into `out`, or
+    /// the first frame before then.
+    fn load_at(&mut self, at: Duration, out: &mut [u8]) -> io::Result<()> {
+        let latest = self.times.partition_point(|&t| self.first + t <= at).max(1) - 1;
+        self.read(latest, out)
+    }
+
+    fn read(&mut self, frame: usize, out: &mut [u8]) -> io::Result<()> {
+        self.frames
+            .seek(SeekFrom::Start(frame as u64 * self.frame_bytes))?;
+        self.frames.read_exact(out)?;
+        self.loaded = Some(frame);
+        Ok(())
+    }
+
+    /// Time of the last frame from when the recording started.
+    fn end(&self) -> Duration {
+        self.first + self.times.last().copied().unwrap_or_default()
+    }
+}
+
+fn invalid(msg: String) -> Error {
+    Error::IO(
+        io::Error::new(io::ErrorKind::InvalidData, msg),
+        "opening replay".to_string(),
+    )
+}
+
+impl<B: OwnedWriteBuffer + 'static> TryFrom<Config> for Loader<B> {
+    type Error = Error;
+
+    fn try_from(cfg: Config) -> Result<Self> {
+        let (mut reader, (w, h, chans)) = StreamReader::open(&cfg)?;
+        Ok(Self::new_blocking(w, h, chans, move |buf| {
+            if let Err(err) = reader.load(buf) {
+                tracing::warn!("failed to replay camera {}: {err}", cfg.camera);
+            }
+        }))
+    }
+}
+
+/// Path of the config recorded along with the cameras in `dir`.
+#[must_use]
+pub fn config_path(dir: &Path) -> PathBuf {
+    dir.join("live.toml")
+}
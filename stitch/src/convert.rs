@@ -0,0 +1,132 @@
+use rayon::prelude::*;
+
+use crate::{buf::PixelFormat, DimErrorKind, Result};
+
+/// Converts a `width` by `height` frame in `format` into RGBA8, clamping RGBA16F frames to
+/// the range 8 bits can hold.
+///
+/// # Errors
+/// `src` or `dst` don't match the `width` and `height` given
+pub fn to_rgba(
+    format: PixelFormat,
+    src: &[u8],
+    dst: &mut [u8],
+    width: usize,
+    height: usize,
+) -> Result<()> {
+    DimErrorKind::Bytes.check(format.frame_bytes(width, height), src.len())?;
+    DimErrorKind::Bytes.check(width * height * 4, dst.len())?;
+
+    match format {
+        PixelFormat::Rgba8 => dst.copy_from_slice(src),
+        PixelFormat::Rgb8 => src
+            .par_chunks_exact(3)
+            .zip(dst.par_chunks_exact_mut(4))
+            .for_each(|(rgb, out)| out.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255])),
+        PixelFormat::Gray8 => src
+            .par_iter()
+            .zip(dst.par_chunks_exact_mut(4))
+            .for_each(|(&g, out)| out.copy_from_slice(&[g, g, g, 255])),
+        PixelFormat::Rgba16F => {
+            src.par_chunks_exact(2)
+                .zip(dst.par_iter_mut())
+                .for_each(|(half, out)| {
+                    let v = f16_to_f32(u16::from_le_bytes([half[0], half[1]]));
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    let n = (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    *out = n;
+                })
+        }
+        PixelFormat::Yuyv => return yuyv_to_rgba(src, dst, width, height),
+        PixelFormat::Nv12 => return nv12_to_rgba(src, dst, width, height),
+    }
+    Ok(())
+}
+
+/// Converts packed YUYV (YUY2) 4:2:2 data into RGBA8.
+///
+/// # Errors
+/// `src` or `dst` don't match the `width` and `height` given
+pub fn yuyv_to_rgba(src: &[u8], dst: &mut [u8], width: usize, height: usize) -> Result<()> {
+    // odd widths end each row on a macropixel whose second pixel is padding
+    let row = width.div_ceil(2) * 4;
+    DimErrorKind::Bytes.check(row * height, src.len())?;
+    DimErrorKind::Bytes.check(width * height * 4, dst.len())?;
+    if width == 0 {
+        return Ok(());
+    }
+
+    src.par_chunks_exact(row)
+        .zip(dst.par_chunks_exact_mut(width * 4))
+        .for_each(|(src, dst)| {
+            for (yuv, out) in src.chunks_exact(4).zip(dst.chunks_mut(8)) {
+                let (y0, u, y1, v) = (yuv[0], yuv[1], yuv[2], yuv[3]);
+                out[..4].copy_from_slice(&yuv_to_rgba(y0, u, v));
+                if let Some(out) = out.get_mut(4..8) {
+                    out.copy_from_slice(&yuv_to_rgba(y1, u, v));
+                }
+            }
+        });
+
+    Ok(())
+}
+
+/// Converts semi-planar NV12 4:2:0 data into RGBA8.
+///
+/// # Errors
+/// `src` or `dst` don't match the `width` and `height` given
+pub fn nv12_to_rgba(src: &[u8], dst: &mut [u8], width: usize, height: usize) -> Result<()> {
+    // odd widths still have a UV pair for the last pixel
+    let stride = width.div_ceil(2) * 2;
+    DimErrorKind::Bytes.check(width * height + stride * height.div_ceil(2), src.len())?;
+    DimErrorKind::Bytes.check(width * height * 4, dst.len())?;
+
+    let (luma, chroma) = src.split_at(width * height);
+    dst.par_chunks_exact_mut(width * 4)
+        .enumerate()
+        .for_each(|(y, out)| {
+            let luma = &luma[y * width..][..width];
+            let chroma = &chroma[(y / 2) * stride..][..stride];
+
+            for (x, px) in out.chunks_exact_mut(4).enumerate() {
+                let uv = &chroma[x & !1..][..2];
+                px.copy_from_slice(&yuv_to_rgba(luma[x], uv[0], uv[1]));
+            }
+        });
+
+    Ok(())
+}
+
+/// BT.601 limited range conversion, which is what nearly all webcams output.
+#[inline]
+fn yuv_to_rgba(y: u8, u: u8, v: u8) -> [u8; 4] {
+    let c = 298 * (i32::from(y) - 16);
+    let d = i32::from(u) - 128;
+    let e = i32::from(v) - 128;
+
+    let clamp = |n: i32| ((n + 128) >> 8).clamp(0, 255) as u8;
+    [
+        clamp(c + 409 * e),
+        clamp(c - 100 * d - 208 * e),
+        clamp(c + 516 * d),
+        255,
+    ]
+}
+
+/// Widens a half precision float, given as its bits.
+fn f16_to_f32(h: u16) -> f32 {
+    let sign = u32::from(h & 0x8000) << 16;
+    let exp = u32::from((h >> 10) & 0x1f);
+    let frac = u32::from(h & 0x3ff);
+    let bits = match exp {
+        // zero or subnormal, which is frac * 2^-24
+        0 => {
+            #[allow(clippy::cast_precision_loss)]
+            let v = frac as f32 * 2f32.powi(-24);
+            return if sign == 0 { v } else { -v };
+        }
+        0x1f => sign | 0x7f80_0000 | (frac << 13),
+        _ => sign | ((exp + 127 - 15) << 23) | (frac << 13),
+    };
+    f32::from_bits(bits)
+}
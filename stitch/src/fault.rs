@@ -0,0 +1,211 @@
+//! Faults injected into the frames of a camera, to see how everything downstream copes with a
+//! camera misbehaving without having to unplug one. Faults land on the same frames every run.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buf::FrameSize,
+    loader::{Loader, OwnedWriteBuffer},
+    Error,
+};
+
+/// Faults injected into the frames of a camera, none unless set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    /// Milliseconds the first frame is held back for, like a camera slow to start
+    pub start_delay_ms: u64,
+    /// Percentage of frames that fail to load, spread evenly
+    pub drop_percent: u8,
+    /// Percentage of frames that load noise instead, spread evenly
+    pub corrupt_percent: u8,
+    pub stall: Option<Stall>,
+}
+
+/// Frames held back like a camera that stops responding for a while.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stall {
+    /// Frames loaded before the first stall
+    pub after: u64,
+    /// Milliseconds every stall lasts
+    pub ms: u64,
+    /// Frames loaded between stalls, once unless set
+    pub every: Option<u64>,
+}
+
+impl Stall {
+    /// Whether the frame numbered `n`, counting from 1, stalls.
+    const fn hits(&self, n: u64) -> bool {
+        match (n.checked_sub(self.after + 1), self.every) {
+            (Some(0), _) => true,
+            (Some(since), Some(every)) => every > 0 && since % every == 0,
+            _ => false,
+        }
+    }
+}
+
+impl Faults {
+    pub const NONE: Self = Self {
+        start_delay_ms: 0,
+        drop_percent: 0,
+        corrupt_percent: 0,
+        stall: None,
+    };
+
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Frames of `src` with the faults injected on their own thread. Dropped frames count
+    /// towards the returned loader failing, see [`crate::loader::LoaderStatus`].
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn apply<B: OwnedWriteBuffer + 'static>(self, src: Loader<Box<[u8]>>) -> Loader<B> {
+        let (w, h) = (src.width() as u32, src.height() as u32);
+        let format = src.pixel_format();
+        let mut buf = Some(vec![0u8; src.num_bytes()].into_boxed_slice());
+        let mut n = 0u64;
+
+        Loader::new_captured(w, h, 0, None, move |out| {
+            if n == 0 {
+                std::thread::sleep(Duration::from_millis(self.start_delay_ms));
+            }
+            n += 1;
+
+            let frame = buf.take().ok_or(Error::BufferLost)?;
+            let (frame, captured) = src.give(frame)?.block_take_captured()?;
+            let len = out.len().min(frame.len());
+            out[..len].copy_from_slice(&frame[..len]);
+            buf = Some(frame);
+
+            if let Some(stall) = self.stall.filter(|s| s.hits(n)) {
+                tracing::debug!("stalling frame {n} for {}ms", stall.ms);
+                std::thread::sleep(Duration::from_millis(stall.ms));
+            }
+            if spread_hits(self.drop_percent, n) {
+                return Err(Error::Fault("dropped frame"));
+            }
+            if spread_hits(self.corrupt_percent, n) {
+                fill_noise(out, n);
+            }
+            Ok(captured)
+        })
+        .with_pixel_format(format)
+    }
+}
+
+/// Whether the frame numbered `n`, counting from 1, is one of `percent` of frames spread
+/// evenly.
+fn spread_hits(percent: u8, n: u64) -> bool {
+    let percent = u64::from(percent.min(100));
+    n * percent / 100 > (n - 1) * percent / 100
+}
+
+/// Fills `buf` with noise that's the same for every `seed`.
+#[allow(clippy::cast_possible_truncation)]
+fn fill_noise(buf: &mut [u8], seed: u64) {
+    let mut x = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    for b in buf {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        *b = (x >> 32) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames `faults` lets through from a loader of 4 byte frames filled with their number
+    /// counting from 1, with whether they were captured.
+    fn frames(faults: Faults, n: usize) -> Vec<(Box<[u8]>, bool)> {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _rt = rt.enter();
+        let mut count = 0u8;
+        let src = Loader::new_blocking(2, 2, 1, move |buf| {
+            count += 1;
+            buf.fill(count);
+        });
+        let faulty: Loader<Box<[u8]>> = faults.apply(src);
+
+        (0..n)
+            .map(|_| {
+                let buf = vec![0; 4].into_boxed_slice();
+                let (buf, captured) = faulty.give(buf).unwrap().block_take_captured().unwrap();
+                (buf, captured.is_some())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spreads_percent_evenly() {
+        for percent in [0, 1, 25, 33, 50, 99, 100] {
+            let hits = (1..=100).filter(|&n| spread_hits(percent, n)).count();
+            assert_eq!(hits, usize::from(percent));
+        }
+        let halves = (1..=8).filter(|&n| spread_hits(50, n)).collect::<Vec<_>>();
+        assert_eq!(halves, [2, 4, 6, 8]);
+        assert!((1..=10).all(|n| spread_hits(200, n)));
+    }
+
+    #[test]
+    fn stalls_after_and_every() {
+        let stalled = |every| {
+            let stall = Stall {
+                after: 3,
+                ms: 0,
+                every,
+            };
+            (1..=10).filter(|&n| stall.hits(n)).collect::<Vec<_>>()
+        };
+        assert_eq!(stalled(None), [4]);
+        assert_eq!(stalled(Some(0)), [4]);
+        assert_eq!(stalled(Some(3)), [4, 7, 10]);
+    }
+
+    #[test]
+    fn noise_is_the_same_every_run() {
+        let noise = |seed| {
+            let mut buf = [0u8; 64];
+            fill_noise(&mut buf, seed);
+            buf
+        };
+        assert_eq!(noise(7), noise(7));
+        assert_ne!(noise(7), noise(8));
+        assert!(noise(7).iter().any(|&b| b != noise(7)[0]));
+    }
+
+    #[test]
+    fn no_faults_pass_frames_through() {
+        let got = frames(Faults::NONE, 3);
+        for (n, (buf, captured)) in (1..).zip(got) {
+            assert_eq!(*buf, [n; 4]);
+            assert!(captured);
+        }
+    }
+
+    #[test]
+    fn drops_and_corrupts_the_same_frames() {
+        let faults = Faults {
+            drop_percent: 50,
+            ..Faults::NONE
+        };
+        let captured = frames(faults, 6).into_iter().map(|(_, c)| c);
+        assert!(captured.eq([true, false, true, false, true, false]));
+
+        let faults = Faults {
+            corrupt_percent: 100,
+            ..Faults::NONE
+        };
+        for (n, (buf, captured)) in (1..).zip(frames(faults, 3)) {
+            let mut noise = [0; 4];
+            fill_noise(&mut noise, n);
+            assert_eq!(*buf, noise);
+            assert!(captured);
+        }
+    }
+}
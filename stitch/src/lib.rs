@@ -2,12 +2,63 @@ pub mod camera;
 
 pub mod buf;
 
+pub mod convert;
+
+pub mod fault;
+
+#[cfg(feature = "calib")]
+pub mod calib;
+
+#[cfg(any(feature = "gpu", feature = "cpu", feature = "calib"))]
+mod linalg;
+
 pub mod loader;
 
 pub mod proj;
 
+pub mod proto;
+
+pub mod transform;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Optional parts of the crate that were compiled into this build.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Capabilities {
+    pub toml_cfg: bool,
+    pub tokio: bool,
+    pub live: bool,
+    pub gpu: bool,
+    pub cpu: bool,
+    pub calib: bool,
+    pub live_formats: &'static [&'static str],
+    pub lenses: &'static [&'static str],
+    /// Formats masks, watermarks and height fields can be loaded from
+    pub image_formats: Vec<String>,
+}
+
+#[must_use]
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        toml_cfg: cfg!(feature = "toml-cfg"),
+        tokio: cfg!(feature = "tokio"),
+        live: cfg!(feature = "live"),
+        gpu: cfg!(feature = "gpu"),
+        cpu: cfg!(feature = "cpu"),
+        calib: cfg!(feature = "calib"),
+        live_formats: if cfg!(feature = "live") {
+            &["mjpeg", "yuyv", "nv12"]
+        } else {
+            &[]
+        },
+        lenses: &["rectilinear", "equidistant", "equisolid"],
+        image_formats: image::ImageFormat::all()
+            .filter(|f| f.reading_enabled())
+            .map(|f| format!("{f:?}").to_lowercase())
+            .collect(),
+    }
+}
+
 #[derive(thiserror::Error)]
 pub enum Error {
     #[error("io error while {1}: {0}")]
@@ -28,6 +79,30 @@ pub enum Error {
     #[error("loader failed to accept or return buffer")]
     BufferLost,
 
+    #[error("the loader shared by every subscriber failed to load a frame")]
+    SourceFailed,
+
+    #[error("camera {0} has no resolution set")]
+    MissingResolution(usize),
+
+    #[error("world heights can only be updated for a height field")]
+    NotHeightField,
+
+    #[error("focal distance not set, the camera's fov has to be resolved with its size first")]
+    FocalDistanceUnset,
+
+    #[error("failed to load mask {path:?}: {source}")]
+    MaskLoad {
+        path: std::path::PathBuf,
+        source: image::ImageError,
+    },
+
+    #[error("failed to load watermark {path:?}: {source}")]
+    WatermarkLoad {
+        path: std::path::PathBuf,
+        source: image::ImageError,
+    },
+
     #[cfg(feature = "toml-cfg")]
     #[error("decode error: {0}")]
     DecodeError(#[from] toml::de::Error),
@@ -36,10 +111,36 @@ pub enum Error {
     #[error("live err: {0}")]
     LiveErr(#[from] nokhwa::NokhwaError),
 
+    #[cfg(feature = "live")]
+    #[error("failed to open camera {camera}: {source}")]
+    AdapterInit {
+        camera: u32,
+        source: nokhwa::NokhwaError,
+    },
+
     #[cfg(feature = "gpu")]
     #[error("gpu error: {0}")]
     GpuError(#[from] smpgpu::Error),
 
+    #[cfg(feature = "gpu")]
+    #[error("failed to read back from the gpu: {0}")]
+    GpuMap(smpgpu::Error),
+
+    #[cfg(feature = "gpu")]
+    #[error("can't project more than {0} cameras")]
+    TooManyCameras(usize),
+
+    #[cfg(feature = "calib")]
+    #[error("calibration failed: {0}")]
+    Calibration(&'static str),
+
+    #[cfg(feature = "gpu")]
+    #[error("stereo depth unavailable: {0}")]
+    Stereo(&'static str),
+
+    #[error("injected fault: {0}")]
+    Fault(&'static str),
+
     #[error("an option had the value of none, which shouldn't be possible")]
     UnexpectedNone,
 }
@@ -64,6 +165,7 @@ pub enum DimErrorKind {
     Height,
     Channel,
     Bytes,
+    Heights,
 }
 
 impl std::fmt::Display for DimErrorKind {
@@ -73,6 +175,7 @@ impl std::fmt::Display for DimErrorKind {
             Self::Height => write!(f, "height"),
             Self::Channel => write!(f, "channel"),
             Self::Bytes => write!(f, "bytes"),
+            Self::Heights => write!(f, "heights"),
         }
     }
 }
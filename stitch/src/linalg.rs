@@ -0,0 +1,33 @@
+//! Small dense solvers for the handful of unknowns in gain and calibration fits.
+
+/// Solves `a * x = b` for a row major `n` by `n` matrix `a` with Gaussian elimination,
+/// returns `None` if `a` is singular. Both `a` and `b` are clobbered.
+pub fn solve(a: &mut [f32], b: &mut [f32], n: usize) -> Option<Vec<f32>> {
+    for col in 0..n {
+        let pivot =
+            (col..n).max_by(|&x, &y| a[x * n + col].abs().total_cmp(&a[y * n + col].abs()))?;
+        if a[pivot * n + col].abs() < f32::EPSILON {
+            return None;
+        }
+
+        for k in 0..n {
+            a.swap(col * n + k, pivot * n + k);
+        }
+        b.swap(col, pivot);
+
+        for row in col + 1..n {
+            let f = a[row * n + col] / a[col * n + col];
+            for k in col..n {
+                a[row * n + k] -= f * a[col * n + k];
+            }
+            b[row] -= f * b[col];
+        }
+    }
+
+    let mut x = vec![0f32; n];
+    for row in (0..n).rev() {
+        let rest = (row + 1..n).map(|k| a[row * n + k] * x[k]).sum::<f32>();
+        x[row] = (b[row] - rest) / a[row * n + row];
+    }
+    Some(x)
+}
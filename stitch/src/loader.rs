@@ -1,5 +1,16 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    buf::{FrameBufferView, FrameSize},
+    buf::{FrameBufferView, FrameSize, PixelFormat},
     camera::Camera,
     Error, Result,
 };
@@ -12,19 +23,130 @@ pub trait OwnedWriteBuffer {
 }
 
 impl<T: std::ops::DerefMut<Target = [u8]>> OwnedWriteBuffer for T {
-    type View<'a> = &'a mut [u8] where Self: 'a;
+    type View<'a>
+        = &'a mut [u8]
+    where
+        Self: 'a;
 
     fn owned_to_view(&mut self) -> Self::View<'_> {
         self
     }
 }
 
+/// Sequence number and time of a frame a camera captured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capture {
+    /// Counts up from 1 with every frame the device captures.
+    pub id: u64,
+    /// When the frame finished loading into its buffer.
+    pub at: Instant,
+}
+
+/// Longest a frame can take to load before its loader is [`LoaderStatus::Stalled`].
+pub const STALLED_AFTER: Duration = Duration::from_secs(2);
+
+/// Frames in a row that have to fail to load before their loader is [`LoaderStatus::Failed`].
+pub const FAILED_AFTER: u32 = 30;
+
+/// State of the device a [`Loader`] loads from, see [`Loader::status`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum LoaderStatus {
+    /// No frame has loaded yet.
+    Initializing,
+    /// Frames are loading, `fps` of them a second lately.
+    Streaming { fps: f32 },
+    /// A frame has been loading for longer than [`STALLED_AFTER`].
+    Stalled,
+    /// The device is being opened again. Loaders never report it themselves, whatever reopens
+    /// the device does.
+    Reconnecting,
+    /// The last [`FAILED_AFTER`] frames failed to load, or the device couldn't be opened.
+    Failed { error: String },
+}
+
+/// What a [`Loader`] has loaded lately, shared with its thread and every loader made from it.
+/// It outlives the loader, which keeps it at its last state.
+#[derive(Clone, Debug, Default)]
+pub struct LoaderHealth(Arc<Mutex<Health>>);
+
+#[derive(Debug, Default)]
+struct Health {
+    /// When the frame loading now was asked for.
+    loading_since: Option<Instant>,
+    last_frame: Option<Instant>,
+    fps: f32,
+    /// Frames that failed to load since the last one that loaded.
+    failures: u32,
+    error: Option<String>,
+}
+
+impl LoaderHealth {
+    #[must_use]
+    pub fn status(&self) -> LoaderStatus {
+        let health = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if health.failures >= FAILED_AFTER {
+            return LoaderStatus::Failed {
+                error: health.error.clone().unwrap_or_default(),
+            };
+        }
+        if health
+            .loading_since
+            .is_some_and(|since| since.elapsed() > STALLED_AFTER)
+        {
+            return LoaderStatus::Stalled;
+        }
+        match health.last_frame {
+            Some(_) => LoaderStatus::Streaming { fps: health.fps },
+            None => LoaderStatus::Initializing,
+        }
+    }
+
+    fn loading(&self) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .loading_since = Some(Instant::now());
+    }
+
+    fn loaded(&self, loaded: &Result<Option<Capture>>) {
+        let mut health = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        health.loading_since = None;
+        match loaded {
+            Ok(Some(_)) => {
+                let now = Instant::now();
+                if let Some(last) = health.last_frame {
+                    let frame_fps = 1. / (now - last).as_secs_f32();
+                    health.fps = if health.fps == 0. {
+                        frame_fps
+                    } else {
+                        health.fps * 0.9 + frame_fps * 0.1
+                    };
+                }
+                health.last_frame = Some(now);
+                health.failures = 0;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                health.failures += 1;
+                health.error = Some(err.to_string());
+            }
+        }
+    }
+}
+
+type Request<B> = (B, kanal::OneshotSender<(B, Option<Capture>)>);
+
 #[derive(Clone, Debug)]
 pub struct Loader<B: OwnedWriteBuffer> {
-    req_send: kanal::Sender<(B, kanal::OneshotSender<B>)>,
+    req_send: kanal::Sender<Request<B>>,
+    health: LoaderHealth,
     width: u32,
     height: u32,
     chans: u32,
+    bytes_per_chan: u32,
+    /// Set when the frames aren't packed channels, see [`Self::with_pixel_format`].
+    format: Option<PixelFormat>,
 }
 
 impl<B: OwnedWriteBuffer + 'static> Loader<B> {
@@ -34,24 +156,107 @@ impl<B: OwnedWriteBuffer + 'static> Loader<B> {
         chans: u32,
         mut cb: impl FnMut(&mut [u8]) + Send + 'static,
     ) -> Self {
-        let (req_send, req_recv) = kanal::bounded::<(B, kanal::OneshotSender<B>)>(4);
+        Self::new_fallible(width, height, chans, move |buf| {
+            cb(buf);
+            Ok(())
+        })
+    }
+
+    /// Like [`Self::new_blocking`], but `cb` can fail to load a frame, which is handed back
+    /// without a capture and counts towards [`LoaderStatus::Failed`].
+    pub fn new_fallible(
+        width: u32,
+        height: u32,
+        chans: u32,
+        mut cb: impl FnMut(&mut [u8]) -> Result<()> + Send + 'static,
+    ) -> Self {
+        let mut id = 0;
+        Self::new_captured(width, height, chans, None, move |buf| {
+            cb(buf)?;
+            id += 1;
+            Ok(Some(Capture {
+                id,
+                at: Instant::now(),
+            }))
+        })
+    }
+
+    /// Like [`Self::new_fallible`], but `cb` reports which capture it loaded, if any. Loaders
+    /// made from another one pass its `health`, which they report instead of their own.
+    pub(crate) fn new_captured(
+        width: u32,
+        height: u32,
+        chans: u32,
+        health: Option<LoaderHealth>,
+        mut cb: impl FnMut(&mut [u8]) -> Result<Option<Capture>> + Send + 'static,
+    ) -> Self {
+        let (req_send, req_recv) = kanal::bounded::<Request<B>>(4);
+        let (health, recorded) = match health {
+            Some(health) => (health, None),
+            None => {
+                let health = LoaderHealth::default();
+                (health.clone(), Some(health))
+            }
+        };
 
+        RUNNING.fetch_add(1, Ordering::AcqRel);
         tokio::task::spawn_blocking(move || {
             while let Ok((mut req, resp_send)) = req_recv.recv() {
-                cb(req.owned_to_view().as_mut());
+                if let Some(health) = &recorded {
+                    health.loading();
+                }
+                let loaded = cb(req.owned_to_view().as_mut());
+                if let Some(health) = &recorded {
+                    health.loaded(&loaded);
+                }
                 // if the receiver has been dropped, they don't want their buffer back!
-                _ = resp_send.send(req);
+                _ = resp_send.send((req, loaded.ok().flatten()));
             }
+            // closes the device the callback reads from
+            drop(cb);
+            RUNNING.fetch_sub(1, Ordering::AcqRel);
         });
 
         Self {
             req_send,
+            health,
             width,
             height,
             chans,
+            bytes_per_chan: 1,
+            format: None,
         }
     }
 
+    /// State of the device frames are loaded from, or of the loader this one was made from.
+    #[must_use]
+    pub fn status(&self) -> LoaderStatus {
+        self.health.status()
+    }
+
+    /// Handle of [`Self::status`] that can be kept apart from the loader.
+    #[must_use]
+    pub fn health(&self) -> LoaderHealth {
+        self.health.clone()
+    }
+
+    /// Loads frames with `n` bytes in every channel instead of 1, like 2 for RGBA16F frames.
+    #[must_use]
+    pub fn with_bytes_per_chan(mut self, n: u32) -> Self {
+        self.bytes_per_chan = n;
+        self
+    }
+
+    /// Loads frames in `format` as delivered, which sets the channels and their bytes too.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn with_pixel_format(mut self, format: PixelFormat) -> Self {
+        self.chans = format.chans() as u32;
+        self.bytes_per_chan = format.bytes_per_chan() as u32;
+        self.format = Some(format);
+        self
+    }
+
     /// # Errors
     /// loader doesn't exist anymore
     pub fn give(&self, buf: B) -> Result<Ticket<B>> {
@@ -63,6 +268,197 @@ impl<B: OwnedWriteBuffer + 'static> Loader<B> {
     }
 }
 
+impl<B: OwnedWriteBuffer + Send + 'static> Loader<B> {
+    /// Loads a frame into `buf` without blocking, the async counterpart of [`Self::give`] and
+    /// [`Ticket::take`].
+    ///
+    /// # Errors
+    /// loader doesn't exist anymore
+    pub async fn give_async(&self, buf: B) -> Result<B> {
+        let (buf_send, buf_recv) = kanal::oneshot();
+        self.req_send
+            .as_async()
+            .send((buf, buf_send))
+            .await
+            .map_err(|_| Error::BufferLost)?;
+
+        Ticket(buf_recv).take().await
+    }
+
+    /// Stream of frames loaded into buffers from `alloc`, ends once the loader stops.
+    pub fn frames<'a>(
+        &'a self,
+        mut alloc: impl FnMut() -> B + Send + 'a,
+    ) -> impl Stream<Item = B> + Send + 'a {
+        futures::stream::unfold(self, move |loader| {
+            let buf = alloc();
+            async move { loader.give_async(buf).await.ok().map(|b| (b, loader)) }
+        })
+    }
+}
+
+impl Loader<Box<[u8]>> {
+    /// Splits this loader into `n` loaders that all receive frames from the same device.
+    /// See [`SharedLoader`].
+    #[must_use]
+    pub fn tee<B: OwnedWriteBuffer + 'static>(self, n: usize) -> Vec<Loader<B>> {
+        let shared = SharedLoader::new(self);
+        (0..n).map(|_| shared.subscribe()).collect()
+    }
+
+    /// Loader of `width` by `height` frames in `format`, each made by `f` from a frame of this
+    /// loader on its own thread. Frames keep the capture of the frame they were made from.
+    #[must_use]
+    pub fn map_frames<B: OwnedWriteBuffer + 'static>(
+        self,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        mut f: impl FnMut(&[u8], &mut [u8]) + Send + 'static,
+    ) -> Loader<B> {
+        let mut buf = Some(vec![0u8; self.num_bytes()].into_boxed_slice());
+        let health = self.health();
+        Loader::new_captured(width, height, 0, Some(health), move |out| {
+            let src = buf.take().ok_or(Error::BufferLost)?;
+            let (src, captured) = self.give(src)?.block_take_captured()?;
+            f(&src, out);
+            buf = Some(src);
+            Ok(captured)
+        })
+        .with_pixel_format(format)
+    }
+}
+
+/// Lets multiple consumers read from one [`Loader`] without opening the device twice.
+///
+/// A subscriber only triggers a new capture once it has already seen the latest frame,
+/// otherwise it is handed a copy of the frame another subscriber captured.
+#[derive(Clone)]
+pub struct SharedLoader {
+    frame: Arc<Mutex<SharedFrame>>,
+    health: LoaderHealth,
+    width: u32,
+    height: u32,
+    chans: u32,
+    bytes_per_chan: u32,
+    format: Option<PixelFormat>,
+}
+
+struct SharedFrame {
+    src: Loader<Box<[u8]>>,
+    /// Latest frame, kept when capturing the next one fails.
+    buf: Box<[u8]>,
+    /// Captured into next, a new one is made if the last was lost to a failed capture.
+    spare: Option<Box<[u8]>>,
+    captured: Option<Capture>,
+    gen: u64,
+}
+
+impl SharedFrame {
+    /// Captures the next frame from the source into the spare buffer, leaving the latest frame
+    /// as it was if that fails.
+    fn capture(&mut self) -> Result<()> {
+        let spare = (self.spare.take())
+            .unwrap_or_else(|| vec![0u8; self.src.num_bytes()].into_boxed_slice());
+        let (buf, captured) = match self.src.give(spare).and_then(Ticket::block_take_captured) {
+            Ok(taken) => taken,
+            Err(err) => {
+                // the source's thread is gone, so it can't report this itself
+                let failed = Err(err);
+                self.src.health.loaded(&failed);
+                return failed.map(drop);
+            }
+        };
+        // a frame that failed to load comes back without a capture, which the source's thread
+        // already counted towards its health
+        let Some(captured) = captured else {
+            self.spare = Some(buf);
+            return Err(Error::SourceFailed);
+        };
+        self.spare = Some(std::mem::replace(&mut self.buf, buf));
+        self.captured = Some(captured);
+        self.gen += 1;
+        Ok(())
+    }
+}
+
+impl SharedLoader {
+    #[must_use]
+    pub fn new(src: Loader<Box<[u8]>>) -> Self {
+        let buf = vec![0u8; src.num_bytes()].into_boxed_slice();
+        Self {
+            health: src.health(),
+            width: src.width,
+            height: src.height,
+            chans: src.chans,
+            bytes_per_chan: src.bytes_per_chan,
+            format: src.format,
+            frame: Arc::new(Mutex::new(SharedFrame {
+                src,
+                buf,
+                spare: None,
+                captured: None,
+                gen: 0,
+            })),
+        }
+    }
+
+    pub fn subscribe<B: OwnedWriteBuffer + 'static>(&self) -> Loader<B> {
+        let frame = self.frame.clone();
+        let mut seen = 0;
+
+        // subscribers report the capture of the source, so every copy of a frame shares its id
+        let health = Some(self.health.clone());
+        let loader =
+            Loader::new_captured(self.width, self.height, self.chans, health, move |out| {
+                // a subscriber that panicked leaves the frame as it was
+                let mut frame = frame.lock().unwrap_or_else(PoisonError::into_inner);
+                if frame.gen <= seen {
+                    frame.capture()?;
+                }
+                seen = frame.gen;
+
+                out.copy_from_slice(&frame.buf);
+                Ok(frame.captured)
+            })
+            .with_bytes_per_chan(self.bytes_per_chan);
+        match self.format {
+            Some(format) => loader.with_pixel_format(format),
+            None => loader,
+        }
+    }
+}
+
+impl FrameSize for SharedLoader {
+    fn width(&self) -> usize {
+        self.width as _
+    }
+
+    fn height(&self) -> usize {
+        self.height as _
+    }
+
+    fn chans(&self) -> usize {
+        self.chans as _
+    }
+
+    fn bytes_per_chan(&self) -> usize {
+        self.bytes_per_chan as _
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.format
+            .unwrap_or_else(|| PixelFormat::packed(self.chans(), self.bytes_per_chan()))
+    }
+
+    fn num_bytes(&self) -> usize {
+        match self.format {
+            Some(format) => format.frame_bytes(self.width(), self.height()),
+            None => self.width() * self.height() * self.chans() * self.bytes_per_chan(),
+        }
+    }
+}
+
 pub async fn collect_empty_camera_tickets<
     B: OwnedWriteBuffer + Send,
     K: Sync,
@@ -80,6 +476,23 @@ pub async fn collect_empty_camera_tickets<
     .await
 }
 
+/// Loader threads that haven't stopped yet, see [`block_until_stopped`].
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// Waits up to `timeout` for the thread of every dropped loader to stop, closing the device
+/// it loads from, and returns whether they all did. Loaders that are still around keep
+/// running, so they have to be dropped first.
+pub fn block_until_stopped(timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while RUNNING.load(Ordering::Acquire) > 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
 #[inline]
 pub fn block_discard_tickets<B: OwnedWriteBuffer>(tickets: Vec<Ticket<B>>) {
     for ticket in tickets {
@@ -87,12 +500,31 @@ pub fn block_discard_tickets<B: OwnedWriteBuffer>(tickets: Vec<Ticket<B>>) {
     }
 }
 
-pub struct Ticket<R>(kanal::OneshotReceiver<R>);
+/// Like [`block_discard_tickets`], but returns the capture each frame came from, `None` for
+/// any that failed to load.
+pub fn block_discard_tickets_captured<B: OwnedWriteBuffer>(
+    tickets: Vec<Ticket<B>>,
+) -> Vec<Option<Capture>> {
+    tickets
+        .into_iter()
+        .map(|ticket| ticket.block_take_captured().ok().and_then(|(_, c)| c))
+        .collect()
+}
+
+pub struct Ticket<R>(kanal::OneshotReceiver<(R, Option<Capture>)>);
 
 impl<R> Ticket<R> {
     /// # Errors
     /// loading thread exited
     pub fn block_take(self) -> Result<R> {
+        self.block_take_captured().map(|(buf, _)| buf)
+    }
+
+    /// [`Self::block_take`], along with the capture loaded into the buffer.
+    ///
+    /// # Errors
+    /// loading thread exited
+    pub fn block_take_captured(self) -> Result<(R, Option<Capture>)> {
         self.0.recv().map_err(|_| Error::BufferLost)
     }
 }
@@ -105,6 +537,7 @@ impl<R: Send> Ticket<R> {
             .to_async()
             .recv()
             .await
+            .map(|(buf, _)| buf)
             .map_err(|_| Error::BufferLost)
     }
 }
@@ -121,4 +554,87 @@ impl<B: OwnedWriteBuffer> FrameSize for Loader<B> {
     fn chans(&self) -> usize {
         self.chans as _
     }
+
+    fn bytes_per_chan(&self) -> usize {
+        self.bytes_per_chan as _
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.format
+            .unwrap_or_else(|| PixelFormat::packed(self.chans(), self.bytes_per_chan()))
+    }
+
+    fn num_bytes(&self) -> usize {
+        match self.format {
+            Some(format) => format.frame_bytes(self.width(), self.height()),
+            None => self.width() * self.height() * self.chans() * self.bytes_per_chan(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loader of 2 by 1 gray frames filled with their number counting from 1, which fails to
+    /// load the frames `fails` picks.
+    fn numbered(fails: fn(u8) -> bool) -> Loader<Box<[u8]>> {
+        let mut n = 0u8;
+        Loader::new_fallible(2, 1, 1, move |buf| {
+            n += 1;
+            if fails(n) {
+                return Err(Error::Fault("test failure"));
+            }
+            buf.fill(n);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn shared_frame_survives_failed_capture() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _rt = rt.enter();
+        let shared = SharedLoader::new(numbered(|n| n == 2));
+        let mut frame = shared.frame.lock().unwrap();
+
+        frame.capture().unwrap();
+        assert_eq!(*frame.buf, [1, 1]);
+        assert_eq!(frame.gen, 1);
+        let captured = frame.captured;
+        assert!(captured.is_some());
+
+        assert!(matches!(frame.capture(), Err(Error::SourceFailed)));
+        assert_eq!(*frame.buf, [1, 1]);
+        assert_eq!(frame.gen, 1);
+        assert_eq!(frame.captured, captured);
+
+        frame.capture().unwrap();
+        assert_eq!(*frame.buf, [3, 3]);
+        assert_eq!(frame.gen, 2);
+    }
+
+    #[test]
+    fn subscribers_keep_last_frame_while_source_fails() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _rt = rt.enter();
+        let shared = SharedLoader::new(numbered(|n| n > 1));
+        let sub: Loader<Box<[u8]>> = shared.subscribe();
+        let load = |buf| sub.give(buf).unwrap().block_take_captured().unwrap();
+
+        let (buf, captured) = load(vec![0; 2].into_boxed_slice());
+        assert_eq!(*buf, [1, 1]);
+        assert!(captured.is_some());
+
+        for _ in 0..FAILED_AFTER {
+            let (_, captured) = load(vec![0; 2].into_boxed_slice());
+            assert_eq!(captured, None);
+        }
+        let frame = shared.frame.lock().unwrap();
+        assert_eq!(*frame.buf, [1, 1]);
+        assert_eq!(frame.gen, 1);
+        assert!(matches!(
+            shared.health.status(),
+            LoaderStatus::Failed { .. }
+        ));
+    }
 }
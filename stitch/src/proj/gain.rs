@@ -0,0 +1,128 @@
+//! Exposure and color gain compensation between overlapping cameras, see Brown & Lowe,
+//! "Automatic Panoramic Image Stitching using Invariant Features" (2007) section 6.
+
+use crate::linalg;
+
+/// Weight of the intensity error between overlaps, roughly `1 / sigma_n^2` with pixel values
+/// in 0..=255.
+const ERR_WEIGHT: f32 = 0.01;
+/// Weight pulling every gain back towards 1, roughly `1 / sigma_g^2`.
+const PRIOR_WEIGHT: f32 = 100.0;
+
+/// Solves per camera RGB gains from the overlap sums of `n` cameras.
+///
+/// `stats[(i * n + j) * 4..][..4]` holds the number of samples where cameras `i` and `j` both
+/// see the same point, followed by the sums of camera `i`'s red, green and blue values there.
+pub fn solve(stats: &[u32], n: usize) -> Vec<glam::Vec4> {
+    let mut gains = vec![glam::Vec4::ONE; n];
+
+    for c in 0..3 {
+        let mut a = vec![0f32; n * n];
+        let mut b = vec![0f32; n];
+
+        for i in 0..n {
+            for j in 0..n {
+                let s = &stats[(i * n + j) * 4..][..4];
+                if i == j || s[0] == 0 {
+                    continue;
+                }
+
+                #[allow(clippy::cast_precision_loss)]
+                let count = s[0] as f32;
+                #[allow(clippy::cast_precision_loss)]
+                let mean_ij = stats[(i * n + j) * 4 + 1 + c] as f32 / count;
+                #[allow(clippy::cast_precision_loss)]
+                let mean_ji = stats[(j * n + i) * 4 + 1 + c] as f32 / count;
+
+                a[i * n + i] += count * 2.0 * ERR_WEIGHT * mean_ij * mean_ij;
+                a[i * n + j] -= count * 2.0 * ERR_WEIGHT * mean_ij * mean_ji;
+                a[i * n + i] += count * PRIOR_WEIGHT;
+                b[i] += count * PRIOR_WEIGHT;
+            }
+        }
+
+        // cameras without any overlap keep their gain
+        for i in 0..n {
+            if a[i * n + i] == 0.0 {
+                a[i * n + i] = 1.0;
+                b[i] = 1.0;
+            }
+        }
+
+        if let Some(g) = linalg::solve(&mut a, &mut b, n) {
+            for (gain, g) in gains.iter_mut().zip(g) {
+                gain[c] = g.clamp(0.5, 2.0);
+            }
+        }
+    }
+
+    gains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Overlap sums of `n` cameras where each of `overlaps` is `(i, j, count, mean_i, mean_j)`,
+    /// with every channel at the same mean.
+    fn stats(n: usize, overlaps: &[(usize, usize, u32, u32, u32)]) -> Vec<u32> {
+        let mut stats = vec![0; n * n * 4];
+        for &(i, j, count, mean_i, mean_j) in overlaps {
+            for (a, b, mean) in [(i, j, mean_i), (j, i, mean_j)] {
+                let s = &mut stats[(a * n + b) * 4..][..4];
+                s.copy_from_slice(&[count, count * mean, count * mean, count * mean]);
+            }
+        }
+        stats
+    }
+
+    fn assert_near(gain: glam::Vec4, rgb: f32) {
+        assert!(
+            gain.abs_diff_eq(glam::Vec4::new(rgb, rgb, rgb, 1.0), 1e-4),
+            "{gain} isn't {rgb}"
+        );
+    }
+
+    #[test]
+    fn identical_overlaps_keep_gains() {
+        let gains = solve(&stats(3, &[(0, 1, 500, 128, 128), (1, 2, 200, 40, 40)]), 3);
+        for gain in gains {
+            assert_near(gain, 1.0);
+        }
+    }
+
+    #[test]
+    fn two_cameras_meet_in_between() {
+        // with means of 100 and 200 the normal equations are
+        //   300 g0 - 400 g1 = 100
+        //  -400 g0 + 900 g1 = 100
+        let gains = solve(&stats(2, &[(0, 1, 1000, 100, 200)]), 2);
+        assert_near(gains[0], 13.0 / 11.0);
+        assert_near(gains[1], 7.0 / 11.0);
+    }
+
+    #[test]
+    fn cameras_without_overlap_stay_finite() {
+        assert!(solve(&[], 0).is_empty());
+        for gain in solve(&stats(2, &[]), 2) {
+            assert_near(gain, 1.0);
+        }
+
+        // the last camera sees nothing the others do, and a black overlap only has the prior
+        let gains = solve(&stats(3, &[(0, 1, 50, 0, 0)]), 3);
+        assert!(gains.iter().all(|g| g.is_finite()));
+        for gain in gains {
+            assert_near(gain, 1.0);
+        }
+    }
+
+    #[test]
+    fn prior_and_clamp_bound_gains() {
+        // the prior holds the dark camera near 1, and the bright one would drop to about 0.075
+        //   100.02 g0 -    5.1 g1 = 100
+        //     -5.1 g0 + 1400.5 g1 = 100
+        let gains = solve(&stats(2, &[(0, 1, 1000, 1, 255)]), 2);
+        assert_near(gains[0], 140_560.0 / 140_052.0);
+        assert_near(gains[1], 0.5);
+    }
+}
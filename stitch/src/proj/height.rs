@@ -0,0 +1,72 @@
+//! Ground shaped by a heightmap, see [`super::WorldStyle::HeightField`].
+
+use std::path::Path;
+
+use crate::{DimErrorKind, Result};
+
+/// Largest heightmap side used, bigger ones are downscaled.
+const MAX_SIDE: u32 = 256;
+
+/// Grid of ground heights spanning `size` world units centered on the origin, stored row by
+/// row from north to south.
+#[derive(Clone, Copy, Debug)]
+pub struct HeightGrid {
+    pub w: u32,
+    pub h: u32,
+    pub size: f32,
+}
+
+impl HeightGrid {
+    /// Opens the heightmap at `p`, where image rows run south and white is `scale` units above
+    /// black, returning its grid and heights.
+    ///
+    /// # Errors
+    /// the heightmap can't be loaded or is less than 2 pixels wide or tall
+    pub fn load(p: &Path, scale: f32, size: f32) -> Result<(Self, Vec<f32>)> {
+        let mut img = image::open(p)?.to_luma16();
+        if img.width() > MAX_SIDE || img.height() > MAX_SIDE {
+            img = image::imageops::resize(
+                &img,
+                img.width().min(MAX_SIDE),
+                img.height().min(MAX_SIDE),
+                image::imageops::FilterType::Triangle,
+            );
+        }
+
+        let (w, h) = img.dimensions();
+        if w < 2 || h < 2 {
+            return Err(DimErrorKind::Width.err(2, w.min(h) as _).into());
+        }
+
+        let heights = img
+            .pixels()
+            .map(|p| f32::from(p[0]) / f32::from(u16::MAX) * scale)
+            .collect();
+        Ok((Self { w, h, size }, heights))
+    }
+
+    /// Height of the ground at world `x`, `y` from the grid's `heights`, split into the same
+    /// two triangles per cell as the GPU mesh. `None` outside the grid.
+    #[cfg(feature = "cpu")]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn height_at(&self, heights: &[f32], x: f32, y: f32) -> Option<f32> {
+        let gx = (x / self.size + 0.5) * (self.w - 1) as f32;
+        let gy = (0.5 - y / self.size) * (self.h - 1) as f32;
+        if !(0.0..=(self.w - 1) as f32).contains(&gx) || !(0.0..=(self.h - 1) as f32).contains(&gy)
+        {
+            return None;
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (cx, cy) = ((gx as u32).min(self.w - 2), (gy as u32).min(self.h - 2));
+        let (u, t) = (gx - cx as f32, gy - cy as f32);
+        let at = |dx: u32, dy: u32| heights[((cy + dy) * self.w + cx + dx) as usize];
+
+        // cells are split along the diagonal from their south west to north east corner
+        Some(if u + t >= 1.0 {
+            at(0, 1) * (1.0 - u) + at(1, 1) * (u + t - 1.0) + at(1, 0) * (1.0 - t)
+        } else {
+            at(1, 0) * u + at(0, 0) * (1.0 - u - t) + at(0, 1) * t
+        })
+    }
+}
@@ -0,0 +1,93 @@
+//! Camera masks shared by the projectors, one `u32` per input pixel that is either every bit
+//! set where the camera is used or 0 where it isn't.
+
+use std::path::{Path, PathBuf};
+
+use crate::{DimErrorKind, Error, Result};
+
+/// Masks of every camera from `paths`, each `w` by `h`, where cameras without a mask or whose
+/// mask fails to load see everything.
+///
+/// # Errors
+/// a mask doesn't match the input size while `strict` is set
+pub fn load_all(
+    paths: &[Option<PathBuf>],
+    (w, h, n): (u32, u32, u32),
+    strict: bool,
+) -> Result<Box<[u32]>> {
+    let img_size = (w * h) as usize;
+    let mut out = vec![0; img_size * n as usize].into_boxed_slice();
+
+    for (p, view) in paths.iter().zip(out.chunks_mut(img_size)) {
+        let opt_data = p.as_deref().and_then(|p| {
+            open(p)
+                .inspect_err(|err| tracing::error!("{err}"))
+                .ok()
+                .map(|data| (p, data))
+        });
+
+        if let Some((p, data)) = opt_data {
+            let name = p.display().to_string();
+            let mask = fit(&name, data, (w, h), strict)?;
+            mask.iter().zip(view).for_each(|(p, o)| *o = value(*p));
+        } else {
+            view.fill(!0);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Mask image at `path` as gray.
+///
+/// # Errors
+/// the image can't be read or decoded
+pub fn open(path: &Path) -> Result<image::GrayImage> {
+    image::open(path)
+        .map(|img| img.to_luma8())
+        .map_err(|source| Error::MaskLoad {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Rescales `mask` to `w` by `h` with nearest neighbor sampling, or fails if `strict`.
+///
+/// # Errors
+/// `mask` isn't `w` by `h` while `strict` is set
+pub fn fit(
+    name: &str,
+    mask: image::GrayImage,
+    (w, h): (u32, u32),
+    strict: bool,
+) -> Result<image::GrayImage> {
+    if mask.dimensions() == (w, h) {
+        return Ok(mask);
+    }
+
+    if strict {
+        DimErrorKind::Width.check(w as _, mask.width() as _)?;
+        DimErrorKind::Height.check(h as _, mask.height() as _)?;
+    }
+
+    tracing::warn!(
+        "mask {name} is {}x{}, rescaling to {w}x{h}",
+        mask.width(),
+        mask.height()
+    );
+    Ok(image::imageops::resize(
+        &mask,
+        w,
+        h,
+        image::imageops::FilterType::Nearest,
+    ))
+}
+
+#[inline]
+pub const fn value(p: u8) -> u32 {
+    if p >= 128 {
+        !0
+    } else {
+        0
+    }
+}
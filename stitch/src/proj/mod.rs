@@ -1,17 +1,91 @@
+use std::{path::PathBuf, time::Duration};
+
 use serde::{Deserialize, Serialize};
 
+#[cfg(any(feature = "gpu", feature = "cpu"))]
+mod font;
+#[cfg(any(feature = "gpu", feature = "cpu"))]
+mod gain;
+#[cfg(any(feature = "gpu", feature = "cpu"))]
+mod height;
+#[cfg(any(feature = "gpu", feature = "cpu"))]
+mod mask;
+#[cfg(feature = "cpu")]
+mod render_cpu;
+#[cfg(feature = "cpu")]
+pub use render_cpu::CpuProjector;
 #[cfg(feature = "gpu")]
 mod render_gpu;
 #[cfg(feature = "gpu")]
-pub use render_gpu::{GpuDirectBufferWrite, GpuProjector};
+pub use render_gpu::{GpuContext, GpuDirectBufferWrite, GpuProjector};
+#[cfg(any(feature = "gpu", feature = "cpu"))]
+mod watermark;
 
-use crate::camera;
 #[cfg(feature = "live")]
 use crate::camera::live;
+use crate::{buf::PixelFormat, camera, transform::Rotation};
+
+/// Name of the view a projector is built with, sized by its builder's `out_size`.
+pub const MAIN_VIEW: &str = "main";
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Config<C> {
     pub style: ProjectionStyle,
+    /// Shape of the ground the cameras are projected onto
+    #[serde(default)]
+    pub world: WorldStyle,
+    /// Heights of a [`WorldStyle::HeightField`] found from a pair of cameras while running
+    #[serde(default)]
+    pub stereo: Option<StereoConfig>,
+    /// Number of previous frames kept per camera for temporal passes
+    #[serde(default)]
+    pub frame_history: u32,
+    /// Reject masks that don't match the camera resolution instead of rescaling them
+    #[serde(default)]
+    pub strict_masks: bool,
+    /// Reload masks when their files change
+    #[serde(default)]
+    pub watch_masks: bool,
+    /// Generate masks from the projection geometry, keeping pixels that see the world within
+    /// this many degrees of straight on
+    #[serde(default)]
+    pub auto_mask_incidence: Option<f32>,
+    /// How overlapping cameras are combined along seams
+    #[serde(default)]
+    pub blend: SeamBlend,
+    /// How camera frames are filtered where the projection lands between their pixels
+    #[serde(default)]
+    pub sampling: Sampling,
+    /// Render the ground with 4 samples per pixel, smoothing its edges
+    #[serde(default)]
+    pub msaa: bool,
+    /// Exposure and curve bringing the blended colors into the output's range
+    #[serde(default)]
+    pub tone_map: ToneMap,
+    /// How the camera frames and outputs are encoded
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    /// Frames between exposure/color gain compensation updates, 0 disables it
+    #[serde(default)]
+    pub gain_interval: u32,
+    /// Raw camera feeds drawn over the output
+    #[serde(default)]
+    pub overlays: Vec<PipOverlay>,
+    /// Text burned into the output
+    #[serde(default)]
+    pub hud: HudConfig,
+    /// Text or picture marking every view, so footage saved from them can be traced
+    #[serde(default)]
+    pub watermark: Option<Watermark>,
+    /// Metric grid and compass rose drawn on the ground
+    #[serde(default)]
+    pub ground_overlay: GroundOverlay,
+    /// Parts of the ground watched for motion
+    #[serde(default)]
+    pub motion: MotionConfig,
+    /// Named viewpoints views can be switched to
+    #[serde(default)]
+    pub views: ViewsConfig,
     pub cameras: Vec<camera::Config<C>>,
 }
 
@@ -29,19 +103,887 @@ impl Config<live::Config> {
     }
 }
 
+impl<C> Config<C> {
+    /// See [`ConfigBuilder::new`].
+    pub fn builder(style: ProjectionStyle) -> ConfigBuilder<C> {
+        ConfigBuilder::new(style)
+    }
+}
+
+/// Builds a [`Config`] in code, starting from the same defaults as the config file.
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder<C>(Config<C>);
+
+impl<C> ConfigBuilder<C> {
+    /// No cameras and every optional setting left at its default.
+    pub fn new(style: ProjectionStyle) -> Self {
+        Self(Config {
+            style,
+            world: WorldStyle::default(),
+            stereo: None,
+            frame_history: 0,
+            strict_masks: false,
+            watch_masks: false,
+            auto_mask_incidence: None,
+            blend: SeamBlend::default(),
+            sampling: Sampling::default(),
+            msaa: false,
+            tone_map: ToneMap::default(),
+            color_space: ColorSpace::default(),
+            gain_interval: 0,
+            overlays: Vec::new(),
+            hud: HudConfig::default(),
+            watermark: None,
+            ground_overlay: GroundOverlay::default(),
+            motion: MotionConfig::default(),
+            views: ViewsConfig::default(),
+            cameras: Vec::new(),
+        })
+    }
+
+    pub fn world(mut self, world: WorldStyle) -> Self {
+        self.0.world = world;
+        self
+    }
+
+    pub fn stereo(mut self, stereo: StereoConfig) -> Self {
+        self.0.stereo = Some(stereo);
+        self
+    }
+
+    pub const fn frame_history(mut self, n: u32) -> Self {
+        self.0.frame_history = n;
+        self
+    }
+
+    pub const fn strict_masks(mut self, strict: bool) -> Self {
+        self.0.strict_masks = strict;
+        self
+    }
+
+    pub const fn watch_masks(mut self, watch: bool) -> Self {
+        self.0.watch_masks = watch;
+        self
+    }
+
+    /// See [`Config::auto_mask_incidence`].
+    pub const fn auto_mask_incidence(mut self, deg: f32) -> Self {
+        self.0.auto_mask_incidence = Some(deg);
+        self
+    }
+
+    pub const fn blend(mut self, blend: SeamBlend) -> Self {
+        self.0.blend = blend;
+        self
+    }
+
+    pub const fn sampling(mut self, sampling: Sampling) -> Self {
+        self.0.sampling = sampling;
+        self
+    }
+
+    pub const fn msaa(mut self, msaa: bool) -> Self {
+        self.0.msaa = msaa;
+        self
+    }
+
+    pub const fn tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.0.tone_map = tone_map;
+        self
+    }
+
+    pub const fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.0.color_space = color_space;
+        self
+    }
+
+    pub const fn gain_interval(mut self, n: u32) -> Self {
+        self.0.gain_interval = n;
+        self
+    }
+
+    pub fn overlay(mut self, overlay: PipOverlay) -> Self {
+        self.0.overlays.push(overlay);
+        self
+    }
+
+    pub fn hud(mut self, hud: HudConfig) -> Self {
+        self.0.hud = hud;
+        self
+    }
+
+    pub fn watermark(mut self, watermark: Watermark) -> Self {
+        self.0.watermark = Some(watermark);
+        self
+    }
+
+    pub fn ground_overlay(mut self, ground_overlay: GroundOverlay) -> Self {
+        self.0.ground_overlay = ground_overlay;
+        self
+    }
+
+    pub fn motion(mut self, motion: MotionConfig) -> Self {
+        self.0.motion = motion;
+        self
+    }
+
+    pub fn preset(mut self, preset: ViewPreset) -> Self {
+        self.0.views.presets.push(preset);
+        self
+    }
+
+    /// Adds the next camera, taking a [`camera::ConfigBuilder`] as is.
+    pub fn camera(mut self, cam: impl Into<camera::Config<C>>) -> Self {
+        self.0.cameras.push(cam.into());
+        self
+    }
+
+    pub fn build(self) -> Config<C> {
+        self.0
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorldStyle {
+    /// Level ground
+    #[default]
+    Flat,
+    /// Ground shaped by a grayscale heightmap spanning `size` world units centered on the origin,
+    /// with white `scale` units above black. Only used by [`ProjectionStyle::Hemisphere`].
+    HeightField {
+        path: PathBuf,
+        scale: f32,
+        #[serde(default = "WorldStyle::default_size")]
+        size: f32,
+    },
+}
+
+impl WorldStyle {
+    const fn default_size() -> f32 {
+        1000.
+    }
+}
+
+/// Depth from two overlapping cameras, moving each point of a [`WorldStyle::HeightField`] to the
+/// height where both cameras see the ground around it most alike. Near objects then stand up
+/// out of the ground instead of being smeared across it twice. Points the cameras can't match,
+/// where one doesn't see them or the ground is too plain, keep the heightmap's height.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StereoConfig {
+    /// Indices of the two cameras
+    pub cameras: [usize; 2],
+    /// Lowest and highest heights searched, in world units
+    #[serde(default = "StereoConfig::default_heights")]
+    pub heights: [f32; 2],
+    /// Heights tried between them
+    #[serde(default = "StereoConfig::default_steps")]
+    pub steps: u32,
+    /// Side of the square of ground compared between the cameras, in world units
+    #[serde(default = "StereoConfig::default_window")]
+    pub window: f32,
+    /// Frames between updates of the heights
+    #[serde(default = "StereoConfig::default_interval")]
+    pub interval: u32,
+}
+
+impl StereoConfig {
+    #[must_use]
+    pub const fn new(cameras: [usize; 2]) -> Self {
+        Self {
+            cameras,
+            heights: Self::default_heights(),
+            steps: Self::default_steps(),
+            window: Self::default_window(),
+            interval: Self::default_interval(),
+        }
+    }
+
+    const fn default_heights() -> [f32; 2] {
+        [0.0, 3.0]
+    }
+
+    const fn default_steps() -> u32 {
+        32
+    }
+
+    const fn default_window() -> f32 {
+        0.5
+    }
+
+    const fn default_interval() -> u32 {
+        1
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeamBlend {
+    /// Each pixel comes from the camera closest to looking straight at it
+    #[default]
+    Nearest,
+    /// Every camera that sees a pixel contributes, weighted by how centered it is and faded out
+    /// over `width` input pixels from the edges of its image
+    Feather { width: f32 },
+}
+
+impl SeamBlend {
+    /// Feather width passed to the shaders, where 0 selects the nearest camera.
+    #[must_use]
+    pub const fn width(self) -> f32 {
+        match self {
+            Self::Nearest => 0.0,
+            Self::Feather { width } => width,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sampling {
+    /// The camera pixel the projection lands in
+    #[default]
+    Nearest,
+    /// Linear blend of the 2x2 camera pixels around where the projection lands
+    Bilinear,
+    /// Catmull-Rom spline through the 4x4 camera pixels around where the projection lands,
+    /// sharper than bilinear when the output is zoomed in
+    Bicubic,
+}
+
+impl Sampling {
+    /// Filter passed to the shaders, where 0 is nearest, 1 bilinear and 2 bicubic.
+    #[must_use]
+    pub const fn id(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Rotation from a [`ProjectionStyle::SingleCamera`] view to its camera, in the camera's frame
+/// of x right, y forward and z up.
+#[cfg(any(feature = "gpu", feature = "cpu"))]
+fn dewarp_turn(yaw: f32, pitch: f32) -> glam::Mat3 {
+    glam::Mat3::from_rotation_z(-yaw.to_radians()) * glam::Mat3::from_rotation_x(pitch.to_radians())
+}
+
+/// Projection a [`ProjectionStyle::SingleCamera`] view de-warps its camera to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DewarpLens {
+    /// Pinhole view with straight lines kept straight, stretched towards the edges of wide ones
+    #[default]
+    Rectilinear,
+    /// Angles spread evenly across the width and straight up and down, for wide panoramas
+    Cylindrical,
+}
+
+impl DewarpLens {
+    /// Lens passed to the shaders, where 0 is rectilinear and 1 cylindrical.
+    #[must_use]
+    pub const fn id(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Pixel format of camera frames or rendered views, 4 channels either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameFormat {
+    /// 8 bit unsigned normalized channels
+    #[default]
+    Rgba8,
+    /// 16 bit float channels, for HDR frames with values past 1
+    Rgba16F,
+}
+
+impl FrameFormat {
+    #[must_use]
+    pub const fn bytes_per_chan(self) -> usize {
+        match self {
+            Self::Rgba8 => 1,
+            Self::Rgba16F => 2,
+        }
+    }
+}
+
+impl From<FrameFormat> for PixelFormat {
+    fn from(f: FrameFormat) -> Self {
+        match f {
+            FrameFormat::Rgba8 => Self::Rgba8,
+            FrameFormat::Rgba16F => Self::Rgba16F,
+        }
+    }
+}
+
+/// Work done on the frames of a camera by the GPU projector before they're projected, in the
+/// order of the fields, to take it off the CPU loading them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preprocess {
+    /// Upload YUYV and NV12 frames as the camera delivers them, converting them to RGBA on the
+    /// GPU instead of the capture thread
+    #[serde(default)]
+    pub decode: bool,
+    /// File of the pixel every pixel of the undistorted frame is taken from, as little endian
+    /// `f32` x and y for each pixel of the camera's frame, row by row
+    pub undistort: Option<PathBuf>,
+    #[serde(default)]
+    pub rotate: Rotation,
+    /// Mirror the frame left to right
+    #[serde(default)]
+    pub flip: bool,
+}
+
+impl Preprocess {
+    pub const NONE: Self = Self {
+        decode: false,
+        undistort: None,
+        rotate: Rotation::None,
+        flip: false,
+    };
+
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToneMap {
+    #[serde(default)]
+    pub curve: ToneCurve,
+    /// Stops the colors are brightened by before the curve, negative to darken them
+    #[serde(default)]
+    pub exposure: f32,
+}
+
+impl ToneMap {
+    /// Multiplier of the colors before the curve.
+    #[must_use]
+    pub fn scale(self) -> f32 {
+        self.exposure.exp2()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneCurve {
+    /// Colors are kept as they are, clipped by 8 bit outputs
+    #[default]
+    Clip,
+    /// `c / (1 + c)`, compressing highlights evenly
+    Reinhard,
+    /// Filmic curve fitted to ACES, with more contrast than Reinhard
+    Aces,
+}
+
+impl ToneCurve {
+    /// Curve passed to the shaders, where 0 clips, 1 is Reinhard and 2 ACES.
+    #[must_use]
+    pub const fn id(self) -> u32 {
+        self as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    /// sRGB encoded, like the frames of most cameras. Colors are linearized before they are
+    /// filtered, blended, gained and tone mapped, then encoded again for the output.
+    #[default]
+    Srgb,
+    /// Used as they are, for linear frames like most HDR ones
+    Linear,
+}
+
+impl ColorSpace {
+    /// Color space passed to the shaders, where 0 is sRGB and 1 linear.
+    #[must_use]
+    pub const fn id(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Sub-rectangle of a view's projection that is stretched over the whole output, in fractions
+/// of the output's width and height from its top left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewCrop {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Default for ViewCrop {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl ViewCrop {
+    /// The whole projection.
+    pub const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        w: 1.0,
+        h: 1.0,
+    };
+
+    /// Digital zoom by `zoom` times around `center`, given like the crop's corner.
+    #[must_use]
+    pub fn zoomed(center: [f32; 2], zoom: f32) -> Self {
+        let size = 1.0 / zoom.max(1.0);
+        Self {
+            x: center[0] - size / 2.0,
+            y: center[1] - size / 2.0,
+            w: size,
+            h: size,
+        }
+        .clamped()
+    }
+
+    /// Shrinks and moves the crop to fit inside the projection.
+    #[must_use]
+    pub fn clamped(self) -> Self {
+        let w = self.w.clamp(f32::EPSILON, 1.0);
+        let h = self.h.clamp(f32::EPSILON, 1.0);
+        Self {
+            x: self.x.clamp(0.0, 1.0 - w),
+            y: self.y.clamp(0.0, 1.0 - h),
+            w,
+            h,
+        }
+    }
+}
+
+/// Raw camera feed drawn over a view as a picture in picture.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PipOverlay {
+    /// Index of the camera to show
+    pub camera: u32,
+    /// [x, y, w, h] of the output it covers, in fractions from the top left corner
+    pub rect: [f32; 4],
+    /// Border width in output pixels
+    #[serde(default = "PipOverlay::default_border_width")]
+    pub border_width: f32,
+    /// RGBA border color with values in 0..=1
+    #[serde(default = "PipOverlay::default_border_color")]
+    pub border_color: [f32; 4],
+    /// Text drawn in the top left corner
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl PipOverlay {
+    const fn default_border_width() -> f32 {
+        2.0
+    }
+
+    const fn default_border_color() -> [f32; 4] {
+        [1.0; 4]
+    }
+}
+
+/// Text burned into a view's output.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HudLabel {
+    /// Printable ASCII, other characters show as `?` and newlines start a new line
+    pub text: String,
+    /// Top left corner in fractions of the output
+    pub pos: [f32; 2],
+    /// Output pixels per font pixel, glyphs are 8 font pixels square
+    #[serde(default = "HudLabel::default_scale")]
+    pub scale: u32,
+    /// RGBA text color with values in 0..=1
+    #[serde(default = "HudLabel::default_color")]
+    pub color: [f32; 4],
+    /// RGBA color behind the text, transparent by default
+    #[serde(default)]
+    pub background: [f32; 4],
+}
+
+impl HudLabel {
+    #[must_use]
+    pub fn new(text: impl Into<String>, pos: [f32; 2]) -> Self {
+        Self {
+            text: text.into(),
+            pos,
+            scale: Self::default_scale(),
+            color: Self::default_color(),
+            background: [0.0; 4],
+        }
+    }
+
+    const fn default_scale() -> u32 {
+        2
+    }
+
+    const fn default_color() -> [f32; 4] {
+        [1.0; 4]
+    }
+}
+
+/// Heads up display drawn over the output by the server.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HudConfig {
+    /// Show the time each frame was rendered
+    #[serde(default)]
+    pub timestamp: bool,
+    /// Show the rendering rate
+    #[serde(default)]
+    pub fps: bool,
+    #[serde(default)]
+    pub labels: Vec<HudLabel>,
+}
+
+/// Text and picture drawn over every other layer of a view, the picture at `pos` with the text
+/// right below it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Watermark {
+    /// Printable ASCII like a [`HudLabel`], with `{view}` replaced by the name of the view so
+    /// every client's view is marked apart
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Picture drawn by its alpha channel, PNG or any other format with one
+    #[serde(default)]
+    pub image: Option<PathBuf>,
+    /// Top left corner in fractions of the output
+    #[serde(default = "Watermark::default_pos")]
+    pub pos: [f32; 2],
+    /// Width of the picture in fractions of the output's width, keeping its aspect ratio
+    #[serde(default = "Watermark::default_width")]
+    pub width: f32,
+    /// Output pixels per font pixel of the text
+    #[serde(default = "Watermark::default_scale")]
+    pub scale: u32,
+    /// From 0 for invisible to 1 for opaque
+    #[serde(default = "Watermark::default_opacity")]
+    pub opacity: f32,
+}
+
+impl Watermark {
+    /// Only `text`, in the bottom left corner.
+    #[must_use]
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: Some(text.into()),
+            image: None,
+            pos: Self::default_pos(),
+            width: Self::default_width(),
+            scale: Self::default_scale(),
+            opacity: Self::default_opacity(),
+        }
+    }
+
+    const fn default_pos() -> [f32; 2] {
+        [0.02, 0.9]
+    }
+
+    const fn default_width() -> f32 {
+        0.15
+    }
+
+    const fn default_scale() -> u32 {
+        2
+    }
+
+    const fn default_opacity() -> f32 {
+        0.5
+    }
+}
+
+/// Metric grid and compass rose drawn on the ground of the projection styles that see it, in
+/// world units around the origin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GroundOverlay {
+    /// Drawn on the main view, client views start out the same and can toggle it themselves
+    #[serde(default)]
+    pub enabled: bool,
+    /// Distance between grid lines, 0 leaves the grid out
+    #[serde(default = "GroundOverlay::default_spacing")]
+    pub spacing: f32,
+    /// Distance from the origin the grid reaches
+    #[serde(default = "GroundOverlay::default_radius")]
+    pub radius: f32,
+    /// Radius of the compass rose around the origin, 0 leaves it out
+    #[serde(default = "GroundOverlay::default_compass")]
+    pub compass: f32,
+    /// Degrees clockwise from north that the world's +y axis points
+    #[serde(default)]
+    pub heading: f32,
+    /// RGBA color of the lines with values in 0..=1, the north arrow is always red
+    #[serde(default = "GroundOverlay::default_color")]
+    pub color: [f32; 4],
+}
+
+impl GroundOverlay {
+    const fn default_spacing() -> f32 {
+        1.0
+    }
+
+    const fn default_radius() -> f32 {
+        20.0
+    }
+
+    const fn default_compass() -> f32 {
+        2.0
+    }
+
+    const fn default_color() -> [f32; 4] {
+        [1.0, 1.0, 1.0, 0.5]
+    }
+}
+
+impl Default for GroundOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spacing: Self::default_spacing(),
+            radius: Self::default_radius(),
+            compass: Self::default_compass(),
+            heading: 0.0,
+            color: Self::default_color(),
+        }
+    }
+}
+
+/// Motion detection over zones of the ground, found by comparing the stitched brightness of
+/// a grid of points over each zone between consecutive frames.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MotionConfig {
+    /// Smallest change in a point's brightness, from 0 to 1 in the output, counted as motion
+    #[serde(default = "MotionConfig::default_threshold")]
+    pub threshold: f32,
+    /// Part of the points of a zone that have to change for it to be in motion
+    #[serde(default = "MotionConfig::default_min_area")]
+    pub min_area: f32,
+    /// Seconds a zone stays in motion after the last frame that had enough change
+    #[serde(default = "MotionConfig::default_hold")]
+    pub hold: f32,
+    /// Zones watched, none disables motion detection
+    #[serde(default)]
+    pub zones: Vec<MotionZone>,
+}
+
+impl MotionConfig {
+    const fn default_threshold() -> f32 {
+        0.1
+    }
+
+    const fn default_min_area() -> f32 {
+        0.02
+    }
+
+    const fn default_hold() -> f32 {
+        2.0
+    }
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Self::default_threshold(),
+            min_area: Self::default_min_area(),
+            hold: Self::default_hold(),
+            zones: Vec::new(),
+        }
+    }
+}
+
+/// Rectangle of the ground watched for motion, in world units.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MotionZone {
+    /// Name events about the zone are reported under
+    pub name: String,
+    /// [x, y] of one corner
+    pub min: [f32; 2],
+    /// [x, y] of the opposite corner
+    pub max: [f32; 2],
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ProjectionStyle {
     RawCamera(u8),
-    Hemisphere { pos: [f32; 3], radius: f32 },
+    Hemisphere {
+        pos: [f32; 3],
+        radius: f32,
+    },
+    /// Full 360x180 panorama seen from `pos`, projected onto the ground and a dome of `radius`
+    Equirect {
+        pos: [f32; 3],
+        radius: f32,
+    },
+    /// Six cube faces seen from `pos`, laid out in a 3x2 grid of square faces in the OpenGL
+    /// order +X, -X, +Y, -Y, +Z, -Z (y up). See [`ProjectionStyle::cube_face_size`].
+    CubeMap {
+        pos: [f32; 3],
+        radius: f32,
+    },
+    /// Frame of camera `index` on its own, de-warped to a `lens` view `fov` degrees wide that
+    /// is turned `yaw` degrees right and `pitch` degrees up from where the camera looks
+    SingleCamera {
+        index: u8,
+        #[serde(default)]
+        lens: DewarpLens,
+        fov: f32,
+        #[serde(default)]
+        yaw: f32,
+        #[serde(default)]
+        pitch: f32,
+    },
 }
 
 impl ProjectionStyle {
     #[must_use]
     pub const fn radius(self) -> f32 {
         match self {
-            Self::RawCamera(_) => 100.0,
-            Self::Hemisphere { radius, .. } => radius,
+            Self::RawCamera(_) | Self::SingleCamera { .. } => 100.0,
+            Self::Hemisphere { radius, .. }
+            | Self::Equirect { radius, .. }
+            | Self::CubeMap { radius, .. } => radius,
+        }
+    }
+
+    /// Style `t` of the way from this one to `to`, when both are the same kind of view, of the
+    /// same camera and lens if they're single camera ones. Otherwise there's nothing in between,
+    /// so it's `to`.
+    #[must_use]
+    pub fn lerp(self, to: Self, t: f32) -> Self {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        let mix3 = |a: [f32; 3], b: [f32; 3]| [mix(a[0], b[0]), mix(a[1], b[1]), mix(a[2], b[2])];
+        match (self, to) {
+            (Self::Hemisphere { pos: p, radius: r }, Self::Hemisphere { pos, radius }) => {
+                Self::Hemisphere {
+                    pos: mix3(p, pos),
+                    radius: mix(r, radius),
+                }
+            }
+            (Self::Equirect { pos: p, radius: r }, Self::Equirect { pos, radius }) => {
+                Self::Equirect {
+                    pos: mix3(p, pos),
+                    radius: mix(r, radius),
+                }
+            }
+            (Self::CubeMap { pos: p, radius: r }, Self::CubeMap { pos, radius }) => Self::CubeMap {
+                pos: mix3(p, pos),
+                radius: mix(r, radius),
+            },
+            (
+                Self::SingleCamera {
+                    index: i,
+                    lens: l,
+                    fov: f,
+                    yaw: y,
+                    pitch: p,
+                },
+                Self::SingleCamera {
+                    index,
+                    lens,
+                    fov,
+                    yaw,
+                    pitch,
+                },
+            ) if i == index && l == lens => Self::SingleCamera {
+                index,
+                lens,
+                fov: mix(f, fov),
+                yaw: mix(y, yaw),
+                pitch: mix(p, pitch),
+            },
+            _ => to,
+        }
+    }
+
+    /// Size of each face of a [`ProjectionStyle::CubeMap`] rendered to an output of this size,
+    /// any space left over to the right and bottom of the grid is transparent.
+    #[must_use]
+    pub const fn cube_face_size(out_w: usize, out_h: usize) -> usize {
+        let (w, h) = (out_w / 3, out_h / 2);
+        if w < h {
+            w
+        } else {
+            h
+        }
+    }
+}
+
+/// Settings of the views rendered from a [`Config`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ViewsConfig {
+    #[serde(default)]
+    pub presets: Vec<ViewPreset>,
+}
+
+impl ViewsConfig {
+    #[must_use]
+    pub fn preset(&self, name: &str) -> Option<&ViewPreset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+}
+
+/// Named viewpoint a view can be switched to instead of setting its style and crop by hand.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewPreset {
+    pub name: String,
+    pub style: ProjectionStyle,
+    /// Crop set along with the style, which keeps the view's crop unless set
+    #[serde(default)]
+    pub crop: Option<ViewCrop>,
+    /// Seconds the view eases into the preset over, or at once unless set
+    #[serde(default)]
+    pub transition: Option<f32>,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// How a [`ViewTransition`] speeds up and slows down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Part of the way a transition has moved once `t` of its time has passed.
+    #[must_use]
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2.0 - t),
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// Change of a view from one style to another over `duration` seconds, instead of all at once.
+/// See [`ProjectionStyle::lerp`] for the styles that can be moved between.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewTransition {
+    pub from: ProjectionStyle,
+    pub to: ProjectionStyle,
+    pub duration: f32,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+impl ViewTransition {
+    /// Style of the view `elapsed` into the transition, along with whether it's over.
+    #[must_use]
+    pub fn at(&self, elapsed: Duration) -> (ProjectionStyle, bool) {
+        let t = if self.duration > 0.0 {
+            elapsed.as_secs_f32() / self.duration
+        } else {
+            1.0
+        };
+        if t >= 1.0 {
+            return (self.to, true);
         }
+        (self.from.lerp(self.to, self.easing.apply(t)), false)
     }
 }
@@ -0,0 +1,1284 @@
+//! Projection without a GPU, following `render.wgsl`, `overlay.wgsl` and `hud.wgsl` pixel for
+//! pixel so both projectors give the same output within rounding.
+
+use std::{f32::consts::PI, ops::DerefMut, path::PathBuf};
+
+use glam::{IVec2, Mat3, UVec2, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rayon::prelude::*;
+
+#[cfg(feature = "live")]
+use crate::camera::{live, Config};
+use crate::{
+    buf::FrameSize,
+    camera::{Camera, Distortion, ViewParams},
+    loader::{Loader, Ticket},
+    DimErrorKind, Error, Result,
+};
+
+use super::{
+    font, gain, height::HeightGrid, mask, watermark, ColorSpace, DewarpLens, HudLabel, PipOverlay,
+    ProjectionStyle, Sampling, SeamBlend, ToneCurve, ToneMap, ViewCrop, Watermark, WorldStyle,
+    MAIN_VIEW,
+};
+
+/// Half the side of the square a flat world is drawn on, like the GPU's flat bound mesh.
+const FLAT_BOUND: f32 = 500.;
+
+/// Height the hemisphere view looks down from, with what it sees clipped to between `NEAR` and
+/// `FAR` below it like the GPU's orthographic projection.
+const EYE_HEIGHT: f32 = 100.;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 200.;
+
+/// Samples per side of the grid used to gather gain compensation stats, as on the GPU.
+const GAIN_GRID: usize = 64;
+
+/// Most picture in picture overlays on a view, as on the GPU.
+const MAX_PIPS: usize = 8;
+
+/// Most characters of text on a view, as on the GPU.
+const MAX_HUD_CHARS: usize = 1024;
+
+/// Alpha bits of a packed pixel, 0 where a camera sees nothing.
+const ALPHA: u32 = 0xff00_0000;
+
+pub struct CpuProjector {
+    inp_size: (u32, u32, u32),
+    /// Latest RGBA frame of every camera
+    inp_frames: Vec<Box<[u8]>>,
+    inp_specs: Vec<InputSpec>,
+    /// Whether each camera is loaded and rendered, see [`CpuProjector::set_camera_enabled`].
+    cam_enabled: Vec<bool>,
+    inp_masks: Box<[u32]>,
+    inp_gains: Vec<Vec4>,
+    strict_masks: bool,
+    blend_width: f32,
+    sampling: Sampling,
+    tone_map: ToneMap,
+    color_space: ColorSpace,
+    gain_interval: u32,
+    frame_count: u32,
+    world: Option<(HeightGrid, Vec<f32>)>,
+    views: Vec<CpuView>,
+}
+
+/// Output rendered by [`CpuProjector::update_render`], with its own size and projection style.
+struct CpuView {
+    name: String,
+    size: (usize, usize),
+    style: Option<ProjectionStyle>,
+    crop: ViewCrop,
+    overlays: Vec<PipOverlay>,
+    hud_labels: Vec<HudLabel>,
+    pip_labels: Vec<HudLabel>,
+    watermark: Option<Watermark>,
+    watermark_image: Option<image::RgbaImage>,
+    out: Box<[u8]>,
+}
+
+impl CpuView {
+    fn new(name: String, size: (usize, usize), style: Option<ProjectionStyle>) -> Self {
+        Self {
+            name,
+            size,
+            style,
+            crop: ViewCrop::FULL,
+            overlays: Vec::new(),
+            hud_labels: Vec::new(),
+            pip_labels: Vec::new(),
+            watermark: None,
+            watermark_image: None,
+            out: vec![0; size.0 * size.1 * 4].into_boxed_slice(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct InputSpec {
+    pos: Vec3,
+    rev_mat: Mat3,
+    img_off: Vec2,
+    /// Focal distance, relative to diagonal radius of 1
+    foc_dist: f32,
+    lens_type: u32,
+    distortion: Distortion,
+}
+
+impl TryFrom<ViewParams> for InputSpec {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(s: ViewParams) -> Result<Self> {
+        Ok(Self {
+            pos: s.pos.into(),
+            rev_mat: Mat3::from_euler(glam::EulerRot::ZXY, s.azimuth, s.pitch, s.roll),
+            img_off: s.sensor.img_off.into(),
+            foc_dist: s
+                .sensor
+                .fov
+                .assume_focal_dist()
+                .ok_or(Error::FocalDistanceUnset)?,
+            lens_type: s.lens as _,
+            distortion: s.sensor.distortion,
+        })
+    }
+}
+
+/// What the shaders read from the pass info of the view being rendered.
+#[derive(Clone, Copy, Debug)]
+struct Pass {
+    out_size: UVec2,
+    crop: Vec4,
+    view_pos: Vec3,
+    bound_radius: f32,
+}
+
+/// Quad covering part of a view's output, in output pixels.
+#[derive(Clone, Copy, Debug)]
+struct Quad {
+    min: Vec2,
+    size: Vec2,
+}
+
+impl Quad {
+    /// From `[x, y, w, h]` in fractions of an output `size`.
+    fn new(rect: Vec4, size: Vec2) -> Self {
+        Self {
+            min: rect.xy() * size,
+            size: rect.zw() * size,
+        }
+    }
+
+    /// Where the center of pixel `x`, `y` is in the quad from 0 to 1, if it's covered.
+    #[allow(clippy::cast_precision_loss)]
+    fn uv(self, x: usize, y: usize) -> Option<Vec2> {
+        let uv = (Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - self.min) / self.size;
+        (uv.cmpge(Vec2::ZERO).all() && uv.cmplt(Vec2::ONE).all()).then_some(uv)
+    }
+}
+
+/// Character of a HUD label, see `hud.wgsl`.
+#[derive(Clone, Copy, Debug)]
+struct HudChar {
+    quad: Quad,
+    color: Vec4,
+    background: Vec4,
+    glyph: u32,
+}
+
+#[derive(Clone)]
+pub struct CpuProjectorBuilder {
+    out_size: (usize, usize),
+    input_size: (u32, u32, u32),
+    world: WorldStyle,
+    mask_paths: Vec<Option<PathBuf>>,
+    strict_masks: bool,
+    blend: SeamBlend,
+    sampling: Sampling,
+    tone_map: ToneMap,
+    color_space: ColorSpace,
+    gain_interval: u32,
+}
+
+impl CpuProjectorBuilder {
+    pub const fn input_size(mut self, w: u32, h: u32, n: u32) -> Self {
+        self.input_size = (w, h, n);
+        self
+    }
+
+    pub const fn out_size(mut self, w: usize, h: usize) -> Self {
+        self.out_size = (w, h);
+        self
+    }
+
+    /// Replaces the flat ground with the world's ground when it isn't flat.
+    pub fn world(mut self, world: WorldStyle) -> Self {
+        self.world = world;
+        self
+    }
+
+    #[cfg(feature = "live")]
+    pub fn masks_from_cfgs(self, cfgs: &[Config<live::Config>]) -> Self {
+        self.mask_paths(cfgs.iter().map(|c| c.meta.mask_path.clone()).collect())
+    }
+
+    /// Mask file of every camera, `None` for cameras that see everything.
+    pub fn mask_paths(mut self, paths: Vec<Option<PathBuf>>) -> Self {
+        self.mask_paths = paths;
+        self
+    }
+
+    /// Fail to build if a mask doesn't match the input size, instead of rescaling it.
+    pub const fn strict_masks(mut self, strict: bool) -> Self {
+        self.strict_masks = strict;
+        self
+    }
+
+    pub const fn blend(mut self, blend: SeamBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub const fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub const fn tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    pub const fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Re-solve exposure/color gains between overlapping cameras every `n` frames, 0 disables it.
+    pub const fn gain_interval(mut self, n: u32) -> Self {
+        self.gain_interval = n;
+        self
+    }
+
+    /// # Errors
+    /// a mask doesn't match the input size while [`Self::strict_masks`] is set, or the world's
+    /// heightmap can't be loaded
+    pub fn build(mut self) -> Result<CpuProjector> {
+        let (w, h, n) = self.input_size;
+        self.mask_paths.resize(n as usize, None);
+        let world = match &self.world {
+            WorldStyle::Flat => None,
+            WorldStyle::HeightField { path, scale, size } => {
+                Some(HeightGrid::load(path, *scale, *size)?)
+            }
+        };
+
+        let mut proj = CpuProjector {
+            inp_size: self.input_size,
+            inp_frames: vec![vec![0; (w * h * 4) as usize].into_boxed_slice(); n as usize],
+            inp_specs: Vec::new(),
+            cam_enabled: vec![true; n as usize],
+            inp_masks: mask::load_all(&self.mask_paths, self.input_size, self.strict_masks)?,
+            inp_gains: vec![Vec4::ONE; n as usize],
+            strict_masks: self.strict_masks,
+            blend_width: self.blend.width(),
+            sampling: self.sampling,
+            tone_map: self.tone_map,
+            color_space: self.color_space,
+            gain_interval: self.gain_interval,
+            frame_count: 0,
+            world,
+            views: Vec::new(),
+        };
+        proj.views
+            .push(CpuView::new(MAIN_VIEW.to_string(), self.out_size, None));
+        Ok(proj)
+    }
+}
+
+impl CpuProjector {
+    #[must_use]
+    pub fn builder() -> CpuProjectorBuilder {
+        CpuProjectorBuilder {
+            out_size: (0, 0),
+            input_size: (0, 0, 0),
+            world: WorldStyle::Flat,
+            mask_paths: Vec::new(),
+            strict_masks: false,
+            blend: SeamBlend::Nearest,
+            sampling: Sampling::Nearest,
+            tone_map: ToneMap::default(),
+            color_space: ColorSpace::Srgb,
+            gain_interval: 0,
+        }
+    }
+
+    /// Sets the projection style of the main view.
+    #[inline]
+    pub fn update_proj_view(&mut self, style: ProjectionStyle) {
+        self.views[0].style = Some(style);
+    }
+
+    /// Adds an output view of `w` by `h` pixels, rendered alongside the main view every
+    /// [`Self::update_render`] and read with [`Self::copy_view_to`].
+    ///
+    /// # Panics
+    /// there is already a view called `name`
+    pub fn add_view(
+        &mut self,
+        name: impl Into<String>,
+        w: usize,
+        h: usize,
+        style: ProjectionStyle,
+    ) {
+        let name = name.into();
+        assert!(
+            self.views.iter().all(|v| v.name != name),
+            "view {name} already exists"
+        );
+
+        self.views.push(CpuView::new(name, (w, h), Some(style)));
+    }
+
+    /// Removes the view called `name`, doing nothing if there is none.
+    ///
+    /// # Panics
+    /// `name` is the main view
+    pub fn remove_view(&mut self, name: &str) {
+        assert!(name != MAIN_VIEW, "the main view can't be removed");
+        self.views.retain(|v| v.name != name);
+    }
+
+    /// Names of every output view, starting with the main view.
+    pub fn view_names(&self) -> impl Iterator<Item = &str> {
+        self.views.iter().map(|v| v.name.as_str())
+    }
+
+    /// Width and height of the view called `name`, if there is one.
+    #[must_use]
+    pub fn view_size(&self, name: &str) -> Option<(usize, usize)> {
+        self.views.iter().find(|v| v.name == name).map(|v| v.size)
+    }
+
+    /// Renders the view called `name` at `w` by `h` from the next frame on, keeping its style,
+    /// crop, overlays and HUD.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn resize_view(&mut self, name: &str, w: usize, h: usize) {
+        let view = self.view_mut(name);
+        view.size = (w, h);
+        view.out = vec![0; w * h * 4].into_boxed_slice();
+        // the labels of the overlays are placed in pixels
+        let overlays = std::mem::take(&mut view.overlays);
+        self.update_view_overlays(name, &overlays);
+    }
+
+    /// # Panics
+    /// there is no view called `name`
+    #[inline]
+    pub fn update_view_style(&mut self, name: &str, style: ProjectionStyle) {
+        self.view_mut(name).style = Some(style);
+    }
+
+    /// Zooms the view called `name` into `crop` of its projection, keeping its resolution.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    #[inline]
+    pub fn update_view_crop(&mut self, name: &str, crop: ViewCrop) {
+        self.view_mut(name).crop = crop.clamped();
+    }
+
+    /// Draws `overlays` over the view called `name`, replacing any it had. Overlays past the
+    /// first 8 or of cameras that don't exist are left out.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn update_view_overlays(&mut self, name: &str, overlays: &[PipOverlay]) {
+        let cams = self.inp_size.2;
+        let view = self.view_mut(name);
+        if overlays.len() > MAX_PIPS {
+            tracing::warn!("view {name} only draws the first {MAX_PIPS} overlays");
+        }
+
+        view.overlays = overlays
+            .iter()
+            .filter(|o| {
+                let exists = o.camera < cams;
+                if !exists {
+                    tracing::warn!("view {name} has an overlay of missing camera {}", o.camera);
+                }
+                exists
+            })
+            .take(MAX_PIPS)
+            .cloned()
+            .collect();
+
+        #[allow(clippy::cast_precision_loss)]
+        let (w, h) = (view.size.0 as f32, view.size.1 as f32);
+        view.pip_labels = view
+            .overlays
+            .iter()
+            .filter_map(|o| {
+                let [x, y, ..] = o.rect;
+                let inset = o.border_width + 2.0;
+                let mut label = HudLabel::new(o.label.clone()?, [x + inset / w, y + inset / h]);
+                label.background = [0.0, 0.0, 0.0, 0.6];
+                Some(label)
+            })
+            .collect();
+    }
+
+    /// Burns `labels` into the view called `name`, replacing any it had. Characters past the
+    /// first 1024 are left out.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn update_view_hud(&mut self, name: &str, labels: &[HudLabel]) {
+        self.view_mut(name).hud_labels = labels.to_vec();
+    }
+
+    /// Marks the view called `name` with `watermark` over everything else drawn on it, or
+    /// removes its watermark.
+    ///
+    /// # Errors
+    /// the watermark's picture can't be loaded, leaving the view as it was
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn update_view_watermark(
+        &mut self,
+        name: &str,
+        watermark: Option<&Watermark>,
+    ) -> Result<()> {
+        let image = watermark.map(watermark::open).transpose()?.flatten();
+        let view = self.view_mut(name);
+        view.watermark = watermark.cloned();
+        view.watermark_image = image;
+        Ok(())
+    }
+
+    fn view(&self, name: &str) -> &CpuView {
+        self.views
+            .iter()
+            .find(|v| v.name == name)
+            .unwrap_or_else(|| panic!("no view called {name}"))
+    }
+
+    fn view_mut(&mut self, name: &str) -> &mut CpuView {
+        self.views
+            .iter_mut()
+            .find(|v| v.name == name)
+            .unwrap_or_else(|| panic!("no view called {name}"))
+    }
+
+    /// Sets where every camera is and how it sees, keeping the previous specs if any can't
+    /// be used.
+    ///
+    /// # Errors
+    /// a camera's focal distance isn't set
+    #[inline]
+    pub fn update_cam_specs<T>(&mut self, cams: &[Camera<T>]) -> Result<()> {
+        self.inp_specs = cams
+            .iter()
+            .map(|c| c.view.try_into())
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Leaves camera `idx` out of every view and stops loading its frames while disabled,
+    /// so a failed camera doesn't take the others down with it.
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras
+    pub fn set_camera_enabled(&mut self, idx: usize, enabled: bool) {
+        self.cam_enabled[idx] = enabled;
+    }
+
+    /// Changes how camera frames are filtered from the next [`Self::update_render`].
+    pub fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    /// Changes the exposure and curve of the views from the next [`Self::update_render`].
+    pub fn set_tone_map(&mut self, tone_map: ToneMap) {
+        self.tone_map = tone_map;
+    }
+
+    /// Changes how the frames and views are encoded from the next [`Self::update_render`].
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+    }
+
+    /// Replaces the RGBA frame of camera `idx`.
+    ///
+    /// # Errors
+    /// `frame` isn't the input size
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras
+    pub fn update_input(&mut self, idx: usize, frame: &[u8]) -> Result<()> {
+        let buf = &mut self.inp_frames[idx];
+        DimErrorKind::Bytes.check(buf.len(), frame.len())?;
+        buf.copy_from_slice(frame);
+        Ok(())
+    }
+
+    /// Captures the next frame of every enabled camera, waiting for all of them.
+    ///
+    /// # Errors
+    /// a camera's frames aren't the input size, or it failed to capture
+    pub fn block_load_inputs(&mut self, cams: &[Camera<Loader<Box<[u8]>>>]) -> Result<()> {
+        let len = self.inp_frames[0].len();
+        let res = cams
+            .iter()
+            .zip(&mut self.inp_frames)
+            .zip(&self.cam_enabled)
+            .map(|((c, buf), &enabled)| {
+                if !enabled {
+                    return Ok(None);
+                }
+                DimErrorKind::Bytes.check(len, c.data.num_bytes())?;
+                c.data.give(std::mem::take(buf)).map(Some)
+            })
+            .collect::<Result<Vec<_>>>()
+            .and_then(|tickets| {
+                tickets
+                    .into_iter()
+                    .zip(&mut self.inp_frames)
+                    .try_for_each(|(ticket, buf)| {
+                        if let Some(ticket) = ticket {
+                            *buf = Ticket::block_take(ticket)?;
+                        }
+                        Ok(())
+                    })
+            });
+
+        // frames lost to a failed camera start over as black
+        for buf in &mut self.inp_frames {
+            if buf.is_empty() {
+                *buf = vec![0; len].into_boxed_slice();
+            }
+        }
+        res
+    }
+
+    /// Replaces the mask of camera `idx`, rescaled like the masks given when building.
+    ///
+    /// # Errors
+    /// the mask doesn't match the input size while strict masks are set
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras
+    pub fn update_mask(&mut self, idx: usize, mask: image::GrayImage) -> Result<()> {
+        let (w, h, n) = self.inp_size;
+        assert!(idx < n as usize, "no camera {idx} to update the mask of");
+
+        let mask = mask::fit(
+            &format!("for camera {idx}"),
+            mask,
+            (w, h),
+            self.strict_masks,
+        )?;
+        let img_size = (w * h) as usize;
+        self.inp_masks[idx * img_size..][..img_size]
+            .iter_mut()
+            .zip(mask.iter())
+            .for_each(|(o, p)| *o = mask::value(*p));
+        Ok(())
+    }
+
+    /// Replaces every camera's mask with the pixels that see the ground or dome of the main
+    /// view's projection style at less than `max_incidence` radians from straight on, so it
+    /// should be called after [`Self::update_cam_specs`] and [`Self::update_proj_view`].
+    pub fn auto_masks(&mut self, max_incidence: f32) {
+        let (w, h, _) = self.inp_size;
+        let size = UVec2::new(w, h);
+        let pass = self.pass(&self.views[0]);
+        let min_cos = max_incidence.cos();
+
+        let specs = &self.inp_specs;
+        self.inp_masks
+            .par_chunks_mut(w as usize)
+            .enumerate()
+            .for_each(|(row, out)| {
+                let spec = specs[row / h as usize];
+                #[allow(clippy::cast_precision_loss)]
+                let y = (row % h as usize) as f32;
+                for (x, o) in out.iter_mut().enumerate() {
+                    #[allow(clippy::cast_precision_loss)]
+                    let c = Vec2::new(x as f32, y) + 0.5 - spec.img_off;
+                    let d = world_from_img(&spec, img_from_coord(c, size));
+
+                    // the world is centered under the view, where the ground's normal is up and
+                    // the dome's points back at the center
+                    let hit = dome_hit(
+                        spec.pos - pass.view_pos.xy().extend(0.0),
+                        d,
+                        pass.bound_radius,
+                    );
+                    let mut incidence = d.dot(hit.normalize());
+                    if hit.z < 1e-3 {
+                        incidence = -d.z;
+                    }
+                    *o = if incidence >= min_cos { !0 } else { 0 };
+                }
+            });
+    }
+
+    /// Width and height of the grid [`Self::update_world_heights`] expects, which is the world's
+    /// heightmap after any downscaling.
+    #[must_use]
+    pub fn world_grid_size(&self) -> Option<(u32, u32)> {
+        self.world.as_ref().map(|(g, _)| (g.w, g.h))
+    }
+
+    /// Moves the ground to `heights`, given row by row from north to south over the grid in
+    /// [`Self::world_grid_size`].
+    ///
+    /// # Errors
+    /// the world isn't a [`WorldStyle::HeightField`], or `heights` doesn't cover the
+    /// heightmap's grid
+    pub fn update_world_heights(&mut self, heights: &[f32]) -> Result<()> {
+        let (grid, current) = self.world.as_mut().ok_or(Error::NotHeightField)?;
+        DimErrorKind::Heights.check((grid.w * grid.h) as _, heights.len())?;
+        current.copy_from_slice(heights);
+        Ok(())
+    }
+
+    /// Renders every view from the current input frames.
+    pub fn update_render(&mut self) {
+        let frame = self.frame_count;
+        self.frame_count = frame.wrapping_add(1);
+        let stats = (self.gain_interval > 0 && frame.is_multiple_of(self.gain_interval))
+            .then(|| self.gain_stats());
+
+        for i in 0..self.views.len() {
+            let mut out = std::mem::take(&mut self.views[i].out);
+            self.render_view(&self.views[i], &mut out);
+            self.views[i].out = out;
+        }
+
+        // like on the GPU, new gains apply from the next frame
+        if let Some(stats) = stats {
+            self.inp_gains = gain::solve(&stats, self.inp_size.2 as usize);
+        }
+    }
+
+    /// Copies out the main view.
+    #[inline]
+    pub fn copy_render_to<T: DerefMut<Target = [u8]> + FrameSize>(&self, buf: &mut T) {
+        buf.copy_from_slice(&self.views[0].out);
+    }
+
+    /// Copies out the view called `name`.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    #[inline]
+    pub fn copy_view_to<T: DerefMut<Target = [u8]> + FrameSize>(&self, name: &str, buf: &mut T) {
+        buf.copy_from_slice(&self.view(name).out);
+    }
+
+    /// RGBA output of the view called `name` from the last [`Self::update_render`].
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    #[must_use]
+    pub fn view_output(&self, name: &str) -> &[u8] {
+        &self.view(name).out
+    }
+
+    fn pass(&self, view: &CpuView) -> Pass {
+        let ViewCrop { x, y, w, h } = view.crop;
+        let (view_pos, bound_radius) = match view.style {
+            Some(
+                ProjectionStyle::Hemisphere { pos, radius }
+                | ProjectionStyle::Equirect { pos, radius }
+                | ProjectionStyle::CubeMap { pos, radius },
+            ) => (pos.into(), radius),
+            Some(ProjectionStyle::RawCamera(..) | ProjectionStyle::SingleCamera { .. }) | None => {
+                (Vec3::ZERO, 0.0)
+            }
+        };
+
+        #[allow(clippy::cast_possible_truncation)]
+        Pass {
+            out_size: UVec2::new(view.size.0 as _, view.size.1 as _),
+            crop: Vec4::new(x, y, w, h),
+            view_pos,
+            bound_radius,
+        }
+    }
+
+    /// Projection, then overlays, then the HUD, then the watermark, like the passes of a GPU
+    /// view.
+    fn render_view(&self, view: &CpuView, out: &mut [u8]) {
+        let pass = self.pass(view);
+        let (w, _) = view.size;
+        #[allow(clippy::cast_precision_loss)]
+        let size = Vec2::new(view.size.0 as f32, view.size.1 as f32);
+
+        let pips = view
+            .overlays
+            .iter()
+            .map(|o| (Quad::new(o.rect.into(), size), o))
+            .collect::<Vec<_>>();
+        let placed = view.watermark.as_ref().map(|wm| {
+            let img = view
+                .watermark_image
+                .as_ref()
+                .map(image::RgbaImage::dimensions);
+            watermark::place(wm, &view.name, img, pass.out_size.into())
+        });
+        let mark = placed
+            .as_ref()
+            .zip(view.watermark_image.as_ref())
+            .map(|(p, img)| (Quad::new(p.rect.into(), size), p.opacity, img));
+        let watermark_labels = placed.as_ref().and_then(|p| p.label.as_ref());
+        let chars = hud_chars(
+            view.pip_labels
+                .iter()
+                .chain(&view.hud_labels)
+                .chain(watermark_labels),
+            size,
+        );
+        if chars.len() > MAX_HUD_CHARS {
+            tracing::warn!(
+                "view {} only draws the first {MAX_HUD_CHARS} HUD characters",
+                view.name
+            );
+        }
+        let chars = &chars[..chars.len().min(MAX_HUD_CHARS)];
+
+        out.par_chunks_mut(w * 4)
+            .enumerate()
+            .for_each_init(Vec::new, |opts, (y, row)| {
+                for (x, px) in row.chunks_exact_mut(4).enumerate() {
+                    let mut p = self.tone_mapped(self.shade(&pass, view.style, x, y, opts));
+                    if let Some(pip) = pips
+                        .iter()
+                        .rev()
+                        .find_map(|(q, o)| Some((q.uv(x, y)?, q, o)))
+                    {
+                        p = self.shade_pip(pip.0, pip.1.size, pip.2);
+                    }
+
+                    let mut c = unpack(p);
+                    for ch in chars {
+                        if let Some(src) = ch.quad.uv(x, y).and_then(|uv| shade_char(ch, uv)) {
+                            c = blend_over(src, c);
+                        }
+                    }
+                    if let Some((quad, opacity, img)) = mark {
+                        if let Some(uv) = quad.uv(x, y) {
+                            c = blend_over(shade_watermark(img, uv, opacity), c);
+                        }
+                    }
+                    px.copy_from_slice(&pack(c).to_le_bytes());
+                }
+            });
+    }
+
+    /// Projected pixel at `x`, `y` of a view, transparent where nothing is drawn.
+    #[allow(clippy::cast_precision_loss)]
+    fn shade(
+        &self,
+        pass: &Pass,
+        style: Option<ProjectionStyle>,
+        x: usize,
+        y: usize,
+        opts: &mut Vec<Vec2>,
+    ) -> u32 {
+        let frag = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+        let size = pass.out_size.as_vec2();
+        let pos = (pass.crop.xy() + frag / size * pass.crop.zw()) * size;
+
+        match style {
+            Some(ProjectionStyle::Hemisphere {
+                pos: [cx, cy, _],
+                radius,
+            }) => {
+                let uv = pos / size;
+                let ndc = Vec2::new(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+                let aspect = size.x / size.y;
+                let wx = (ndc.x * radius).mul_add(aspect, cx);
+                let wy = ndc.y.mul_add(radius, cy);
+
+                let Some(z) = self.ground_height(wx, wy) else {
+                    return 0;
+                };
+                if !(EYE_HEIGHT - FAR..=EYE_HEIGHT - NEAR).contains(&z) {
+                    return 0;
+                }
+                self.back_proj(Vec3::new(wx, wy, z), opts)
+            }
+            Some(ProjectionStyle::Equirect { .. }) => {
+                let uv = pos / size;
+                let lon = uv.x.mul_add(2.0, -1.0) * PI;
+                let lat = (0.5 - uv.y) * PI;
+                let dir = Vec3::new(lat.cos() * lon.sin(), lat.cos() * lon.cos(), lat.sin());
+
+                let bound = dome_hit(pass.view_pos, dir, pass.bound_radius);
+                self.back_proj(bound, opts)
+            }
+            Some(ProjectionStyle::CubeMap { .. }) => {
+                let face_size = (pass.out_size.x / 3).min(pass.out_size.y / 2) as f32;
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let cell = (pos / face_size).as_uvec2();
+                if cell.x >= 3 || cell.y >= 2 {
+                    return 0;
+                }
+
+                let st = (pos / face_size - cell.as_vec2()) * 2.0 - 1.0;
+                let gl_dir = cube_dir(cell.x + cell.y * 3, st);
+                let dir = Vec3::new(gl_dir.x, -gl_dir.z, gl_dir.y).normalize();
+
+                let bound = dome_hit(pass.view_pos, dir, pass.bound_radius);
+                self.back_proj(bound, opts)
+            }
+            // the whole frame of one camera, like a picture in picture covering the output
+            Some(ProjectionStyle::RawCamera(n)) if u32::from(n) < self.inp_size.2 => {
+                self.raw_pixel(u32::from(n), pos / size)
+            }
+            Some(ProjectionStyle::SingleCamera {
+                index,
+                lens,
+                fov,
+                yaw,
+                pitch,
+            }) if usize::from(index) < self.inp_specs.len() => {
+                let ndc = (pos / size * 2.0 - 1.0) * Vec2::new(1.0, -size.y / size.x);
+                let half = fov.to_radians() / 2.0;
+
+                // in the camera's frame of x right, y forward and z up
+                let d = match lens {
+                    DewarpLens::Rectilinear => (ndc * half.tan()).extend(1.0).xzy(),
+                    DewarpLens::Cylindrical => {
+                        let lon = ndc.x * half;
+                        Vec3::new(lon.sin(), lon.cos(), ndc.y * half)
+                    }
+                };
+                let ds = super::dewarp_turn(yaw, pitch) * d.normalize();
+                self.opt_input_pixel(usize::from(index), opt_from_dir(ds))
+            }
+            Some(ProjectionStyle::RawCamera(_) | ProjectionStyle::SingleCamera { .. }) | None => 0,
+        }
+    }
+
+    /// Ground under world `x`, `y`, if the world covers it.
+    fn ground_height(&self, x: f32, y: f32) -> Option<f32> {
+        match &self.world {
+            None => (x.abs() <= FLAT_BOUND && y.abs() <= FLAT_BOUND).then_some(0.0),
+            Some((grid, heights)) => grid.height_at(heights, x, y),
+        }
+    }
+
+    /// Input pixel the most centered camera that sees `bound` has of it.
+    fn back_proj(&self, bound: Vec3, opts: &mut Vec<Vec2>) -> u32 {
+        if self.blend_width > 0.0 {
+            return self.blend_proj(bound);
+        }
+
+        opts.clear();
+        opts.extend(self.inp_specs.iter().map(|s| opt_from_world(s, bound)));
+
+        let mut min_opt = 0.0;
+        for _ in 0..opts.len() {
+            let mut best_index = 0;
+            let mut best = opts[0];
+            for (n, opt) in opts.iter().enumerate().skip(1) {
+                if opt.x < best.x && opt.x > min_opt {
+                    best = *opt;
+                    best_index = n;
+                }
+            }
+
+            let p = self.opt_input_pixel(best_index, best);
+            if p & ALPHA != 0 {
+                return self.gained(best_index, p);
+            }
+
+            min_opt = best.x;
+        }
+
+        0
+    }
+
+    /// Weighted average of every camera that sees `bound`, favoring the most centered ones and
+    /// fading each out over the blend width from the edges of its image.
+    fn blend_proj(&self, bound: Vec3) -> u32 {
+        let (w, h, _) = self.inp_size;
+        let inp_size = UVec2::new(w, h);
+        let inp = inp_size.as_vec2();
+
+        let mut sum = Vec3::ZERO;
+        let mut total = 0.0;
+        for (n, spec) in self.inp_specs.iter().enumerate() {
+            let os = opt_from_world(spec, bound);
+
+            let img_pos = coord_from_img(img_from_opt(spec, os), inp_size) + spec.img_off;
+            if img_pos.cmplt(Vec2::ZERO).any() || img_pos.cmpge(inp).any() {
+                continue;
+            }
+
+            let p = self.sample_input(n, img_pos);
+            if p & ALPHA == 0 {
+                continue;
+            }
+
+            let edge = img_pos
+                .x
+                .min(img_pos.y)
+                .min((inp.x - img_pos.x).min(inp.y - img_pos.y));
+            let weight = (edge / self.blend_width).clamp(1e-3, 1.0) * (PI - os.x);
+            sum += self.linear(p).xyz() * self.inp_gains[n].xyz() * weight;
+            total += weight;
+        }
+
+        if total <= 0.0 {
+            return 0;
+        }
+
+        self.encoded((sum / total).extend(1.0))
+    }
+
+    /// Applies the exposure/color compensation solved for camera `n`.
+    fn gained(&self, n: usize, p: u32) -> u32 {
+        let c = self.linear(p);
+        self.encoded((c.xyz() * self.inp_gains[n].xyz()).extend(c.w))
+    }
+
+    fn opt_input_pixel(&self, n: usize, os: Vec2) -> u32 {
+        let (w, h, _) = self.inp_size;
+        let inp_size = UVec2::new(w, h);
+        let spec = &self.inp_specs[n];
+
+        let img_pos = coord_from_img(img_from_opt(spec, os), inp_size) + spec.img_off;
+        if img_pos.cmplt(Vec2::ZERO).any() || img_pos.cmpge(inp_size.as_vec2()).any() {
+            return 0;
+        }
+
+        self.sample_input(n, img_pos)
+    }
+
+    /// Pixel of camera `n` at `img_pos`, filtered like the shader. Only the colors are
+    /// filtered, so the camera sees exactly the parts of the world it does with nearest sampling.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn sample_input(&self, n: usize, img_pos: Vec2) -> u32 {
+        let p = self.input_pixel(n, img_pos.as_uvec2());
+        if self.sampling == Sampling::Nearest || p & ALPHA == 0 {
+            return p;
+        }
+
+        // taps either side of the pixel centers around `img_pos`, skipping masked ones
+        let f = img_pos - 0.5;
+        let base = f.floor().as_ivec2();
+        let t = f - f.floor();
+        let (w, h, _) = self.inp_size;
+        let last = IVec2::new(w as i32, h as i32) - 1;
+        let r = self.sampling.id() as i32;
+        let mut sum = Vec4::ZERO;
+        for y in 1 - r..=r {
+            for x in 1 - r..=r {
+                let q = (base + IVec2::new(x, y)).clamp(IVec2::ZERO, last);
+                let c = self.linear(self.input_pixel(n, q.as_uvec2()));
+                let weight = self.tap_weight(x as f32 - t.x) * self.tap_weight(y as f32 - t.y);
+                sum += c.xyz().extend(1.0) * c.w * weight;
+            }
+        }
+
+        if sum.w <= 1e-3 {
+            return p;
+        }
+        self.encoded((sum.xyz() / sum.w).extend(unpack(p).w))
+    }
+
+    /// Weight of a tap `d` pixels from the sample, linear when bilinear and Catmull-Rom when
+    /// bicubic.
+    fn tap_weight(&self, d: f32) -> f32 {
+        let a = d.abs();
+        if self.sampling == Sampling::Bilinear {
+            return (1.0 - a).max(0.0);
+        }
+        if a < 1.0 {
+            (1.5 * a - 2.5) * a * a + 1.0
+        } else if a < 2.0 {
+            ((-0.5 * a + 2.5) * a - 4.0) * a + 2.0
+        } else {
+            0.0
+        }
+    }
+
+    fn input_pixel(&self, n: usize, p: UVec2) -> u32 {
+        if !self.cam_enabled[n] {
+            return 0;
+        }
+        let (w, h, _) = self.inp_size;
+        let off = (p.x + p.y * w) as usize;
+        let mask = self.inp_masks[off + n * (w * h) as usize];
+        mask.min(self.frame_pixel(n, off))
+    }
+
+    fn frame_pixel(&self, n: usize, off: usize) -> u32 {
+        u32::from_le_bytes(self.inp_frames[n][off * 4..][..4].try_into().unwrap())
+    }
+
+    /// Unmasked pixel of camera `n` at `uv` across its frame.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn raw_pixel(&self, n: u32, uv: Vec2) -> u32 {
+        let (w, h, _) = self.inp_size;
+        let inp_size = UVec2::new(w, h);
+        let p = (uv * inp_size.as_vec2()).as_uvec2().min(inp_size - 1);
+        self.frame_pixel(n as usize, (p.x + p.y * w) as usize)
+    }
+
+    /// Picture in picture pixel at `uv` of an overlay drawn `size` output pixels big.
+    fn shade_pip(&self, uv: Vec2, size: Vec2, pip: &PipOverlay) -> u32 {
+        let px = uv * size;
+        let edge = px.min(size - px);
+        if edge.x.min(edge.y) < pip.border_width {
+            return pack(pip.border_color.into());
+        }
+        self.tone_mapped(self.raw_pixel(pip.camera, uv))
+    }
+
+    /// Brings a projected color into the output's range, like the shader.
+    fn tone_mapped(&self, p: u32) -> u32 {
+        let c = self.linear(p);
+        let x = c.xyz() * self.tone_map.scale();
+        let mapped = match self.tone_map.curve {
+            ToneCurve::Clip => x,
+            ToneCurve::Reinhard => x / (1.0 + x),
+            ToneCurve::Aces => x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14),
+        };
+        self.encoded(mapped.extend(c.w))
+    }
+
+    /// Linear color of a pixel kept in the frames' color space. Pixels stay encoded between
+    /// steps so 8 bits keep the detail of the dark parts, while the GPU works on linear floats.
+    fn linear(&self, p: u32) -> Vec4 {
+        let c = unpack(p);
+        match self.color_space {
+            ColorSpace::Srgb => srgb_decode(c.xyz()).extend(c.w),
+            ColorSpace::Linear => c,
+        }
+    }
+
+    /// Inverse of [`Self::linear`].
+    fn encoded(&self, c: Vec4) -> u32 {
+        match self.color_space {
+            ColorSpace::Srgb => pack(srgb_encode(c.xyz()).extend(c.w)),
+            ColorSpace::Linear => pack(c),
+        }
+    }
+
+    /// Sums the raw colors of every pair of cameras that both see the same point, over a grid on
+    /// the ground under the main view. See [`gain::solve`].
+    #[allow(clippy::cast_precision_loss)]
+    fn gain_stats(&self) -> Vec<u32> {
+        let pass = self.pass(&self.views[0]);
+        let n = self.inp_size.2 as usize;
+        let mut stats = vec![0u32; n * n * 4];
+        let mut pixels = vec![0u32; n];
+
+        for gy in 0..GAIN_GRID {
+            for gx in 0..GAIN_GRID {
+                let uv = (Vec2::new(gx as f32, gy as f32) + 0.5) / GAIN_GRID as f32 * 2.0 - 1.0;
+                if uv.length() > 1.0 {
+                    continue;
+                }
+
+                let bound = (pass.view_pos.xy() + uv * pass.bound_radius).extend(0.0);
+                for (i, (p, spec)) in pixels.iter_mut().zip(&self.inp_specs).enumerate() {
+                    *p = self.opt_input_pixel(i, opt_from_world(spec, bound));
+                }
+
+                for i in 0..n {
+                    for j in 0..n {
+                        if i == j || pixels[i] & ALPHA == 0 || pixels[j] & ALPHA == 0 {
+                            continue;
+                        }
+
+                        // summed as linear 8 bit values, like the GPU
+                        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                        let c = (self.linear(pixels[i]).xyz() * 255.0).round().as_uvec3();
+                        let s = &mut stats[(i * n + j) * 4..][..4];
+                        s[0] += 1;
+                        s[1] += c.x;
+                        s[2] += c.y;
+                        s[3] += c.z;
+                    }
+                }
+            }
+        }
+        stats
+    }
+}
+
+/// Lays out `labels` on an output of `size` pixels, one quad per character.
+#[allow(clippy::cast_precision_loss)]
+fn hud_chars<'a>(labels: impl Iterator<Item = &'a HudLabel>, size: Vec2) -> Vec<HudChar> {
+    let mut chars = Vec::new();
+    for label in labels {
+        let cell = 8.0 * label.scale as f32;
+        let x = (label.pos[0] * size.x).round();
+        let y = (label.pos[1] * size.y).round();
+
+        for (row, line) in label.text.lines().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                chars.push(HudChar {
+                    quad: Quad {
+                        min: Vec2::new(
+                            (col as f32).mul_add(cell, x),
+                            (row as f32).mul_add(cell, y),
+                        ),
+                        size: Vec2::splat(cell),
+                    },
+                    color: label.color.into(),
+                    background: label.background.into(),
+                    glyph: font::glyph_index(c),
+                });
+            }
+        }
+    }
+    chars
+}
+
+/// Color of a character at `uv` across its cell, `None` where it is see through.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn shade_char(c: &HudChar, uv: Vec2) -> Option<Vec4> {
+    let cell = (uv * 8.0).as_uvec2().min(UVec2::splat(7));
+    let row = font::GLYPHS[c.glyph as usize][cell.y as usize];
+    if (row >> cell.x) & 1 == 1 {
+        return Some(c.color);
+    }
+    (c.background.w > 0.0).then_some(c.background)
+}
+
+/// Color of a watermark's picture at `uv` across it, see `watermark.wgsl`.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn shade_watermark(img: &image::RgbaImage, uv: Vec2, opacity: f32) -> Vec4 {
+    let size = UVec2::from(img.dimensions());
+    let p = (uv * size.as_vec2()).as_uvec2().min(size - 1);
+    let c = Vec4::from(img.get_pixel(p.x, p.y).0.map(f32::from)) / 255.0;
+    c.xyz().extend(c.w * opacity)
+}
+
+/// `src` drawn over `dst` by its alpha, like the HUD pass' blend state.
+fn blend_over(src: Vec4, dst: Vec4) -> Vec4 {
+    (src.xyz() * src.w + dst.xyz() * (1.0 - src.w)).extend(src.w + dst.w * (1.0 - src.w))
+}
+
+/// Direction through `st` on a face, using the OpenGL cube map convention (y up, -z forward).
+fn cube_dir(face: u32, st: Vec2) -> Vec3 {
+    let Vec2 { x: s, y: t } = st;
+    match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    }
+}
+
+/// Where a ray from `o` hits the world, modeled as the ground plane capped by a dome of
+/// radius `r` centered under `o`.
+fn dome_hit(o: Vec3, d: Vec3, r: f32) -> Vec3 {
+    if d.z < 0.0 {
+        let g = o - d * (o.z / d.z);
+        if (g.xy() - o.xy()).length() <= r {
+            return g;
+        }
+    }
+
+    let b = o.z * d.z;
+    let t = (b * b - o.z * o.z + r * r).sqrt() - b;
+    o + d * t
+}
+
+// Spaces:
+// world -> (x, y, z)
+// optical -> (opt_ang, rot_ang)
+// image -> (ux, uy) on unit circle spanning diagonal
+
+fn opt_from_world(s: &InputSpec, rev_pos: Vec3) -> Vec2 {
+    let rev_dir = (rev_pos - s.pos).normalize();
+    opt_from_dir(s.rev_mat * rev_dir)
+}
+
+/// Optical angles of a unit direction in a camera's frame.
+fn opt_from_dir(ds: Vec3) -> Vec2 {
+    let rot_ang = wgsl_sign(ds.z) * (ds.x / Vec2::new(ds.x, ds.z).length()).acos();
+    Vec2::new(ds.y.acos(), rot_ang)
+}
+
+fn img_from_opt(s: &InputSpec, angs: Vec2) -> Vec2 {
+    let r = match s.lens_type {
+        1 => s.foc_dist * angs.x,
+        2 => 2.0 * s.foc_dist * (angs.x / 2.0).sin(),
+        _ => s.foc_dist * angs.x.tan(),
+    };
+
+    let u = Vec2::new(angs.y.cos(), angs.y.sin()) * r / s.foc_dist;
+    Vec2::from(s.distortion.apply(u.into())) * s.foc_dist
+}
+
+/// Inverse of [`img_from_opt`] followed by [`opt_from_world`], as a world direction.
+fn world_from_img(s: &InputSpec, distorted: Vec2) -> Vec3 {
+    let img = Vec2::from(s.distortion.remove((distorted / s.foc_dist).into())) * s.foc_dist;
+    let r = img.length();
+    let ang = match s.lens_type {
+        1 => r / s.foc_dist,
+        2 => 2.0 * (r / (2.0 * s.foc_dist)).min(1.0).asin(),
+        _ => (r / s.foc_dist).atan(),
+    };
+
+    let ds = if r > 0.0 {
+        Vec3::new(ang.sin() * img.x / r, ang.cos(), ang.sin() * img.y / r)
+    } else {
+        Vec3::Y
+    };
+    s.rev_mat.transpose() * ds
+}
+
+fn img_from_coord(c: Vec2, size: UVec2) -> Vec2 {
+    let sf = size.as_vec2();
+    Vec2::new(1.0, -1.0) * (c * 2.0 - sf) / sf.length()
+}
+
+fn coord_from_img(rp: Vec2, size: UVec2) -> Vec2 {
+    let sf = size.as_vec2();
+    (Vec2::new(1.0, -1.0) * rp * sf.length() + sf) / 2.0
+}
+
+/// WGSL's `sign`, which is 0 for 0 unlike [`f32::signum`].
+fn wgsl_sign(v: f32) -> f32 {
+    if v == 0.0 {
+        0.0
+    } else {
+        v.signum()
+    }
+}
+
+fn srgb_decode(c: Vec3) -> Vec3 {
+    c.map(|v| {
+        if v <= 0.04045 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+fn srgb_encode(c: Vec3) -> Vec3 {
+    c.max(Vec3::ZERO).map(|v| {
+        if v <= 0.003_130_8 {
+            v * 12.92
+        } else {
+            1.055 * v.powf(1.0 / 2.4) - 0.055
+        }
+    })
+}
+
+/// Like WGSL's `unpack4x8unorm`, with red in the lowest byte.
+fn unpack(p: u32) -> Vec4 {
+    let [r, g, b, a] = p.to_le_bytes();
+    Vec4::new(r.into(), g.into(), b.into(), a.into()) / 255.0
+}
+
+/// Like WGSL's `pack4x8unorm`, and storing to an `Rgba8Unorm` texture.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn pack(c: Vec4) -> u32 {
+    let [r, g, b, a] = (c.clamp(Vec4::ZERO, Vec4::ONE) * 255.0 + 0.5)
+        .floor()
+        .to_array()
+        .map(|v| v as u8);
+    u32::from_le_bytes([r, g, b, a])
+}
@@ -1,31 +1,328 @@
-use std::{cell::Cell, num::NonZero, ops::DerefMut, path::PathBuf, sync::Arc};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    num::NonZero,
+    ops::DerefMut,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
 use encase::ShaderType;
 use glam::Mat4;
-use smpgpu::{Bindable, Bindings, Buffer, Context, MemMapper, RenderCheckpoint, Texture};
+use smpgpu::{
+    reexport::TextureFormat, Bindable, Bindings, Buffer, CommandBuilder, ComputeCheckpoint,
+    Context, MemMapper, RenderCheckpoint, Shader, Texture,
+};
 use tokio::runtime::Handle;
-use zerocopy::FromZeros;
 
 use crate::{
-    buf::FrameSize,
+    buf::{FrameSize, PixelFormat},
     camera::{live, Camera, Config, ViewParams},
     loader::{self, Loader, OwnedWriteBuffer},
-    Result,
+    transform::Rotation,
+    DimErrorKind, Error, Result,
+};
+
+use super::{
+    font, gain, height::HeightGrid, mask, watermark, ColorSpace, FrameFormat, GroundOverlay,
+    HudLabel, MotionConfig, PipOverlay, Preprocess, ProjectionStyle, Sampling, SeamBlend,
+    StereoConfig, ToneCurve, ToneMap, ViewCrop, Watermark, WorldStyle, MAIN_VIEW,
 };
 
-use super::ProjectionStyle;
+/// GPU device projectors are built on, see [`GpuProjector::builder_shared`].
+pub use smpgpu::Context as GpuContext;
+
+/// Samples per side of the grid used to gather gain compensation stats, must match
+/// `GAIN_GRID` in the shader.
+const GAIN_GRID: usize = 64;
+
+/// Most picture in picture overlays on a view, must match `MAX_PIPS` in the overlay shader.
+const MAX_PIPS: usize = 8;
+
+/// Most characters of text on a view.
+const MAX_HUD_CHARS: usize = 1024;
+
+/// Most zones watched for motion, must match `MAX_MOTION_ZONES` in the shader.
+const MAX_MOTION_ZONES: usize = 16;
+
+/// Points per side of the grid sampled over each motion zone, must match `MOTION_GRID` in the
+/// shader.
+const MOTION_GRID: usize = 32;
+
+/// Side in pixels of the blocks of a camera's frame given a motion vector each, must match
+/// `FLOW_BLOCK` in the shader.
+const FLOW_BLOCK: u32 = 16;
 
 pub struct GpuProjector {
     ctx: Arc<Context>,
-    out_texture: Texture,
-    out_staging: Buffer,
+    views: Vec<OutputView>,
+    /// Pass info of the main view, shared by the compute passes
     pass_info: Buffer,
     pass_info_data: Cell<PassInfo>,
-    view_mat: Buffer,
-    inp_frames: Arc<Buffer>,
-    inp_specs: Buffer,
+    inputs: GpuInputs,
+    /// Last specs written to the input specs
+    inp_specs_data: RefCell<Vec<InputSpec>>,
+    /// Whether each camera is loaded and rendered, see [`GpuProjector::set_camera_enabled`]
+    cam_enabled: RefCell<Vec<bool>>,
+    /// See [`GpuProjector::set_camera_leads`]
+    cam_leads: RefCell<Vec<f32>>,
+    /// Whether the motion of every camera is found for [`GpuProjector::set_camera_leads`]
+    latency_compensation: bool,
+    strict_masks: bool,
+    mask_watch: Option<RefCell<MaskWatch>>,
+    /// Last time the shaders' files were checked, see [`GpuProjector::reload_changed_shaders`]
+    shader_poll: Cell<Instant>,
+    gain_interval: u32,
+    gain_pending: Cell<bool>,
+    /// Last zones given to [`GpuProjector::set_motion`]
+    motion_info: Cell<MotionInfo>,
+    motion_pending: Cell<bool>,
+    /// Motion read back since the last [`GpuProjector::take_motion`]
+    motion: RefCell<Option<Vec<f32>>>,
+    frame_count: Cell<u32>,
+    /// Staging buffers of each view, one per frame that can be rendered before it's read back
+    frames_in_flight: usize,
+    /// Format every view is rendered to
+    out_format: TextureFormat,
+    /// Samples per pixel the world mesh is rendered with
+    msaa: u32,
     bound_mesh: Buffer,
+    mesh_len: u32,
+    glyphs: Buffer,
+    world_grid: Option<HeightGrid>,
+    /// Present when the world is a height field
+    stereo: Option<StereoPass>,
+    /// Frames between stereo sweeps, 0 while [`GpuProjector::set_stereo`] has no pair
+    stereo_interval: Cell<u32>,
+}
+
+/// Buffers holding something for every camera, and the compute passes bound to them, which
+/// are all replaced when a camera is added or removed.
+struct GpuInputs {
+    /// Written by each camera, then copied to its layer of `frames` before rendering
+    uploads: Vec<Arc<Buffer>>,
+    /// Layer for every camera
+    frames: Texture,
+    specs: Buffer,
+    masks: Texture,
+    /// Every camera's layer of `frames` for each frame in the history, by slot
+    history: Texture,
+    gains: Buffer,
+    gain_stats: Buffer,
+    gain_staging: Buffer,
+    gain_cp: ComputeCheckpoint,
+    auto_mask_out: Texture,
+    auto_mask_cp: ComputeCheckpoint,
+    motion_info: Buffer,
+    /// Brightness of every point of every motion zone in the last frame
+    motion_prev: Buffer,
+    motion_stats: Buffer,
+    motion_staging: Buffer,
+    motion_cp: ComputeCheckpoint,
+    /// Motion of every block of each camera's frame, see [`GpuProjector::set_camera_leads`]
+    flow: Buffer,
+    flow_out: Buffer,
+    flow_cp: ComputeCheckpoint,
+    /// Pass of every camera whose frames are preprocessed before they're copied to `frames`
+    preps: Vec<Option<InputPrep>>,
+    /// View matrix of the compute passes, which never read it
+    compute_view: Buffer,
+}
+
+/// Passes finding the heights of the height field from a pair of cameras, then moving its mesh
+/// to them, see [`StereoConfig`].
+struct StereoPass {
+    info: Buffer,
+    /// Heights of the grid's points, then the ones found
+    heights: Buffer,
+    grid: HeightGrid,
+    cp: ComputeCheckpoint,
+    mesh_cp: ComputeCheckpoint,
+}
+
+#[derive(ShaderType, Clone, Copy, Debug)]
+struct StereoInfo {
+    cams: glam::UVec2,
+    /// Points per side of the grid
+    grid: glam::UVec2,
+    /// See [`StereoConfig::heights`]
+    heights: glam::Vec2,
+    /// World units the grid spans
+    size: f32,
+    steps: u32,
+    /// See [`StereoConfig::window`]
+    window: f32,
+}
+
+impl StereoInfo {
+    /// Sweeps of `stereo` over `grid`, or none at all, which keeps the heightmap's heights.
+    #[allow(clippy::cast_possible_truncation)]
+    fn new(stereo: Option<&StereoConfig>, grid: HeightGrid) -> Self {
+        let none = StereoConfig {
+            steps: 0,
+            ..StereoConfig::new([0, 0])
+        };
+        let s = stereo.unwrap_or(&none);
+        Self {
+            cams: glam::uvec2(s.cameras[0] as _, s.cameras[1] as _),
+            grid: glam::uvec2(grid.w, grid.h),
+            heights: s.heights.into(),
+            size: grid.size,
+            steps: s.steps,
+            window: s.window,
+        }
+    }
+}
+
+/// Compute pass turning the frames a camera uploads into its layer of the input frames, see
+/// [`Preprocess`].
+struct InputPrep {
+    /// Frames ready to be copied to the camera's layer
+    out: Buffer,
+    cp: ComputeCheckpoint,
+    /// Bound to `cp`, kept along with it
+    _info: Buffer,
+    _lut: Buffer,
+}
+
+#[derive(ShaderType, Clone, Copy, Debug)]
+struct PrepInfo {
+    src_size: glam::UVec2,
+    out_size: glam::UVec2,
+    /// 0 is RGBA8, 1 RGBA16F, 2 YUYV and 3 NV12
+    src_format: u32,
+    /// 1 when the input frames are RGBA16F
+    out_half: u32,
+    /// Quarter turns clockwise
+    turns: u32,
+    flip: u32,
+    undistort: u32,
+    /// Words in a row of the output, which is padded to be copied to the camera's layer
+    out_stride: u32,
+}
+
+/// Output rendered from the input frames by [`GpuProjector::update_render`], with its own
+/// size and projection style.
+struct OutputView {
+    name: String,
+    texture: Texture,
+    /// Rendered to by `back_cp` and resolved into `texture` when the mesh is multisampled
+    msaa: Option<Texture>,
+    /// Used in turn by consecutive frames
+    staging: Vec<Buffer>,
+    pass_info: Buffer,
+    view_mat: Buffer,
     back_cp: RenderCheckpoint,
+    equirect_cp: RenderCheckpoint,
+    cube_cp: RenderCheckpoint,
+    dewarp_cp: RenderCheckpoint,
+    raw_cp: RenderCheckpoint,
+    overlays: Buffer,
+    overlay_cp: RenderCheckpoint,
+    has_overlays: Cell<bool>,
+    /// Overlays last given to [`GpuProjector::update_view_overlays`]
+    pips: RefCell<Vec<PipOverlay>>,
+    hud_chars: Buffer,
+    hud_cp: RenderCheckpoint,
+    hud_labels: RefCell<Vec<HudLabel>>,
+    pip_labels: RefCell<Vec<HudLabel>>,
+    /// Characters drawn from the start of `hud_chars`
+    hud_len: Cell<u32>,
+    /// Watermark last given to [`GpuProjector::update_view_watermark`]
+    watermark: RefCell<Option<Watermark>>,
+    watermark_pass: RefCell<Option<WatermarkPass>>,
+    watermark_labels: RefCell<Vec<HudLabel>>,
+    style: Cell<Option<ProjectionStyle>>,
+    crop: Cell<ViewCrop>,
+    /// Whether the [`GroundOverlay`] is drawn
+    ground_overlay: Cell<bool>,
+}
+
+#[derive(ShaderType, Clone, Copy, Debug, Default)]
+struct PipSpec {
+    /// [x, y, w, h] in fractions of the output, empty for unused slots
+    rect: glam::Vec4,
+    border_color: glam::Vec4,
+    border_width: f32,
+    cam: u32,
+}
+
+#[derive(ShaderType, Clone, Copy, Debug, Default)]
+struct HudChar {
+    /// [x, y, w, h] in fractions of the output, empty for unused slots
+    rect: glam::Vec4,
+    color: glam::Vec4,
+    background: glam::Vec4,
+    glyph: u32,
+}
+
+/// Picture of a [`Watermark`] and the pass drawing it over a view.
+struct WatermarkPass {
+    image: Texture,
+    spec: Buffer,
+    cp: RenderCheckpoint,
+}
+
+#[derive(ShaderType, Clone, Copy, Debug)]
+struct WatermarkSpec {
+    /// [x, y, w, h] in fractions of the output
+    rect: glam::Vec4,
+    opacity: f32,
+}
+
+#[derive(ShaderType, Clone, Copy, Debug)]
+struct OverlaySpecs {
+    inp_size: glam::UVec2,
+    out_size: glam::UVec2,
+    /// Tone mapping and encoding of the camera frames, like [`PassInfo`]
+    exposure: f32,
+    tone_curve: u32,
+    color_space: u32,
+    pips: [PipSpec; MAX_PIPS],
+}
+
+#[derive(ShaderType, Clone, Copy, Debug)]
+struct MotionInfo {
+    zones: u32,
+    /// See [`MotionConfig::threshold`]
+    threshold: f32,
+    /// [x, y] of the lowest corner then the highest of each zone
+    rects: [glam::Vec4; MAX_MOTION_ZONES],
+}
+
+impl MotionInfo {
+    /// No zones, leaving motion detection off.
+    const NONE: Self = Self {
+        zones: 0,
+        threshold: 0.0,
+        rects: [glam::Vec4::ZERO; MAX_MOTION_ZONES],
+    };
+
+    fn new(motion: &MotionConfig) -> Self {
+        if motion.zones.len() > MAX_MOTION_ZONES {
+            tracing::warn!("only the first {MAX_MOTION_ZONES} motion zones are watched");
+        }
+
+        let mut rects = [glam::Vec4::ZERO; MAX_MOTION_ZONES];
+        for (rect, zone) in rects.iter_mut().zip(&motion.zones) {
+            let (a, b) = (glam::Vec2::from(zone.min), glam::Vec2::from(zone.max));
+            let (lo, hi) = (a.min(b), a.max(b));
+            *rect = glam::vec4(lo.x, lo.y, hi.x, hi.y);
+        }
+        Self {
+            zones: motion.zones.len().min(MAX_MOTION_ZONES) as _,
+            threshold: motion.threshold,
+            rects,
+        }
+    }
+}
+
+/// Mask files and when they were last loaded, for reloading them as they change.
+struct MaskWatch {
+    paths: Vec<Option<PathBuf>>,
+    loaded: Vec<Option<SystemTime>>,
+    last_poll: Instant,
 }
 
 #[derive(ShaderType, Clone, Copy, Debug, Default)]
@@ -40,14 +337,25 @@ struct InputSpec {
     foc_dist: f32,
     /// Camera's lens type
     lens_type: u32,
+    /// 0 when the camera is left out
+    enabled: u32,
+    /// See [`GpuProjector::set_camera_leads`]
+    lead: f32,
+    /// Radial terms of the camera's [`crate::camera::Distortion`]
+    dist_k: glam::Vec4,
+    /// Tangential terms of the camera's [`crate::camera::Distortion`]
+    dist_p: glam::Vec2,
 }
 
-impl From<ViewParams> for InputSpec {
+impl TryFrom<ViewParams> for InputSpec {
+    type Error = Error;
+
     #[inline]
-    fn from(s: ViewParams) -> Self {
+    fn try_from(s: ViewParams) -> Result<Self> {
         let rev_mat = glam::Mat3::from_euler(glam::EulerRot::ZXY, s.azimuth, s.pitch, s.roll);
+        let d = s.sensor.distortion;
 
-        Self {
+        Ok(Self {
             pos: s.pos.into(),
             rev_mat,
             img_off: s.sensor.img_off.into(),
@@ -55,9 +363,13 @@ impl From<ViewParams> for InputSpec {
                 .sensor
                 .fov
                 .assume_focal_dist()
-                .expect("focal distance not set"),
+                .ok_or(Error::FocalDistanceUnset)?,
             lens_type: s.lens as _,
-        }
+            enabled: 1,
+            lead: 0.0,
+            dist_k: glam::vec4(d.k1, d.k2, d.k3, d.k4),
+            dist_p: glam::vec2(d.p1, d.p2),
+        })
     }
 }
 
@@ -65,9 +377,51 @@ impl From<ViewParams> for InputSpec {
 struct PassInfo {
     inp_sizes: glam::UVec3,
     bound_radius: f32,
+    /// Number of frame sets the history ring can hold
+    hist_cap: u32,
+    /// Number of frame sets currently in the history ring
+    hist_len: u32,
+    /// Slot of the most recent frame set in the history ring
+    hist_head: u32,
+    /// Position rays are cast from for panoramic styles
+    view_pos: glam::Vec3,
+    out_size: glam::UVec2,
+    /// Feather width in input pixels, 0 disables blending
+    blend_width: f32,
+    /// Cosine of the largest incidence angle kept by [`GpuProjector::auto_masks`]
+    mask_incidence: f32,
+    /// Filter of camera frames, see [`Sampling::id`]
+    sampling: u32,
+    /// Multiplier of the colors before tone mapping, see [`ToneMap::scale`]
+    exposure: f32,
+    /// See [`ToneCurve::id`]
+    tone_curve: u32,
+    /// See [`ColorSpace::id`]
+    color_space: u32,
+    /// Camera of a [`ProjectionStyle::SingleCamera`] or [`ProjectionStyle::RawCamera`] view
+    dewarp_cam: u32,
+    /// See [`super::DewarpLens::id`]
+    dewarp_lens: u32,
+    /// Width of a single camera view in radians
+    dewarp_fov: f32,
+    /// Part of the projection drawn to the output as [x, y, w, h], see [`ViewCrop`]
+    crop: glam::Vec4,
+    /// Rotation from a single camera view to its camera, see [`super::dewarp_turn`]
+    dewarp_turn: glam::Mat3,
+    /// See [`GroundOverlay::spacing`]
+    grid_spacing: f32,
+    /// See [`GroundOverlay::radius`]
+    grid_radius: f32,
+    /// See [`GroundOverlay::compass`]
+    compass_radius: f32,
+    /// Direction of north on the ground
+    north: glam::Vec2,
+    ground_color: glam::Vec4,
+    /// 1 when the view draws the [`GroundOverlay`]
+    ground_overlay: u32,
 }
 
-#[derive(ShaderType)]
+#[derive(ShaderType, Clone)]
 struct Vertex {
     pub pos: glam::Vec4,
 }
@@ -87,7 +441,22 @@ pub struct GpuProjectorBuilder<'a> {
     out_size: (usize, usize),
     input_size: (u32, u32, u32),
     bound_mesh: &'a [Vertex],
+    world: WorldStyle,
     mask_paths: Vec<Option<PathBuf>>,
+    strict_masks: bool,
+    watch_masks: bool,
+    history_len: u32,
+    blend: SeamBlend,
+    sampling: Sampling,
+    tone_map: ToneMap,
+    color_space: ColorSpace,
+    input_format: FrameFormat,
+    output_format: FrameFormat,
+    gain_interval: u32,
+    motion: MotionInfo,
+    frames_in_flight: usize,
+    msaa: u32,
+    latency_compensation: bool,
 }
 
 impl<'a> GpuProjectorBuilder<'a> {
@@ -97,7 +466,25 @@ impl<'a> GpuProjectorBuilder<'a> {
             out_size: (0, 0),
             input_size: (0, 0, 0),
             bound_mesh: &[],
+            world: WorldStyle::Flat,
             mask_paths: Vec::new(),
+            strict_masks: false,
+            watch_masks: false,
+            history_len: 0,
+            blend: SeamBlend::Nearest,
+            sampling: Sampling::Nearest,
+            tone_map: ToneMap {
+                curve: ToneCurve::Clip,
+                exposure: 0.0,
+            },
+            color_space: ColorSpace::Srgb,
+            input_format: FrameFormat::Rgba8,
+            output_format: FrameFormat::Rgba8,
+            gain_interval: 0,
+            motion: MotionInfo::NONE,
+            frames_in_flight: 1,
+            msaa: 1,
+            latency_compensation: false,
         }
     }
 
@@ -111,6 +498,78 @@ impl<'a> GpuProjectorBuilder<'a> {
         self
     }
 
+    /// Keep the previous `n` frames of every camera on the GPU for temporal passes.
+    pub const fn history(mut self, n: u32) -> Self {
+        self.history_len = n;
+        self
+    }
+
+    pub const fn blend(mut self, blend: SeamBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub const fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub const fn tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    pub const fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Format the cameras load their frames in, with an alpha of 1. RGBA16F frames need loaders
+    /// with 2 bytes per channel, see [`Loader::with_bytes_per_chan`].
+    pub const fn input_format(mut self, format: FrameFormat) -> Self {
+        self.input_format = format;
+        self
+    }
+
+    /// Format the views are rendered and read back in.
+    pub const fn output_format(mut self, format: FrameFormat) -> Self {
+        self.output_format = format;
+        self
+    }
+
+    /// Re-solve exposure/color gains between overlapping cameras every `n` frames, 0 disables it.
+    pub const fn gain_interval(mut self, n: u32) -> Self {
+        self.gain_interval = n;
+        self
+    }
+
+    /// Watch the zones of `motion` for motion, see [`GpuProjector::take_motion`].
+    pub fn motion(mut self, motion: &MotionConfig) -> Self {
+        self.motion = MotionInfo::new(motion);
+        self
+    }
+
+    /// Give every view a staging buffer for each of `n` frames, so a frame can be read back
+    /// with [`GpuProjector::block_copy_prev_view_to`] while the next one renders.
+    pub const fn frames_in_flight(mut self, n: usize) -> Self {
+        self.frames_in_flight = if n > 1 { n } else { 1 };
+        self
+    }
+
+    /// Render the world mesh with `samples` per pixel, smoothing its edges. Only 1, which
+    /// disables it, and 4 work on every GPU.
+    pub const fn msaa(mut self, samples: u32) -> Self {
+        self.msaa = if samples > 1 { samples } else { 1 };
+        self
+    }
+
+    /// Find the motion of every camera each frame, so [`GpuProjector::set_camera_leads`] can
+    /// push frames ahead along it. Keeps at least one frame of history to compare with.
+    pub const fn latency_compensation(mut self, enabled: bool) -> Self {
+        self.latency_compensation = enabled;
+        self
+    }
+
     pub fn flat_bound(mut self) -> Self {
         static MESH_DATA: [Vertex; 6] = [
             Vertex::new(-500., -500., 0.),
@@ -124,21 +583,40 @@ impl<'a> GpuProjectorBuilder<'a> {
         self
     }
 
+    /// Replaces the bound mesh with the world's ground when it isn't flat.
+    pub fn world(mut self, world: WorldStyle) -> Self {
+        self.world = world;
+        self
+    }
+
     pub fn masks_from_cfgs(mut self, cfgs: &[Config<live::Config>]) -> Self {
         self.mask_paths = cfgs.iter().map(|c| c.meta.mask_path.clone()).collect();
         self
     }
 
-    pub fn build(self) -> GpuProjector {
-        let ctx = self.ctx.as_ref();
+    /// Fail to build if a mask doesn't match the input size, instead of rescaling it.
+    pub const fn strict_masks(mut self, strict: bool) -> Self {
+        self.strict_masks = strict;
+        self
+    }
 
-        let out_texture = Texture::builder(ctx)
-            .label("out_texture")
-            .size(self.out_size.0, self.out_size.1)
-            .render_target()
-            .readable()
-            .build();
-        let out_staging = out_texture.new_staging(ctx);
+    /// Reload masks when their files change, see [`GpuProjector::reload_changed_masks`].
+    pub const fn watch_masks(mut self, watch: bool) -> Self {
+        self.watch_masks = watch;
+        self
+    }
+
+    /// # Errors
+    /// there are more than [`GpuProjector::MAX_CAMERAS`] inputs, a mask doesn't match the
+    /// input size while [`Self::strict_masks`] is set, or the world's heightmap can't be loaded
+    pub fn build(mut self) -> Result<GpuProjector> {
+        if self.latency_compensation {
+            self.history_len = self.history_len.max(1);
+        }
+        let ctx = self.ctx.as_ref();
+        if self.input_size.2 as usize > GpuProjector::MAX_CAMERAS {
+            return Err(Error::TooManyCameras(GpuProjector::MAX_CAMERAS));
+        }
 
         let pass_info = Buffer::builder(ctx)
             .label("pass_info")
@@ -147,6 +625,134 @@ impl<'a> GpuProjectorBuilder<'a> {
             .writable()
             .build();
 
+        let masks = mask::load_all(&self.mask_paths, self.input_size, self.strict_masks)?;
+        let inputs = GpuInputs::new(
+            ctx,
+            &pass_info,
+            self.input_size,
+            texture_format(self.input_format),
+            self.history_len,
+            Some(&masks),
+        );
+
+        let (world_grid, mesh) = match &self.world {
+            WorldStyle::Flat => (None, Cow::Borrowed(self.bound_mesh)),
+            WorldStyle::HeightField { path, scale, size } => {
+                let (grid, heights) = HeightGrid::load(path, *scale, *size)?;
+                let mesh = grid.mesh(|x, y| heights[(y * grid.w + x) as usize]);
+                (Some((grid, heights)), Cow::Owned(mesh))
+            }
+        };
+        // moved by the stereo passes
+        let bound_mesh = Buffer::builder(ctx)
+            .label("bound_mesh")
+            .vertex()
+            .storage()
+            .writable()
+            .readable()
+            .build_with_data(&mesh);
+        let stereo = world_grid.as_ref().map(|(grid, heights)| {
+            StereoPass::new(ctx, &pass_info, &inputs, &bound_mesh, *grid, heights)
+        });
+
+        let glyphs = Buffer::builder(ctx)
+            .label("glyphs")
+            .storage()
+            .build_with_data(&font::packed());
+
+        let mut proj = GpuProjector {
+            ctx: self.ctx,
+            views: Vec::new(),
+            pass_info,
+            pass_info_data: Cell::new(PassInfo {
+                inp_sizes: self.input_size.into(),
+                bound_radius: f32::NAN,
+                hist_cap: self.history_len,
+                hist_len: 0,
+                hist_head: self.history_len.saturating_sub(1),
+                view_pos: glam::Vec3::ZERO,
+                out_size: glam::uvec2(self.out_size.0 as _, self.out_size.1 as _),
+                blend_width: self.blend.width(),
+                mask_incidence: 0.0,
+                sampling: self.sampling.id(),
+                exposure: self.tone_map.scale(),
+                tone_curve: self.tone_map.curve.id(),
+                color_space: self.color_space.id(),
+                dewarp_cam: 0,
+                dewarp_lens: 0,
+                dewarp_fov: 0.0,
+                crop: glam::vec4(0.0, 0.0, 1.0, 1.0),
+                dewarp_turn: glam::Mat3::IDENTITY,
+                grid_spacing: 0.0,
+                grid_radius: 0.0,
+                compass_radius: 0.0,
+                north: glam::Vec2::Y,
+                ground_color: glam::Vec4::ZERO,
+                ground_overlay: 0,
+            }),
+            inputs,
+            inp_specs_data: RefCell::new(Vec::new()),
+            cam_enabled: RefCell::new(vec![true; self.input_size.2 as usize]),
+            cam_leads: RefCell::new(Vec::new()),
+            latency_compensation: self.latency_compensation,
+            strict_masks: self.strict_masks,
+            mask_watch: self.watch_masks.then(|| {
+                RefCell::new(MaskWatch {
+                    loaded: self
+                        .mask_paths
+                        .iter()
+                        .map(|p| modified(p.as_ref()))
+                        .collect(),
+                    paths: self.mask_paths,
+                    last_poll: Instant::now(),
+                })
+            }),
+            shader_poll: Cell::new(Instant::now()),
+            gain_interval: self.gain_interval,
+            gain_pending: Cell::new(false),
+            motion_info: Cell::new(self.motion),
+            motion_pending: Cell::new(false),
+            motion: RefCell::new(None),
+            frame_count: Cell::new(0),
+            frames_in_flight: self.frames_in_flight,
+            out_format: texture_format(self.output_format),
+            msaa: self.msaa,
+            bound_mesh,
+            mesh_len: mesh.len().try_into()?,
+            glyphs,
+            world_grid: world_grid.map(|(grid, _)| grid),
+            stereo,
+            stereo_interval: Cell::new(0),
+        };
+
+        let main = proj.new_view(MAIN_VIEW.to_string(), self.out_size.0, self.out_size.1);
+        proj.views.push(main);
+        proj.ctx
+            .write_uniform(&proj.inputs.motion_info, &self.motion);
+        Ok(proj)
+    }
+}
+
+impl GpuInputs {
+    /// Inputs for `n` cameras of `w` by `h` in `format`, starting with `mask_data` or empty
+    /// masks.
+    fn new(
+        ctx: &Context,
+        pass_info: &Buffer,
+        (w, h, n): (u32, u32, u32),
+        format: TextureFormat,
+        history_len: u32,
+        mask_data: Option<&[u32]>,
+    ) -> Self {
+        let layer = |label| {
+            Texture::builder(ctx)
+                .label(label)
+                .size(w as _, h as _)
+                .writable()
+                .readable()
+        };
+
+        // compute passes don't read the view, but share the layout of the render passes
         let view_mat = Buffer::builder(ctx)
             .label("view")
             .size_for::<glam::Mat4>()
@@ -154,98 +760,379 @@ impl<'a> GpuProjectorBuilder<'a> {
             .writable()
             .build();
 
-        let inp_frames = Buffer::builder(ctx)
-            .label("inp_frames")
-            .size(self.input_bytes())
+        let frames = layer("inp_frames").format(format).layers(n as _).build();
+        let uploads = (0..n)
+            .map(|i| {
+                let buf = Buffer::builder(ctx)
+                    .label(&format!("inp_upload_{i}"))
+                    .size((frames.padded_row_bytes() * h) as _)
+                    .storage()
+                    .writable()
+                    .copyable()
+                    .build();
+                Arc::new(buf)
+            })
+            .collect();
+
+        let specs = Buffer::builder(ctx)
+            .label("inp_specs")
+            .size_for_many::<InputSpec>(n.into())
             .storage()
             .writable()
             .build();
 
-        let inp_specs = Buffer::builder(ctx)
-            .label("inp_specs")
-            .size_for_many::<InputSpec>(self.input_size.2.into())
+        let masks = layer("inp_masks").layers(n as _).build();
+        let layers = mask_data.into_iter().flat_map(|m| m.chunks((w * h) as _));
+        for (i, mask) in layers.enumerate() {
+            let bytes = mask
+                .iter()
+                .flat_map(|v| v.to_ne_bytes())
+                .collect::<Vec<_>>();
+            ctx.write_texture_layer(&masks, &bytes, i as _);
+        }
+
+        // bindings can't be empty, so a disabled history still gets a placeholder
+        let history = layer("inp_history")
+            .format(format)
+            .layers((history_len * n).max(1) as _)
+            .build();
+
+        let gains = Buffer::builder(ctx)
+            .label("inp_gains")
+            .storage()
+            .writable()
+            .build_with_data(&vec![glam::Vec4::ONE; n as usize]);
+
+        // one u32 for every stat of every pair of cameras, see [`gain::solve`]
+        let gain_stats_bytes = (n * n * 4 * 4) as usize;
+        let gain_stats = Buffer::builder(ctx)
+            .label("gain_stats")
+            .size(gain_stats_bytes)
+            .storage()
+            .writable()
+            .readable()
+            .build();
+        let gain_staging = Buffer::builder(ctx)
+            .label("gain_staging")
+            .size(gain_stats_bytes)
+            .writable()
+            .build();
+
+        let auto_mask_out = Texture::builder(ctx)
+            .label("auto_mask_out")
+            .size(w as _, h as _)
+            .layers(n as _)
+            .storage()
+            .readable()
+            .build();
+
+        let motion_info = Buffer::builder(ctx)
+            .label("motion_info")
+            .size_for::<MotionInfo>()
+            .uniform()
+            .writable()
+            .build();
+        // negative until a point has been seen, so the first frame isn't all motion
+        let motion_prev = Buffer::builder(ctx)
+            .label("motion_prev")
             .storage()
+            .readable()
+            .build_with_data(&vec![-1.0f32; MAX_MOTION_ZONES * MOTION_GRID * MOTION_GRID]);
+        // seen and changed points of every zone
+        let motion_stats_bytes = MAX_MOTION_ZONES * 2 * 4;
+        let motion_stats = Buffer::builder(ctx)
+            .label("motion_stats")
+            .size(motion_stats_bytes)
+            .storage()
+            .writable()
+            .readable()
+            .build();
+        let motion_staging = Buffer::builder(ctx)
+            .label("motion_staging")
+            .size(motion_stats_bytes)
             .writable()
             .build();
 
-        let inp_masks = Buffer::builder(ctx)
-            .label("inp_masks")
+        let (flow_w, flow_h) = (w.div_ceil(FLOW_BLOCK), h.div_ceil(FLOW_BLOCK));
+        let flow_len = u64::from(flow_w * flow_h * n);
+        let flow = Buffer::builder(ctx)
+            .label("inp_flow")
+            .size_for_many::<glam::Vec2>(flow_len)
             .storage()
             .writable()
-            .build_with_data(&self.generate_masks());
+            .build();
+        let flow_out = Buffer::builder(ctx)
+            .label("flow_out")
+            .size_for_many::<glam::Vec2>(flow_len)
+            .storage()
+            .readable()
+            .build();
 
-        let bound_mesh = Buffer::builder(ctx)
-            .label("bound_mesh")
-            .vertex()
-            .build_with_data(self.bound_mesh);
+        let compute_cp = |entry| {
+            ComputeCheckpoint::builder(ctx)
+                .group(input_bindings(
+                    pass_info, &view_mat, &frames, &specs, &masks, &history, &gains, &flow,
+                ))
+                .group(compute_bindings(
+                    &gain_stats,
+                    &auto_mask_out,
+                    &motion_info,
+                    &motion_prev,
+                    &motion_stats,
+                    &flow_out,
+                ))
+                .shader(
+                    Shader::new()
+                        .module(smpgpu::reexport::include_wgsl!("shaders/render.wgsl"))
+                        .entry(entry),
+                )
+                .build()
+        };
+        let gain_cp = compute_cp("cs_gain_stats").work_groups(GAIN_GRID / 8, GAIN_GRID / 8, 1);
+        let auto_mask_cp =
+            compute_cp("cs_auto_mask").work_groups(w.div_ceil(8) as _, h.div_ceil(8) as _, n as _);
+        let motion_cp =
+            compute_cp("cs_motion").work_groups(MOTION_GRID / 8, MOTION_GRID / 8, MAX_MOTION_ZONES);
+        let flow_cp = compute_cp("cs_flow").work_groups(
+            flow_w.div_ceil(8) as _,
+            flow_h.div_ceil(8) as _,
+            n as _,
+        );
 
-        let back_cp = RenderCheckpoint::builder(ctx)
-            .group(
-                Bindings::new()
-                    .bind(pass_info.in_frag())
-                    .bind(view_mat.in_vertex())
-                    .bind(inp_frames.in_frag())
-                    .bind(inp_specs.in_frag())
-                    .bind(inp_masks.in_frag()),
+        Self {
+            uploads,
+            frames,
+            specs,
+            masks,
+            history,
+            gains,
+            gain_stats,
+            gain_staging,
+            gain_cp,
+            auto_mask_out,
+            auto_mask_cp,
+            motion_info,
+            motion_prev,
+            motion_stats,
+            motion_staging,
+            motion_cp,
+            flow,
+            flow_out,
+            flow_cp,
+            preps: (0..n).map(|_| None).collect(),
+            compute_view: view_mat,
+        }
+    }
+
+    /// Pass running `entry` of the render shader with the groups of the other compute passes,
+    /// then `group`.
+    fn compute_pass_with(
+        &self,
+        ctx: &Context,
+        pass_info: &Buffer,
+        entry: &str,
+        group: Bindings,
+    ) -> ComputeCheckpoint {
+        ComputeCheckpoint::builder(ctx)
+            .group(self.bindings(pass_info, &self.compute_view))
+            .group(compute_bindings(
+                &self.gain_stats,
+                &self.auto_mask_out,
+                &self.motion_info,
+                &self.motion_prev,
+                &self.motion_stats,
+                &self.flow_out,
+            ))
+            .group(group)
+            .shader(
+                Shader::new()
+                    .module(smpgpu::reexport::include_wgsl!("shaders/render.wgsl"))
+                    .entry(entry),
             )
-            .shader(smpgpu::include_shader!("shaders/render.wgsl" => "vs_proj" & "fs_proj"))
-            .vert_buffer_of::<Vertex>(&smpgpu::vertex_attr_array![0 => Float32x4])
-            .frag_target(out_texture.format())
             .build()
-            .vertices(0..self.bound_mesh.len().try_into().unwrap());
+    }
 
-        GpuProjector {
-            ctx: self.ctx,
-            out_texture,
-            out_staging,
+    fn bindings<'a>(&'a self, pass_info: &'a Buffer, view_mat: &'a Buffer) -> Bindings<'a> {
+        input_bindings(
             pass_info,
-            pass_info_data: Cell::new(PassInfo {
-                inp_sizes: self.input_size.into(),
-                bound_radius: f32::NAN,
-            }),
             view_mat,
-            inp_frames: Arc::new(inp_frames),
-            inp_specs,
-            bound_mesh,
-            back_cp,
+            &self.frames,
+            &self.specs,
+            &self.masks,
+            &self.history,
+            &self.gains,
+            &self.flow,
+        )
+    }
+}
+
+/// Group 0 of every pass, with the pass info and view matrix of the view being rendered.
+#[allow(clippy::too_many_arguments)]
+fn input_bindings<'a>(
+    pass_info: &'a Buffer,
+    view_mat: &'a Buffer,
+    frames: &'a Texture,
+    specs: &'a Buffer,
+    masks: &'a Texture,
+    history: &'a Texture,
+    gains: &'a Buffer,
+    flow: &'a Buffer,
+) -> Bindings<'a> {
+    Bindings::new()
+        .bind(pass_info.in_frag().in_compute())
+        .bind(view_mat.in_vertex())
+        .bind(frames.in_frag().in_compute())
+        .bind(specs.in_frag().in_compute())
+        .bind(masks.in_frag().in_compute())
+        .bind(history.in_frag().in_compute())
+        .bind(gains.in_frag())
+        .bind(flow.in_frag().in_compute())
+}
+
+/// Group 1 of every compute pass, with what they write.
+fn compute_bindings<'a>(
+    gain_stats: &'a Buffer,
+    auto_mask_out: &'a Texture,
+    motion_info: &'a Buffer,
+    motion_prev: &'a Buffer,
+    motion_stats: &'a Buffer,
+    flow_out: &'a Buffer,
+) -> Bindings<'a> {
+    Bindings::new()
+        .bind(gain_stats.in_compute())
+        .bind(auto_mask_out.in_compute())
+        .bind(motion_info.in_compute())
+        .bind(motion_prev.in_compute())
+        .bind(motion_stats.in_compute())
+        .bind(flow_out.in_compute())
+}
+
+impl StereoPass {
+    /// Passes for `grid`, starting from its `heights` without a pair to sweep with.
+    fn new(
+        ctx: &Context,
+        pass_info: &Buffer,
+        inputs: &GpuInputs,
+        mesh: &Buffer,
+        grid: HeightGrid,
+        heights: &[f32],
+    ) -> Self {
+        let info = Buffer::builder(ctx)
+            .label("stereo_info")
+            .size_for::<StereoInfo>()
+            .uniform()
+            .writable()
+            .build();
+        ctx.write_uniform(&info, &StereoInfo::new(None, grid));
+        let heights = Buffer::builder(ctx)
+            .label("stereo_heights")
+            .storage()
+            .writable()
+            .readable()
+            .build_with_data(&heights.repeat(2));
+
+        let (cp, mesh_cp) = Self::passes(ctx, pass_info, inputs, &info, &heights, mesh, grid);
+        Self {
+            info,
+            heights,
+            grid,
+            cp,
+            mesh_cp,
         }
     }
 
-    const fn input_bytes(&self) -> usize {
-        (self.input_size.0 * self.input_size.1 * self.input_size.2 * 4) as _
+    fn passes(
+        ctx: &Context,
+        pass_info: &Buffer,
+        inputs: &GpuInputs,
+        info: &Buffer,
+        heights: &Buffer,
+        mesh: &Buffer,
+        grid: HeightGrid,
+    ) -> (ComputeCheckpoint, ComputeCheckpoint) {
+        let group = || {
+            Bindings::new()
+                .bind(info.in_compute())
+                .bind(heights.in_compute())
+                .bind(mesh.in_compute())
+        };
+        let cp = inputs
+            .compute_pass_with(ctx, pass_info, "cs_stereo", group())
+            .work_groups(grid.w.div_ceil(8) as _, grid.h.div_ceil(8) as _, 1);
+        let mesh_len = (grid.w - 1) * (grid.h - 1) * 6;
+        let mesh_cp = inputs
+            .compute_pass_with(ctx, pass_info, "cs_stereo_mesh", group())
+            .work_groups(mesh_len.div_ceil(64) as _, 1, 1);
+        (cp, mesh_cp)
     }
 
-    fn generate_masks(&self) -> Box<[u32]> {
-        let img_size = self.input_size.0 * self.input_size.1;
+    /// Binds the passes to the new `inputs`.
+    fn rebind(&mut self, ctx: &Context, pass_info: &Buffer, inputs: &GpuInputs, mesh: &Buffer) {
+        (self.cp, self.mesh_cp) = Self::passes(
+            ctx,
+            pass_info,
+            inputs,
+            &self.info,
+            &self.heights,
+            mesh,
+            self.grid,
+        );
+    }
 
-        let mut out =
-            <[u32]>::new_box_zeroed_with_elems((img_size * self.input_size.2) as _).unwrap();
+    /// Sweeps the heights and moves the mesh to them.
+    fn encode(&self, ctx: &Context) -> CommandBuilder {
+        CommandBuilder::new(ctx).then(&self.cp).then(&self.mesh_cp)
+    }
+}
 
-        self.mask_paths
-            .iter()
-            .zip(out.chunks_mut(img_size as _))
-            .for_each(|(p, view)| {
-                let opt_data = p.as_deref().and_then(|p| {
-                    image::open(p)
-                        .inspect_err(|err| tracing::error!("failed to load mask {:?}: {err}", p))
-                        .ok()
-                });
-
-                if let Some(data) = opt_data {
-                    data.to_luma8()
-                        .iter()
-                        .zip(view)
-                        .for_each(|(p, o)| *o = if *p >= 128 { !0 } else { 0 });
-                } else {
-                    view.fill(!0);
-                }
-            });
+impl HeightGrid {
+    /// Two triangles for every 4 neighboring heights.
+    #[allow(clippy::cast_precision_loss)]
+    fn mesh(self, height: impl Fn(u32, u32) -> f32) -> Vec<Vertex> {
+        let Self { w, h, size } = self;
+        let vert = |x: u32, y: u32| {
+            Vertex::new(
+                (x as f32 / (w - 1) as f32 - 0.5) * size,
+                (0.5 - y as f32 / (h - 1) as f32) * size,
+                height(x, y),
+            )
+        };
 
+        let mut out = Vec::with_capacity(((w - 1) * (h - 1) * 6) as _);
+        for y in 0..h - 1 {
+            for x in 0..w - 1 {
+                out.extend([
+                    vert(x, y + 1),
+                    vert(x + 1, y + 1),
+                    vert(x + 1, y),
+                    vert(x + 1, y),
+                    vert(x, y),
+                    vert(x, y + 1),
+                ]);
+            }
+        }
         out
     }
 }
 
+/// Undistortion lookup table at `p` for frames of `w` by `h`, see [`Preprocess::undistort`].
+fn load_lut(p: &Path, [w, h]: [u32; 2]) -> Result<Vec<f32>> {
+    let bytes = std::fs::read(p).map_err(Error::io_ctx(format!("reading {p:?}")))?;
+    DimErrorKind::Bytes.check((w * h * 8) as _, bytes.len())?;
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect())
+}
+
+fn modified(p: Option<&PathBuf>) -> Option<SystemTime> {
+    std::fs::metadata(p?).and_then(|m| m.modified()).ok()
+}
+
 impl GpuProjector {
+    /// Most cameras the inputs can have, must match `MAX_CAMERAS` in the shader.
+    pub const MAX_CAMERAS: usize = 8;
+
     /// # Errors
     /// see [`smpgpu::ctx::ContextAdapterBuilder::request_adapter`] and [`smpgpu::ctx::ContextDeviceBuilder::request_build`]
     #[inline]
@@ -259,81 +1146,1359 @@ impl GpuProjector {
         ))
     }
 
+    /// Like [`Self::builder_auto`], building pipelines with the driver cache in `dir`, see
+    /// [`smpgpu::ctx::ContextDeviceBuilder::pipeline_cache_dir`].
+    ///
+    /// # Errors
+    /// see [`Self::builder_auto`], or the cache can't be read
     #[inline]
-    pub fn update_proj_view(&self, style: ProjectionStyle) {
-        match style {
-            ProjectionStyle::Hemisphere {
-                pos: [x, y, _],
-                radius,
-            } => {
-                let mut pass_info_data = self.pass_info_data.get();
-                pass_info_data.bound_radius = radius;
-                self.pass_info_data.set(pass_info_data);
-
-                self.ctx.write_uniform(&self.pass_info, &pass_info_data);
-                let out_size = self.out_texture.size();
-
-                let rh = radius;
-
-                #[allow(clippy::cast_precision_loss)]
-                let aspect = out_size.width as f32 / out_size.height as f32;
-
-                let view = Mat4::orthographic_rh(
-                    rh.mul_add(-aspect, x),
-                    rh.mul_add(aspect, x),
-                    -rh + y,
-                    rh + y,
-                    0.1,
-                    200.,
-                ) * Mat4::look_at_rh(
-                    glam::vec3(0., 0., 100.),
-                    glam::vec3(0., 0., 0.),
-                    glam::Vec3::Y,
-                );
-                self.ctx.write_uniform(&self.view_mat, &view);
-            }
-            ProjectionStyle::RawCamera(..) => todo!(),
-        }
+    pub async fn builder_cached(dir: impl Into<PathBuf>) -> Result<GpuProjectorBuilder<'static>> {
+        Ok(GpuProjectorBuilder::new(
+            smpgpu::Context::builder()
+                .request_adapter()
+                .await?
+                .pipeline_cache_dir(dir)
+                .request_build()
+                .await?,
+        ))
     }
 
+    /// Builds on the GPU of another projector, see [`Self::context`], so both share its memory
+    /// and pipelines instead of each opening the device.
+    #[must_use]
     #[inline]
-    pub fn update_cam_specs<T>(&self, cams: &[Camera<T>]) {
-        self.ctx.write_storage(
-            &self.inp_specs,
-            &cams
-                .iter()
-                .map(|c| c.view.into())
-                .collect::<Vec<InputSpec>>(),
-        );
+    pub const fn builder_shared(ctx: Arc<Context>) -> GpuProjectorBuilder<'static> {
+        GpuProjectorBuilder::new(ctx)
     }
 
+    /// GPU the projector renders on, to build others on with [`Self::builder_shared`].
+    #[must_use]
     #[inline]
-    pub fn update_render(&self) {
-        let back_cmd = self
-            .back_cp
-            .encoder(&*self.ctx)
-            .vert_buf(&self.bound_mesh)
-            .attach(&self.out_texture.render_attach())
-            .then(self.out_texture.copy_to_buf_op(&self.out_staging))
-            .build();
+    pub const fn context(&self) -> &Arc<Context> {
+        &self.ctx
+    }
 
-        self.ctx.submit([back_cmd]);
-        self.ctx.signal_wake();
+    /// Saves the pipelines compiled so far for the next projector built with
+    /// [`Self::builder_cached`], returning whether there was a cache to save.
+    ///
+    /// # Errors
+    /// the cache can't be written
+    #[inline]
+    pub fn save_pipeline_cache(&self) -> Result<bool> {
+        Ok(self.ctx.save_pipeline_cache()?)
     }
 
+    #[must_use]
     #[inline]
-    pub fn block_copy_render_to<T: DerefMut<Target = [u8]> + FrameSize>(&self, buf: &mut T) {
-        let cpy_fut = MemMapper::new()
-            .with_cb(&self.out_staging, |data| {
-                buf.copy_from_slice(&data);
-            })
-            .run_all();
+    pub fn adapter_info(&self) -> &smpgpu::reexport::AdapterInfo {
+        self.ctx.adapter_info()
+    }
 
+    /// Sets the projection style of the main view.
+    #[inline]
+    pub fn update_proj_view(&self, style: ProjectionStyle) {
+        let main = &self.views[0];
+        main.set_style(&self.ctx, style);
+
+        let mut pass_info_data = self.pass_info_data.get();
+        main.place(&mut pass_info_data);
+        self.pass_info_data.set(pass_info_data);
+        self.ctx.write_uniform(&self.pass_info, &pass_info_data);
+    }
+
+    /// Adds an output view of `w` by `h` pixels, rendered alongside the main view every
+    /// [`Self::update_render`] and read back with [`Self::block_copy_view_to`].
+    ///
+    /// # Panics
+    /// there is already a view called `name`
+    pub fn add_view(
+        &mut self,
+        name: impl Into<String>,
+        w: usize,
+        h: usize,
+        style: ProjectionStyle,
+    ) {
+        let name = name.into();
+        assert!(
+            self.views.iter().all(|v| v.name != name),
+            "view {name} already exists"
+        );
+
+        let view = self.new_view(name, w, h);
+        view.set_style(&self.ctx, style);
+        self.views.push(view);
+    }
+
+    /// Removes the view called `name`, doing nothing if there is none.
+    ///
+    /// # Panics
+    /// `name` is the main view
+    pub fn remove_view(&mut self, name: &str) {
+        assert!(name != MAIN_VIEW, "the main view can't be removed");
+        self.views.retain(|v| v.name != name);
+    }
+
+    /// Names of every output view, starting with the main view.
+    pub fn view_names(&self) -> impl Iterator<Item = &str> {
+        self.views.iter().map(|v| v.name.as_str())
+    }
+
+    /// Width and height of the view called `name`, if there is one.
+    #[must_use]
+    pub fn view_size(&self, name: &str) -> Option<(usize, usize)> {
+        let view = self.views.iter().find(|v| v.name == name)?;
+        let size = view.texture.size();
+        Some((size.width as _, size.height as _))
+    }
+
+    /// # Panics
+    /// there is no view called `name`
+    #[inline]
+    pub fn update_view_style(&self, name: &str, style: ProjectionStyle) {
+        if name == MAIN_VIEW {
+            self.update_proj_view(style);
+        } else {
+            self.view(name).set_style(&self.ctx, style);
+        }
+    }
+
+    /// Zooms the view called `name` into `crop` of its projection, keeping its resolution.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    #[inline]
+    pub fn update_view_crop(&self, name: &str, crop: ViewCrop) {
+        self.view(name).crop.set(crop.clamped());
+    }
+
+    /// Draws the ground grid and compass rose set by [`Self::set_ground_overlay`] on the view
+    /// called `name`, or stops drawing them.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    #[inline]
+    pub fn update_view_ground_overlay(&self, name: &str, shown: bool) {
+        self.view(name).ground_overlay.set(shown);
+    }
+
+    /// Draws `overlays` over the view called `name`, replacing any it had. Overlays past the
+    /// first 8 or of cameras that don't exist are left out.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn update_view_overlays(&self, name: &str, overlays: &[PipOverlay]) {
+        let view = self.view(name);
+        let size = view.texture.size();
+
+        let info = self.pass_info_data.get();
+        let inp_size = info.inp_sizes;
+        let mut specs = OverlaySpecs {
+            inp_size: inp_size.truncate(),
+            out_size: glam::uvec2(size.width, size.height),
+            exposure: info.exposure,
+            tone_curve: info.tone_curve,
+            color_space: info.color_space,
+            pips: [PipSpec::default(); MAX_PIPS],
+        };
+        if overlays.len() > MAX_PIPS {
+            tracing::warn!("view {name} only draws the first {MAX_PIPS} overlays");
+        }
+
+        let valid = overlays
+            .iter()
+            .filter(|o| {
+                let exists = o.camera < inp_size.z;
+                if !exists {
+                    tracing::warn!("view {name} has an overlay of missing camera {}", o.camera);
+                }
+                exists
+            })
+            .take(MAX_PIPS)
+            .collect::<Vec<_>>();
+        for (spec, o) in specs.pips.iter_mut().zip(&valid) {
+            *spec = PipSpec {
+                rect: o.rect.into(),
+                border_color: o.border_color.into(),
+                border_width: o.border_width,
+                cam: o.camera,
+            };
+        }
+
+        self.ctx.write_uniform(&view.overlays, &specs);
+        view.has_overlays.set(!valid.is_empty());
+        view.pips.replace(overlays.to_vec());
+
+        #[allow(clippy::cast_precision_loss)]
+        let pip_labels = valid
+            .iter()
+            .filter_map(|o| {
+                let [x, y, ..] = o.rect;
+                let inset = o.border_width + 2.0;
+                let mut label = HudLabel::new(
+                    o.label.clone()?,
+                    [
+                        x + inset / size.width as f32,
+                        y + inset / size.height as f32,
+                    ],
+                );
+                label.background = [0.0, 0.0, 0.0, 0.6];
+                Some(label)
+            })
+            .collect();
+        view.pip_labels.replace(pip_labels);
+        view.write_hud(&self.ctx);
+    }
+
+    /// Marks the view called `name` with `watermark` over everything else drawn on it, or
+    /// removes its watermark.
+    ///
+    /// # Errors
+    /// the watermark's picture can't be loaded, leaving the view as it was
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn update_view_watermark(&self, name: &str, watermark: Option<&Watermark>) -> Result<()> {
+        let view = self.view(name);
+        let image = watermark.map(watermark::open).transpose()?.flatten();
+        let pass = image.map(|img| {
+            let ctx = self.ctx.as_ref();
+            let image = Texture::builder(ctx)
+                .label(&format!("{name}_watermark"))
+                .size(img.width() as _, img.height() as _)
+                .writable()
+                .build();
+            ctx.write_texture_layer(&image, img.as_raw(), 0);
+
+            let spec = Buffer::builder(ctx)
+                .label(&format!("{name}_watermark_spec"))
+                .size_for::<WatermarkSpec>()
+                .uniform()
+                .writable()
+                .build();
+            let cp = RenderCheckpoint::builder(ctx)
+                .group(
+                    Bindings::new()
+                        .bind(spec.in_vertex().in_frag())
+                        .bind(image.in_frag()),
+                )
+                .shader(smpgpu::include_shader!(
+                    "shaders/watermark.wgsl" => "vs_watermark" & "fs_watermark"
+                ))
+                .frag_target_alpha(view.texture.format())
+                .build()
+                .vertices(0..6);
+            WatermarkPass { image, spec, cp }
+        });
+
+        view.watermark.replace(watermark.cloned());
+        view.watermark_pass.replace(pass);
+        view.place_watermark(&self.ctx);
+        view.write_hud(&self.ctx);
+        Ok(())
+    }
+
+    /// Column major clip from world matrix the view called `name` projects the ground with,
+    /// for the styles that have one.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    #[must_use]
+    pub fn view_matrix(&self, name: &str) -> Option<[f32; 16]> {
+        self.view(name).view_matrix().map(|m| m.to_cols_array())
+    }
+
+    /// Burns `labels` into the view called `name`, replacing any it had. Characters past the
+    /// first 1024 are left out.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn update_view_hud(&self, name: &str, labels: &[HudLabel]) {
+        let view = self.view(name);
+        view.hud_labels.replace(labels.to_vec());
+        view.write_hud(&self.ctx);
+    }
+
+    fn view(&self, name: &str) -> &OutputView {
+        self.views
+            .iter()
+            .find(|v| v.name == name)
+            .unwrap_or_else(|| panic!("no view called {name}"))
+    }
+
+    /// Renders the view called `name` at `w` by `h` from the next frame on, keeping its style,
+    /// crop, overlays and HUD. Like any view, `w` has to be a multiple of 64 so its rows can be
+    /// copied back as they are. Frames rendered before can't be read back anymore.
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    pub fn resize_view(&mut self, name: &str, w: usize, h: usize) {
+        let (texture, msaa, staging) = self.new_target(name, w, h);
+        let view = self
+            .views
+            .iter_mut()
+            .find(|v| v.name == name)
+            .unwrap_or_else(|| panic!("no view called {name}"));
+        view.texture = texture;
+        view.msaa = msaa;
+        view.staging = staging;
+
+        // the hemisphere's view matrix, overlays, watermark and labels all depend on the size
+        if let Some(style) = view.style.get() {
+            view.set_style(&self.ctx, style);
+        }
+        if name == MAIN_VIEW {
+            let mut info = self.pass_info_data.get();
+            info.out_size = glam::uvec2(w as _, h as _);
+            self.pass_info_data.set(info);
+        }
+        let view = self.view(name);
+        view.place_watermark(&self.ctx);
+        let overlays = view.pips.take();
+        self.update_view_overlays(name, &overlays);
+    }
+
+    /// Texture a view is rendered to, the multisampled one its mesh is rendered to first, and
+    /// its staging buffers, one per frame in flight.
+    fn new_target(
+        &self,
+        name: &str,
+        w: usize,
+        h: usize,
+    ) -> (Texture, Option<Texture>, Vec<Buffer>) {
+        let ctx = self.ctx.as_ref();
+        let texture = Texture::builder(ctx)
+            .label(&format!("{name}_texture"))
+            .size(w, h)
+            .format(self.out_format)
+            .render_target()
+            .readable()
+            .build();
+        let msaa = (self.msaa > 1).then(|| {
+            Texture::builder(ctx)
+                .label(&format!("{name}_msaa"))
+                .size(w, h)
+                .format(self.out_format)
+                .samples(self.msaa)
+                .render_target()
+                .build()
+        });
+        let staging = (0..self.frames_in_flight)
+            .map(|_| texture.new_staging(ctx))
+            .collect();
+        (texture, msaa, staging)
+    }
+
+    fn new_view(&self, name: String, w: usize, h: usize) -> OutputView {
+        let ctx = self.ctx.as_ref();
+
+        let (texture, msaa, staging) = self.new_target(&name, w, h);
+
+        let pass_info = Buffer::builder(ctx)
+            .label(&format!("{name}_pass_info"))
+            .size_for::<PassInfo>()
+            .uniform()
+            .writable()
+            .build();
+
+        let view_mat = Buffer::builder(ctx)
+            .label(&format!("{name}_view"))
+            .size_for::<glam::Mat4>()
+            .uniform()
+            .writable()
+            .build();
+
+        let overlays = Buffer::builder(ctx)
+            .label(&format!("{name}_overlays"))
+            .size_for::<OverlaySpecs>()
+            .uniform()
+            .writable()
+            .build();
+
+        let [back_cp, equirect_cp, cube_cp, dewarp_cp, raw_cp, overlay_cp] =
+            self.input_passes(&texture, &pass_info, &view_mat, &overlays);
+
+        let hud_chars = Buffer::builder(ctx)
+            .label(&format!("{name}_hud_chars"))
+            .size_for_many::<HudChar>(MAX_HUD_CHARS as _)
+            .storage()
+            .writable()
+            .build();
+
+        let hud_cp = RenderCheckpoint::builder(ctx)
+            .group(
+                Bindings::new()
+                    .bind(hud_chars.in_vertex().in_frag())
+                    .bind(self.glyphs.in_frag()),
+            )
+            .shader(smpgpu::include_shader!("shaders/hud.wgsl" => "vs_hud" & "fs_hud"))
+            .frag_target_alpha(texture.format())
+            .build()
+            .vertices(0..6)
+            .instances(0..MAX_HUD_CHARS as _);
+
+        OutputView {
+            name,
+            texture,
+            msaa,
+            staging,
+            pass_info,
+            view_mat,
+            back_cp,
+            equirect_cp,
+            cube_cp,
+            dewarp_cp,
+            raw_cp,
+            overlays,
+            overlay_cp,
+            has_overlays: Cell::new(false),
+            pips: RefCell::new(Vec::new()),
+            hud_chars,
+            hud_cp,
+            hud_labels: RefCell::new(Vec::new()),
+            pip_labels: RefCell::new(Vec::new()),
+            hud_len: Cell::new(0),
+            watermark: RefCell::new(None),
+            watermark_pass: RefCell::new(None),
+            watermark_labels: RefCell::new(Vec::new()),
+            style: Cell::new(None),
+            crop: Cell::new(ViewCrop::FULL),
+            ground_overlay: Cell::new(false),
+        }
+    }
+
+    /// Passes of a view that read the inputs: one for each projection style, then the picture
+    /// in picture overlays.
+    fn input_passes(
+        &self,
+        texture: &Texture,
+        pass_info: &Buffer,
+        view_mat: &Buffer,
+        overlays: &Buffer,
+    ) -> [RenderCheckpoint; 6] {
+        let ctx = self.ctx.as_ref();
+        let format = texture.format();
+        let bindings = || self.inputs.bindings(pass_info, view_mat);
+
+        let back_cp = RenderCheckpoint::builder(ctx)
+            .group(bindings())
+            .shader(smpgpu::include_shader!("shaders/render.wgsl" => "vs_proj" & "fs_proj"))
+            .vert_buffer_of::<Vertex>(&smpgpu::vertex_attr_array![0 => Float32x4])
+            .frag_target(format)
+            .multisample(self.msaa)
+            .build()
+            .vertices(0..self.mesh_len);
+
+        let equirect_cp = RenderCheckpoint::builder(ctx)
+            .group(bindings())
+            .shader(smpgpu::include_shader!("shaders/render.wgsl" => "vs_full" & "fs_equirect"))
+            .frag_target(format)
+            .build()
+            .vertices(0..3);
+
+        let cube_cp = RenderCheckpoint::builder(ctx)
+            .group(bindings())
+            .shader(smpgpu::include_shader!("shaders/render.wgsl" => "vs_full" & "fs_cube"))
+            .frag_target(format)
+            .build()
+            .vertices(0..3);
+
+        let dewarp_cp = RenderCheckpoint::builder(ctx)
+            .group(bindings())
+            .shader(smpgpu::include_shader!("shaders/render.wgsl" => "vs_full" & "fs_dewarp"))
+            .frag_target(format)
+            .build()
+            .vertices(0..3);
+
+        let raw_cp = RenderCheckpoint::builder(ctx)
+            .group(bindings())
+            .shader(smpgpu::include_shader!("shaders/render.wgsl" => "vs_full" & "fs_raw"))
+            .frag_target(format)
+            .build()
+            .vertices(0..3);
+
+        let overlay_cp = RenderCheckpoint::builder(ctx)
+            .group(
+                Bindings::new()
+                    .bind(overlays.in_vertex().in_frag())
+                    .bind(self.inputs.frames.in_frag()),
+            )
+            .shader(smpgpu::include_shader!("shaders/overlay.wgsl" => "vs_pip" & "fs_pip"))
+            .frag_target(format)
+            .build()
+            .vertices(0..6)
+            .instances(0..MAX_PIPS as _);
+
+        [back_cp, equirect_cp, cube_cp, dewarp_cp, raw_cp, overlay_cp]
+    }
+
+    /// Replaces the mask of camera `idx`, rescaled like the masks given when building.
+    ///
+    /// # Errors
+    /// the mask doesn't match the input size while strict masks are set
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras
+    pub fn update_mask(&self, idx: usize, mask: image::GrayImage) -> Result<()> {
+        let size = self.pass_info_data.get().inp_sizes;
+        assert!(
+            idx < size.z as usize,
+            "no camera {idx} to update the mask of"
+        );
+
+        let mask = mask::fit(
+            &format!("for camera {idx}"),
+            mask,
+            (size.x, size.y),
+            self.strict_masks,
+        )?;
+
+        let bytes = mask
+            .iter()
+            .flat_map(|p| mask::value(*p).to_ne_bytes())
+            .collect::<Vec<_>>();
+        self.ctx
+            .write_texture_layer(&self.inputs.masks, &bytes, idx.try_into()?);
+
+        Ok(())
+    }
+
+    /// Changes how camera frames are filtered from the next [`Self::update_render`].
+    pub fn set_sampling(&self, sampling: Sampling) {
+        let mut pass_info_data = self.pass_info_data.get();
+        pass_info_data.sampling = sampling.id();
+        self.pass_info_data.set(pass_info_data);
+    }
+
+    /// Changes the exposure and curve of the views from the next [`Self::update_render`].
+    pub fn set_tone_map(&self, tone_map: ToneMap) {
+        let mut pass_info_data = self.pass_info_data.get();
+        pass_info_data.exposure = tone_map.scale();
+        pass_info_data.tone_curve = tone_map.curve.id();
+        self.pass_info_data.set(pass_info_data);
+        self.rewrite_overlays();
+    }
+
+    /// Changes how the frames and views are encoded from the next [`Self::update_render`].
+    pub fn set_color_space(&self, color_space: ColorSpace) {
+        let mut pass_info_data = self.pass_info_data.get();
+        pass_info_data.color_space = color_space.id();
+        self.pass_info_data.set(pass_info_data);
+        self.rewrite_overlays();
+    }
+
+    /// Changes the ground grid and compass rose of every view that draws them from the next
+    /// [`Self::update_render`], see [`Self::update_view_ground_overlay`].
+    pub fn set_ground_overlay(&self, ground: &GroundOverlay) {
+        let heading = ground.heading.to_radians();
+        let mut pass_info_data = self.pass_info_data.get();
+        pass_info_data.grid_spacing = ground.spacing.max(0.0);
+        pass_info_data.grid_radius = ground.radius;
+        pass_info_data.compass_radius = ground.compass.max(0.0);
+        pass_info_data.north = glam::vec2(-heading.sin(), heading.cos());
+        pass_info_data.ground_color = ground.color.into();
+        self.pass_info_data.set(pass_info_data);
+    }
+
+    /// Watches the zones of `motion` from the next [`Self::update_render`], starting over
+    /// without a previous frame to compare with.
+    pub fn set_motion(&self, motion: &MotionConfig) {
+        let info = MotionInfo::new(motion);
+        self.motion_info.set(info);
+        self.ctx.write_uniform(&self.inputs.motion_info, &info);
+        self.ctx.write_storage(
+            &self.inputs.motion_prev,
+            &vec![-1.0f32; (self.inputs.motion_prev.size() / 4) as usize],
+        );
+    }
+
+    /// Part of the points of every motion zone, in the order given, whose brightness changed
+    /// between the last two frames rendered, out of the ones seen in both. `None` until motion
+    /// has been read back with a view since the last call.
+    pub fn take_motion(&self) -> Option<Vec<f32>> {
+        self.motion.take()
+    }
+
+    /// Writes the overlays of every view again, for changes to what they depend on.
+    fn rewrite_overlays(&self) {
+        for view in &self.views {
+            let overlays = view.pips.take();
+            self.update_view_overlays(&view.name, &overlays);
+        }
+    }
+
+    /// Replaces every camera's mask with the pixels that see the ground or dome of the current
+    /// projection style at less than `max_incidence` radians from straight on, so it should be
+    /// called after [`Self::update_cam_specs`] and [`Self::update_proj_view`].
+    pub fn auto_masks(&self, max_incidence: f32) {
+        let mut pass_info_data = self.pass_info_data.get();
+        pass_info_data.mask_incidence = max_incidence.cos();
+        self.pass_info_data.set(pass_info_data);
+        self.ctx.write_uniform(&self.pass_info, &pass_info_data);
+
+        let cmd = self
+            .inputs
+            .auto_mask_cp
+            .encoder(&*self.ctx)
+            .then(self.inputs.auto_mask_out.copy_layers_to_op(
+                0,
+                self.inputs.frames.size().depth_or_array_layers,
+                &self.inputs.masks,
+                0,
+            ))
+            .build();
+        self.ctx.submit([cmd]);
+        self.ctx.signal_wake();
+    }
+
+    /// Width and height of the grid [`Self::update_world_heights`] expects, which is the world's
+    /// heightmap after any downscaling.
+    #[must_use]
+    pub fn world_grid_size(&self) -> Option<(u32, u32)> {
+        self.world_grid.map(|g| (g.w, g.h))
+    }
+
+    /// Moves the ground to `heights`, for example from live depth data, given row by row from
+    /// north to south over the grid in [`Self::world_grid_size`].
+    ///
+    /// # Errors
+    /// the world isn't a [`WorldStyle::HeightField`], or `heights` doesn't cover the
+    /// heightmap's grid
+    pub fn update_world_heights(&self, heights: &[f32]) -> Result<()> {
+        let grid = self.world_grid.ok_or(Error::NotHeightField)?;
+        DimErrorKind::Heights.check((grid.w * grid.h) as _, heights.len())?;
+
+        let mesh = grid.mesh(|x, y| heights[(y * grid.w + x) as usize]);
+        self.ctx.write_storage(&self.bound_mesh, &mesh);
+        if let Some(stereo) = &self.stereo {
+            // points the stereo pair can't match fall back to these
+            self.ctx.write_storage(&stereo.heights, &heights.repeat(2));
+        }
+        Ok(())
+    }
+
+    /// Finds the heights of the height field with the pair of cameras of `stereo` every
+    /// [`StereoConfig::interval`] frames from the next [`Self::update_render`], or puts back the
+    /// heightmap's when `None`.
+    ///
+    /// # Errors
+    /// the world isn't a [`WorldStyle::HeightField`], or the pair isn't two different cameras
+    pub fn set_stereo(&self, stereo: Option<&StereoConfig>) -> Result<()> {
+        let Some(pass) = &self.stereo else {
+            return match stereo {
+                Some(_) => Err(Error::Stereo("the world isn't a height field")),
+                None => Ok(()),
+            };
+        };
+
+        if let Some(cfg) = stereo {
+            let n = self.pass_info_data.get().inp_sizes.z as usize;
+            let [a, b] = cfg.cameras;
+            if a == b || a >= n || b >= n {
+                return Err(Error::Stereo("the pair isn't two different cameras"));
+            }
+        }
+
+        self.ctx
+            .write_uniform(&pass.info, &StereoInfo::new(stereo, pass.grid));
+        match stereo {
+            Some(cfg) => self.stereo_interval.set(cfg.interval.max(1)),
+            None => {
+                // without steps, every point goes back to the heightmap
+                self.stereo_interval.set(0);
+                self.ctx.submit([pass.encode(&self.ctx).build()]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reloads every mask whose file changed since it was loaded, if built with
+    /// [`GpuProjectorBuilder::watch_masks`]. Files are checked at most once a second and
+    /// ones that fail to load are retried on the next check.
+    pub fn reload_changed_masks(&self) {
+        let Some(watch) = &self.mask_watch else {
+            return;
+        };
+        let mut watch = watch.borrow_mut();
+        if watch.last_poll.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        watch.last_poll = Instant::now();
+
+        let MaskWatch { paths, loaded, .. } = &mut *watch;
+        for (idx, (p, loaded)) in paths.iter().zip(loaded).enumerate() {
+            let Some(p) = p else {
+                continue;
+            };
+            let Some(time) = modified(Some(p)).filter(|t| Some(*t) != *loaded) else {
+                continue;
+            };
+
+            let res = mask::open(p).and_then(|img| self.update_mask(idx, img));
+            match res {
+                Ok(()) => {
+                    tracing::info!("reloaded mask {p:?}");
+                    *loaded = Some(time);
+                }
+                Err(err) => tracing::warn!("failed to reload mask: {err}"),
+            }
+        }
+    }
+
+    /// Rebuilds every pass whose shader file changed, which is only watched in debug builds so
+    /// shaders can be edited while running. Files are checked at most once a second, and a
+    /// shader that fails to compile is logged and leaves its pass as it was.
+    pub fn reload_changed_shaders(&self) {
+        if self.shader_poll.get().elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.shader_poll.set(Instant::now());
+
+        let ctx = &*self.ctx;
+        let inputs = &self.inputs;
+        let compute = [
+            &inputs.gain_cp,
+            &inputs.auto_mask_cp,
+            &inputs.motion_cp,
+            &inputs.flow_cp,
+        ]
+        .into_iter()
+        .chain(inputs.preps.iter().flatten().map(|p| &p.cp))
+        .chain(self.stereo.iter().flat_map(|s| [&s.cp, &s.mesh_cp]))
+        .map(|cp| cp.reload_changed(ctx));
+        let render = self
+            .views
+            .iter()
+            .flat_map(|v| {
+                [
+                    &v.back_cp,
+                    &v.equirect_cp,
+                    &v.cube_cp,
+                    &v.dewarp_cp,
+                    &v.raw_cp,
+                    &v.overlay_cp,
+                    &v.hud_cp,
+                ]
+            })
+            .map(|cp| cp.reload_changed(ctx));
+        let watermarks = self.views.iter().filter_map(|v| {
+            let pass = v.watermark_pass.borrow();
+            pass.as_ref().map(|p| p.cp.reload_changed(ctx))
+        });
+
+        let mut reloaded = 0;
+        for res in compute.chain(render).chain(watermarks) {
+            match res {
+                Ok(changed) => reloaded += usize::from(changed),
+                Err(err) => tracing::warn!("{err}"),
+            }
+        }
+        if reloaded > 0 {
+            tracing::info!("reloaded the shaders of {reloaded} passes");
+        }
+    }
+
+    /// Switches camera `idx` to the mask at `path`, or to no mask at all, watching the new file
+    /// instead of the old one if built with [`GpuProjectorBuilder::watch_masks`].
+    ///
+    /// # Errors
+    /// the mask can't be loaded, or doesn't match the input size while strict masks are set
+    pub fn replace_mask(&self, idx: usize, path: Option<PathBuf>) -> Result<()> {
+        self.update_mask(idx, self.open_mask(path.as_ref())?)?;
+
+        if let Some(watch) = &self.mask_watch {
+            let mut watch = watch.borrow_mut();
+            watch.loaded[idx] = modified(path.as_ref());
+            watch.paths[idx] = path;
+        }
+        Ok(())
+    }
+
+    /// Mask at `path`, or one that keeps every pixel.
+    fn open_mask(&self, path: Option<&PathBuf>) -> Result<image::GrayImage> {
+        Ok(match path {
+            Some(p) => mask::open(p)?,
+            None => {
+                let size = self.pass_info_data.get().inp_sizes;
+                image::GrayImage::from_pixel(size.x, size.y, image::Luma([u8::MAX]))
+            }
+        })
+    }
+
+    /// Sets where every camera is and how it sees, keeping the previous specs if any can't
+    /// be used.
+    ///
+    /// # Errors
+    /// a camera's focal distance isn't set
+    #[inline]
+    pub fn update_cam_specs<T>(&self, cams: &[Camera<T>]) -> Result<()> {
+        let specs = cams
+            .iter()
+            .map(|c| c.view.try_into())
+            .collect::<Result<_>>()?;
+        self.inp_specs_data.replace(specs);
+        self.write_cam_specs();
+        Ok(())
+    }
+
+    /// Leaves camera `idx` out of every view and stops loading its frames while disabled,
+    /// so a failed camera doesn't take the others down with it.
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras
+    pub fn set_camera_enabled(&self, idx: usize, enabled: bool) {
+        self.cam_enabled.borrow_mut()[idx] = enabled;
+        self.write_cam_specs();
+    }
+
+    /// Pushes the frame of every camera ahead by its number of `leads` of its own time between
+    /// frames, along the motion since its previous frame, so cameras behind the newest one line
+    /// up with it. Only moves frames when built with
+    /// [`GpuProjectorBuilder::latency_compensation`], and cameras left out stay where they are.
+    pub fn set_camera_leads(&self, leads: &[f32]) {
+        let leads = leads.iter().map(|l| l.max(0.0));
+        self.cam_leads.replace(leads.collect());
+        self.write_cam_specs();
+    }
+
+    fn write_cam_specs(&self) {
+        let mut specs = self.inp_specs_data.borrow_mut();
+        for (spec, &enabled) in specs.iter_mut().zip(self.cam_enabled.borrow().iter()) {
+            spec.enabled = enabled.into();
+        }
+        for (spec, &lead) in specs.iter_mut().zip(self.cam_leads.borrow().iter()) {
+            spec.lead = lead;
+        }
+        if !specs.is_empty() {
+            self.ctx.write_storage(&self.inputs.specs, &*specs);
+        }
+    }
+
+    /// Number of cameras the inputs are rendered from.
+    #[must_use]
+    pub fn camera_count(&self) -> usize {
+        self.pass_info_data.get().inp_sizes.z as _
+    }
+
+    /// Inserts `cam` as camera `idx` with the mask at `mask_path`, preprocessed as `prep`
+    /// says, moving every camera from `idx` on up one with its mask, so cameras can be swapped
+    /// without building a new projector. The history and gains start over.
+    ///
+    /// # Errors
+    /// the camera's frames aren't the input size once preprocessed, its focal distance isn't
+    /// set, its mask can't be loaded, there are already [`Self::MAX_CAMERAS`] cameras, or see
+    /// [`Self::set_preprocess`]
+    ///
+    /// # Panics
+    /// `idx` is past the last camera
+    pub fn add_camera(
+        &mut self,
+        idx: usize,
+        cam: &Camera<Loader<GpuDirectBufferWrite>>,
+        mask_path: Option<PathBuf>,
+        prep: &Preprocess,
+    ) -> Result<()> {
+        let n = self.camera_count();
+        assert!(idx <= n, "can't add camera {idx} after the last of {n}");
+        if n >= Self::MAX_CAMERAS {
+            return Err(Error::TooManyCameras(Self::MAX_CAMERAS));
+        }
+
+        let size = self.pass_info_data.get().inp_sizes;
+        let spec = InputSpec::try_from(cam.view)?;
+        let prep_info = self.prep_info(&cam.data, prep)?;
+        let mask = mask::fit(
+            &format!("for camera {idx}"),
+            self.open_mask(mask_path.as_ref())?,
+            (size.x, size.y),
+            self.strict_masks,
+        )?;
+
+        let keep = (0..=n)
+            .map(|i| match i.cmp(&idx) {
+                std::cmp::Ordering::Less => Some(i),
+                std::cmp::Ordering::Equal => None,
+                std::cmp::Ordering::Greater => Some(i - 1),
+            })
+            .collect::<Vec<_>>();
+        self.resize_inputs(&keep);
+        self.update_mask(idx, mask)?;
+        self.inputs.preps[idx] = prep_info.map(|(info, lut)| self.new_prep(idx, info, &lut));
+
+        // specs only exist once they've been given
+        let mut specs = self.inp_specs_data.borrow_mut();
+        if specs.len() == n {
+            specs.insert(idx, spec);
+        }
+        drop(specs);
+        self.cam_enabled.borrow_mut().insert(idx, true);
+        if let Some(watch) = &self.mask_watch {
+            let mut watch = watch.borrow_mut();
+            watch.loaded.insert(idx, modified(mask_path.as_ref()));
+            watch.paths.insert(idx, mask_path);
+        }
+        self.write_cam_specs();
+        Ok(())
+    }
+
+    /// Removes camera `idx`, moving every camera after it down one with its mask. Like
+    /// [`Self::add_camera`], the history and gains start over.
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras, or it's the only one
+    pub fn remove_camera(&mut self, idx: usize) {
+        let n = self.camera_count();
+        assert!(idx < n, "no camera {idx} to remove");
+        assert!(n > 1, "the last camera can't be removed");
+
+        let keep = (0..n).filter(|&i| i != idx).map(Some).collect::<Vec<_>>();
+        self.resize_inputs(&keep);
+
+        let mut specs = self.inp_specs_data.borrow_mut();
+        if specs.len() == n {
+            specs.remove(idx);
+        }
+        drop(specs);
+        self.cam_enabled.borrow_mut().remove(idx);
+        if let Some(watch) = &self.mask_watch {
+            let mut watch = watch.borrow_mut();
+            watch.loaded.remove(idx);
+            watch.paths.remove(idx);
+        }
+        self.write_cam_specs();
+    }
+
+    /// Replaces the inputs with ones for a camera in every slot of `keep`, copying the frame
+    /// and mask of the old camera in it, and rebinds every pass that reads them.
+    fn resize_inputs(&mut self, keep: &[Option<usize>]) {
+        let mut info = self.pass_info_data.get();
+        let (w, h) = (info.inp_sizes.x, info.inp_sizes.y);
+        let mut inputs = GpuInputs::new(
+            &self.ctx,
+            &self.pass_info,
+            (w, h, keep.len().try_into().unwrap()),
+            self.inputs.frames.format(),
+            info.hist_cap,
+            None,
+        );
+
+        // kept cameras go on loading into the same upload buffer
+        let mut copy = CommandBuilder::new(&*self.ctx);
+        for (old, i) in keep.iter().zip(0u32..) {
+            let Some(old) = old else {
+                continue;
+            };
+            inputs.uploads[i as usize] = self.inputs.uploads[*old].clone();
+            inputs.preps[i as usize] = self.inputs.preps[*old].take();
+            let old = (*old).try_into().unwrap();
+            copy = copy
+                .then(
+                    self.inputs
+                        .frames
+                        .copy_layers_to_op(old, 1, &inputs.frames, i),
+                )
+                .then(
+                    self.inputs
+                        .masks
+                        .copy_layers_to_op(old, 1, &inputs.masks, i),
+                );
+        }
+        self.ctx.submit([copy.build()]);
+        self.inputs = inputs;
+        if let Some(stereo) = &mut self.stereo {
+            stereo.rebind(&self.ctx, &self.pass_info, &self.inputs, &self.bound_mesh);
+        }
+        self.ctx
+            .write_uniform(&self.inputs.motion_info, &self.motion_info.get());
+
+        info.inp_sizes.z = keep.len() as _;
+        info.hist_len = 0;
+        info.hist_head = info.hist_cap.saturating_sub(1);
+        self.pass_info_data.set(info);
+        self.ctx.write_uniform(&self.pass_info, &info);
+        self.gain_pending.set(false);
+        self.motion_pending.set(false);
+
+        let mut views = std::mem::take(&mut self.views);
+        for view in &mut views {
+            [
+                view.back_cp,
+                view.equirect_cp,
+                view.cube_cp,
+                view.dewarp_cp,
+                view.raw_cp,
+                view.overlay_cp,
+            ] = self.input_passes(
+                &view.texture,
+                &view.pass_info,
+                &view.view_mat,
+                &view.overlays,
+            );
+        }
+        self.views = views;
+
+        // overlays are checked against the new number of cameras
+        self.rewrite_overlays();
+    }
+
+    /// Renders every view from the current input frames in a single submission.
+    #[inline]
+    pub fn update_render(&self) {
+        let mut pass_info_data = self.pass_info_data.get();
+        self.ctx.write_uniform(&self.pass_info, &pass_info_data);
+
+        let frame = self.frame_count.get();
+        self.frame_count.set(frame.wrapping_add(1));
+        let slot = frame as usize % self.frames_in_flight;
+
+        // disabled cameras keep their last frame, which is never read
+        let enabled = self.cam_enabled.borrow();
+        // preprocessed frames are copied once their pass has run
+        let upload_cmd = self
+            .inputs
+            .uploads
+            .iter()
+            .zip(&self.inputs.preps)
+            .zip(0..)
+            .filter(|&(_, i)| enabled[i as usize])
+            .fold(
+                CommandBuilder::new(&*self.ctx),
+                |cmd, ((buf, prep), i)| match prep {
+                    Some(prep) => cmd
+                        .then(&prep.cp)
+                        .then(self.inputs.frames.copy_from_buf_op(&prep.out, i)),
+                    None => cmd.then(self.inputs.frames.copy_from_buf_op(buf, i)),
+                },
+            );
+        drop(enabled);
+
+        let mut view_cmds = self
+            .views
+            .iter()
+            .flat_map(|v| v.encode(&self.ctx, pass_info_data, &self.bound_mesh, slot))
+            .collect::<Vec<_>>();
+
+        // motion is found from the previous frames, before they're pushed to the history
+        let flow_cmd = (self.latency_compensation && pass_info_data.hist_len > 0).then(|| {
+            self.inputs
+                .flow_cp
+                .encoder(&*self.ctx)
+                .then(self.inputs.flow_out.copy_to_buf_op(&self.inputs.flow))
+                .build()
+        });
+
+        let solve_gains = self.gain_interval > 0 && frame.is_multiple_of(self.gain_interval);
+        let gain_cmd = solve_gains.then(|| {
+            self.ctx.write_storage(
+                &self.inputs.gain_stats,
+                &vec![0u32; (self.inputs.gain_stats.size() / 4) as usize],
+            );
+            self.gain_pending.set(true);
+            self.inputs
+                .gain_cp
+                .encoder(&*self.ctx)
+                .then(
+                    self.inputs
+                        .gain_stats
+                        .copy_to_buf_op(&self.inputs.gain_staging),
+                )
+                .build()
+        });
+
+        let motion_cmd = (self.motion_info.get().zones > 0).then(|| {
+            self.ctx.write_storage(
+                &self.inputs.motion_stats,
+                &vec![0u32; (self.inputs.motion_stats.size() / 4) as usize],
+            );
+            self.motion_pending.set(true);
+            self.inputs
+                .motion_cp
+                .encoder(&*self.ctx)
+                .then(
+                    self.inputs
+                        .motion_stats
+                        .copy_to_buf_op(&self.inputs.motion_staging),
+                )
+                .build()
+        });
+
+        let stereo_interval = self.stereo_interval.get();
+        let stereo_cmd = self
+            .stereo
+            .as_ref()
+            .filter(|_| stereo_interval > 0 && frame.is_multiple_of(stereo_interval))
+            .map(|s| s.encode(&self.ctx).build());
+
+        // the current frames are pushed after rendering, so shaders only ever see previous ones
+        if pass_info_data.hist_cap > 0 {
+            let slot = (pass_info_data.hist_head + 1) % pass_info_data.hist_cap;
+            let n = pass_info_data.inp_sizes.z;
+            let push_op =
+                self.inputs
+                    .frames
+                    .copy_layers_to_op(0, n, &self.inputs.history, slot * n);
+            let last = view_cmds.pop().unwrap().then(push_op);
+            view_cmds.push(last);
+
+            pass_info_data.hist_head = slot;
+            pass_info_data.hist_len = (pass_info_data.hist_len + 1).min(pass_info_data.hist_cap);
+            self.pass_info_data.set(pass_info_data);
+        }
+
+        self.ctx.submit(
+            [upload_cmd.build()]
+                .into_iter()
+                .chain(flow_cmd)
+                .chain(gain_cmd)
+                .chain(motion_cmd)
+                .chain(stereo_cmd)
+                .chain(view_cmds.into_iter().map(CommandBuilder::build)),
+        );
         self.ctx.signal_wake();
+    }
+
+    /// Waits for the GPU to finish everything submitted so far, like renders that haven't
+    /// been read back.
+    #[inline]
+    pub fn block_until_idle(&self) {
+        _ = self.ctx.block_poll_device();
+    }
 
-        Handle::current().block_on(cpy_fut);
+    /// Reads back the main view.
+    ///
+    /// # Errors
+    /// see [`Self::block_copy_view_to`]
+    #[inline]
+    pub fn block_copy_render_to<T: DerefMut<Target = [u8]> + FrameSize>(
+        &self,
+        buf: &mut T,
+    ) -> Result<()> {
+        self.block_copy_staging(self.staging(&self.views[0], 0), buf)
     }
 
+    /// Reads back the view called `name`.
+    ///
+    /// # Errors
+    /// the view's buffer can't be mapped, like once the device is lost
+    ///
+    /// # Panics
+    /// there is no view called `name`
+    #[inline]
+    pub fn block_copy_view_to<T: DerefMut<Target = [u8]> + FrameSize>(
+        &self,
+        name: &str,
+        buf: &mut T,
+    ) -> Result<()> {
+        self.block_copy_staging(self.staging(self.view(name), 0), buf)
+    }
+
+    /// Reads back the view called `name` as rendered by the [`Self::update_render`] before the
+    /// latest one, which the GPU can still be working on.
+    ///
+    /// # Errors
+    /// see [`Self::block_copy_view_to`]
+    ///
+    /// # Panics
+    /// there is no view called `name`, or the projector was built with only one frame in flight
+    #[inline]
+    pub fn block_copy_prev_view_to<T: DerefMut<Target = [u8]> + FrameSize>(
+        &self,
+        name: &str,
+        buf: &mut T,
+    ) -> Result<()> {
+        assert!(
+            self.frames_in_flight > 1,
+            "reading back the previous frame needs more than one frame in flight"
+        );
+        self.block_copy_staging(self.staging(self.view(name), 1), buf)
+    }
+
+    /// Staging buffer of `view` written by the render `frames_ago` before the latest.
+    fn staging<'v>(&self, view: &'v OutputView, frames_ago: u32) -> &'v Buffer {
+        let frame = self.frame_count.get().wrapping_sub(1 + frames_ago);
+        &view.staging[frame as usize % self.frames_in_flight]
+    }
+
+    /// Copies `staging` into `buf`, solving the gains and reading the motion too if their stats
+    /// are waiting.
+    fn block_copy_staging<T: DerefMut<Target = [u8]>>(
+        &self,
+        staging: &Buffer,
+        buf: &mut T,
+    ) -> Result<()> {
+        let mut stats = None;
+        let mut motion = None;
+        let mut mapper = MemMapper::new().with_cb(staging, |data| {
+            buf.copy_from_slice(&data);
+        });
+        if self.gain_pending.replace(false) {
+            mapper = mapper.with_cb(&self.inputs.gain_staging, |data| {
+                stats = Some(
+                    data.chunks_exact(4)
+                        .map(|b| u32::from_ne_bytes(b.try_into().unwrap()))
+                        .collect::<Vec<_>>(),
+                );
+            });
+        }
+        if self.motion_pending.replace(false) {
+            mapper = mapper.with_cb(&self.inputs.motion_staging, |data| {
+                motion = Some(
+                    data.chunks_exact(4)
+                        .map(|b| u32::from_ne_bytes(b.try_into().unwrap()))
+                        .collect::<Vec<_>>(),
+                );
+            });
+        }
+        let cpy_fut = mapper.run_all();
+
+        self.ctx.signal_wake();
+
+        Handle::current().block_on(cpy_fut).map_err(Error::GpuMap)?;
+
+        if let Some(stats) = stats {
+            let n = self.pass_info_data.get().inp_sizes.z as usize;
+            self.ctx
+                .write_storage(&self.inputs.gains, &gain::solve(&stats, n));
+        }
+        if let Some(motion) = motion {
+            #[allow(clippy::cast_precision_loss)]
+            let changed = motion
+                .chunks_exact(2)
+                .take(self.motion_info.get().zones as _)
+                .map(|c| c[1] as f32 / c[0].max(1) as f32)
+                .collect();
+            self.motion.replace(Some(changed));
+        }
+        Ok(())
+    }
+
+    /// Preprocesses the frames of camera `idx` on the GPU as `prep` says before they're
+    /// projected, which also decodes YUYV and NV12 frames. Frames that need neither are
+    /// copied to the camera's layer as they are.
+    ///
+    /// # Errors
+    /// the frames aren't the input size once turned, are RGB or gray, are larger than the
+    /// upload buffers, or the lookup table can't be loaded or doesn't match the frames
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras
+    pub fn set_preprocess(
+        &mut self,
+        idx: usize,
+        cam: &Camera<Loader<GpuDirectBufferWrite>>,
+        prep: &Preprocess,
+    ) -> Result<()> {
+        let info = self.prep_info(&cam.data, prep)?;
+        self.inputs.preps[idx] = info.map(|(info, lut)| self.new_prep(idx, info, &lut));
+        Ok(())
+    }
+
+    /// What the pass of frames from `data` preprocessed as `prep` needs, with its lookup
+    /// table, or none when they can be copied as they are.
+    ///
+    /// # Errors
+    /// see [`Self::set_preprocess`]
+    fn prep_info(
+        &self,
+        data: &Loader<GpuDirectBufferWrite>,
+        prep: &Preprocess,
+    ) -> Result<Option<(PrepInfo, Vec<f32>)>> {
+        let size = self.pass_info_data.get().inp_sizes;
+        let src_size = [data.width() as u32, data.height() as u32];
+        let [w, h] = prep.rotate.turn(src_size);
+        DimErrorKind::Width.check(size.x as _, w as _)?;
+        DimErrorKind::Height.check(size.y as _, h as _)?;
+
+        let src_format = match data.pixel_format() {
+            PixelFormat::Rgba8 => 0,
+            PixelFormat::Rgba16F => 1,
+            PixelFormat::Yuyv => 2,
+            PixelFormat::Nv12 => 3,
+            f @ (PixelFormat::Rgb8 | PixelFormat::Gray8) => {
+                return Err(DimErrorKind::Channel.err(4, f.chans()).into());
+            }
+        };
+        let out_half = u32::from(self.inputs.frames.format() == TextureFormat::Rgba16Float);
+        let upload_bytes = (w * h * self.inputs.frames.pixel_bytes()) as usize;
+        if prep.is_none() && src_format == out_half {
+            DimErrorKind::Bytes.check(upload_bytes, data.num_bytes())?;
+            return Ok(None);
+        }
+        if data.num_bytes() > upload_bytes {
+            return Err(DimErrorKind::Bytes
+                .err(upload_bytes, data.num_bytes())
+                .into());
+        }
+
+        let lut = match &prep.undistort {
+            Some(p) => load_lut(p, src_size)?,
+            None => Vec::new(),
+        };
+        let info = PrepInfo {
+            src_size: src_size.into(),
+            out_size: glam::uvec2(w, h),
+            src_format,
+            out_half,
+            turns: match prep.rotate {
+                Rotation::None => 0,
+                Rotation::Cw90 => 1,
+                Rotation::Cw180 => 2,
+                Rotation::Cw270 => 3,
+            },
+            flip: prep.flip.into(),
+            undistort: prep.undistort.is_some().into(),
+            out_stride: self.inputs.frames.padded_row_bytes() / 4,
+        };
+        Ok(Some((info, lut)))
+    }
+
+    /// Pass preprocessing the frames camera `idx` uploads into the buffer copied to its
+    /// layer.
+    fn new_prep(&self, idx: usize, info: PrepInfo, lut: &[f32]) -> InputPrep {
+        let ctx = self.ctx.as_ref();
+        let out = Buffer::builder(ctx)
+            .label(&format!("inp_prep_{idx}"))
+            .size(self.inputs.uploads[idx].size() as _)
+            .storage()
+            .readable()
+            .build();
+        let info_buf = Buffer::builder(ctx)
+            .label("inp_prep_info")
+            .size_for::<PrepInfo>()
+            .uniform()
+            .writable()
+            .build();
+        ctx.write_uniform(&info_buf, &info);
+        // bindings can't be empty, so frames that aren't undistorted get a placeholder
+        let lut = Buffer::builder(ctx)
+            .label("inp_prep_lut")
+            .storage()
+            .build_with_data(if lut.is_empty() { &[0.0; 2][..] } else { lut });
+
+        let cp = ComputeCheckpoint::builder(ctx)
+            .group(
+                Bindings::new()
+                    .bind(info_buf.in_compute())
+                    .bind(self.inputs.uploads[idx].as_ref().in_compute())
+                    .bind(lut.in_compute())
+                    .bind(out.in_compute()),
+            )
+            .shader(smpgpu::include_shader!("shaders/preprocess.wgsl" => "cs_preprocess"))
+            .build()
+            .work_groups(
+                info.out_size.x.div_ceil(8) as _,
+                info.out_size.y.div_ceil(8) as _,
+                1,
+            );
+        InputPrep {
+            out,
+            cp,
+            _info: info_buf,
+            _lut: lut,
+        }
+    }
+
+    /// Gives every enabled camera its upload buffer to load the next frame into.
+    ///
     /// # Errors
     /// see [`LoadingBuffer::begin_load_with`]
     #[inline]
@@ -341,41 +2506,325 @@ impl GpuProjector {
         &self,
         cams: &[Camera<Loader<GpuDirectBufferWrite>>],
     ) -> Result<Vec<loader::Ticket<GpuDirectBufferWrite>>> {
-        cams.iter()
-            .scan(0, |off, c| {
-                let size = c.data.num_bytes() as u64;
-                let buf_off = *off;
-                *off += size;
-
-                Some(c.data.give(self.inp_buffer_write(buf_off, size)))
-            })
+        let enabled = self.cam_enabled.borrow();
+        (cams.iter().enumerate())
+            .zip(enabled.iter())
+            .filter(|&(_, &enabled)| enabled)
+            .map(|((i, c), _)| self.take_input_buffer(i, c))
             .collect()
     }
 
+    /// Gives camera `idx` its upload buffer, whether it's enabled or not, so a camera that
+    /// fails can be told apart from the others.
+    ///
+    /// # Errors
+    /// the camera's loader doesn't exist anymore
+    ///
+    /// # Panics
+    /// `idx` isn't one of the cameras
     #[inline]
-    fn inp_buffer_write(&self, offset: u64, size: u64) -> GpuDirectBufferWrite {
+    pub fn take_input_buffer(
+        &self,
+        idx: usize,
+        cam: &Camera<Loader<GpuDirectBufferWrite>>,
+    ) -> Result<loader::Ticket<GpuDirectBufferWrite>> {
+        // preprocessed frames are read as the camera delivers them
+        let spread = self.inputs.preps[idx].is_none();
+        let write = self.inp_buffer_write(&self.inputs.uploads[idx], cam.data.num_bytes(), spread);
+        cam.data.give(write)
+    }
+
+    /// Writes the first `size` bytes of `buf`, rounded up to the 4 bytes buffers are written
+    /// in, which odd sized NV12 frames can fall short of. With `spread` set, frames of the
+    /// input size are spread over the padded rows of `buf` instead, unless their rows are
+    /// already that long.
+    #[inline]
+    fn inp_buffer_write(
+        &self,
+        buf: &Arc<Buffer>,
+        size: usize,
+        spread: bool,
+    ) -> GpuDirectBufferWrite {
+        let frames = &self.inputs.frames;
+        let row = (frames.width() * frames.pixel_bytes()) as usize;
+        let padded = frames.padded_row_bytes() as usize;
+        let rows = (spread && row != padded && size == row * frames.height() as usize)
+            .then_some((row, padded));
+        let size = match rows {
+            Some((_, padded)) => padded * frames.height() as usize,
+            None => size.next_multiple_of(4),
+        };
         GpuDirectBufferWrite {
             ctx: self.ctx.clone(),
-            buf: self.inp_frames.clone(),
-            offset,
-            size: size.try_into().unwrap(),
+            buf: buf.clone(),
+            size: (size as u64).try_into().unwrap(),
+            rows,
+        }
+    }
+}
+
+impl OutputView {
+    fn set_style(&self, ctx: &Context, style: ProjectionStyle) {
+        self.style.set(Some(style));
+        if let Some(view) = self.view_matrix() {
+            ctx.write_uniform(&self.view_mat, &view);
+        }
+    }
+
+    /// Clip from world matrix of the hemisphere, the only style projecting the ground mesh.
+    fn view_matrix(&self) -> Option<Mat4> {
+        let Some(ProjectionStyle::Hemisphere { pos, radius }) = self.style.get() else {
+            return None;
+        };
+        let [x, y, _] = pos;
+        let out_size = self.texture.size();
+
+        let rh = radius;
+
+        #[allow(clippy::cast_precision_loss)]
+        let aspect = out_size.width as f32 / out_size.height as f32;
+
+        Some(
+            Mat4::orthographic_rh(
+                rh.mul_add(-aspect, x),
+                rh.mul_add(aspect, x),
+                -rh + y,
+                rh + y,
+                0.1,
+                200.,
+            ) * Mat4::look_at_rh(
+                glam::vec3(0., 0., 100.),
+                glam::vec3(0., 0., 0.),
+                glam::Vec3::Y,
+            ),
+        )
+    }
+
+    /// Fills in the parts of `info` that depend on this view.
+    fn place(&self, info: &mut PassInfo) {
+        let size = self.texture.size();
+        info.out_size = glam::uvec2(size.width, size.height);
+
+        let ViewCrop { x, y, w, h } = self.crop.get();
+        info.crop = glam::vec4(x, y, w, h);
+        info.ground_overlay = self.ground_overlay.get().into();
+
+        match self.style.get() {
+            Some(
+                ProjectionStyle::Hemisphere { pos, radius }
+                | ProjectionStyle::Equirect { pos, radius }
+                | ProjectionStyle::CubeMap { pos, radius },
+            ) => {
+                info.bound_radius = radius;
+                info.view_pos = pos.into();
+            }
+            Some(ProjectionStyle::SingleCamera {
+                index,
+                lens,
+                fov,
+                yaw,
+                pitch,
+            }) => {
+                info.dewarp_cam = index.into();
+                info.dewarp_lens = lens.id();
+                info.dewarp_fov = fov.to_radians();
+                info.dewarp_turn = super::dewarp_turn(yaw, pitch);
+            }
+            Some(ProjectionStyle::RawCamera(n)) => info.dewarp_cam = n.into(),
+            None => {}
+        }
+    }
+
+    /// Commands that render the view and copy it to its staging buffer `slot`, in submission
+    /// order.
+    fn encode(
+        &self,
+        ctx: &Context,
+        mut info: PassInfo,
+        bound_mesh: &Buffer,
+        slot: usize,
+    ) -> Vec<CommandBuilder> {
+        self.place(&mut info);
+        ctx.write_uniform(&self.pass_info, &info);
+
+        let target = self.texture.render_attach();
+        let mesh_target = match &self.msaa {
+            Some(msaa) => msaa.render_attach().resolve(&self.texture),
+            None => self.texture.render_attach(),
+        };
+        let encoder = match self.style.get() {
+            Some(ProjectionStyle::Equirect { .. }) => self.equirect_cp.encoder(ctx).attach(&target),
+            Some(ProjectionStyle::CubeMap { .. }) => self.cube_cp.encoder(ctx).attach(&target),
+            Some(ProjectionStyle::SingleCamera { .. }) => {
+                self.dewarp_cp.encoder(ctx).attach(&target)
+            }
+            Some(ProjectionStyle::RawCamera(..)) => self.raw_cp.encoder(ctx).attach(&target),
+            _ => self
+                .back_cp
+                .encoder(ctx)
+                .vert_buf(bound_mesh)
+                .attach(&mesh_target),
+        };
+        let mut cmds = vec![encoder.build()];
+
+        let over = self.texture.render_attach().load();
+        if self.has_overlays.get() {
+            cmds.push(self.overlay_cp.encoder(ctx).attach(&over).build());
+        }
+        let hud_len = self.hud_len.get();
+        if hud_len > 0 {
+            cmds.push(
+                self.hud_cp
+                    .encoder(ctx)
+                    .instances(0..hud_len)
+                    .attach(&over)
+                    .build(),
+            );
+        }
+        if let Some(pass) = &*self.watermark_pass.borrow() {
+            cmds.push(pass.cp.encoder(ctx).attach(&over).build());
         }
+
+        let last = cmds
+            .pop()
+            .unwrap()
+            .then(self.texture.copy_to_buf_op(&self.staging[slot]));
+        cmds.push(last);
+        cmds
+    }
+
+    /// Places the watermark's picture and text for the view's size, leaving the HUD to be
+    /// written again.
+    fn place_watermark(&self, ctx: &Context) {
+        let watermark = self.watermark.borrow();
+        let pass = self.watermark_pass.borrow();
+        let Some(watermark) = watermark.as_ref() else {
+            self.watermark_labels.replace(Vec::new());
+            return;
+        };
+
+        let size = self.texture.size();
+        let img = pass.as_ref().map(|p| {
+            let img = p.image.size();
+            (img.width, img.height)
+        });
+        let placed = watermark::place(watermark, &self.name, img, (size.width, size.height));
+        if let Some(pass) = pass.as_ref() {
+            let spec = WatermarkSpec {
+                rect: placed.rect.into(),
+                opacity: placed.opacity,
+            };
+            ctx.write_uniform(&pass.spec, &spec);
+        }
+        self.watermark_labels
+            .replace(placed.label.into_iter().collect());
+    }
+
+    /// Lays out the HUD, picture in picture and watermark labels, one quad per character.
+    #[allow(clippy::cast_precision_loss)]
+    fn write_hud(&self, ctx: &Context) {
+        let size = self.texture.size();
+        let (w, h) = (size.width as f32, size.height as f32);
+
+        let mut chars = Vec::with_capacity(MAX_HUD_CHARS);
+        let hud_labels = self.hud_labels.borrow();
+        let pip_labels = self.pip_labels.borrow();
+        let watermark_labels = self.watermark_labels.borrow();
+        for label in pip_labels
+            .iter()
+            .chain(hud_labels.iter())
+            .chain(watermark_labels.iter())
+        {
+            let cell = 8.0 * label.scale as f32;
+            let x = (label.pos[0] * w).round();
+            let y = (label.pos[1] * h).round();
+
+            for (row, line) in label.text.lines().enumerate() {
+                for (col, c) in line.chars().enumerate() {
+                    chars.push(HudChar {
+                        rect: glam::vec4(
+                            (col as f32).mul_add(cell, x) / w,
+                            (row as f32).mul_add(cell, y) / h,
+                            cell / w,
+                            cell / h,
+                        ),
+                        color: label.color.into(),
+                        background: label.background.into(),
+                        glyph: font::glyph_index(c),
+                    });
+                }
+            }
+        }
+
+        if chars.len() > MAX_HUD_CHARS {
+            tracing::warn!(
+                "view {} only draws the first {MAX_HUD_CHARS} HUD characters",
+                self.name
+            );
+        }
+        self.hud_len.set(chars.len().min(MAX_HUD_CHARS) as _);
+        chars.resize(MAX_HUD_CHARS, HudChar::default());
+        ctx.write_storage(&self.hud_chars, &chars);
+    }
+}
+
+const fn texture_format(format: FrameFormat) -> TextureFormat {
+    match format {
+        FrameFormat::Rgba8 => TextureFormat::Rgba8Unorm,
+        FrameFormat::Rgba16F => TextureFormat::Rgba16Float,
     }
 }
 
 pub struct GpuDirectBufferWrite {
     ctx: Arc<Context>,
     buf: Arc<Buffer>,
-    offset: u64,
     size: NonZero<u64>,
+    /// Bytes in a row of the frame and in a row of `buf`, when they differ
+    rows: Option<(usize, usize)>,
 }
 
 impl OwnedWriteBuffer for GpuDirectBufferWrite {
-    type View<'a> = smpgpu::DirectWritableBufferView<'a>
+    type View<'a>
+        = GpuBufferWriteView<'a>
     where
         Self: 'a;
 
     fn owned_to_view(&mut self) -> Self::View<'_> {
-        self.ctx.write_with(&self.buf, self.offset, self.size)
+        let mapped = self.ctx.write_with(&self.buf, 0, self.size);
+        let packed = self.rows.map(|(row, padded)| {
+            let n = mapped.len() / padded;
+            (vec![0; row * n], row, padded)
+        });
+        GpuBufferWriteView { mapped, packed }
+    }
+}
+
+/// Frame written straight into an upload buffer, or written packed and spread over the
+/// buffer's padded rows once dropped.
+pub struct GpuBufferWriteView<'a> {
+    mapped: smpgpu::DirectWritableBufferView<'a>,
+    /// Frame, bytes in its rows and in the rows of `mapped`
+    packed: Option<(Vec<u8>, usize, usize)>,
+}
+
+impl AsMut<[u8]> for GpuBufferWriteView<'_> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        match &mut self.packed {
+            Some((frame, ..)) => frame,
+            None => self.mapped.as_mut(),
+        }
+    }
+}
+
+impl Drop for GpuBufferWriteView<'_> {
+    fn drop(&mut self) {
+        if let Some((frame, row, padded)) = self.packed.take() {
+            for (src, dst) in frame
+                .chunks_exact(row)
+                .zip(self.mapped.chunks_exact_mut(padded))
+            {
+                dst[..row].copy_from_slice(src);
+            }
+        }
     }
 }
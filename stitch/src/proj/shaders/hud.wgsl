@@ -0,0 +1,60 @@
+@group(0)
+@binding(0)
+var<storage, read> hud_chars: array<HudChar>;
+
+// Every glyph of font.rs as 2 words of 4 rows each
+@group(0)
+@binding(1)
+var<storage, read> glyphs: array<u32>;
+
+struct HudChar {
+    // [x, y, w, h] in fractions of the output, empty for unused slots
+    rect: vec4<f32>,
+    color: vec4<f32>,
+    background: vec4<f32>,
+    glyph: u32,
+}
+
+struct HudVertex {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) char_idx: u32,
+}
+
+// One quad per character, instanced over every slot
+@vertex
+fn vs_hud(@builtin(vertex_index) v: u32, @builtin(instance_index) i: u32) -> HudVertex {
+    var corners = array(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(1.0, 1.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.0, 1.0),
+        vec2f(0.0, 0.0),
+    );
+    let uv = corners[v];
+    let rect = hud_chars[i].rect;
+    let p = rect.xy + uv * rect.zw;
+
+    var out: HudVertex;
+    out.pos = vec4f(p.x * 2.0 - 1.0, 1.0 - p.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.char_idx = i;
+    return out;
+}
+
+@fragment
+fn fs_hud(vert: HudVertex) -> @location(0) vec4<f32> {
+    let c = hud_chars[vert.char_idx];
+
+    let cell = min(vec2u(vert.uv * 8.0), vec2u(7u));
+    let word = glyphs[c.glyph * 2u + cell.y / 4u];
+    let lit = (word >> ((cell.y % 4u) * 8u + cell.x)) & 1u;
+    if lit == 1u {
+        return c.color;
+    }
+    if c.background.a <= 0.0 {
+        discard;
+    }
+    return c.background;
+}
@@ -0,0 +1,104 @@
+// Must match `MAX_PIPS` in render_gpu.rs
+const MAX_PIPS: u32 = 8u;
+
+@group(0)
+@binding(0)
+var<uniform> overlays: Overlays;
+
+@group(0)
+@binding(1)
+var inp_frames: texture_2d_array<f32>;
+
+struct Overlays {
+    inp_size: vec2<u32>,
+    out_size: vec2<u32>,
+    // tone mapping and encoding of the camera frames, like the projection's
+    exposure: f32,
+    tone_curve: u32,
+    color_space: u32,
+    pips: array<Pip, MAX_PIPS>,
+}
+
+struct Pip {
+    // [x, y, w, h] in fractions of the output, empty for unused slots
+    rect: vec4<f32>,
+    border_color: vec4<f32>,
+    border_width: f32,
+    cam: u32,
+}
+
+struct PipVertex {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) pip: u32,
+}
+
+// One quad per picture in picture, instanced over every slot
+@vertex
+fn vs_pip(@builtin(vertex_index) v: u32, @builtin(instance_index) i: u32) -> PipVertex {
+    var corners = array(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(1.0, 1.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.0, 1.0),
+        vec2f(0.0, 0.0),
+    );
+    let uv = corners[v];
+    let rect = overlays.pips[i].rect;
+    let p = rect.xy + uv * rect.zw;
+
+    var out: PipVertex;
+    out.pos = vec4f(p.x * 2.0 - 1.0, 1.0 - p.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.pip = i;
+    return out;
+}
+
+@fragment
+fn fs_pip(vert: PipVertex) -> @location(0) vec4<f32> {
+    let pip = overlays.pips[vert.pip];
+
+    let size = pip.rect.zw * vec2f(overlays.out_size);
+    let px = vert.uv * size;
+    let edge = min(px, size - px);
+    if min(edge.x, edge.y) < pip.border_width {
+        return pip.border_color;
+    }
+
+    let inp_size = overlays.inp_size;
+    let p = min(vec2u(vert.uv * vec2f(inp_size)), inp_size - 1u);
+    let c = textureLoad(inp_frames, p, pip.cam, 0);
+    if overlays.color_space == 0u {
+        return tone_map(vec4(srgb_decode(c.rgb), c.a));
+    }
+    return tone_map(c);
+}
+
+// Same as `tone_map` in render.wgsl.
+fn tone_map(c: vec4<f32>) -> vec4<f32> {
+    let x = c.rgb * overlays.exposure;
+    var y = x;
+    switch overlays.tone_curve {
+        case 1u: {
+            y = x / (1.0 + x);
+        }
+        case 2u: {
+            y = x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14);
+        }
+        default: {}
+    }
+    if overlays.color_space == 0u {
+        y = srgb_encode(y);
+    }
+    return vec4(y, c.a);
+}
+
+fn srgb_decode(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3f(2.4)), c / 12.92, c <= vec3f(0.04045));
+}
+
+fn srgb_encode(c: vec3<f32>) -> vec3<f32> {
+    let x = max(c, vec3f(0.0));
+    return select(1.055 * pow(x, vec3f(1.0 / 2.4)) - 0.055, x * 12.92, x <= vec3f(0.0031308));
+}
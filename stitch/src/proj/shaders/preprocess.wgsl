@@ -0,0 +1,119 @@
+// Turns the frame a camera uploaded into the pixels copied to its layer of the input frames,
+// decoding, undistorting, turning and mirroring it as its `Preprocess` says.
+
+struct PrepInfo {
+    // Size of the frame the camera uploads
+    src_size: vec2<u32>,
+    // Size of the layer it's turned into
+    out_size: vec2<u32>,
+    // 0 is RGBA8, 1 RGBA16F, 2 YUYV and 3 NV12
+    src_format: u32,
+    // 1 when the layer is RGBA16F instead of RGBA8
+    out_half: u32,
+    // Quarter turns clockwise
+    turns: u32,
+    flip: u32,
+    undistort: u32,
+    // Words in a row of the output, which is padded to be copied to the layer
+    out_stride: u32,
+}
+
+@group(0) @binding(0) var<uniform> info: PrepInfo;
+@group(0) @binding(1) var<storage, read> src: array<u32>;
+// Pixel of the frame every pixel of the undistorted frame is taken from
+@group(0) @binding(2) var<storage, read> lut: array<vec2<f32>>;
+@group(0) @binding(3) var<storage, read_write> dst: array<u32>;
+
+fn src_byte(i: u32) -> u32 {
+    return (src[i / 4u] >> ((i % 4u) * 8u)) & 0xffu;
+}
+
+// BT.601 limited range, like `yuv_to_rgba` in convert.rs
+fn yuv_to_rgba(y: u32, u: u32, v: u32) -> vec4<f32> {
+    let c = 1.164 * (f32(y) - 16.0);
+    let d = f32(u) - 128.0;
+    let e = f32(v) - 128.0;
+    let rgb = vec3(c + 1.598 * e, c - 0.391 * d - 0.813 * e, c + 2.016 * d) / 255.0;
+    return vec4(clamp(rgb, vec3(0.0), vec3(1.0)), 1.0);
+}
+
+fn texel(p: vec2<u32>) -> vec4<f32> {
+    let w = info.src_size.x;
+    let i = p.y * w + p.x;
+    switch info.src_format {
+        case 1u: {
+            return vec4(unpack2x16float(src[i * 2u]), unpack2x16float(src[i * 2u + 1u]));
+        }
+        case 2u: {
+            let pair = src[p.y * ((w + 1u) / 2u) + p.x / 2u];
+            let y = select(pair & 0xffu, (pair >> 16u) & 0xffu, (p.x & 1u) == 1u);
+            return yuv_to_rgba(y, (pair >> 8u) & 0xffu, pair >> 24u);
+        }
+        case 3u: {
+            let uv = w * info.src_size.y + (p.y / 2u) * ((w + 1u) / 2u * 2u) + p.x / 2u * 2u;
+            return yuv_to_rgba(src_byte(i), src_byte(uv), src_byte(uv + 1u));
+        }
+        default: {
+            return unpack4x8unorm(src[i]);
+        }
+    }
+}
+
+// Blends the 4 pixels around `pos`, transparent black past the edges of the frame.
+fn bilinear(pos: vec2<f32>) -> vec4<f32> {
+    let last = vec2<f32>(info.src_size - 1u);
+    if any(pos < vec2(-0.5)) || any(pos > last + 0.5) {
+        return vec4(0.0);
+    }
+
+    let p = clamp(pos, vec2(0.0), last);
+    let p0 = vec2<u32>(floor(p));
+    let p1 = min(p0 + 1u, info.src_size - 1u);
+    let t = p - floor(p);
+    let top = mix(texel(p0), texel(vec2(p1.x, p0.y)), t.x);
+    let bottom = mix(texel(vec2(p0.x, p1.y)), texel(p1), t.x);
+    return mix(top, bottom, t.y);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_preprocess(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= info.out_size) {
+        return;
+    }
+
+    var o = id.xy;
+    if info.flip != 0u {
+        o.x = info.out_size.x - 1u - o.x;
+    }
+
+    // pixel of the frame before it was turned
+    let s = info.src_size;
+    var p = o;
+    switch info.turns {
+        case 1u: {
+            p = vec2(o.y, s.y - 1u - o.x);
+        }
+        case 2u: {
+            p = vec2(s.x - 1u - o.x, s.y - 1u - o.y);
+        }
+        case 3u: {
+            p = vec2(s.x - 1u - o.y, o.x);
+        }
+        default: {}
+    }
+
+    var color: vec4<f32>;
+    if info.undistort != 0u {
+        color = bilinear(lut[p.y * s.x + p.x]);
+    } else {
+        color = texel(p);
+    }
+
+    let row = id.y * info.out_stride;
+    if info.out_half != 0u {
+        dst[row + id.x * 2u] = pack2x16float(color.xy);
+        dst[row + id.x * 2u + 1u] = pack2x16float(color.zw);
+    } else {
+        dst[row + id.x] = pack4x8unorm(color);
+    }
+}
@@ -1,4 +1,17 @@
 const PI: f32 = 3.141592653589793;
+// Samples per side of the gain stats grid
+const GAIN_GRID: u32 = 64u;
+// Must match `GpuProjector::MAX_CAMERAS`
+const MAX_CAMERAS: u32 = 8u;
+// Most zones watched for motion
+const MAX_MOTION_ZONES: u32 = 16u;
+// Points per side of the grid sampled over each motion zone
+const MOTION_GRID: u32 = 32u;
+// Least correlation between the two cameras of a stereo pair for a height to be kept
+const STEREO_MIN_MATCH: f32 = 0.5;
+// Side in pixels of the blocks of a camera's frame given a motion vector each, must match
+// `FLOW_BLOCK` in the projector
+const FLOW_BLOCK: u32 = 16u;
 
 @group(0)
 @binding(0)
@@ -7,15 +20,50 @@ var<uniform> pass_info: PassInfo;
 struct PassInfo {
     inp_sizes: vec3<u32>,
     bound_radius: f32,
+    hist_cap: u32,
+    hist_len: u32,
+    hist_head: u32,
+    view_pos: vec3<f32>,
+    out_size: vec2<u32>,
+    blend_width: f32,
+    mask_incidence: f32,
+    // 0 nearest, 1 bilinear, 2 bicubic
+    sampling: u32,
+    // multiplier of the colors before the tone curve
+    exposure: f32,
+    // 0 clip, 1 Reinhard, 2 ACES
+    tone_curve: u32,
+    // 0 sRGB, 1 linear
+    color_space: u32,
+    // camera, lens (0 rectilinear, 1 cylindrical) and width in radians of a single camera view,
+    // where the camera is also the one a raw camera view shows
+    dewarp_cam: u32,
+    dewarp_lens: u32,
+    dewarp_fov: f32,
+    // [x, y, w, h] of the projection drawn to the output, in fractions of the output size
+    crop: vec4<f32>,
+    // rotation from a single camera view to its camera
+    dewarp_turn: mat3x3<f32>,
+    // distance between grid lines and how far the grid reaches, 0 spacing leaving it out
+    grid_spacing: f32,
+    grid_radius: f32,
+    // radius of the compass rose, 0 leaving it out
+    compass_radius: f32,
+    // direction of north on the ground
+    north: vec2<f32>,
+    ground_color: vec4<f32>,
+    // 1 when the view draws the grid and compass rose
+    ground_overlay: u32,
 }
 
 @group(0)
 @binding(1)
 var<uniform> view: mat4x4<f32>;
 
+// Layer for every camera
 @group(0)
 @binding(2)
-var<storage, read> inp_frames: array<u32>;
+var inp_frames: texture_2d_array<f32>;
 
 @group(0)
 @binding(3)
@@ -23,7 +71,83 @@ var<storage, read> inp_specs: array<InputSpec>;
 
 @group(0)
 @binding(4)
-var<storage, read> inp_masks: array<u32>;
+var inp_masks: texture_2d_array<f32>;
+
+// Every camera's layer for each slot of the history ring, at slot * n + camera
+@group(0)
+@binding(5)
+var inp_history: texture_2d_array<f32>;
+
+@group(0)
+@binding(6)
+var<storage, read> inp_gains: array<vec4<f32>>;
+
+// Pixels every block of each camera's frame moved by since its previous frame, row by row
+@group(0)
+@binding(7)
+var<storage, read> inp_flow: array<vec2<f32>>;
+
+// [count, r, g, b] sums of camera i where it overlaps camera j, at (i * n + j) * 4
+@group(1)
+@binding(0)
+var<storage, read_write> gain_stats: array<atomic<u32>>;
+
+@group(1)
+@binding(1)
+var auto_mask_out: texture_storage_2d_array<rgba8unorm, write>;
+
+@group(1)
+@binding(2)
+var<uniform> motion_info: MotionInfo;
+
+struct MotionInfo {
+    zones: u32,
+    threshold: f32,
+    // [min x, min y, max x, max y] of each zone on the ground
+    rects: array<vec4<f32>, MAX_MOTION_ZONES>,
+}
+
+// Brightness of every point of every zone in the last frame, negative where nothing saw it
+@group(1)
+@binding(3)
+var<storage, read_write> motion_prev: array<f32>;
+
+// [seen, changed] points of each zone, at zone * 2
+@group(1)
+@binding(4)
+var<storage, read_write> motion_stats: array<atomic<u32>>;
+
+// Written by `cs_flow`, then copied to `inp_flow`
+@group(1)
+@binding(5)
+var<storage, read_write> flow_out: array<vec2<f32>>;
+
+struct StereoInfo {
+    cams: vec2<u32>,
+    // points per side of the height field's grid
+    grid: vec2<u32>,
+    // lowest and highest heights searched
+    heights: vec2<f32>,
+    // world units the grid spans
+    size: f32,
+    steps: u32,
+    // side of the square of ground compared between the cameras
+    window: f32,
+}
+
+@group(2)
+@binding(0)
+var<uniform> stereo_info: StereoInfo;
+
+// Heights of the grid's points, row by row from north to south, then the ones found
+@group(2)
+@binding(1)
+var<storage, read_write> stereo_heights: array<f32>;
+
+// Vertices of the height field's mesh, see `HeightGrid::mesh`
+@group(2)
+@binding(2)
+var<storage, read_write> world_mesh: array<vec4<f32>>;
 
 struct InputSpec {
     pos: vec3<f32>,
@@ -31,6 +155,12 @@ struct InputSpec {
     img_off: vec2<f32>,
     foc_dist: f32,
     lens_type: u32,
+    enabled: u32,
+    // frames the camera is behind the newest one, which it's pushed forward along its motion
+    lead: f32,
+    // radial k1..k4 and tangential p1, p2 lens distortion, see `camera::Distortion`
+    dist_k: vec4<f32>,
+    dist_p: vec2<f32>,
 }
 
 struct VertexOutput {
@@ -41,7 +171,7 @@ struct VertexOutput {
 @vertex
 fn vs_proj(@location(0) v_pos: vec4<f32>) -> VertexOutput {
     var out: VertexOutput;
-    out.proj_pos = view * v_pos;
+    out.proj_pos = crop_clip(view * v_pos);
     out.world_pos = v_pos;
     return out;
 }
@@ -49,12 +179,235 @@ fn vs_proj(@location(0) v_pos: vec4<f32>) -> VertexOutput {
 @fragment
 fn fs_proj(vert: VertexOutput) -> @location(0) vec4<f32> {
     // vec3(100.0 * img_from_coord(vec2f(id.xy), pass_info.out_size), 0.0)
-    let p = back_proj(vert.world_pos.xyz);
-    return unpack4x8unorm(p);
+    let p = vert.world_pos.xyz;
+    return ground_overlay(p, true, tone_map(back_proj(p)));
+}
+
+// Moves a clip space position of the whole projection to where it lands in the crop
+fn crop_clip(p: vec4<f32>) -> vec4<f32> {
+    let c = pass_info.crop;
+    let uv = (vec2f(p.x, -p.y) / p.w + 1.0) / 2.0;
+    let cropped = (uv - c.xy) / c.zw * 2.0 - 1.0;
+    return vec4f(vec2f(cropped.x, -cropped.y) * p.w, p.zw);
 }
 
-fn back_proj(bound: vec3<f32>) -> u32 {
-    var opts: array<vec2<f32>, 4>;
+// Position in the whole projection of an output pixel, which only covers the crop
+fn uncropped(frag: vec2<f32>) -> vec2<f32> {
+    let size = vec2f(pass_info.out_size);
+    return (pass_info.crop.xy + frag / size * pass_info.crop.zw) * size;
+}
+
+// Covers the whole target with a single triangle
+@vertex
+fn vs_full(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2f(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_equirect(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = uncropped(frag.xy) / vec2f(pass_info.out_size);
+    let lon = (uv.x * 2.0 - 1.0) * PI;
+    let lat = (0.5 - uv.y) * PI;
+    let dir = vec3(cos(lat) * sin(lon), cos(lat) * cos(lon), sin(lat));
+
+    let hit = dome_hit(pass_info.view_pos, dir, pass_info.bound_radius);
+    return ground_overlay(hit, on_ground(hit), tone_map(back_proj(hit)));
+}
+
+@fragment
+fn fs_cube(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let face_size = f32(min(pass_info.out_size.x / 3u, pass_info.out_size.y / 2u));
+    let pos = uncropped(frag.xy);
+    let cell = vec2u(pos / face_size);
+
+    let st = (pos / face_size - vec2f(cell)) * 2.0 - 1.0;
+    let gl_dir = cube_dir(cell.x + cell.y * 3u, st);
+    let dir = normalize(vec3(gl_dir.x, -gl_dir.z, gl_dir.y));
+
+    // the overlay takes derivatives, so pixels past the faces are only blanked at the end
+    let hit = dome_hit(pass_info.view_pos, dir, pass_info.bound_radius);
+    let c = ground_overlay(hit, on_ground(hit), tone_map(back_proj(hit)));
+    return select(c, vec4f(0.0), cell.x >= 3u || cell.y >= 2u);
+}
+
+// One camera's frame on its own, de-warped to a rectilinear or cylindrical view.
+@fragment
+fn fs_dewarp(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let n = pass_info.dewarp_cam;
+    if n >= pass_info.inp_sizes.z {
+        return vec4f(0.0);
+    }
+
+    let size = vec2f(pass_info.out_size);
+    let ndc = (uncropped(frag.xy) / size * 2.0 - 1.0) * vec2f(1.0, -size.y / size.x);
+    let half = pass_info.dewarp_fov / 2.0;
+
+    // in the camera's frame of x right, y forward and z up
+    var d = vec3(ndc.x * tan(half), 1.0, ndc.y * tan(half));
+    if pass_info.dewarp_lens == 1u {
+        let lon = ndc.x * half;
+        d = vec3(sin(lon), cos(lon), ndc.y * half);
+    }
+    let os = opt_from_dir(pass_info.dewarp_turn * normalize(d));
+    return tone_map(opt_input_pixel(n, os));
+}
+
+// One camera's whole frame as it came in, stretched over the output.
+@fragment
+fn fs_raw(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let n = pass_info.dewarp_cam;
+    if n >= pass_info.inp_sizes.z {
+        return vec4f(0.0);
+    }
+
+    let inp_size = pass_info.inp_sizes.xy;
+    let uv = uncropped(frag.xy) / vec2f(pass_info.out_size);
+    let p = min(vec2u(uv * vec2f(inp_size)), inp_size - 1u);
+    return tone_map(linear(textureLoad(inp_frames, p, n, 0)));
+}
+
+// Direction through `st` on a face, using the OpenGL cube map convention (y up, -z forward).
+fn cube_dir(face: u32, st: vec2<f32>) -> vec3<f32> {
+    let s = st.x;
+    let t = st.y;
+    switch face {
+        case 0u: {
+            return vec3(1.0, -t, -s);
+        }
+        case 1u: {
+            return vec3(-1.0, -t, s);
+        }
+        case 2u: {
+            return vec3(s, 1.0, t);
+        }
+        case 3u: {
+            return vec3(s, -1.0, -t);
+        }
+        case 4u: {
+            return vec3(s, -t, 1.0);
+        }
+        default: {
+            return vec3(-s, -t, -1.0);
+        }
+    }
+}
+
+// Where a ray from `o` hits the world, modeled as the ground plane capped by a dome of
+// radius `r` centered under `o`.
+fn dome_hit(o: vec3<f32>, d: vec3<f32>, r: f32) -> vec3<f32> {
+    if d.z < 0.0 {
+        let g = o - d * (o.z / d.z);
+        if length(g.xy - o.xy) <= r {
+            return g;
+        }
+    }
+
+    let b = o.z * d.z;
+    let t = sqrt(b * b - o.z * o.z + r * r) - b;
+    return o + d * t;
+}
+
+// Whether a point found by `dome_hit` is on the ground rather than the dome.
+fn on_ground(p: vec3<f32>) -> bool {
+    return p.z <= 1e-3 * pass_info.bound_radius;
+}
+
+// Draws the grid and compass rose over `c`, the output color of world point `p`, where it is
+// on the ground. Lines are kept about a pixel wide by the derivatives of `p`, so it has to be
+// called in uniform control flow.
+fn ground_overlay(p: vec3<f32>, ground: bool, c: vec4<f32>) -> vec4<f32> {
+    // world units per output pixel
+    let px = fwidth(p.xy);
+    let pw = max(max(px.x, px.y), 1e-6);
+    if pass_info.ground_overlay == 0u || !ground {
+        return c;
+    }
+
+    let col = pass_info.ground_color;
+    var out = c;
+
+    let s = pass_info.grid_spacing;
+    if s > 0.0 && length(p.xy) <= pass_info.grid_radius {
+        let d = abs(fract(p.xy / s + 0.5) - 0.5) * s / max(px, vec2f(1e-6));
+        // fades out where the lines get too close together to tell apart
+        let fade = 1.0 - smoothstep(0.125, 0.25, pw / s);
+        let line = 1.0 - clamp(min(d.x, d.y) - 0.5, 0.0, 1.0);
+        out = vec4(mix(out.rgb, col.rgb, line * fade * col.a), out.a);
+    }
+
+    let r = pass_info.compass_radius;
+    if r > 0.0 {
+        let north = pass_info.north;
+        let east = vec2(north.y, -north.x);
+        let v = dot(p.xy, north);
+        let u = dot(p.xy, east);
+        let dist = length(p.xy);
+
+        // ring, then a tick every 30 degrees clockwise from north with longer cardinal ones
+        var line = 1.0 - clamp(abs(dist - r) / pw - 0.75, 0.0, 1.0);
+        let ang = atan2(u, v);
+        let step = PI / 6.0;
+        let tick = abs(fract(ang / step + 0.5) - 0.5) * step * dist / pw;
+        let cardinal = abs(fract(ang / (3.0 * step) + 0.5) - 0.5) < 0.1;
+        let inner = select(0.8, 0.6, cardinal) * r;
+        if dist >= inner && dist <= r {
+            line = max(line, 1.0 - clamp(tick - 0.75, 0.0, 1.0));
+        }
+        out = vec4(mix(out.rgb, col.rgb, line * col.a), out.a);
+
+        // arrow along the north-south axis, red towards north
+        let len = 0.9 * r;
+        let edge = (0.15 * r * (1.0 - abs(v) / len) - abs(u)) / pw;
+        let arrow = clamp(edge + 0.5, 0.0, 1.0) * f32(abs(v) <= len);
+        let arrow_col = select(col.rgb, vec3(1.0, 0.0, 0.0), v >= 0.0);
+        out = vec4(mix(out.rgb, arrow_col, arrow), out.a);
+    }
+    return out;
+}
+
+// Brings a linear projected color into the output's range and encoding.
+fn tone_map(c: vec4<f32>) -> vec4<f32> {
+    let x = c.rgb * pass_info.exposure;
+    var y = x;
+    switch pass_info.tone_curve {
+        case 1u: {
+            y = x / (1.0 + x);
+        }
+        case 2u: {
+            y = x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14);
+        }
+        default: {}
+    }
+    if pass_info.color_space == 0u {
+        y = srgb_encode(y);
+    }
+    return vec4(y, c.a);
+}
+
+fn srgb_decode(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3f(2.4)), c / 12.92, c <= vec3f(0.04045));
+}
+
+fn srgb_encode(c: vec3<f32>) -> vec3<f32> {
+    let x = max(c, vec3f(0.0));
+    return select(1.055 * pow(x, vec3f(1.0 / 2.4)) - 0.055, x * 12.92, x <= vec3f(0.0031308));
+}
+
+// Color a camera's frame holds, linearized when it's sRGB encoded.
+fn linear(c: vec4<f32>) -> vec4<f32> {
+    if pass_info.color_space == 0u {
+        return vec4(srgb_decode(c.rgb), c.a);
+    }
+    return c;
+}
+
+fn back_proj(bound: vec3<f32>) -> vec4<f32> {
+    if pass_info.blend_width > 0.0 {
+        return blend_proj(bound);
+    }
+
+    var opts: array<vec2<f32>, MAX_CAMERAS>;
     for (var n = 0u; n < pass_info.inp_sizes.z; n += 1u) {
         opts[n] = opt_from_world(inp_specs[n], bound);
     }
@@ -71,31 +424,401 @@ fn back_proj(bound: vec3<f32>) -> u32 {
         }
 
         let p = opt_input_pixel(best_index, best);
-        if (p & 0xff000000u) != 0u {
-            return p;
+        if p.a > 0.0 {
+            return gained(best_index, p);
         }
 
         min_opt = best.x;
     }
 
-    return 0u;
+    return vec4f(0.0);
+}
+
+// Weighted average of every camera that sees `bound`, favoring the most centered ones and
+// fading each out over `blend_width` pixels from the edges of its image.
+fn blend_proj(bound: vec3<f32>) -> vec4<f32> {
+    let inpSize = vec2f(pass_info.inp_sizes.xy);
+
+    var sum = vec3f(0.0);
+    var total: f32 = 0.0;
+    for (var n = 0u; n < pass_info.inp_sizes.z; n += 1u) {
+        let spec = inp_specs[n];
+        let os = opt_from_world(spec, bound);
+
+        let imgPos = coord_from_img(img_from_opt(spec, os), pass_info.inp_sizes.xy) + spec.img_off;
+        if any(imgPos < vec2f(0.0, 0.0)) || any(imgPos >= inpSize) {
+            continue;
+        }
+
+        let p = sample_input(n, imgPos);
+        if p.a <= 0.0 {
+            continue;
+        }
+
+        let edge = min(min(imgPos.x, imgPos.y), min(inpSize.x - imgPos.x, inpSize.y - imgPos.y));
+        let w = clamp(edge / pass_info.blend_width, 1e-3, 1.0) * (PI - os.x);
+        sum += p.rgb * inp_gains[n].rgb * w;
+        total += w;
+    }
+
+    if total <= 0.0 {
+        return vec4f(0.0);
+    }
+
+    return vec4(sum / total, 1.0);
 }
 
-fn opt_input_pixel(n: u32, os: vec2<f32>) -> u32 {
+// Applies the exposure/color compensation solved for camera `n`.
+fn gained(n: u32, c: vec4<f32>) -> vec4<f32> {
+    return vec4(c.rgb * inp_gains[n].rgb, c.a);
+}
+
+// Samples a grid over the ground under `view_pos`, summing the raw colors of every pair of
+// cameras that both see the same point.
+@compute
+@workgroup_size(8, 8)
+fn cs_gain_stats(@builtin(global_invocation_id) id: vec3<u32>) {
+    let uv = (vec2f(id.xy) + 0.5) / f32(GAIN_GRID) * 2.0 - 1.0;
+    if length(uv) > 1.0 {
+        return;
+    }
+
+    let bound = vec3(pass_info.view_pos.xy + uv * pass_info.bound_radius, 0.0);
+    let n = pass_info.inp_sizes.z;
+
+    var pixels: array<vec4<f32>, MAX_CAMERAS>;
+    for (var i = 0u; i < n; i += 1u) {
+        pixels[i] = opt_input_pixel(i, opt_from_world(inp_specs[i], bound));
+    }
+
+    for (var i = 0u; i < n; i += 1u) {
+        for (var j = 0u; j < n; j += 1u) {
+            if i == j || pixels[i].a <= 0.0 || pixels[j].a <= 0.0 {
+                continue;
+            }
+
+            // summed as 8 bit values, HDR ones past 255
+            let c = vec3u(round(max(pixels[i].rgb, vec3f(0.0)) * 255.0));
+            let off = (i * n + j) * 4u;
+            atomicAdd(&gain_stats[off], 1u);
+            atomicAdd(&gain_stats[off + 1u], c.r);
+            atomicAdd(&gain_stats[off + 2u], c.g);
+            atomicAdd(&gain_stats[off + 3u], c.b);
+        }
+    }
+}
+
+// Keeps the pixels of each camera that see the world at an incidence angle whose cosine is at
+// least `mask_incidence`.
+@compute
+@workgroup_size(8, 8)
+fn cs_auto_mask(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = pass_info.inp_sizes;
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let spec = inp_specs[id.z];
+    let d = world_from_img(spec, img_from_coord(vec2f(id.xy) + 0.5 - spec.img_off, size.xy));
+
+    // the world is centered under the view, where the ground's normal is up and the dome's
+    // points back at the center
+    let hit = dome_hit(spec.pos - vec3(pass_info.view_pos.xy, 0.0), d, pass_info.bound_radius);
+    var incidence = dot(d, normalize(hit));
+    if hit.z < 1e-3 {
+        incidence = -d.z;
+    }
+
+    let keep = select(0.0, 1.0, incidence >= pass_info.mask_incidence);
+    textureStore(auto_mask_out, id.xy, id.z, vec4f(keep));
+}
+
+// Compares the output brightness of a grid of points over each motion zone with the last
+// frame, counting the points seen both times and the ones that changed by the threshold.
+@compute
+@workgroup_size(8, 8)
+fn cs_motion(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.z >= motion_info.zones {
+        return;
+    }
+
+    let r = motion_info.rects[id.z];
+    let uv = (vec2f(id.xy) + 0.5) / f32(MOTION_GRID);
+    let c = back_proj(vec3(mix(r.xy, r.zw, uv), 0.0));
+    var luma = -1.0;
+    if c.a > 0.0 {
+        luma = dot(clamp(tone_map(c).rgb, vec3f(0.0), vec3f(1.0)), vec3(0.2126, 0.7152, 0.0722));
+    }
+
+    let i = (id.z * MOTION_GRID + id.y) * MOTION_GRID + id.x;
+    let prev = motion_prev[i];
+    motion_prev[i] = luma;
+    if luma < 0.0 || prev < 0.0 {
+        return;
+    }
+
+    atomicAdd(&motion_stats[id.z * 2u], 1u);
+    if abs(luma - prev) >= motion_info.threshold {
+        atomicAdd(&motion_stats[id.z * 2u + 1u], 1u);
+    }
+}
+
+// Sweeps the ground around every point of the height field's grid through the heights of
+// `stereo_info`, keeping the one where its two cameras see it most alike, or the grid's own
+// height where they don't match anywhere.
+@compute
+@workgroup_size(8, 8)
+fn cs_stereo(@builtin(global_invocation_id) id: vec3<u32>) {
+    let grid = stereo_info.grid;
+    if id.x >= grid.x || id.y >= grid.y {
+        return;
+    }
+
+    let i = id.y * grid.x + id.x;
+    let uv = vec2f(id.xy) / vec2f(grid - 1u);
+    let xy = vec2(uv.x - 0.5, 0.5 - uv.y) * stereo_info.size;
+
+    var height = stereo_heights[i];
+    var best = STEREO_MIN_MATCH;
+    let last = f32(max(stereo_info.steps, 2u) - 1u);
+    for (var s = 0u; s < stereo_info.steps; s += 1u) {
+        let z = mix(stereo_info.heights.x, stereo_info.heights.y, f32(s) / last);
+        let score = stereo_match(vec3(xy, z));
+        if score > best {
+            best = score;
+            height = z;
+        }
+    }
+    stereo_heights[grid.x * grid.y + i] = height;
+}
+
+// Correlation from -1 to 1 of the brightness both cameras of `stereo_info` see over a square of
+// ground around `p`, -1 when either doesn't see all of it or it's too plain to tell apart.
+fn stereo_match(p: vec3<f32>) -> f32 {
+    let a = stereo_info.cams.x;
+    let b = stereo_info.cams.y;
+    let step = stereo_info.window / 4.0;
+
+    var sums_a = vec2f(0.0);
+    var sums_b = vec2f(0.0);
+    var sum_ab = 0.0;
+    for (var y = -2; y <= 2; y += 1) {
+        for (var x = -2; x <= 2; x += 1) {
+            let q = p + vec3(vec2f(f32(x), f32(y)) * step, 0.0);
+            let ca = opt_input_pixel(a, opt_from_world(inp_specs[a], q));
+            let cb = opt_input_pixel(b, opt_from_world(inp_specs[b], q));
+            if ca.a <= 0.0 || cb.a <= 0.0 {
+                return -1.0;
+            }
+
+            let la = brightness(ca.rgb);
+            let lb = brightness(cb.rgb);
+            sums_a += vec2(la, la * la);
+            sums_b += vec2(lb, lb * lb);
+            sum_ab += la * lb;
+        }
+    }
+
+    let n = 25.0;
+    let var_a = sums_a.y - sums_a.x * sums_a.x / n;
+    let var_b = sums_b.y - sums_b.x * sums_b.x / n;
+    if var_a < 1e-3 || var_b < 1e-3 {
+        return -1.0;
+    }
+    return (sum_ab - sums_a.x * sums_b.x / n) / sqrt(var_a * var_b);
+}
+
+fn brightness(c: vec3<f32>) -> f32 {
+    return dot(c, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Moves every vertex of the height field's mesh to the height found for its point of the grid.
+@compute
+@workgroup_size(64)
+fn cs_stereo_mesh(@builtin(global_invocation_id) id: vec3<u32>) {
+    let grid = stereo_info.grid;
+    let cells = grid - 1u;
+    let cell = id.x / 6u;
+    if cell >= cells.x * cells.y {
+        return;
+    }
+
+    // corners of the two triangles of a cell, in the order they're built
+    var corners = array(
+        vec2u(0u, 1u),
+        vec2u(1u, 1u),
+        vec2u(1u, 0u),
+        vec2u(1u, 0u),
+        vec2u(0u, 0u),
+        vec2u(0u, 1u),
+    );
+    let p = vec2(cell % cells.x, cell / cells.x) + corners[id.x % 6u];
+    world_mesh[id.x].z = stereo_heights[grid.x * grid.y + p.y * grid.x + p.x];
+}
+
+fn opt_input_pixel(n: u32, os: vec2<f32>) -> vec4<f32> {
     let inpSize = pass_info.inp_sizes.xy;
     let spec = inp_specs[n];
 
     let imgPos = coord_from_img(img_from_opt(spec, os), inpSize) + spec.img_off;
     if any(imgPos < vec2f(0.0, 0.0)) || any(imgPos >= vec2f(inpSize)) {
-        return 0u;
+        return vec4f(0.0);
+    }
+
+    return sample_input(n, imgPos);
+}
+
+// Pixel of camera `n` at `pos`, filtered by `sampling` and moved ahead by its lead. Only the
+// colors are filtered, so the camera sees exactly the parts of the world it does with nearest
+// sampling.
+fn sample_input(n: u32, pos: vec2<f32>) -> vec4<f32> {
+    let img_pos = led(n, pos);
+    let p = input_pixel(n, vec2u(img_pos));
+    if pass_info.sampling == 0u || p.a <= 0.0 {
+        return p;
+    }
+
+    // taps either side of the pixel centers around `img_pos`, skipping masked ones
+    let f = img_pos - 0.5;
+    let base = vec2i(floor(f));
+    let t = f - floor(f);
+    let last = vec2i(pass_info.inp_sizes.xy) - 1;
+    let r = i32(pass_info.sampling);
+    var sum = vec4f(0.0);
+    for (var y = 1 - r; y <= r; y += 1) {
+        for (var x = 1 - r; x <= r; x += 1) {
+            let q = clamp(base + vec2i(x, y), vec2i(0), last);
+            let c = input_pixel(n, vec2u(q));
+            let w = tap_weight(f32(x) - t.x) * tap_weight(f32(y) - t.y);
+            sum += vec4(c.rgb, 1.0) * c.a * w;
+        }
     }
 
-    return input_pixel(n, vec2u(imgPos));
+    if sum.a <= 1e-3 {
+        return p;
+    }
+    return vec4(sum.rgb / sum.a, p.a);
 }
 
-fn input_pixel(n: u32, p: vec2<u32>) -> u32 {
-    let off = p.x + (p.y + n * pass_info.inp_sizes.y) * pass_info.inp_sizes.x;
-    return min(inp_masks[off], inp_frames[off]);
+// Where the content at `pos` in camera `n`'s frame is that many frames before, following the
+// motion of the blocks around it.
+fn led(n: u32, pos: vec2<f32>) -> vec2<f32> {
+    let lead = inp_specs[n].lead;
+    if lead <= 0.0 {
+        return pos;
+    }
+
+    let blocks = vec2i(flow_blocks());
+    let f = pos / f32(FLOW_BLOCK) - 0.5;
+    let base = vec2i(floor(f));
+    let t = f - floor(f);
+    var mv = vec2f(0.0);
+    for (var y = 0; y <= 1; y += 1) {
+        for (var x = 0; x <= 1; x += 1) {
+            let b = clamp(base + vec2i(x, y), vec2i(0), blocks - 1);
+            let w = mix(1.0 - t.x, t.x, f32(x)) * mix(1.0 - t.y, t.y, f32(y));
+            mv += inp_flow[(n * u32(blocks.y) + u32(b.y)) * u32(blocks.x) + u32(b.x)] * w;
+        }
+    }
+
+    let last = vec2f(pass_info.inp_sizes.xy) - 0.5;
+    return clamp(pos - mv * lead, vec2f(0.0), last);
+}
+
+// Blocks per row and column of a camera's frame.
+fn flow_blocks() -> vec2<u32> {
+    return (pass_info.inp_sizes.xy + FLOW_BLOCK - 1u) / FLOW_BLOCK;
+}
+
+// Finds how far every block of each leading camera's frame moved since its previous frame, by
+// searching coarsely around where it was, then finely around the best match.
+@compute
+@workgroup_size(8, 8)
+fn cs_flow(@builtin(global_invocation_id) id: vec3<u32>) {
+    let blocks = flow_blocks();
+    let n = id.z;
+    if id.x >= blocks.x || id.y >= blocks.y || n >= pass_info.inp_sizes.z {
+        return;
+    }
+
+    let i = (n * blocks.y + id.y) * blocks.x + id.x;
+    if inp_specs[n].lead <= 0.0 || pass_info.hist_len == 0u {
+        flow_out[i] = vec2f(0.0);
+        return;
+    }
+
+    var best = vec2i(0);
+    var best_cost = flow_cost(n, id.xy, best);
+    for (var stage = 0; stage < 2; stage += 1) {
+        let step = select(1, 4, stage == 0);
+        let reach = select(2, 4, stage == 0);
+        let center = best;
+        for (var y = -reach; y <= reach; y += 1) {
+            for (var x = -reach; x <= reach; x += 1) {
+                let d = center + vec2i(x, y) * step;
+                let cost = flow_cost(n, id.xy, d);
+                if cost < best_cost {
+                    best_cost = cost;
+                    best = d;
+                }
+            }
+        }
+    }
+    flow_out[i] = vec2f(best);
+}
+
+// Difference between a grid of pixels over `block` of camera `n`'s frame and the same pixels
+// `d` back in its previous frame, slightly favoring short moves so still blocks stay still.
+fn flow_cost(n: u32, block: vec2<u32>, d: vec2<i32>) -> f32 {
+    let origin = vec2i(block * FLOW_BLOCK) + 2;
+    let last = vec2i(pass_info.inp_sizes.xy) - 1;
+    var sum = 0.0;
+    for (var y = 0; y < 4; y += 1) {
+        for (var x = 0; x < 4; x += 1) {
+            let p = clamp(origin + vec2i(x, y) * 4, vec2i(0), last);
+            let q = clamp(p - d, vec2i(0), last);
+            let a = input_pixel(n, vec2u(p));
+            let b = history_pixel(n, 1u, vec2u(q));
+            sum += abs(brightness(a.rgb) - brightness(b.rgb));
+        }
+    }
+    return sum + 0.002 * length(vec2f(d));
+}
+
+// Weight of a tap `d` pixels from the sample, linear when bilinear and Catmull-Rom when bicubic.
+fn tap_weight(d: f32) -> f32 {
+    let a = abs(d);
+    if pass_info.sampling == 1u {
+        return max(1.0 - a, 0.0);
+    }
+    if a < 1.0 {
+        return (1.5 * a - 2.5) * a * a + 1.0;
+    }
+    if a < 2.0 {
+        return ((-0.5 * a + 2.5) * a - 4.0) * a + 2.0;
+    }
+    return 0.0;
+}
+
+// Linear pixel of camera `n`. Masks are 0 or 1 in every channel, so they cut out pixels
+// without clipping HDR frames.
+fn input_pixel(n: u32, p: vec2<u32>) -> vec4<f32> {
+    if inp_specs[n].enabled == 0u {
+        return vec4f(0.0);
+    }
+    return linear(textureLoad(inp_masks, p, n, 0) * textureLoad(inp_frames, p, n, 0));
+}
+
+// Pixel from `age` frames ago, where an age of 1 is the previous frame.
+// Returns transparent black if that frame isn't in the history.
+fn history_pixel(n: u32, age: u32, p: vec2<u32>) -> vec4<f32> {
+    if age == 0u || age > pass_info.hist_len || inp_specs[n].enabled == 0u {
+        return vec4f(0.0);
+    }
+
+    let slot = (pass_info.hist_head + pass_info.hist_cap + 1u - age) % pass_info.hist_cap;
+    let layer = slot * pass_info.inp_sizes.z + n;
+    return linear(textureLoad(inp_masks, p, n, 0) * textureLoad(inp_history, p, layer, 0));
 }
 
 // Spaces:
@@ -105,8 +828,11 @@ fn input_pixel(n: u32, p: vec2<u32>) -> u32 {
 
 fn opt_from_world(s: InputSpec, rev_pos: vec3<f32>) -> vec2<f32> {
     let rev_dir = normalize(rev_pos - s.pos);
-    let ds = s.rev_mat * rev_dir;
+    return opt_from_dir(s.rev_mat * rev_dir);
+}
 
+// Optical angles of a unit direction in a camera's frame.
+fn opt_from_dir(ds: vec3<f32>) -> vec2<f32> {
     let rot_ang = sign(ds.z) * acos(ds.x / length(ds.xz));
     return vec2(acos(ds.y), rot_ang);
 }
@@ -125,7 +851,66 @@ fn img_from_opt(s: InputSpec, angs: vec2<f32>) -> vec2<f32> {
         }
     }
 
-    return vec2(r * cos(angs.y), r * sin(angs.y));
+    return distort(s, vec2(cos(angs.y), sin(angs.y)) * r / s.foc_dist) * s.foc_dist;
+}
+
+// Where the lens moves `u`, in focal distances from the optical center, mirroring
+// `Distortion::apply`.
+fn distort(s: InputSpec, u: vec2<f32>) -> vec2<f32> {
+    let r2 = dot(u, u);
+    let k = s.dist_k;
+    let radial = 1.0 + r2 * (k.x + r2 * (k.y + r2 * (k.z + r2 * k.w)));
+    return u * radial + distort_tangential(s, u, r2);
+}
+
+fn distort_tangential(s: InputSpec, u: vec2<f32>, r2: f32) -> vec2<f32> {
+    let p = s.dist_p;
+    let xy = u.x * u.y;
+    return vec2(
+        -2.0 * p.x * xy + p.y * (r2 + 2.0 * u.x * u.x),
+        2.0 * p.y * xy - p.x * (r2 + 2.0 * u.y * u.y),
+    );
+}
+
+// Inverse of `distort`, mirroring `Distortion::remove`.
+fn undistort(s: InputSpec, d: vec2<f32>) -> vec2<f32> {
+    var u = d;
+    for (var i = 0; i < 10; i++) {
+        let r2 = dot(u, u);
+        let k = s.dist_k;
+        let radial = 1.0 + r2 * (k.x + r2 * (k.y + r2 * (k.z + r2 * k.w)));
+        u = (d - distort_tangential(s, u, r2)) / radial;
+    }
+    return u;
+}
+
+// Inverse of `img_from_opt` followed by `opt_from_world`, as a world direction.
+fn world_from_img(s: InputSpec, distorted: vec2<f32>) -> vec3<f32> {
+    let img = undistort(s, distorted / s.foc_dist) * s.foc_dist;
+    let r = length(img);
+    var ang: f32 = 0.0;
+    switch s.lens_type {
+        case 0u, default: {
+            ang = atan(r / s.foc_dist);
+        }
+        case 1u: {
+            ang = r / s.foc_dist;
+        }
+        case 2u: {
+            ang = 2.0 * asin(min(r / (2.0 * s.foc_dist), 1.0));
+        }
+    }
+
+    var ds = vec3(0.0, 1.0, 0.0);
+    if r > 0.0 {
+        ds = vec3(sin(ang) * img.x / r, cos(ang), sin(ang) * img.y / r);
+    }
+    return transpose(s.rev_mat) * ds;
+}
+
+fn img_from_coord(c: vec2<f32>, size: vec2<u32>) -> vec2<f32> {
+    let sf = vec2f(size);
+    return vec2f(1, -1) * (c * 2.0 - sf) / length(sf);
 }
 
 fn coord_from_img(rp: vec2<f32>, size: vec2<u32>) -> vec2<f32> {
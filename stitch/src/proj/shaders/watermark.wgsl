@@ -0,0 +1,46 @@
+@group(0)
+@binding(0)
+var<uniform> watermark: Watermark;
+
+@group(0)
+@binding(1)
+var image: texture_2d<f32>;
+
+struct Watermark {
+    // [x, y, w, h] in fractions of the output
+    rect: vec4<f32>,
+    opacity: f32,
+}
+
+struct WatermarkVertex {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_watermark(@builtin(vertex_index) v: u32) -> WatermarkVertex {
+    var corners = array(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(1.0, 1.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.0, 1.0),
+        vec2f(0.0, 0.0),
+    );
+    let uv = corners[v];
+    let rect = watermark.rect;
+    let p = rect.xy + uv * rect.zw;
+
+    var out: WatermarkVertex;
+    out.pos = vec4f(p.x * 2.0 - 1.0, 1.0 - p.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_watermark(vert: WatermarkVertex) -> @location(0) vec4<f32> {
+    let size = textureDimensions(image);
+    let p = min(vec2u(vert.uv * vec2f(size)), size - 1u);
+    let c = textureLoad(image, p, 0);
+    return vec4(c.rgb, c.a * watermark.opacity);
+}
@@ -0,0 +1,62 @@
+//! Watermarks shared by the projectors, laid out the same way by both.
+
+use image::RgbaImage;
+
+use crate::{Error, Result};
+
+use super::{HudLabel, Watermark};
+
+/// Picture of `watermark` as RGBA, if it has one.
+///
+/// # Errors
+/// the picture can't be read or decoded
+pub fn open(watermark: &Watermark) -> Result<Option<RgbaImage>> {
+    let Some(path) = watermark.image.as_deref() else {
+        return Ok(None);
+    };
+    image::open(path)
+        .map(|img| Some(img.to_rgba8()))
+        .map_err(|source| Error::WatermarkLoad {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Where a watermark is drawn on a view.
+pub struct Placed {
+    /// `[x, y, w, h]` of the picture in fractions of the output
+    pub rect: [f32; 4],
+    /// Alpha the picture's alpha is scaled by
+    pub opacity: f32,
+    /// Text below the picture
+    pub label: Option<HudLabel>,
+}
+
+/// Places `watermark` with a picture `img` pixels in size on the view called `view`, `out`
+/// pixels in size.
+#[allow(clippy::cast_precision_loss)]
+pub fn place(
+    watermark: &Watermark,
+    view: &str,
+    img: Option<(u32, u32)>,
+    out: (u32, u32),
+) -> Placed {
+    let [x, y] = watermark.pos;
+    let h = img.map_or(0.0, |(iw, ih)| {
+        watermark.width * (out.0 as f32 / out.1 as f32) * (ih as f32 / iw as f32)
+    });
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+
+    let label = watermark.text.as_ref().map(|text| {
+        let mut label = HudLabel::new(text.replace("{view}", view), [x, y + h]);
+        label.scale = watermark.scale;
+        label.color = [1.0, 1.0, 1.0, opacity];
+        label.background = [0.0, 0.0, 0.0, 0.6 * opacity];
+        label
+    });
+    Placed {
+        rect: [x, y, watermark.width, h],
+        opacity,
+        label,
+    }
+}
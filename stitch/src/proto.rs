@@ -0,0 +1,165 @@
+//! Control messages of the stitching server's websocket protocol, sent as JSON in text messages
+//! or as CBOR in binary ones.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::ViewParams,
+    loader::LoaderStatus,
+    proj::{Easing, ProjectionStyle, ViewCrop, ViewPreset},
+};
+
+/// Request from a client, about the view it is watching.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Answered with the [`ViewState`] of the view.
+    GetView,
+    /// Changes the view, keeping whatever is left out. Answered with the new [`ViewState`].
+    SetView {
+        style: Option<ProjectionStyle>,
+        crop: Option<ViewCrop>,
+        /// Whether the ground grid and compass rose are drawn
+        ground_overlay: Option<bool>,
+    },
+    /// Moves the view to `to` over `duration` seconds, from `from` or wherever it is. Answered
+    /// with the [`ViewState`] as the transition starts, see [`crate::proj::ViewTransition`].
+    TransitionView {
+        from: Option<ProjectionStyle>,
+        to: ProjectionStyle,
+        duration: f32,
+        #[serde(default)]
+        easing: Easing,
+    },
+    /// Answered with [`ServerMessage::Presets`].
+    ListPresets,
+    /// Switches the view to the preset called `name` of the config, the way the preset says.
+    /// Answered with the [`ViewState`] once it's applied, or as its transition starts.
+    SelectPreset { name: String },
+    /// Answered with [`ServerMessage::Cameras`].
+    ListCameras,
+    /// Leaves a camera out of the output, or brings it back. Answered with
+    /// [`ServerMessage::Cameras`] once applied.
+    SetCameraEnabled { index: usize, enabled: bool },
+    /// Answered with [`ServerMessage::Health`].
+    GetHealth,
+    /// Starts or stops a [`Status`] being sent every second, and a [`MotionEvent`] every time a
+    /// motion zone starts or stops moving.
+    Subscribe {
+        status: bool,
+        #[serde(default)]
+        motion: bool,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    View(ViewState),
+    Cameras {
+        cameras: Vec<CameraInfo>,
+    },
+    Presets {
+        presets: Vec<ViewPreset>,
+        /// Name of the preset selected last, kept across restarts
+        last: Option<String>,
+    },
+    Health {
+        cameras: Vec<CameraHealth>,
+    },
+    Status(Status),
+    Motion(MotionEvent),
+    /// The request couldn't be parsed or answered.
+    Error {
+        message: String,
+    },
+}
+
+/// Everything that decides what a view shows.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ViewState {
+    /// Name of the view, `main` unless the client has one of its own.
+    pub view: String,
+    pub style: ProjectionStyle,
+    pub crop: ViewCrop,
+    /// Whether the [`crate::proj::GroundOverlay`] is drawn
+    pub ground_overlay: bool,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CameraInfo {
+    pub index: usize,
+    pub resolution: Option<[u32; 2]>,
+    /// Whether the camera is part of the output, see [`ClientMessage::SetCameraEnabled`].
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub view: ViewParams,
+}
+
+/// State of a camera's device, tagged by its `state`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraHealth {
+    pub index: usize,
+    #[serde(flatten)]
+    pub status: LoaderStatus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    /// Frames rendered per second.
+    pub fps: f32,
+    /// Clients connected to the video stream.
+    pub clients: usize,
+    /// Clients with a view of their own.
+    pub client_views: usize,
+    /// Size of the views relative to full size, lowered when rendering can't keep up.
+    pub render_scale: f32,
+    /// Camera frames per rendered frame, raised when rendering can't keep up.
+    pub frame_skip: u32,
+    pub encoding: bool,
+    pub recording: bool,
+}
+
+/// A zone of [`crate::proj::MotionConfig`] started or stopped moving.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MotionEvent {
+    pub zone: String,
+    /// Whether the zone is in motion from now on
+    pub moving: bool,
+    /// Part of the zone that changed in the frame that started or stopped the motion
+    pub area: f32,
+}
+
+/// What a rendered frame was made from, sent to clients after its pixels and recorded along
+/// with the output.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameMeta {
+    /// Counts up with every frame the server renders
+    pub frame: u64,
+    /// When the frame was read back from the projector, as `YYYY-MM-DD HH:MM:SS.mmm UTC`
+    pub rendered: String,
+    /// Camera frame each camera was rendered from, by camera index, `None` for cameras left
+    /// out or without capture times
+    pub captures: Vec<Option<CaptureMeta>>,
+    /// Name of the view, `main` unless the frame is of a client's own view
+    pub view: String,
+    pub style: ProjectionStyle,
+    pub crop: ViewCrop,
+    /// Column major clip from world matrix the ground is projected with, for the styles that
+    /// have one
+    pub view_matrix: Option<[f32; 16]>,
+    /// Whether every motion zone is moving as of the frame, with the part of it that changed in
+    /// the frame. Empty without motion detection
+    pub motion: Vec<MotionEvent>,
+    /// Hash of every camera's position, angles and lens, changing whenever the calibration does
+    pub calibration: String,
+}
+
+/// Camera frame a rendered frame was made from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureMeta {
+    /// See [`crate::loader::Capture::id`]
+    pub id: u64,
+    /// When the frame finished loading, as `YYYY-MM-DD HH:MM:SS.mmm UTC`
+    pub at: String,
+}
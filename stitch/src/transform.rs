@@ -0,0 +1,263 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buf::{FrameSize, PixelFormat},
+    convert,
+    loader::{Loader, OwnedWriteBuffer},
+    DimErrorKind, Result,
+};
+
+/// Changes made to every frame of a camera before it's projected, in the order of the fields,
+/// like turning the frames of a camera mounted upside down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transform {
+    /// Part of the frame kept, as x, y, width and height in pixels
+    pub crop: Option<[u32; 4]>,
+    #[serde(default)]
+    pub rotate: Rotation,
+    /// Mirror the frame left to right
+    #[serde(default)]
+    pub flip: bool,
+}
+
+/// Degrees a frame is turned clockwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl TryFrom<u32> for Rotation {
+    type Error = String;
+
+    fn try_from(deg: u32) -> std::result::Result<Self, String> {
+        match deg {
+            0 => Ok(Self::None),
+            90 => Ok(Self::Cw90),
+            180 => Ok(Self::Cw180),
+            270 => Ok(Self::Cw270),
+            _ => Err(format!(
+                "can't rotate by {deg} degrees, only 0, 90, 180 or 270"
+            )),
+        }
+    }
+}
+
+impl From<Rotation> for u32 {
+    fn from(r: Rotation) -> Self {
+        match r {
+            Rotation::None => 0,
+            Rotation::Cw90 => 90,
+            Rotation::Cw180 => 180,
+            Rotation::Cw270 => 270,
+        }
+    }
+}
+
+impl Rotation {
+    /// Width and height of a `w` by `h` frame once it's turned.
+    #[must_use]
+    pub const fn turn(self, [w, h]: [u32; 2]) -> [u32; 2] {
+        match self {
+            Self::Cw90 | Self::Cw270 => [h, w],
+            Self::None | Self::Cw180 => [w, h],
+        }
+    }
+}
+
+impl Transform {
+    pub const NONE: Self = Self {
+        crop: None,
+        rotate: Rotation::None,
+        flip: false,
+    };
+
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Width and height of a `w` by `h` frame once it's transformed, as long as the crop fits.
+    #[must_use]
+    pub const fn out_size(&self, [w, h]: [u32; 2]) -> [u32; 2] {
+        match self.crop {
+            Some([_, _, cw, ch]) => self.rotate.turn([cw, ch]),
+            None => self.rotate.turn([w, h]),
+        }
+    }
+
+    /// Part of a `w` by `h` frame that's kept, as x, y, width and height.
+    ///
+    /// # Errors
+    /// the crop doesn't fit in the frame
+    fn crop_rect(&self, (w, h): (u32, u32)) -> Result<[u32; 4]> {
+        let Some([x, y, cw, ch]) = self.crop else {
+            return Ok([0, 0, w, h]);
+        };
+        if x + cw > w {
+            return Err(DimErrorKind::Width.err(w as _, (x + cw) as _).into());
+        }
+        if y + ch > h {
+            return Err(DimErrorKind::Height.err(h as _, (y + ch) as _).into());
+        }
+        Ok([x, y, cw, ch])
+    }
+
+    /// Frames of `src` transformed on their own thread, converted to RGBA8 first if they
+    /// come in another format.
+    ///
+    /// # Errors
+    /// the crop doesn't fit in the frames
+    pub fn apply<B: OwnedWriteBuffer + 'static>(self, src: Loader<Box<[u8]>>) -> Result<Loader<B>> {
+        let (w, h) = (src.width(), src.height());
+        let format = src.pixel_format();
+        let [x, y, cw, ch] = self.crop_rect((w as _, h as _))?;
+        let [ow, oh] = self.rotate.turn([cw, ch]);
+
+        // packed formats are moved a pixel at a time, the rest are converted first
+        let (out_format, mut rgba) = match format {
+            PixelFormat::Rgba8 | PixelFormat::Rgb8 | PixelFormat::Gray8 | PixelFormat::Rgba16F => {
+                (format, None)
+            }
+            PixelFormat::Nv12 | PixelFormat::Yuyv => {
+                (PixelFormat::Rgba8, Some(vec![0u8; w * h * 4]))
+            }
+        };
+        let px = out_format.chans() * out_format.bytes_per_chan();
+        let rect = [x, y, cw, ch].map(|n| n as usize);
+
+        Ok(src.map_frames(ow, oh, out_format, move |frame, out| {
+            let frame = match &mut rgba {
+                Some(rgba) => {
+                    if let Err(err) = convert::to_rgba(format, frame, rgba, w, h) {
+                        tracing::warn!("failed to convert frame to transform: {err}");
+                    }
+                    rgba
+                }
+                None => frame,
+            };
+            self.frame(frame, w, px, rect, ow as _, out);
+        }))
+    }
+
+    /// Transforms the `rect` of `frame`, `w` pixels of `px` bytes wide, into `out`, `ow`
+    /// pixels wide.
+    fn frame(
+        &self,
+        frame: &[u8],
+        w: usize,
+        px: usize,
+        rect: [usize; 4],
+        ow: usize,
+        out: &mut [u8],
+    ) {
+        let [x, y, cw, ch] = rect;
+        out.par_chunks_exact_mut(ow * px)
+            .enumerate()
+            .for_each(|(oy, row)| {
+                for (ox, out_px) in row.chunks_exact_mut(px).enumerate() {
+                    let ox = if self.flip { ow - 1 - ox } else { ox };
+                    let (cx, cy) = match self.rotate {
+                        Rotation::None => (ox, oy),
+                        Rotation::Cw90 => (oy, ch - 1 - ox),
+                        Rotation::Cw180 => (cw - 1 - ox, ch - 1 - oy),
+                        Rotation::Cw270 => (cw - 1 - oy, ox),
+                    };
+                    out_px.copy_from_slice(&frame[((y + cy) * w + x + cx) * px..][..px]);
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3 by 2 frame of one byte pixels, numbered row by row from 1.
+    const FRAME: [u8; 6] = [1, 2, 3, 4, 5, 6];
+
+    /// `FRAME` through `t`, as rows of pixels.
+    fn run(t: Transform) -> Vec<Vec<u8>> {
+        let [x, y, cw, ch] = t.crop_rect((3, 2)).unwrap().map(|n| n as usize);
+        let [ow, oh] = t.out_size([3, 2]).map(|n| n as usize);
+        let mut out = vec![0; ow * oh];
+        t.frame(&FRAME, 3, 1, [x, y, cw, ch], ow, &mut out);
+        out.chunks_exact(ow).map(<[u8]>::to_vec).collect()
+    }
+
+    fn turned(rotate: Rotation, flip: bool) -> Vec<Vec<u8>> {
+        run(Transform {
+            rotate,
+            flip,
+            ..Transform::NONE
+        })
+    }
+
+    #[test]
+    fn rotations() {
+        assert_eq!(turned(Rotation::None, false), [[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(turned(Rotation::Cw90, false), [[4, 1], [5, 2], [6, 3]]);
+        assert_eq!(turned(Rotation::Cw180, false), [[6, 5, 4], [3, 2, 1]]);
+        assert_eq!(turned(Rotation::Cw270, false), [[3, 6], [2, 5], [1, 4]]);
+    }
+
+    #[test]
+    fn flips_after_rotating() {
+        assert_eq!(turned(Rotation::None, true), [[3, 2, 1], [6, 5, 4]]);
+        assert_eq!(turned(Rotation::Cw90, true), [[1, 4], [2, 5], [3, 6]]);
+        assert_eq!(turned(Rotation::Cw180, true), [[4, 5, 6], [1, 2, 3]]);
+        assert_eq!(turned(Rotation::Cw270, true), [[6, 3], [5, 2], [4, 1]]);
+    }
+
+    #[test]
+    fn crops_before_rotating() {
+        let crop = |rotate| {
+            run(Transform {
+                crop: Some([1, 0, 2, 2]),
+                rotate,
+                flip: false,
+            })
+        };
+        assert_eq!(crop(Rotation::None), [[2, 3], [5, 6]]);
+        assert_eq!(crop(Rotation::Cw90), [[5, 2], [6, 3]]);
+
+        let too_wide = Transform {
+            crop: Some([2, 0, 2, 1]),
+            ..Transform::NONE
+        };
+        assert!(too_wide.crop_rect((3, 2)).is_err());
+        let too_tall = Transform {
+            crop: Some([0, 1, 1, 2]),
+            ..Transform::NONE
+        };
+        assert!(too_tall.crop_rect((3, 2)).is_err());
+    }
+
+    #[test]
+    fn moves_whole_pixels() {
+        // the same frame with 2 bytes a pixel, the second byte 10 more than the first
+        let frame = FRAME.iter().flat_map(|&n| [n, n + 10]).collect::<Vec<_>>();
+        let t = Transform {
+            rotate: Rotation::Cw270,
+            ..Transform::NONE
+        };
+        let mut out = vec![0; 12];
+        t.frame(&frame, 3, 2, [0, 0, 3, 2], 2, &mut out);
+        assert_eq!(out, [3, 13, 6, 16, 2, 12, 5, 15, 1, 11, 4, 14]);
+    }
+
+    #[test]
+    fn rotation_degrees() {
+        for deg in [0, 90, 180, 270] {
+            assert_eq!(u32::from(Rotation::try_from(deg).unwrap()), deg);
+        }
+        assert!(Rotation::try_from(45).is_err());
+        assert_eq!(Rotation::Cw90.turn([3, 2]), [2, 3]);
+        assert_eq!(Rotation::Cw180.turn([3, 2]), [3, 2]);
+    }
+}
@@ -0,0 +1,67 @@
+//! Checks the pixel format conversions against frames small enough to work out by hand.
+
+use stitch::convert;
+
+/// Y of 235 with neutral chroma is full white in limited range.
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+/// Bytes in a `width` by `height` NV12 frame, where odd sizes still get a whole UV pair.
+const fn nv12_bytes(width: usize, height: usize) -> usize {
+    width * height + width.div_ceil(2) * 2 * height.div_ceil(2)
+}
+
+#[test]
+fn nv12_odd_dimensions() {
+    for (width, height) in [(3, 2), (2, 3), (3, 3), (1, 1)] {
+        let luma = width * height;
+        let mut src = vec![235; nv12_bytes(width, height)];
+        src[luma..].fill(128);
+        let mut dst = vec![0; width * height * 4];
+
+        convert::nv12_to_rgba(&src, &mut dst, width, height).unwrap();
+        assert!(
+            dst.chunks_exact(4).all(|px| px == WHITE),
+            "{width} * {height} frame isn't white"
+        );
+    }
+}
+
+#[test]
+fn nv12_last_column_uses_its_own_chroma() {
+    // 3 * 2 frame, so one row of chroma with two UV pairs
+    let mut src = vec![128; nv12_bytes(3, 2)];
+    assert_eq!(src.len(), 10);
+    src[..6].fill(235);
+    src[8..].copy_from_slice(&[128, 255]);
+    let mut dst = vec![0; 3 * 2 * 4];
+
+    convert::nv12_to_rgba(&src, &mut dst, 3, 2).unwrap();
+    for row in dst.chunks_exact(3 * 4) {
+        assert_eq!(row[..4], WHITE);
+        assert_eq!(row[4..8], WHITE);
+        assert_ne!(row[8..], WHITE, "last pixel should be tinted by its V");
+    }
+}
+
+#[test]
+fn yuyv_odd_width() {
+    // 3 * 2 frame, so every row ends on a macropixel with a padding pixel
+    let src = [235, 128, 235, 128, 235, 128, 16, 255].repeat(2);
+    assert_eq!(src.len(), 16);
+    let mut dst = vec![0; 3 * 2 * 4];
+
+    convert::yuyv_to_rgba(&src, &mut dst, 3, 2).unwrap();
+    for row in dst.chunks_exact(3 * 4) {
+        assert_eq!(row[..4], WHITE);
+        assert_eq!(row[4..8], WHITE);
+        assert_ne!(row[8..], WHITE, "last pixel should be tinted by its V");
+    }
+}
+
+#[test]
+fn yuyv_rejects_short_rows() {
+    // 3 pixels need 2 macropixels a row, not 1.5
+    let src = vec![128; 3 * 2 * 2];
+    let mut dst = vec![0; 3 * 2 * 4];
+    assert!(convert::yuyv_to_rgba(&src, &mut dst, 3, 2).is_err());
+}
@@ -0,0 +1,411 @@
+//! Renders synthetic pattern cameras through both projectors and compares every scene against
+//! the PNGs in `tests/golden`, so the projection math of the two can't drift apart unnoticed.
+//!
+//! Run with `cargo test -p stitch --features golden`. After an intended change to the
+//! projection, rewrite the goldens from the CPU projector with `STITCH_BLESS=1`. Without a GPU
+//! adapter only the CPU projector is checked, unless `STITCH_GOLDEN_GPU=1` is set to make that
+//! a failure.
+#![cfg(feature = "golden")]
+
+use std::{
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+};
+
+use image::RgbaImage;
+use stitch::{
+    buf::FrameSize,
+    camera::{Camera, Config, Fov, LensKind},
+    loader::{self, Loader, OwnedWriteBuffer},
+    proj::{
+        CpuProjector, DewarpLens, GpuProjector, HudLabel, PipOverlay, ProjectionStyle, Sampling,
+        SeamBlend, ToneCurve, ToneMap, ViewCrop, MAIN_VIEW,
+    },
+};
+
+const INPUT_SIZE: (u32, u32) = (128, 96);
+
+/// Per pixel color difference, as a fraction of the largest possible, that counts as a change.
+const PIXEL_THRESHOLD: f32 = 0.1;
+
+/// Fraction of changed pixels allowed, for seams that land a pixel over from float rounding.
+const MAX_CHANGED: f32 = 0.01;
+
+struct Scene {
+    name: &'static str,
+    size: (usize, usize),
+    style: ProjectionStyle,
+    blend: SeamBlend,
+    sampling: Sampling,
+    tone_map: ToneMap,
+    crop: ViewCrop,
+    overlays: Vec<PipOverlay>,
+    hud: Vec<HudLabel>,
+}
+
+impl Scene {
+    fn new(name: &'static str, style: ProjectionStyle) -> Self {
+        Self {
+            name,
+            size: (160, 120),
+            style,
+            blend: SeamBlend::Nearest,
+            sampling: Sampling::Nearest,
+            tone_map: ToneMap::default(),
+            crop: ViewCrop::FULL,
+            overlays: Vec::new(),
+            hud: Vec::new(),
+        }
+    }
+}
+
+fn scenes() -> Vec<Scene> {
+    let hemisphere = ProjectionStyle::Hemisphere {
+        pos: [0.0, 0.0, 0.0],
+        radius: 30.0,
+    };
+    let dome = [0.0, 0.0, 4.0];
+
+    vec![
+        Scene::new("hemisphere", hemisphere),
+        Scene {
+            blend: SeamBlend::Feather { width: 12.0 },
+            ..Scene::new("hemisphere_feather", hemisphere)
+        },
+        Scene {
+            crop: ViewCrop::zoomed([0.6, 0.4], 2.5),
+            ..Scene::new("hemisphere_crop", hemisphere)
+        },
+        Scene {
+            sampling: Sampling::Bicubic,
+            crop: ViewCrop::zoomed([0.6, 0.4], 2.5),
+            ..Scene::new("hemisphere_bicubic", hemisphere)
+        },
+        Scene {
+            tone_map: ToneMap {
+                curve: ToneCurve::Aces,
+                exposure: 1.0,
+            },
+            ..Scene::new("hemisphere_aces", hemisphere)
+        },
+        Scene::new(
+            "equirect",
+            ProjectionStyle::Equirect {
+                pos: dome,
+                radius: 60.0,
+            },
+        ),
+        Scene {
+            size: (180, 120),
+            ..Scene::new(
+                "cube_map",
+                ProjectionStyle::CubeMap {
+                    pos: dome,
+                    radius: 60.0,
+                },
+            )
+        },
+        Scene::new(
+            "single_camera",
+            ProjectionStyle::SingleCamera {
+                index: 1,
+                lens: DewarpLens::Cylindrical,
+                fov: 140.0,
+                yaw: 10.0,
+                pitch: -5.0,
+            },
+        ),
+        Scene::new("raw_camera", ProjectionStyle::RawCamera(3)),
+        Scene {
+            overlays: vec![PipOverlay {
+                camera: 2,
+                rect: [0.6, 0.05, 0.35, 0.35],
+                border_width: 2.0,
+                border_color: [1.0, 0.5, 0.0, 1.0],
+                label: Some("cam 2".to_string()),
+            }],
+            hud: vec![HudLabel {
+                background: [0.0, 0.0, 0.0, 0.5],
+                ..HudLabel::new("golden\n#1", [0.03, 0.7])
+            }],
+            ..Scene::new("overlay_hud", hemisphere)
+        },
+    ]
+}
+
+/// Cameras looking down and out in every direction, each with a different lens, roll and image
+/// offset so every part of the camera model is exercised.
+fn cameras() -> Vec<Config<()>> {
+    vec![
+        Config::builder((), Fov::D(120.0))
+            .pos(0.0, 0.5, 8.0)
+            .pitch(35.0)
+            .build(),
+        Config::builder((), Fov::W(150.0))
+            .pos(0.5, 0.0, 8.0)
+            .pitch(35.0)
+            .azimuth(90.0)
+            .roll(6.0)
+            .img_off(3.0, -2.0)
+            .lens(LensKind::Equidistant)
+            .build(),
+        Config::builder((), Fov::D(150.0))
+            .pos(0.0, -0.5, 7.5)
+            .pitch(-40.0)
+            .azimuth(180.0)
+            .roll(-4.0)
+            .lens(LensKind::Equisolid)
+            .build(),
+        Config::builder((), Fov::H(70.0))
+            .pos(-0.5, 0.0, 8.5)
+            .pitch(30.0)
+            .azimuth(275.0)
+            .img_off(-4.0, 1.0)
+            .build(),
+    ]
+}
+
+/// Checkerboard in a color of its own for every camera, darkening to the right with a white
+/// block in the top left corner so flips and rotations show.
+fn pattern(cam: usize) -> Vec<u8> {
+    const COLORS: [[u8; 3]; 4] = [[230, 40, 40], [40, 200, 60], [50, 80, 230], [220, 200, 40]];
+    let (w, h) = INPUT_SIZE;
+
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            if x < w / 6 && y < h / 6 {
+                return [255; 4];
+            }
+
+            let shade = 1.0 - 0.6 * x as f32 / w as f32;
+            let dark = if (x / 8 + y / 8) % 2 == 0 { 1.0 } else { 0.5 };
+            let [r, g, b] = COLORS[cam].map(|c| (f32::from(c) * shade * dark) as u8);
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+fn pattern_cams<B: OwnedWriteBuffer + 'static>() -> Vec<Camera<Loader<B>>> {
+    let (w, h) = INPUT_SIZE;
+    cameras()
+        .into_iter()
+        .enumerate()
+        .map(|(i, cfg)| {
+            let frame = pattern(i);
+            let loader = Loader::new_blocking(w, h, 4, move |buf| buf.copy_from_slice(&frame));
+            cfg.with_dims(w as f32, h as f32).with_buffer(loader)
+        })
+        .collect()
+}
+
+fn render_cpu(scene: &Scene) -> Vec<u8> {
+    let (w, h) = INPUT_SIZE;
+    let cams = pattern_cams();
+
+    let mut proj = CpuProjector::builder()
+        .input_size(w, h, cams.len() as u32)
+        .out_size(scene.size.0, scene.size.1)
+        .blend(scene.blend)
+        .sampling(scene.sampling)
+        .tone_map(scene.tone_map)
+        .build()
+        .unwrap();
+    proj.block_load_inputs(&cams).unwrap();
+    proj.update_cam_specs(&cams).unwrap();
+    proj.update_proj_view(scene.style);
+    proj.update_view_crop(MAIN_VIEW, scene.crop);
+    proj.update_view_overlays(MAIN_VIEW, &scene.overlays);
+    proj.update_view_hud(MAIN_VIEW, &scene.hud);
+    proj.update_render();
+
+    proj.view_output(MAIN_VIEW).to_vec()
+}
+
+/// `None` if there is no GPU adapter to render with.
+fn render_gpu(rt: &tokio::runtime::Runtime, scene: &Scene) -> Option<Vec<u8>> {
+    let (w, h) = INPUT_SIZE;
+    let cams = pattern_cams();
+
+    let builder = match rt.block_on(GpuProjector::builder_auto()) {
+        Ok(b) => b,
+        Err(err) => {
+            eprintln!("no GPU to check the goldens with: {err}");
+            return None;
+        }
+    };
+    let proj = builder
+        .input_size(w, h, cams.len() as u32)
+        .out_size(scene.size.0, scene.size.1)
+        .flat_bound()
+        .blend(scene.blend)
+        .sampling(scene.sampling)
+        .tone_map(scene.tone_map)
+        .build()
+        .unwrap();
+    loader::block_discard_tickets(proj.take_input_buffers(&cams).unwrap());
+    proj.update_cam_specs(&cams).unwrap();
+    proj.update_proj_view(scene.style);
+    proj.update_view_crop(MAIN_VIEW, scene.crop);
+    proj.update_view_overlays(MAIN_VIEW, &scene.overlays);
+    proj.update_view_hud(MAIN_VIEW, &scene.hud);
+    proj.update_render();
+
+    let mut out = Output::new(scene.size);
+    proj.block_copy_render_to(&mut out).unwrap();
+    Some(out.data)
+}
+
+/// RGBA output of a view, read back from the GPU.
+struct Output {
+    data: Vec<u8>,
+    size: (usize, usize),
+}
+
+impl Output {
+    fn new(size: (usize, usize)) -> Self {
+        Self {
+            data: vec![0; size.0 * size.1 * 4],
+            size,
+        }
+    }
+}
+
+impl FrameSize for Output {
+    fn width(&self) -> usize {
+        self.size.0
+    }
+
+    fn height(&self) -> usize {
+        self.size.1
+    }
+
+    fn chans(&self) -> usize {
+        4
+    }
+}
+
+impl Deref for Output {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl DerefMut for Output {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"))
+}
+
+/// Difference between two pixels in YIQ, as seen over a white background, from 0 to 1. See
+/// Kotsarenko & Ramos, "Measuring perceived color difference using YIQ NTSC transmission color
+/// space in mobile applications" (2010).
+fn perceived_delta(a: &[u8], b: &[u8]) -> f32 {
+    const MAX_DELTA: f32 = 35215.0;
+
+    let yiq = |p: &[u8]| {
+        let alpha = f32::from(p[3]) / 255.0;
+        let [r, g, b] = [p[0], p[1], p[2]].map(|c| 255.0 + (f32::from(c) - 255.0) * alpha);
+        [
+            r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_2,
+            r * 0.595_978 - g * 0.274_176_1 - b * 0.321_801_9,
+            r * 0.211_470_2 - g * 0.522_617_1 + b * 0.311_146_9,
+        ]
+    };
+
+    let ([y1, i1, q1], [y2, i2, q2]) = (yiq(a), yiq(b));
+    let (dy, di, dq) = (y1 - y2, i1 - i2, q1 - q2);
+    (0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / MAX_DELTA
+}
+
+/// Compares `out` to the golden image, saving it next to the test binaries when they differ.
+fn check(scene: &Scene, renderer: &str, golden: &RgbaImage, out: &[u8]) -> Result<(), String> {
+    let (w, h) = scene.size;
+    if golden.dimensions() != (w as u32, h as u32) {
+        return Err(format!(
+            "{}: golden is {:?}, expected {w}x{h}",
+            scene.name,
+            golden.dimensions()
+        ));
+    }
+
+    let changed = golden
+        .as_raw()
+        .chunks_exact(4)
+        .zip(out.chunks_exact(4))
+        .filter(|(g, o)| perceived_delta(g, o) > PIXEL_THRESHOLD * PIXEL_THRESHOLD)
+        .count();
+    let frac = changed as f32 / (w * h) as f32;
+    if frac <= MAX_CHANGED {
+        return Ok(());
+    }
+
+    let actual = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(format!("golden-{}-{renderer}.png", scene.name));
+    RgbaImage::from_raw(w as u32, h as u32, out.to_vec())
+        .unwrap()
+        .save(&actual)
+        .unwrap();
+    Err(format!(
+        "{} ({renderer}): {:.2}% of pixels changed, see {}",
+        scene.name,
+        frac * 100.0,
+        actual.display()
+    ))
+}
+
+#[test]
+fn projections_match_goldens() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _rt = rt.enter();
+    let bless = std::env::var_os("STITCH_BLESS").is_some();
+    let require_gpu = std::env::var_os("STITCH_GOLDEN_GPU").is_some();
+
+    let mut failures = Vec::new();
+    let mut gpu = true;
+    for scene in scenes() {
+        let path = golden_path(scene.name);
+        let cpu = render_cpu(&scene);
+
+        let (w, h) = scene.size;
+        if bless {
+            RgbaImage::from_raw(w as u32, h as u32, cpu.clone())
+                .unwrap()
+                .save(&path)
+                .unwrap();
+        }
+        let golden = match image::open(&path) {
+            Ok(img) => img.to_rgba8(),
+            Err(err) => {
+                failures.push(format!(
+                    "{}: can't open {}, write it with STITCH_BLESS=1: {err}",
+                    scene.name,
+                    path.display()
+                ));
+                continue;
+            }
+        };
+
+        failures.extend(check(&scene, "cpu", &golden, &cpu).err());
+        if gpu {
+            match render_gpu(&rt, &scene) {
+                Some(out) => failures.extend(check(&scene, "gpu", &golden, &out).err()),
+                None => gpu = false,
+            }
+        }
+    }
+
+    assert!(
+        gpu || !require_gpu,
+        "STITCH_GOLDEN_GPU is set without a GPU"
+    );
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -1,59 +1,179 @@
 use std::{
+    collections::BTreeMap,
     fmt::Debug,
     future::Future,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
-use axum::{extract::ws::Message, routing::get, Router};
-use stitch::proj::ProjectionStyle;
-use tokio::net::{TcpListener, ToSocketAddrs};
+use axum::{
+    extract::{self, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use stitch::{
+    camera::live::{self, Controls},
+    loader::{self, LoaderStatus},
+    proj::{self, GpuContext, ProjectionStyle, ViewCrop, ViewPreset, ViewTransition, MAIN_VIEW},
+    proto::{CameraHealth, CameraInfo, MotionEvent, Status, ViewState},
+};
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    sync::{broadcast, watch},
+};
 
-use crate::{log, util::ws_upgrader};
+use crate::{
+    encode::{self, Codec, EncodeArgs},
+    log,
+    recorder::{RecordArgs, RecordSettings, RecordStatus, Recorder},
+    util::{ws_upgrader, MetricSummary, Metrics},
+};
 
 mod stitcher;
-use stitcher::Sticher;
+pub(crate) use stitcher::build_projector;
+pub use stitcher::RenderArgs;
+use stitcher::{ClientView, Frame, Snapshot, Sticher};
 
+mod motion;
 mod proto;
+mod quality;
+mod reload;
+mod snapshot;
+mod sync;
 mod video;
+#[cfg(feature = "webrtc")]
+mod webrtc;
+
+/// Longest each step of [`App::shutdown`] is waited for.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct App(Arc<AppInner>);
 
 struct AppInner {
     pub stitcher: Sticher,
+    pub recorder: Recorder,
+    pub clients: AtomicUsize,
+    /// Other rigs stitched on the same GPU, see [`RenderArgs::rigs`].
+    pub rigs: BTreeMap<String, App>,
+    /// File the name of the last preset selected is saved to, next to the config.
+    pub preset_path: PathBuf,
+    pub last_preset: Mutex<Option<String>>,
+    /// See [`EncodeArgs::ice_servers`].
+    #[cfg(feature = "webrtc")]
+    pub ice_servers: Vec<String>,
 }
 
 impl App {
     pub fn into_router(self) -> Router {
-        Router::new()
+        let rigs = self
+            .0
+            .rigs
+            .iter()
+            .fold(Router::new(), |router, (name, rig)| {
+                router.nest(&format!("/rigs/{name}"), rig.clone().api_router())
+            });
+
+        self.api_router()
+            .merge(rigs)
             .fallback_service(tower_http::services::ServeDir::new(PathBuf::from(
                 "stitching_server/assets",
             )))
-            .route("/video", get(ws_upgrader(video::conn_state_machine)))
             .layer(log::http_trace_layer())
+    }
+
+    /// Every route of the rig, without the assets.
+    fn api_router(self) -> Router {
+        let router = Router::new();
+        #[cfg(feature = "webrtc")]
+        let router = router.route("/webrtc/offer", axum::routing::post(webrtc::offer));
+
+        router
+            .route("/video", get(ws_upgrader(video::conn_state_machine)))
+            .route(
+                "/video/encoded",
+                get(ws_upgrader(video::encoded_state_machine)),
+            )
+            .route("/capabilities", get(capabilities))
+            .route("/metrics", get(metrics))
+            .route("/health", get(health))
+            .route("/debug/dump", get(debug_events).post(debug_dump))
+            .route("/snapshot", get(snapshot::snapshot))
+            .route("/record", get(record_status).put(record_configure))
+            .route("/record/start", post(record_start))
+            .route("/record/stop", post(record_stop))
+            .route(
+                "/cameras/:id/controls",
+                get(camera_controls).post(camera_controls_set),
+            )
             .with_state(self)
     }
 
+    /// Loads the config at `p`, and the config of every rig in `render`, applying changes to
+    /// them while running if `watch_config` is set.
     pub async fn from_toml_cfg(
         p: impl AsRef<Path> + Send,
         proj_w: usize,
         proj_h: usize,
+        render: RenderArgs,
+        encode: EncodeArgs,
+        record: RecordArgs,
+        watch_config: bool,
     ) -> stitch::Result<Self> {
-        AppInner::from_toml_cfg(p, proj_w, proj_h)
+        let path = p.as_ref().to_path_buf();
+        let mut inner = AppInner::from_toml_cfg(
+            &path,
+            proj_w,
+            proj_h,
+            render.clone(),
+            encode.clone(),
+            record.clone(),
+            None,
+        )
+        .await?;
+
+        let gpu = inner.stitcher.gpu_context().clone();
+        for (name, rig_path) in render.rigs.clone() {
+            // rigs always open their cameras and record next to each other
+            let rig_render = RenderArgs {
+                replay: None,
+                rigs: Vec::new(),
+                ..render.clone()
+            };
+            let rig_record = RecordArgs {
+                record_dir: record.record_dir.join(&name),
+                ..record.clone()
+            };
+            let rig = AppInner::from_toml_cfg(
+                &rig_path,
+                proj_w,
+                proj_h,
+                rig_render,
+                encode.clone(),
+                rig_record,
+                Some(gpu.clone()),
+            )
             .await
             .map(Arc::new)
-            .map(Self)
-    }
-
-    pub async fn listen_and_serve(
-        self,
-        a: impl ToSocketAddrs + Debug + Send + Sync,
-    ) -> std::io::Result<()> {
-        let bind = TcpListener::bind(&a).await?;
-        tracing::info!("listening on {a:?}");
+            .map(Self)?;
+            if watch_config {
+                tokio::spawn(reload::watch_config(rig_path, rig.clone()));
+            }
+            tracing::info!("serving rig {name} under /rigs/{name}/");
+            inner.rigs.insert(name, rig);
+        }
 
-        axum::serve(bind, self.into_router()).await
+        let app = Self(Arc::new(inner));
+        if watch_config {
+            tokio::spawn(reload::watch_config(path, app.clone()));
+        }
+        Ok(app)
     }
 
     pub async fn listen_and_serve_until(
@@ -69,13 +189,300 @@ impl App {
             .await
     }
 
-    pub async fn ws_frame(&self) -> Option<Message> {
-        self.0.stitcher.next_frame_msg().await
+    /// Stops every rig in an order that leaves nothing half written or open: recordings are
+    /// finished while the encoders still feed them, then rendering stops and the cameras are
+    /// closed. Steps that take longer than [`SHUTDOWN_TIMEOUT`] are given up on.
+    pub async fn shutdown(&self) {
+        let apps = std::iter::once(self)
+            .chain(self.0.rigs.values())
+            .collect::<Vec<_>>();
+
+        for app in &apps {
+            let inner = app.0.clone();
+            let finished = tokio::task::spawn_blocking(move || inner.recorder.finish());
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, finished)
+                .await
+                .is_err()
+            {
+                tracing::warn!("gave up waiting for the recording to finish");
+            }
+        }
+        for app in &apps {
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, app.0.stitcher.shutdown())
+                .await
+                .is_err()
+            {
+                tracing::warn!("gave up waiting for rendering to stop");
+            }
+        }
+
+        let closed =
+            tokio::task::spawn_blocking(|| loader::block_until_stopped(SHUTDOWN_TIMEOUT)).await;
+        if !matches!(closed, Ok(true)) {
+            tracing::warn!("gave up waiting for the cameras to close");
+        }
+        tracing::info!("shut down");
+    }
+
+    pub fn subscribe_frames(&self) -> watch::Receiver<Frame> {
+        self.0.stitcher.subscribe_frames()
+    }
+
+    pub async fn open_view(&self) -> Option<ClientView> {
+        self.0.stitcher.open_view().await
+    }
+
+    /// See [`Sticher::subscribe_motion`].
+    pub fn subscribe_motion(&self) -> broadcast::Receiver<MotionEvent> {
+        self.0.stitcher.subscribe_motion()
+    }
+
+    pub async fn view_state(&self, view: &str) -> Option<ViewState> {
+        self.0.stitcher.view_state(view).await
+    }
+
+    pub fn cameras(&self) -> Vec<CameraInfo> {
+        self.0.stitcher.cameras()
+    }
+
+    /// See [`Sticher::camera_health`].
+    pub fn camera_health(&self) -> Vec<CameraHealth> {
+        self.0.stitcher.camera_health()
+    }
+
+    /// Every camera of this rig and the rigs it serves, healthy if they're all streaming.
+    pub fn health(&self) -> Health {
+        let cameras = self.camera_health();
+        let rigs = (self.0.rigs.iter())
+            .map(|(name, rig)| (name.clone(), rig.health()))
+            .collect::<BTreeMap<_, _>>();
+        Health {
+            ok: cameras
+                .iter()
+                .all(|cam| matches!(cam.status, LoaderStatus::Streaming { .. }))
+                && rigs.values().all(|rig| rig.ok),
+            cameras,
+            rigs,
+        }
+    }
+
+    /// Presets of the config, along with the name of the one selected last.
+    pub fn presets(&self) -> (Vec<ViewPreset>, Option<String>) {
+        let last = self.0.last_preset.lock().unwrap().clone();
+        (self.0.stitcher.presets(), last)
+    }
+
+    /// Switches `view` to the preset called `name`. Presets selected for the main view are saved
+    /// as the last one selected, so it starts out at it next time. Returns false if the config
+    /// has no such preset.
+    pub async fn select_preset(&self, view: &str, name: &str) -> bool {
+        let presets = self.0.stitcher.presets();
+        let Some(preset) = presets.iter().find(|p| p.name == name) else {
+            return false;
+        };
+        self.0.stitcher.apply_preset(view, preset);
+        if view != MAIN_VIEW {
+            return true;
+        }
+        *self.0.last_preset.lock().unwrap() = Some(name.to_string());
+
+        let (path, name) = (self.0.preset_path.clone(), name.to_string());
+        let saved = tokio::task::spawn_blocking(move || std::fs::write(&path, name)).await;
+        if let Ok(Err(err)) = saved {
+            tracing::warn!(
+                "failed to save the last preset to {:?}: {err}",
+                self.0.preset_path
+            );
+        }
+        true
+    }
+
+    /// See [`Sticher::set_camera_enabled`].
+    pub async fn set_camera_enabled(&self, idx: usize, enabled: bool) -> bool {
+        self.0.stitcher.set_camera_enabled(idx, enabled).await
+    }
+
+    /// See [`Sticher::camera_controls`].
+    pub async fn camera_controls(&self, idx: usize, controls: Controls) -> Option<Controls> {
+        self.0.stitcher.camera_controls(idx, controls).await
+    }
+
+    /// See [`Sticher::reload`].
+    pub fn reload_config(&self, cfg: proj::Config<live::Config>) -> bool {
+        self.0.stitcher.reload(cfg)
+    }
+
+    /// Counts a video client in [`Self::status`] until the matching [`Self::remove_client`].
+    pub fn add_client(&self) {
+        self.0.clients.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn update_style<F: FnOnce(&mut ProjectionStyle) + Send + 'static>(&self, f: F) {
-        self.0.stitcher.update_style(f);
+    pub fn remove_client(&self) {
+        self.0.clients.fetch_sub(1, Ordering::Relaxed);
     }
+
+    pub fn status(&self) -> Status {
+        let stats = self.0.stitcher.stats();
+        Status {
+            fps: stats.fps,
+            clients: self.0.clients.load(Ordering::Relaxed),
+            client_views: stats.client_views,
+            render_scale: stats.quality.scale,
+            frame_skip: stats.quality.frame_skip,
+            encoding: self.0.stitcher.is_encoding(),
+            recording: self.0.recorder.is_recording(),
+        }
+    }
+
+    pub fn subscribe_encoded(&self) -> Option<(Codec, broadcast::Receiver<Arc<[u8]>>)> {
+        self.0.stitcher.subscribe_encoded()
+    }
+
+    pub fn update_style<F: FnOnce(&mut ProjectionStyle) + Send + 'static>(&self, view: &str, f: F) {
+        self.0.stitcher.update_style(view, f);
+    }
+
+    /// See [`Sticher::transition_style`].
+    pub fn transition_style<F: FnOnce(ProjectionStyle) -> ViewTransition + Send + 'static>(
+        &self,
+        view: &str,
+        f: F,
+    ) {
+        self.0.stitcher.transition_style(view, f);
+    }
+
+    pub fn update_crop(&self, view: &str, crop: ViewCrop) {
+        self.0.stitcher.update_crop(view, crop);
+    }
+
+    pub fn update_ground_overlay(&self, view: &str, shown: bool) {
+        self.0.stitcher.update_ground_overlay(view, shown);
+    }
+
+    pub async fn snapshot(&self, view: &str) -> Option<Snapshot> {
+        self.0.stitcher.snapshot(view).await
+    }
+}
+
+#[derive(Serialize)]
+struct Capabilities {
+    stitch: stitch::Capabilities,
+    capture: bool,
+    webrtc: bool,
+    panel: bool,
+    /// ffmpeg encoders `--encoder` can pick from
+    encoders: Vec<&'static str>,
+    gpu: stitcher::GpuInfo,
+}
+
+async fn capabilities(State(state): State<App>) -> Json<Capabilities> {
+    Json(Capabilities {
+        stitch: stitch::capabilities(),
+        capture: cfg!(feature = "capture"),
+        webrtc: cfg!(feature = "webrtc"),
+        panel: cfg!(feature = "panel"),
+        encoders: tokio::task::spawn_blocking(encode::available_encoders)
+            .await
+            .unwrap_or_default(),
+        gpu: state.0.stitcher.gpu_info().clone(),
+    })
+}
+
+#[derive(Serialize)]
+pub struct Health {
+    pub ok: bool,
+    pub cameras: Vec<CameraHealth>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub rigs: BTreeMap<String, Health>,
+}
+
+/// Answers with service unavailable unless every camera is healthy, so it can be polled as is.
+async fn health(State(state): State<App>) -> (StatusCode, Json<Health>) {
+    let health = state.health();
+    let code = if health.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(health))
+}
+
+async fn metrics() -> Json<BTreeMap<String, MetricSummary>> {
+    Json(Metrics::summaries())
+}
+
+async fn debug_events() -> Json<Vec<log::LoggedEvent>> {
+    Json(log::recent_events())
+}
+
+#[derive(Serialize)]
+struct Dumped {
+    path: PathBuf,
+}
+
+async fn debug_dump() -> Result<Json<Dumped>, (StatusCode, String)> {
+    let path = tokio::task::spawn_blocking(|| log::dump_events(Path::new(".")))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    tracing::info!("dumped recent events to {path:?}");
+    Ok(Json(Dumped { path }))
+}
+
+async fn record_status(State(state): State<App>) -> Json<RecordStatus> {
+    Json(state.0.recorder.status())
+}
+
+async fn record_start(
+    State(state): State<App>,
+) -> Result<Json<RecordStatus>, (StatusCode, String)> {
+    let recorder = &state.0.recorder;
+    if !recorder.has_sources() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "recording needs the server to run with --encode".to_string(),
+        ));
+    }
+
+    recorder
+        .start()
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok(Json(recorder.status()))
+}
+
+async fn record_stop(State(state): State<App>) -> Json<RecordStatus> {
+    state.0.recorder.stop();
+    Json(state.0.recorder.status())
+}
+
+async fn record_configure(
+    State(state): State<App>,
+    Json(settings): Json<RecordSettings>,
+) -> Result<Json<RecordStatus>, (StatusCode, String)> {
+    state
+        .0
+        .recorder
+        .configure(settings)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok(Json(state.0.recorder.status()))
+}
+
+async fn camera_controls(
+    State(state): State<App>,
+    extract::Path(idx): extract::Path<usize>,
+) -> Result<Json<Controls>, (StatusCode, String)> {
+    camera_controls_set(State(state), extract::Path(idx), Json(Controls::NONE)).await
+}
+
+async fn camera_controls_set(
+    State(state): State<App>,
+    extract::Path(idx): extract::Path<usize>,
+    Json(controls): Json<Controls>,
+) -> Result<Json<Controls>, (StatusCode, String)> {
+    state.camera_controls(idx, controls).await.map(Json).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("no camera {idx} with controls"),
+    ))
 }
 
 impl AppInner {
@@ -83,12 +490,62 @@ impl AppInner {
         p: impl AsRef<Path> + Send,
         proj_w: usize,
         proj_h: usize,
+        render: RenderArgs,
+        encode: EncodeArgs,
+        record: RecordArgs,
+        shared_gpu: Option<Arc<GpuContext>>,
     ) -> stitch::Result<Self> {
         let cfg = stitch::proj::Config::open(&p)?;
         tracing::info!("opened config at {:?}", p.as_ref());
 
+        let stitcher = Sticher::from_cfg_gpu(
+            cfg,
+            proj_w,
+            proj_h,
+            &encode,
+            record.record_cameras,
+            &render,
+            shared_gpu,
+        )
+        .await?;
+        let preset_path = p.as_ref().with_extension("preset");
+        let last_preset = std::fs::read_to_string(&preset_path)
+            .ok()
+            .map(|name| name.trim().to_string());
+        if let Some(name) = &last_preset {
+            match stitcher.presets().into_iter().find(|p| &p.name == name) {
+                Some(preset) => {
+                    tracing::info!("starting at preset {name}, selected last");
+                    let preset = ViewPreset {
+                        transition: None,
+                        ..preset
+                    };
+                    stitcher.apply_preset(MAIN_VIEW, &preset);
+                }
+                None => tracing::warn!("preset {name} selected last isn't in the config anymore"),
+            }
+        }
+
+        let recorder = Recorder::new(&record, stitcher.recording_sources());
+        if record.record {
+            if recorder.has_sources() {
+                recorder
+                    .start()
+                    .map_err(stitch::Error::io_ctx("starting recording".to_string()))?;
+            } else {
+                tracing::warn!("not recording, --record needs --encode");
+            }
+        }
+
         Ok(Self {
-            stitcher: Sticher::from_cfg_gpu(cfg, proj_w, proj_h).await,
+            stitcher,
+            recorder,
+            clients: AtomicUsize::new(0),
+            rigs: BTreeMap::new(),
+            preset_path,
+            last_preset: Mutex::new(last_preset),
+            #[cfg(feature = "webrtc")]
+            ice_servers: encode.ice_servers,
         })
     }
 }
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+use stitch::{proj::MotionConfig, proto::MotionEvent};
+
+use crate::util::Metrics;
+
+/// Which motion zones are moving, turning the part of each zone that changed every frame into
+/// events when one starts or stops.
+#[derive(Default)]
+pub struct MotionTracker {
+    /// Last frame each zone had enough change in, while it's moving
+    moving: Vec<Option<Instant>>,
+}
+
+impl MotionTracker {
+    /// Adds the part of every zone of `cfg` that changed in the frame read back at `now`, see
+    /// [`stitch::proj::GpuProjector::take_motion`]. Returns an event for every zone that
+    /// started or stopped moving.
+    pub fn push(&mut self, cfg: &MotionConfig, changed: &[f32], now: Instant) -> Vec<MotionEvent> {
+        self.moving.resize(changed.len(), None);
+        let hold = Duration::from_secs_f32(cfg.hold.max(0.0));
+
+        let mut events = Vec::new();
+        for ((zone, &area), moving) in cfg.zones.iter().zip(changed).zip(&mut self.moving) {
+            Metrics::push(&format!("motion-{}", zone.name), f64::from(area));
+
+            let started = area >= cfg.min_area && moving.replace(now).is_none();
+            let stopped = area < cfg.min_area && moving.is_some_and(|last| now - last >= hold);
+            if stopped {
+                *moving = None;
+            }
+            if started || stopped {
+                events.push(MotionEvent {
+                    zone: zone.name.clone(),
+                    moving: started,
+                    area,
+                });
+            }
+        }
+        events
+    }
+
+    /// Whether every zone of `cfg` is moving as of the last [`Self::push`], with the part of it
+    /// that `changed` in that frame.
+    pub fn state(&self, cfg: &MotionConfig, changed: &[f32]) -> Vec<MotionEvent> {
+        cfg.zones
+            .iter()
+            .zip(changed)
+            .enumerate()
+            .map(|(i, (zone, &area))| MotionEvent {
+                zone: zone.name.clone(),
+                moving: self.moving.get(i).is_some_and(Option::is_some),
+                area,
+            })
+            .collect()
+    }
+
+    /// Stops every zone of `cfg` that is moving, for when the zones change.
+    pub fn stop_all(&mut self, cfg: &MotionConfig) -> Vec<MotionEvent> {
+        std::mem::take(&mut self.moving)
+            .into_iter()
+            .zip(&cfg.zones)
+            .filter(|(moving, _)| moving.is_some())
+            .map(|(_, zone)| MotionEvent {
+                zone: zone.name.clone(),
+                moving: false,
+                area: 0.0,
+            })
+            .collect()
+    }
+}
@@ -1,12 +1,15 @@
 use std::{
     marker::PhantomData,
     ops::{Deref, DerefMut},
-    sync::OnceLock,
+    sync::{Arc, OnceLock},
     time::{Duration, Instant},
 };
 
-use axum::extract::ws::Message;
-use stitch::{buf::FrameSize, proj::ProjectionStyle};
+use stitch::{
+    buf::FrameSize,
+    proj::{ProjectionStyle, ViewCrop},
+    proto::{ClientMessage, FrameMeta, ServerMessage},
+};
 use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
 
 #[derive(Clone, Copy, Debug)]
@@ -16,23 +19,43 @@ enum PacketKind {
     SettingsSync = 1,
     UpdateFrame = 2,
     Timing = 4,
+    Crop = 5,
+    Control = 6,
 }
 
 pub enum RecvPacket {
     Nop,
     SettingsSync(SettingsPacket),
     Timing(TimingPacket),
+    Crop(CropPacket),
+    /// Control message, or why it couldn't be parsed.
+    Control(Result<ClientMessage, String>),
 }
 
 impl RecvPacket {
     pub fn from_raw(data: &[u8]) -> Option<Self> {
-        (data[0] == PacketKind::Nop as _)
+        (data[0] == PacketKind::Nop as u8)
             .then_some(Self::Nop)
             .or_else(|| SettingsPacket::from_raw(data).map(Self::SettingsSync))
             .or_else(|| TimingPacket::from_raw(data).map(Self::Timing))
+            .or_else(|| CropPacket::from_raw(data).map(Self::Crop))
+            .or_else(|| control_from_raw(data).map(Self::Control))
     }
 }
 
+/// [`ClientMessage`] sent as CBOR after the packet kind.
+fn control_from_raw(data: &[u8]) -> Option<Result<ClientMessage, String>> {
+    (data[0] == PacketKind::Control as u8)
+        .then(|| ciborium::from_reader(&data[1..]).map_err(|err| err.to_string()))
+}
+
+/// `msg` as CBOR after the packet kind, to answer a client that sent a control packet.
+pub fn control_packet(msg: &ServerMessage) -> Vec<u8> {
+    let mut out = vec![PacketKind::Control as u8];
+    ciborium::into_writer(msg, &mut out).expect("writing to a Vec can't fail");
+    out
+}
+
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct SettingsPacket {
@@ -55,7 +78,7 @@ impl SettingsPacket {
 
     #[inline]
     pub fn from_raw(data: &[u8]) -> Option<Self> {
-        (data[0] == PacketKind::SettingsSync as _).then_some(Self {
+        (data[0] == PacketKind::SettingsSync as u8).then_some(Self {
             _kind: PacketKind::SettingsSync,
             view_type: data[1],
         })
@@ -98,13 +121,17 @@ impl<O: zerocopy::ByteOrder> VideoPacket<O> {
             .unwrap();
     }
 
-    #[inline]
-    pub fn take_message(&mut self) -> Message {
-        let new_buf = Self::new(self.width(), self.height(), self.chans())
-            .expect("dimension should already be safe if this type exists")
-            .0;
-        let old_buf = std::mem::replace(&mut self.0, new_buf);
-        Message::Binary(old_buf.into_vec())
+    /// Copy of the whole packet followed by `meta` as CBOR, to be sent to any number of
+    /// clients.
+    pub fn share(&self, meta: Option<&FrameMeta>) -> Arc<[u8]> {
+        let mut trailer = Vec::new();
+        if let Some(meta) = meta {
+            if let Err(err) = ciborium::into_writer(meta, &mut trailer) {
+                tracing::warn!("sending frame {} without its metadata: {err}", meta.frame);
+                trailer.clear();
+            }
+        }
+        self.0.iter().chain(&trailer).copied().collect()
     }
 }
 
@@ -167,7 +194,7 @@ impl TimingPacket {
     }
 
     pub fn from_raw(data: &[u8]) -> Option<Self> {
-        if data[0] != PacketKind::Timing as _ {
+        if data[0] != PacketKind::Timing as u8 {
             return None;
         }
 
@@ -186,3 +213,33 @@ impl TimingPacket {
         )
     }
 }
+
+/// Part of the projection the client wants to see, as in [`ViewCrop`].
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout, Clone, Copy, Debug)]
+pub struct CropPacket {
+    _kind: u32,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl CropPacket {
+    pub fn from_raw(data: &[u8]) -> Option<Self> {
+        if data[0] != PacketKind::Crop as u8 {
+            return None;
+        }
+
+        Self::read_from_bytes(data).ok()
+    }
+
+    #[inline]
+    pub const fn crop(self) -> ViewCrop {
+        ViewCrop {
+            x: self.x,
+            y: self.y,
+            w: self.w,
+            h: self.h,
+        }
+    }
+}
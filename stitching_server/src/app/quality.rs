@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+/// Each step down in quality, starting at full resolution rendering every camera frame.
+const LEVELS: [Quality; 5] = [
+    Quality::FULL,
+    Quality {
+        scale: 0.75,
+        frame_skip: 1,
+    },
+    Quality {
+        scale: 0.5,
+        frame_skip: 1,
+    },
+    Quality {
+        scale: 0.5,
+        frame_skip: 2,
+    },
+    Quality {
+        scale: 0.5,
+        frame_skip: 3,
+    },
+];
+
+/// Rendered frames after a change before the next one, letting the frame time settle.
+const SETTLE_FRAMES: u32 = 30;
+
+/// Part of its budget a frame at the next level up has to be expected to fit in before
+/// stepping up, so the quality doesn't flip back and forth.
+const HEADROOM: f32 = 0.8;
+
+/// Resolution and frame rate the views are rendered at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quality {
+    /// Size of every view relative to its full size.
+    pub scale: f32,
+    /// Camera frames per rendered frame.
+    pub frame_skip: u32,
+}
+
+impl Quality {
+    pub const FULL: Self = Self {
+        scale: 1.0,
+        frame_skip: 1,
+    };
+
+    /// `w` by `h` scaled down, keeping the width a multiple of 64 so rows are read back from
+    /// the GPU as they are.
+    #[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+    pub fn scale_size(self, (w, h): (usize, usize)) -> (usize, usize) {
+        if self.scale >= 1.0 {
+            return (w, h);
+        }
+        let sw = (w as f32 * self.scale) as usize / 64 * 64;
+        let sh = (h as f32 * self.scale) as usize / 2 * 2;
+        (sw.clamp(64.min(w), w), sh.clamp(2.min(h), h))
+    }
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// Lowers the [`Quality`] while rendered frames miss their deadline, first the resolution and
+/// then the frame rate, and raises it again once there is room.
+pub struct QualityController {
+    /// Time a frame can take per camera frame, in seconds.
+    deadline: f32,
+    levels: Vec<Quality>,
+    level: usize,
+    /// Smoothed time rendered frames take at the current level, in seconds.
+    frame_time: Option<f32>,
+    since_change: u32,
+}
+
+impl QualityController {
+    /// Keeps rendered frames within `deadline` for every camera frame, only ever lowering the
+    /// frame rate unless `scale_res` is set.
+    pub fn new(deadline: Duration, scale_res: bool) -> Self {
+        Self {
+            deadline: deadline.as_secs_f32(),
+            levels: LEVELS
+                .into_iter()
+                .filter(|q| scale_res || q.scale >= 1.0)
+                .collect(),
+            level: 0,
+            frame_time: None,
+            since_change: 0,
+        }
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.levels[self.level]
+    }
+
+    /// Adds the time a rendered frame took, not counting waiting on the cameras. Returns the
+    /// quality to render at from now on when it changes.
+    pub fn push(&mut self, took: Duration) -> Option<Quality> {
+        let took = took.as_secs_f32();
+        let avg = self.frame_time.map_or(took, |t| t.mul_add(0.9, took * 0.1));
+        self.frame_time = Some(avg);
+        self.since_change += 1;
+        if self.since_change < SETTLE_FRAMES {
+            return None;
+        }
+
+        let cur = self.quality();
+        let missed = avg > self.budget(cur);
+        if missed && self.level + 1 < self.levels.len() {
+            self.level += 1;
+        } else if !missed && self.level > 0 && self.fits(avg, self.levels[self.level - 1]) {
+            self.level -= 1;
+        } else {
+            return None;
+        }
+
+        // the smoothed time was measured at the old level
+        self.frame_time = None;
+        self.since_change = 0;
+        Some(self.quality())
+    }
+
+    /// Whether frames taking `avg` at the current level are expected to leave room at `up`.
+    fn fits(&self, avg: f32, up: Quality) -> bool {
+        // rendering takes longer with every extra pixel
+        let expected = avg * (up.scale / self.quality().scale).powi(2);
+        expected <= HEADROOM * self.budget(up)
+    }
+
+    /// Time a frame at `q` can take.
+    #[allow(clippy::cast_precision_loss)]
+    fn budget(&self, q: Quality) -> f32 {
+        self.deadline * q.frame_skip as f32
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use stitch::{
+    camera::live,
+    proj::{self, GpuProjector},
+};
+
+use super::App;
+
+/// Time between checks of the config file for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+type Config = proj::Config<live::Config>;
+
+/// Applies the config at `path` every time the file changes, until the render loop stops.
+/// Configs that fail to load are skipped, leaving the last one that did in place.
+pub async fn watch_config(path: PathBuf, app: App) {
+    let mut loaded = modified(&path);
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(time) = modified(&path).filter(|t| Some(*t) != loaded) else {
+            continue;
+        };
+        loaded = Some(time);
+
+        let cfg = match Config::open(&path) {
+            Ok(cfg) => cfg,
+            Err(err) => {
+                tracing::warn!("failed to reload config {path:?}: {err}");
+                continue;
+            }
+        };
+        if cfg
+            .cameras
+            .first()
+            .and_then(|c| c.meta.resolution)
+            .is_none()
+        {
+            tracing::warn!("ignoring config {path:?} without a resolution for camera 0");
+            continue;
+        }
+
+        tracing::info!("reloading config {path:?}");
+        if !app.reload_config(cfg) {
+            break;
+        }
+    }
+}
+
+fn modified(p: &Path) -> Option<SystemTime> {
+    std::fs::metadata(p).and_then(|m| m.modified()).ok()
+}
+
+/// What changed between two versions of the config.
+#[derive(Debug, Default)]
+pub struct ConfigDiff {
+    /// Cameras changed in a way they can't be swapped in, or something only set when building
+    /// the projector changed, so both have to be rebuilt.
+    pub rebuild: bool,
+    /// Cameras to close and open, when only some were added or removed.
+    pub swap: Option<CameraSwap>,
+    /// Cameras that moved or changed lens, by their index in the new config.
+    pub views: Vec<usize>,
+    /// Cameras with a different mask file, by their index in the new config.
+    pub masks: Vec<usize>,
+    pub style: bool,
+    pub stereo: bool,
+    pub sampling: bool,
+    pub tone_map: bool,
+    pub color_space: bool,
+    pub motion: bool,
+    pub auto_mask: bool,
+    pub overlays: bool,
+    pub hud: bool,
+    pub watermark: bool,
+    pub ground_overlay: bool,
+    pub presets: bool,
+}
+
+/// Cameras taken out of and put into the config, with every other camera opened the same way
+/// and left in the same order.
+#[derive(Debug, Default)]
+pub struct CameraSwap {
+    /// Indices in the old config, highest first so removing them in turn leaves the rest in
+    /// place.
+    pub removed: Vec<usize>,
+    /// Indices in the new config, lowest first so inserting them in turn puts each in place.
+    pub added: Vec<usize>,
+}
+
+impl ConfigDiff {
+    pub fn new(old: &Config, new: &Config) -> Self {
+        let (swap, kept) = CameraSwap::new(old, new);
+        // added cameras go into the same input buffers as the rest, which can't all be swapped
+        let swappable = !kept.is_empty()
+            && new.cameras.len() <= GpuProjector::MAX_CAMERAS
+            && swap.added.iter().all(|&i| {
+                new.cameras[i].meta.frame_resolution()
+                    == old.cameras.first().and_then(|c| c.meta.frame_resolution())
+            });
+
+        let changed = |f: fn(&Config, &Config, usize, usize) -> bool| {
+            kept.iter()
+                .filter(|&&(a, b)| f(old, new, a, b))
+                .map(|&(_, b)| b)
+                .collect()
+        };
+
+        Self {
+            rebuild: (!swap.is_empty() && !swappable)
+                || old.world != new.world
+                || old.frame_history != new.frame_history
+                || old.strict_masks != new.strict_masks
+                || old.watch_masks != new.watch_masks
+                || old.blend != new.blend
+                || old.msaa != new.msaa
+                || old.gain_interval != new.gain_interval,
+            views: changed(|a, b, i, j| a.cameras[i].view != b.cameras[j].view),
+            masks: changed(|a, b, i, j| a.cameras[i].meta.mask_path != b.cameras[j].meta.mask_path),
+            swap: (!swap.is_empty()).then_some(swap),
+            style: old.style != new.style,
+            stereo: old.stereo != new.stereo,
+            sampling: old.sampling != new.sampling,
+            tone_map: old.tone_map != new.tone_map,
+            color_space: old.color_space != new.color_space,
+            motion: old.motion != new.motion,
+            auto_mask: old.auto_mask_incidence != new.auto_mask_incidence,
+            overlays: old.overlays != new.overlays,
+            hud: old.hud != new.hud,
+            watermark: old.watermark != new.watermark,
+            ground_overlay: old.ground_overlay != new.ground_overlay,
+            presets: old.views.presets != new.views.presets,
+        }
+    }
+}
+
+impl CameraSwap {
+    /// Matches the cameras of `new` in order to ones of `old` opened the same way, returning
+    /// the ones left over and the index in both configs of every camera kept.
+    fn new(old: &Config, new: &Config) -> (Self, Vec<(usize, usize)>) {
+        // opening a camera doesn't depend on its mask
+        let opened = |c: &live::Config| live::Config {
+            mask_path: None,
+            ..c.clone()
+        };
+
+        let mut swap = Self::default();
+        let mut kept = Vec::new();
+        let mut next = 0;
+        for (j, cam) in new.cameras.iter().enumerate() {
+            let found = old.cameras[next..]
+                .iter()
+                .position(|c| opened(&c.meta) == opened(&cam.meta));
+            match found {
+                Some(skip) => {
+                    swap.removed.extend(next..next + skip);
+                    kept.push((next + skip, j));
+                    next += skip + 1;
+                }
+                None => swap.added.push(j),
+            }
+        }
+        swap.removed.extend(next..old.cameras.len());
+        swap.removed.reverse();
+        (swap, kept)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+}
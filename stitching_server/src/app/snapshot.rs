@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    ExtendedColorType, ImageEncoder, ImageResult,
+};
+use serde::Deserialize;
+use stitch::proj::MAIN_VIEW;
+
+use crate::rt;
+
+use super::{stitcher::Snapshot, App};
+
+/// Longest wait for the next frame to be rendered.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    const fn mime(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn encode(self, snap: &Snapshot, quality: u8) -> ImageResult<Vec<u8>> {
+        let (w, h) = (snap.width as u32, snap.height as u32);
+        let mut out = Vec::new();
+        match self {
+            Self::Jpeg => {
+                // JPEG has no alpha channel
+                let rgb = snap
+                    .data
+                    .chunks_exact(4)
+                    .flat_map(|px| &px[..3])
+                    .copied()
+                    .collect::<Vec<_>>();
+                JpegEncoder::new_with_quality(&mut out, quality).write_image(
+                    &rgb,
+                    w,
+                    h,
+                    ExtendedColorType::Rgb8,
+                )?;
+            }
+            Self::Png => {
+                PngEncoder::new(&mut out).write_image(
+                    &snap.data,
+                    w,
+                    h,
+                    ExtendedColorType::Rgba8,
+                )?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotQuery {
+    #[serde(default = "main_view")]
+    view: String,
+    #[serde(default)]
+    format: ImageFormat,
+    /// JPEG quality from 1 to 100
+    #[serde(default = "default_quality")]
+    quality: u8,
+}
+
+fn main_view() -> String {
+    MAIN_VIEW.to_string()
+}
+
+const fn default_quality() -> u8 {
+    85
+}
+
+/// Encodes the next frame rendered for a view as an image.
+pub async fn snapshot(
+    State(state): State<App>,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let snap = tokio::time::timeout(FRAME_TIMEOUT, state.snapshot(&query.view))
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no frame was rendered in time".to_string(),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("no view called {}", query.view),
+            )
+        })?;
+
+    let format = query.format;
+    let quality = query.quality.clamp(1, 100);
+    let body = rt::spawn_encode(move || format.encode(&snap, quality))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    Ok(([(header::CONTENT_TYPE, format.mime())], body))
+}
@@ -1,22 +1,182 @@
-use axum::extract::ws::Message;
+use std::{
+    collections::VecDeque,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
 use stitch::{
     buf::FrameSize,
-    camera::{live, Camera},
-    loader::{self, Loader, OwnedWriteBuffer},
-    proj::{self, GpuDirectBufferWrite, GpuProjector, ProjectionStyle},
+    camera::{
+        self,
+        live::{self, Controls},
+        replay, Camera,
+    },
+    loader::{self, Capture, Loader, LoaderHealth, LoaderStatus, OwnedWriteBuffer, SharedLoader},
+    proj::{
+        self, GpuContext, GpuDirectBufferWrite, GpuProjector, HudLabel, ProjectionStyle, ViewCrop,
+        ViewPreset, ViewTransition,
+    },
+    proto::{CameraHealth, CameraInfo, CaptureMeta, FrameMeta, MotionEvent, ViewState},
     Result,
 };
 
-use crate::util::IntervalTimer;
+use tokio::{
+    runtime::Handle,
+    sync::{broadcast, watch},
+};
+
+use crate::{
+    encode::{Codec, EncodeArgs, Encoder},
+    latency::{FrameTimes, Stage},
+    recorder::Source,
+    util::{fnv1a, utc_timestamp, wall_clock, IntervalTimer},
+};
+
+use super::{
+    motion::MotionTracker,
+    proto::VideoPacket,
+    quality::{Quality, QualityController},
+    reload::{CameraSwap, ConfigDiff},
+    sync::{FrameSync, SyncPolicy},
+};
+
+/// Latest frame of a view, `None` until the first is rendered.
+pub type Frame = Option<ViewFrame>;
+
+/// A rendered view as a whole [`VideoPacket`], with the times it passed through the render loop.
+#[derive(Clone)]
+pub struct ViewFrame {
+    pub packet: Arc<[u8]>,
+    pub times: FrameTimes,
+}
 
-use super::proto::VideoPacket;
+/// Changes to the render loop, each naming the view it applies to.
 pub enum UpdateFn {
-    ProjSpec(Box<dyn FnOnce(&mut ProjectionStyle) + Send>),
+    ProjSpec(String, Box<dyn FnOnce(&mut ProjectionStyle) + Send>),
+    /// See [`Sticher::transition_style`].
+    Transition(
+        String,
+        Box<dyn FnOnce(ProjectionStyle) -> ViewTransition + Send>,
+    ),
+    Crop(String, ViewCrop),
+    GroundOverlay(String, bool),
+    Snapshot(String, kanal::OneshotSender<Option<Snapshot>>),
+    OpenView(String, kanal::OneshotSender<Option<watch::Receiver<Frame>>>),
+    CloseView(String),
+    GetView(String, kanal::OneshotSender<Option<ViewState>>),
+    /// New config for every view, see [`Sticher::reload`].
+    Reload(Box<proj::Config<live::Config>>),
+    /// See [`Sticher::set_camera_enabled`].
+    CameraEnabled(usize, bool, kanal::OneshotSender<bool>),
+    /// See [`Sticher::camera_controls`].
+    CameraControls(usize, Controls, kanal::OneshotSender<Option<Controls>>),
+    /// See [`Sticher::shutdown`].
+    Shutdown(kanal::OneshotSender<()>),
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct RenderArgs {
+    /// Clients that get a view of their own once they change it, the rest share one
+    #[arg(long, default_value_t = 0)]
+    pub client_views: usize,
+    /// Frames worked on at once. 1 renders each frame start to finish, 2 loads the next
+    /// camera frames while one renders, 3 also renders the next frame while one is read back
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=3))]
+    pub pipeline_depth: u8,
+    /// Lower the resolution, then the frame rate, while frames take longer than this many
+    /// milliseconds to render, raising them again once there is room
+    #[arg(long)]
+    pub frame_deadline: Option<f32>,
+    /// How the frames of every camera are lined up before rendering them together
+    #[arg(long, value_enum, default_value_t = SyncPolicy::Latest)]
+    pub sync: SyncPolicy,
+    /// Milliseconds apart the cameras' frames can be captured with --sync wait-all
+    #[arg(long, default_value_t = 5.0)]
+    pub sync_tolerance: f32,
+    /// Milliseconds spent loading cameras again to line them up before rendering anyway
+    #[arg(long, default_value_t = 50.0)]
+    pub sync_timeout: f32,
+    /// Push every camera's frame ahead along its motion by how far it was captured behind the
+    /// newest one, so moving things line up across cameras that aren't in sync
+    #[arg(long)]
+    pub latency_compensation: bool,
+    /// Play back the cameras recorded by `record` in this directory instead of opening them,
+    /// with the config recorded along with them
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+    /// Keep the pipelines the GPU driver compiles in this directory, so later starts don't
+    /// compile them again. Only Vulkan drivers support it
+    #[arg(long)]
+    pub pipeline_cache: Option<PathBuf>,
+    /// Stitch another rig in the same process on the same GPU, as name=config.toml, served
+    /// under /rigs/name/. Can be given more than once
+    #[arg(long = "rig", value_parser = parse_rig)]
+    pub rigs: Vec<(String, PathBuf)>,
+}
+
+fn parse_rig(s: &str) -> std::result::Result<(String, PathBuf), String> {
+    let (name, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=config.toml, got {s:?}"))?;
+    if name.is_empty() || name.contains('/') {
+        return Err(format!("{name:?} can't name a rig in a URL"));
+    }
+    Ok((name.to_string(), PathBuf::from(path)))
+}
+
+/// Render loop figures published every frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub fps: f32,
+    pub client_views: usize,
+    pub quality: Quality,
+}
+
+/// RGBA copy of a rendered view.
+pub struct Snapshot {
+    pub width: usize,
+    pub height: usize,
+    pub data: Box<[u8]>,
+}
+
+impl Snapshot {
+    fn new<T: Deref<Target = [u8]> + FrameSize>(buf: &T) -> Self {
+        Self {
+            width: buf.width(),
+            height: buf.height(),
+            data: buf.to_vec().into_boxed_slice(),
+        }
+    }
 }
 
 pub struct Sticher {
-    msg_recv: kanal::AsyncReceiver<Message>,
+    frames: watch::Receiver<Frame>,
+    stats: watch::Receiver<RenderStats>,
+    cameras: watch::Receiver<Vec<CameraInfo>>,
+    health: watch::Receiver<Vec<CamHealth>>,
+    presets: watch::Receiver<Vec<ViewPreset>>,
+    motion: broadcast::Sender<MotionEvent>,
+    encoded: Option<(Codec, broadcast::Sender<Arc<[u8]>>)>,
+    cam_encoded: Vec<broadcast::Sender<Arc<[u8]>>>,
     update_send: kanal::Sender<UpdateFn>,
+    next_view: AtomicU64,
+    gpu: GpuInfo,
+    ctx: Arc<GpuContext>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GpuInfo {
+    pub name: String,
+    pub backend: String,
+    pub device_type: String,
+    pub driver: String,
 }
 
 impl Sticher {
@@ -24,133 +184,1499 @@ impl Sticher {
         cfg: proj::Config<live::Config>,
         proj_w: usize,
         proj_h: usize,
-    ) -> Self {
-        let cam_res = cfg.cameras[0]
-            .meta
-            .resolution
-            .expect("missing resolution for camera 0");
-
-        let proj = GpuProjector::builder_auto()
-            .await
-            .unwrap()
-            .input_size(
-                cam_res[0],
-                cam_res[1],
-                cfg.cameras.len().try_into().unwrap(),
-            )
-            .out_size(proj_w, proj_h)
-            .flat_bound()
-            .masks_from_cfgs(&cfg.cameras)
-            .build();
+        encode: &EncodeArgs,
+        record_cameras: bool,
+        render: &RenderArgs,
+        shared_gpu: Option<Arc<GpuContext>>,
+    ) -> Result<Self> {
+        let cam_res = (cfg.cameras.first())
+            .and_then(|c| c.meta.resolution)
+            .ok_or(stitch::Error::MissingResolution(0))?;
+        let frames_in_flight = if render.pipeline_depth > 2 { 2 } else { 1 };
+        let pipeline_cache = render.pipeline_cache.clone();
+        let latency_compensation = render.latency_compensation;
+        let mut proj = build_projector(
+            &cfg,
+            proj_w,
+            proj_h,
+            frames_in_flight,
+            pipeline_cache.as_deref(),
+            latency_compensation,
+            shared_gpu.as_ref(),
+        )
+        .await?;
+
+        let ctx = proj.context().clone();
+        let info = proj.adapter_info();
+        let gpu = GpuInfo {
+            name: info.name.clone(),
+            backend: info.backend.to_str().to_string(),
+            device_type: format!("{:?}", info.device_type),
+            driver: format!("{} {}", info.driver, info.driver_info),
+        };
 
-        let (msg_send, msg_recv) = kanal::bounded(0);
+        let (frame_send, frames) = watch::channel(None);
+        let (stats_send, stats) = watch::channel(RenderStats::default());
+        let (cameras_send, cameras) = watch::channel(camera_infos(&cfg, &[]));
+        let (health_send, health) = watch::channel(Vec::new());
+        let (presets_send, presets) = watch::channel(cfg.views.presets.clone());
+        let (motion, _) = broadcast::channel(16);
         let (update_send, update_recv) = kanal::bounded(4);
 
+        let (encoder, encoded) = match encode.encode {
+            Some(codec) => {
+                let (enc, nals) = Encoder::spawn(encode, codec, proj_w, proj_h)
+                    .map_err(stitch::Error::io_ctx("starting encoder".to_string()))?;
+                (Some(enc), Some((codec, nals)))
+            }
+            None => (None, None),
+        };
+        let (cam_encoders, cam_encoded): (Vec<_>, Vec<_>) = match encode.encode {
+            Some(codec) if record_cameras => (0..cfg.cameras.len())
+                .map(|_| Encoder::spawn(encode, codec, cam_res[0] as _, cam_res[1] as _))
+                .collect::<std::io::Result<Vec<_>>>()
+                .map_err(stitch::Error::io_ctx(
+                    "starting camera encoders".to_string(),
+                ))?
+                .into_iter()
+                .map(|(enc, nals)| (Arc::new(enc), nals))
+                .unzip(),
+            _ => (Vec::new(), Vec::new()),
+        };
+
+        let (client_views, pipeline_depth) = (render.client_views, render.pipeline_depth);
+        let sync = FrameSync::new(
+            render.sync,
+            Duration::from_secs_f32(render.sync_tolerance / 1000.0),
+            Duration::from_secs_f32(render.sync_timeout / 1000.0),
+        );
+        let quality = render.frame_deadline.map(|ms| {
+            // the encoder only takes frames of the size it was started with
+            if encode.encode.is_some() {
+                tracing::info!("encoding the output, only its frame rate is lowered under load");
+            }
+            QualityController::new(
+                Duration::from_secs_f32(ms / 1000.0),
+                encode.encode.is_none(),
+            )
+        });
+        let motion_send = motion.clone();
+        let replay = render.replay.clone();
+        let rt = Handle::current();
+        let mut inner = tokio::task::spawn_blocking(move || {
+            SticherInner::from_cfg(
+                cfg,
+                (proj_w, proj_h),
+                frame_send,
+                update_recv,
+                cam_encoders,
+                replay,
+            )
+        })
+        .await
+        .map_err(std::io::Error::other)
+        .map_err(stitch::Error::io_ctx("opening cameras".to_string()))??;
         tokio::task::spawn_blocking(move || {
-            let inner =
-                SticherInner::from_cfg(&cfg, (proj_w, proj_h), msg_send, update_recv).unwrap();
+            inner.encoder = encoder;
+            inner.max_client_views = client_views;
+            inner.pipeline_depth = pipeline_depth;
+            inner.quality = quality;
+            inner.sync = sync;
+            inner.stats = stats_send;
+            inner.cameras = cameras_send;
+            // the cameras were opened before anyone could be told
+            let loaded = inner.health.borrow().clone();
+            inner.health = health_send;
+            inner.health.send_replace(loaded);
+            inner.presets = presets_send;
+            inner.motion_events = motion_send;
+            inner.pipeline_cache = pipeline_cache;
+            inner.latency_compensation = latency_compensation;
+            inner.shared_gpu = shared_gpu;
+            inner.preprocess_cameras(&mut proj);
+            for (i, &enabled) in inner.cam_enabled.iter().enumerate() {
+                proj.set_camera_enabled(i, enabled);
+            }
+            save_pipeline_cache(&proj);
 
-            SticherInner::block(inner, &proj);
+            inner.run(proj, &rt);
         });
 
-        Self {
-            msg_recv: msg_recv.to_async(),
+        Ok(Self {
+            frames,
+            stats,
+            cameras,
+            health,
+            presets,
+            motion,
+            encoded,
+            cam_encoded,
             update_send,
+            next_view: AtomicU64::new(0),
+            gpu,
+            ctx,
+        })
+    }
+
+    pub const fn gpu_info(&self) -> &GpuInfo {
+        &self.gpu
+    }
+
+    /// GPU the projector renders on, to render other rigs on the same one.
+    pub const fn gpu_context(&self) -> &Arc<GpuContext> {
+        &self.ctx
+    }
+
+    /// Frames of the main view, shared by every client without a view of its own.
+    pub fn subscribe_frames(&self) -> watch::Receiver<Frame> {
+        self.frames.clone()
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        *self.stats.borrow()
+    }
+
+    pub fn cameras(&self) -> Vec<CameraInfo> {
+        self.cameras.borrow().clone()
+    }
+
+    /// Presets of the config, see [`proj::ViewsConfig`].
+    pub fn presets(&self) -> Vec<ViewPreset> {
+        self.presets.borrow().clone()
+    }
+
+    /// Switches the view called `view` to `preset`, easing into it if the preset says so.
+    pub fn apply_preset(&self, view: &str, preset: &ViewPreset) {
+        let (to, easing) = (preset.style, preset.easing);
+        match preset.transition {
+            Some(duration) => self.transition_style(view, move |from| ViewTransition {
+                from,
+                to,
+                duration,
+                easing,
+            }),
+            None => self.update_style(view, move |style| *style = to),
+        }
+        if let Some(crop) = preset.crop {
+            self.update_crop(view, crop.clamped());
+        }
+    }
+
+    /// State of every camera's device, see [`LoaderStatus`].
+    pub fn camera_health(&self) -> Vec<CameraHealth> {
+        self.health
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(index, health)| CameraHealth {
+                index,
+                status: health.status(),
+            })
+            .collect()
+    }
+
+    /// Zones of the config starting or stopping moving from now on.
+    pub fn subscribe_motion(&self) -> broadcast::Receiver<MotionEvent> {
+        self.motion.subscribe()
+    }
+
+    /// Current state of the view called `view`, once pending changes to it are applied.
+    pub async fn view_state(&self, view: &str) -> Option<ViewState> {
+        let (reply, state) = kanal::oneshot();
+        self.update_send
+            .send(UpdateFn::GetView(view.to_string(), reply))
+            .ok()?;
+        state.to_async().recv().await.ok().flatten()
+    }
+
+    /// Adds a view for a single client, starting out as a copy of the main view. Returns
+    /// `None` once every client view allowed is in use.
+    pub async fn open_view(&self) -> Option<ClientView> {
+        let name = format!("client-{}", self.next_view.fetch_add(1, Ordering::Relaxed));
+        let (reply, frames) = kanal::oneshot();
+        self.update_send
+            .send(UpdateFn::OpenView(name.clone(), reply))
+            .ok()?;
+        let frames = frames.to_async().recv().await.ok().flatten()?;
+
+        Some(ClientView {
+            name,
+            frames,
+            update_send: self.update_send.clone(),
+        })
+    }
+
+    pub const fn is_encoding(&self) -> bool {
+        self.encoded.is_some()
+    }
+
+    /// Codec and NAL units of the encoded output from now on, if encoding is enabled.
+    pub fn subscribe_encoded(&self) -> Option<(Codec, broadcast::Receiver<Arc<[u8]>>)> {
+        self.encoded
+            .as_ref()
+            .map(|(codec, nals)| (*codec, nals.subscribe()))
+    }
+
+    /// Encoded streams to record, the output followed by every camera's raw feed when those
+    /// are encoded too.
+    pub fn recording_sources(&self) -> Vec<Source> {
+        let Some((codec, nals)) = &self.encoded else {
+            return Vec::new();
+        };
+
+        let cams = self.cam_encoded.iter().enumerate().map(|(i, nals)| Source {
+            name: format!("cam{i}"),
+            codec: *codec,
+            nals: nals.clone(),
+        });
+        std::iter::once(Source {
+            name: proj::MAIN_VIEW.to_string(),
+            codec: *codec,
+            nals: nals.clone(),
+        })
+        .chain(cams)
+        .collect()
+    }
+
+    pub fn update_style<F: FnOnce(&mut ProjectionStyle) + Send + 'static>(&self, view: &str, f: F) {
+        _ = self
+            .update_send
+            .send(UpdateFn::ProjSpec(view.to_string(), Box::new(f)));
+    }
+
+    /// Starts moving the view called `view` through the transition `f` makes from its current
+    /// style, replacing any it was already going through. Changing its style stops it.
+    pub fn transition_style<F: FnOnce(ProjectionStyle) -> ViewTransition + Send + 'static>(
+        &self,
+        view: &str,
+        f: F,
+    ) {
+        _ = self
+            .update_send
+            .send(UpdateFn::Transition(view.to_string(), Box::new(f)));
+    }
+
+    pub fn update_crop(&self, view: &str, crop: ViewCrop) {
+        _ = self
+            .update_send
+            .send(UpdateFn::Crop(view.to_string(), crop));
+    }
+
+    pub fn update_ground_overlay(&self, view: &str, shown: bool) {
+        _ = self
+            .update_send
+            .send(UpdateFn::GroundOverlay(view.to_string(), shown));
+    }
+
+    /// Next frame rendered for the view called `view`, if there is one.
+    pub async fn snapshot(&self, view: &str) -> Option<Snapshot> {
+        let (reply, snap) = kanal::oneshot();
+        self.update_send
+            .send(UpdateFn::Snapshot(view.to_string(), reply))
+            .ok()?;
+        snap.to_async().recv().await.ok().flatten()
+    }
+
+    /// Leaves camera `idx` out of the output until it's enabled again, without reopening
+    /// anything. Returns false if there is no such camera, or it failed and can't be enabled
+    /// until a reload opens it again.
+    pub async fn set_camera_enabled(&self, idx: usize, enabled: bool) -> bool {
+        let (reply, done) = kanal::oneshot();
+        if self
+            .update_send
+            .send(UpdateFn::CameraEnabled(idx, enabled, reply))
+            .is_err()
+        {
+            return false;
+        }
+        done.to_async().recv().await.unwrap_or(false)
+    }
+
+    /// Stops rendering once the GPU is done with the frames in flight, and closes the cameras
+    /// and encoders. Returns once they are closed, or right away if rendering already stopped.
+    pub async fn shutdown(&self) {
+        let (reply, done) = kanal::oneshot();
+        if self.update_send.send(UpdateFn::Shutdown(reply)).is_ok() {
+            _ = done.to_async().recv().await;
+        }
+    }
+
+    /// Changes the controls of camera `idx` set in `controls`, none to only look at them.
+    /// Returns every control set on the camera, or none if there is no such camera or it's
+    /// replayed.
+    pub async fn camera_controls(&self, idx: usize, controls: Controls) -> Option<Controls> {
+        let (reply, done) = kanal::oneshot();
+        self.update_send
+            .send(UpdateFn::CameraControls(idx, controls, reply))
+            .ok()?;
+        done.to_async().recv().await.ok().flatten()
+    }
+
+    /// Switches to `cfg`, applying what it changes between frames and only reopening the
+    /// cameras and rebuilding the projector when needed. Returns false once rendering stopped.
+    pub fn reload(&self, cfg: proj::Config<live::Config>) -> bool {
+        self.update_send
+            .send(UpdateFn::Reload(Box::new(cfg)))
+            .is_ok()
+    }
+}
+
+/// Builds the projector for `cfg` with the main view `proj_w` by `proj_h`, which can have
+/// `frames_in_flight` frames rendered before reading one back, on `shared_gpu` if set or
+/// otherwise a GPU of its own with the pipeline cache in `pipeline_cache` if set.
+pub(crate) async fn build_projector(
+    cfg: &proj::Config<live::Config>,
+    proj_w: usize,
+    proj_h: usize,
+    frames_in_flight: usize,
+    pipeline_cache: Option<&Path>,
+    latency_compensation: bool,
+    shared_gpu: Option<&Arc<GpuContext>>,
+) -> Result<GpuProjector> {
+    let cam_res = (cfg.cameras.first())
+        .and_then(|c| c.meta.frame_resolution())
+        .ok_or(stitch::Error::MissingResolution(0))?;
+
+    let builder = match (shared_gpu, pipeline_cache) {
+        (Some(ctx), _) => GpuProjector::builder_shared(ctx.clone()),
+        (None, Some(dir)) => GpuProjector::builder_cached(dir).await?,
+        (None, None) => GpuProjector::builder_auto().await?,
+    };
+    let proj = builder
+        .input_size(cam_res[0], cam_res[1], cfg.cameras.len().try_into()?)
+        .out_size(proj_w, proj_h)
+        .history(cfg.frame_history)
+        .flat_bound()
+        .world(cfg.world.clone())
+        .masks_from_cfgs(&cfg.cameras)
+        .strict_masks(cfg.strict_masks)
+        .watch_masks(cfg.watch_masks)
+        .blend(cfg.blend)
+        .sampling(cfg.sampling)
+        .msaa(if cfg.msaa { 4 } else { 1 })
+        .tone_map(cfg.tone_map)
+        .color_space(cfg.color_space)
+        .gain_interval(cfg.gain_interval)
+        .motion(&cfg.motion)
+        .frames_in_flight(frames_in_flight)
+        .latency_compensation(latency_compensation)
+        .build()?;
+    proj.update_view_overlays(proj::MAIN_VIEW, &cfg.overlays);
+    proj.update_view_watermark(proj::MAIN_VIEW, cfg.watermark.as_ref())?;
+    proj.set_ground_overlay(&cfg.ground_overlay);
+    proj.set_stereo(cfg.stereo.as_ref())?;
+    Ok(proj)
+}
+
+/// Saves the pipelines of `proj` if it was built with a pipeline cache, once the cameras'
+/// passes are built too.
+fn save_pipeline_cache(proj: &GpuProjector) {
+    if let Err(err) = proj.save_pipeline_cache() {
+        tracing::warn!("failed to save the pipeline cache: {err}");
+    }
+}
+
+/// Every camera in `cfg`, enabled unless `enabled` says otherwise.
+fn camera_infos(cfg: &proj::Config<live::Config>, enabled: &[bool]) -> Vec<CameraInfo> {
+    cfg.cameras
+        .iter()
+        .enumerate()
+        .map(|(index, cam)| CameraInfo {
+            index,
+            resolution: cam.meta.resolution,
+            enabled: enabled.get(index).copied().unwrap_or(true),
+            view: cam.view,
+        })
+        .collect()
+}
+
+/// Where the [`LoaderStatus`] of a camera comes from.
+#[derive(Clone, Debug)]
+enum CamHealth {
+    Open(LoaderHealth),
+    /// Closed to be opened again after a reload.
+    Reconnecting,
+    /// Failed to open, with black frames in its place.
+    Failed(String),
+}
+
+impl CamHealth {
+    fn status(&self) -> LoaderStatus {
+        match self {
+            Self::Open(health) => health.status(),
+            Self::Reconnecting => LoaderStatus::Reconnecting,
+            Self::Failed(error) => LoaderStatus::Failed {
+                error: error.clone(),
+            },
         }
     }
+}
+
+/// View rendered for a single client, removed once dropped.
+pub struct ClientView {
+    name: String,
+    frames: watch::Receiver<Frame>,
+    update_send: kanal::Sender<UpdateFn>,
+}
+
+impl ClientView {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-    pub async fn next_frame_msg(&self) -> Option<Message> {
-        self.msg_recv.recv().await.ok()
+    pub fn subscribe_frames(&self) -> watch::Receiver<Frame> {
+        self.frames.clone()
     }
+}
 
-    pub fn update_style<F: FnOnce(&mut ProjectionStyle) + Send + 'static>(&self, f: F) {
-        _ = self.update_send.send(UpdateFn::ProjSpec(Box::new(f)));
+impl Drop for ClientView {
+    fn drop(&mut self) {
+        _ = self
+            .update_send
+            .send(UpdateFn::CloseView(std::mem::take(&mut self.name)));
     }
 }
 
+/// State of a view on the render thread, the main view or one opened by a client.
+struct RenderView {
+    name: String,
+    style: ProjectionStyle,
+    /// Moving `style` along since it started, see [`Sticher::transition_style`].
+    transition: Option<(ViewTransition, Instant)>,
+    crop: ViewCrop,
+    /// See [`proj::GroundOverlay::enabled`]
+    ground_overlay: bool,
+    buf: VideoPacket,
+    frames: watch::Sender<Frame>,
+    /// Frames rendered into the view since it was added.
+    renders: usize,
+}
+
 struct SticherInner<B: OwnedWriteBuffer> {
-    pub sender: kanal::Sender<Message>,
     pub update_chan: kanal::Receiver<UpdateFn>,
-    pub proj_style: ProjectionStyle,
-    pub proj_buf: VideoPacket,
+    /// The main view first, then every client view.
+    pub views: Vec<RenderView>,
+    pub max_client_views: usize,
+    pub stats: watch::Sender<RenderStats>,
+    pub cameras: watch::Sender<Vec<CameraInfo>>,
+    /// Of every camera in [`Self::cams`].
+    pub health: watch::Sender<Vec<CamHealth>>,
+    pub presets: watch::Sender<Vec<ViewPreset>>,
+    pub motion_events: broadcast::Sender<MotionEvent>,
+    pub motion: MotionTracker,
+    /// Config everything is currently rendered with.
+    pub cfg: proj::Config<live::Config>,
+    /// Reloaded config waiting for the cameras and projector to be rebuilt.
+    pub rebuild: Option<proj::Config<live::Config>>,
+    pub encoder: Option<Encoder>,
+    /// See [`RenderArgs::pipeline_depth`].
+    pub pipeline_depth: u8,
+    /// Lowers the quality when frames miss [`RenderArgs::frame_deadline`].
+    pub quality: Option<QualityController>,
+    /// Lines up the camera frames following [`RenderArgs::sync`].
+    pub sync: FrameSync,
+    /// Size of every view at full quality.
+    pub full_size: (usize, usize),
+    pub snapshots: Vec<(String, kanal::OneshotSender<Option<Snapshot>>)>,
     pub cams: Vec<Camera<Loader<B>>>,
+    /// Controls of every camera, unless it's replayed.
+    pub controls: Vec<Option<live::ControlHandle>>,
+    /// Camera frames loading for the next render.
+    pub next_inputs: Option<Vec<loader::Ticket<B>>>,
+    /// Whether each camera is rendered.
+    pub cam_enabled: Vec<bool>,
+    /// Whether each camera was disabled through [`Sticher::set_camera_enabled`], kept across
+    /// rebuilds unlike the cameras disabled for failing.
+    pub cam_disabled: Vec<bool>,
+    pub cam_encoders: Vec<Arc<Encoder>>,
+    pub raw_feeds: Vec<RawFeed>,
+    /// See [`RenderArgs::replay`].
+    pub replay: Option<PathBuf>,
+    /// See [`RenderArgs::pipeline_cache`].
+    pub pipeline_cache: Option<PathBuf>,
+    /// See [`RenderArgs::latency_compensation`].
+    pub latency_compensation: bool,
+    /// GPU shared with other rigs that rebuilt projectors go on too.
+    pub shared_gpu: Option<Arc<GpuContext>>,
+    /// Told once everything is closed after [`Sticher::shutdown`].
+    pub shutdown: Option<kanal::OneshotSender<()>>,
 }
 
 impl<B: OwnedWriteBuffer + 'static> SticherInner<B> {
     pub fn from_cfg(
-        cfg: &proj::Config<live::Config>,
+        cfg: proj::Config<live::Config>,
         proj_size: (usize, usize),
-        sender: kanal::Sender<Message>,
+        frames: watch::Sender<Frame>,
         update_chan: kanal::Receiver<UpdateFn>,
+        cam_encoders: Vec<Arc<Encoder>>,
+        replay: Option<PathBuf>,
     ) -> Result<Self> {
-        let cams = cfg
-            .cameras
-            .iter()
-            .map(|cfg| {
-                let cam = cfg.clone().load()?;
-                let (w, h, c) = cam.data.frame_size();
-                tracing::info!("loaded camera {:?} ({w} * {h} * {c})", cfg.meta.live_index);
-                Ok(cam)
-            })
-            .collect::<Result<Vec<_>>>()?;
+        let main = RenderView {
+            name: proj::MAIN_VIEW.to_string(),
+            style: cfg.style,
+            transition: None,
+            crop: ViewCrop::FULL,
+            ground_overlay: cfg.ground_overlay.enabled,
+            buf: VideoPacket::new(proj_size.0, proj_size.1, 4)?,
+            frames,
+            renders: 0,
+        };
+
+        let mut inner = Self {
+            update_chan,
+            views: vec![main],
+            max_client_views: 0,
+            stats: watch::channel(RenderStats::default()).0,
+            cameras: watch::channel(Vec::new()).0,
+            health: watch::channel(Vec::new()).0,
+            presets: watch::channel(Vec::new()).0,
+            motion_events: broadcast::channel(1).0,
+            motion: MotionTracker::default(),
+            cfg,
+            rebuild: None,
+            encoder: None,
+            pipeline_depth: 1,
+            quality: None,
+            sync: FrameSync::new(SyncPolicy::Latest, Duration::ZERO, Duration::ZERO),
+            full_size: proj_size,
+            snapshots: Vec::new(),
+            cams: Vec::new(),
+            controls: Vec::new(),
+            next_inputs: None,
+            cam_enabled: Vec::new(),
+            cam_disabled: Vec::new(),
+            cam_encoders,
+            raw_feeds: Vec::new(),
+            replay,
+            pipeline_cache: None,
+            latency_compensation: false,
+            shared_gpu: None,
+            shutdown: None,
+        };
+        inner.load_cameras()?;
+        Ok(inner)
+    }
+
+    /// Opens every camera in the config, teeing each one with an encoder of the same size to
+    /// that encoder. Cameras that fail to open are left out with black frames in their place,
+    /// so the rest keep going.
+    fn load_cameras(&mut self) -> Result<()> {
+        let mut failed = Vec::new();
+        let mut health = Vec::with_capacity(self.cfg.cameras.len());
+        let cfgs = self.cfg.cameras.clone();
+        for (i, cfg) in cfgs.iter().enumerate() {
+            let (cam, controls) = match self.open_fed_camera(i, cfg) {
+                Ok(opened) => {
+                    health.push(CamHealth::Open(opened.0.data.health()));
+                    opened
+                }
+                Err(err) => {
+                    tracing::error!("leaving out camera {:?}: {err}", cfg.meta.live_index);
+                    failed.push(i);
+                    health.push(CamHealth::Failed(err.to_string()));
+                    (placeholder_camera(cfg)?, None)
+                }
+            };
+            let (w, h, c) = cam.data.frame_size();
+            tracing::info!("loaded camera {:?} ({w} * {h} * {c})", cfg.meta.live_index);
+            self.cams.push(cam);
+            self.controls.push(controls);
+        }
+        self.health.send_replace(health);
 
+        self.cam_disabled.resize(self.cams.len(), false);
+        self.cam_enabled = (self.cam_disabled.iter())
+            .enumerate()
+            .map(|(i, &disabled)| !disabled && !failed.contains(&i))
+            .collect();
         tracing::info!("finished loading cameras");
+        self.cameras
+            .send_replace(camera_infos(&self.cfg, &self.cam_enabled));
+        Ok(())
+    }
 
-        Ok(Self {
-            sender,
-            update_chan,
-            proj_style: cfg.style,
-            proj_buf: VideoPacket::new(proj_size.0, proj_size.1, 4)?,
-            cams,
-        })
+    /// Opens camera `i` of the config like [`Self::open_camera`], feeding it to its encoder if
+    /// it has one of the same size.
+    fn open_fed_camera(
+        &mut self,
+        i: usize,
+        cfg: &camera::Config<live::Config>,
+    ) -> Result<(Camera<Loader<B>>, Option<live::ControlHandle>)> {
+        let size = cfg.meta.resolution.map(|[w, h]| (w as usize, h as usize));
+        match self.cam_encoders.get(i) {
+            Some(enc) if Some(enc.size()) == size => {
+                // the encoder takes RGBA frames
+                let mut cfg = cfg.clone();
+                cfg.meta.preprocess.decode = false;
+                let (raw, controls): (Camera<Loader<Box<[u8]>>>, _) = self.open_camera(&cfg)?;
+                let shared = SharedLoader::new(raw.data);
+                let feed = spawn_raw_feed(i, shared.subscribe(), self.cam_encoders[i].clone())?;
+                self.raw_feeds.push(feed);
+                Ok((Camera::new(raw.view, shared.subscribe()), controls))
+            }
+            Some(_) => {
+                tracing::warn!("camera {i} changed resolution, its feed is no longer encoded");
+                self.open_camera(cfg)
+            }
+            None => self.open_camera(cfg),
+        }
+    }
+
+    /// Opens the camera of `cfg` along with its controls, or plays back its recording when
+    /// replaying, which has no controls. Either way its faults are injected.
+    fn open_camera<T: OwnedWriteBuffer + 'static>(
+        &self,
+        cfg: &camera::Config<live::Config>,
+    ) -> Result<(Camera<Loader<T>>, Option<live::ControlHandle>)> {
+        let (data, controls) = match &self.replay {
+            Some(dir) => {
+                let replayed = replay::Config::new(dir, cfg.meta.live_index);
+                let faults = cfg.meta.faults;
+                let data = if faults.is_none() {
+                    replayed.try_into()?
+                } else {
+                    faults.apply(Loader::<Box<[u8]>>::try_from(replayed)?)
+                };
+                (data, None)
+            }
+            None => {
+                let (data, controls) = cfg.meta.clone().open()?;
+                (data, Some(controls))
+            }
+        };
+        // frames the projector turns are projected with their sides swapped
+        let (w, h, _) = data.frame_size();
+        let [w, h] = cfg.meta.preprocess.rotate.turn([w as u32, h as u32]);
+        let view = cfg.view.with_dims(w as f32, h as f32);
+        Ok((Camera::new(view, data), controls))
     }
 }
 
+/// Black frames of the resolution `cfg` asks for, standing in for a camera that failed to open.
+///
+/// # Errors
+/// `cfg` has no resolution to make frames of
+fn placeholder_camera<B: OwnedWriteBuffer + 'static>(
+    cfg: &camera::Config<live::Config>,
+) -> Result<Camera<Loader<B>>> {
+    let [w, h] = cfg.meta.resolution.ok_or(stitch::Error::UnexpectedNone)?;
+    let data = Loader::new_blocking(w, h, 4, |buf| buf.fill(0));
+    let [w, h] = cfg.meta.preprocess.rotate.turn([w, h]);
+    Ok(Camera::new(cfg.view.with_dims(w as f32, h as f32), data))
+}
+
+/// Camera feed encoded on its own thread, which is stopped and joined once dropped so the
+/// camera can be opened again.
+struct RawFeed {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for RawFeed {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+/// Encodes every frame `loader` captures on its own thread, until the camera or the returned
+/// feed stops.
+fn spawn_raw_feed(i: usize, loader: Loader<Box<[u8]>>, enc: Arc<Encoder>) -> Result<RawFeed> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let thread = std::thread::Builder::new()
+        .name(format!("cam{i}-encode"))
+        .spawn(move || {
+            let mut buf = vec![0; loader.num_bytes()].into_boxed_slice();
+            while let Ok(frame) = loader.give(buf).and_then(loader::Ticket::block_take) {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                enc.push_frame(&frame, None, None);
+                buf = frame;
+            }
+        })
+        .map_err(stitch::Error::io_ctx(format!(
+            "starting camera {i} encoder"
+        )))?;
+    Ok(RawFeed {
+        stop,
+        thread: Some(thread),
+    })
+}
+
 impl SticherInner<GpuDirectBufferWrite> {
-    pub fn block(mut self, proj: &GpuProjector) {
+    /// Renders until the server stops, rebuilding the cameras and projector every time a
+    /// reloaded config needs it.
+    pub fn run(mut self, mut proj: GpuProjector, rt: &Handle) {
+        while let Some(cfg) = self.block(&mut proj) {
+            drop(proj);
+            let old = self.cfg.clone();
+            proj = match self.rebuild(cfg, rt) {
+                Ok(proj) => proj,
+                Err(err) => {
+                    tracing::error!("failed to rebuild for the reloaded config: {err}");
+                    match self.rebuild(old, rt) {
+                        Ok(proj) => proj,
+                        Err(err) => {
+                            tracing::error!("failed to restore the previous config: {err}");
+                            break;
+                        }
+                    }
+                }
+            };
+        }
+
+        // the cameras go before the encoders they feed and the projector they load into
+        self.raw_feeds.clear();
+        self.cams.clear();
+        self.controls.clear();
+        self.cam_encoders.clear();
+        self.encoder = None;
+        tracing::info!("stitching thread exiting");
+        if let Some(done) = self.shutdown.take() {
+            _ = done.send(());
+        }
+    }
+
+    /// Renders until the update channel closes, or returns the config to rebuild for.
+    ///
+    /// Consecutive frames overlap as far as [`RenderArgs::pipeline_depth`] allows, but never
+    /// wait on the encoder or clients, which skip the frames they are too slow for.
+    fn block(&mut self, proj: &mut GpuProjector) -> Option<proj::Config<live::Config>> {
         // first frame load takes much longer, do it before we starting profiling.
-        loader::block_discard_tickets(proj.take_input_buffers(&self.cams).unwrap());
+        loader::block_discard_tickets(self.take_inputs(proj));
+
+        self.auto_masks(proj);
+        let labels = self.hud_labels(0.0);
+        for view in &self.views {
+            proj.update_view_hud(&view.name, &labels);
+        }
+        let mut fps = 0.0;
+        let mut last_frame = Instant::now();
+        let mut frame_id = 0u64;
+        // rendered, but not read back yet
+        let mut in_flight = VecDeque::new();
 
         let mut timer = IntervalTimer::new();
-        while self.avail_updates() {
+        while self.avail_updates(proj) {
+            frame_id += 1;
+            let span =
+                tracing::debug_span!("frame", id = frame_id, captures = tracing::field::Empty);
+            let _span = span.enter();
             timer.start();
-            let buf_tickets = proj.take_input_buffers(&self.cams).unwrap();
+            let started = Instant::now();
+            let buf_tickets = match self.next_inputs.take() {
+                Some(tickets) => tickets,
+                None => self.take_inputs(proj),
+            };
 
-            proj.update_cam_specs(&self.cams);
-            proj.update_proj_view(self.proj_style);
+            if let Err(err) = proj.update_cam_specs(&self.cams) {
+                tracing::error!("keeping the previous camera specs: {err}");
+            }
+            let live_hud = self.cfg.hud.timestamp || self.cfg.hud.fps;
+            let labels = live_hud.then(|| self.hud_labels(fps));
+            for view in &mut self.views {
+                if let Some((transition, started)) = view.transition {
+                    let (style, done) = transition.at(started.elapsed());
+                    view.style = style;
+                    if done {
+                        view.transition = None;
+                    }
+                }
+            }
+            for view in &self.views {
+                proj.update_view_style(&view.name, view.style);
+                proj.update_view_crop(&view.name, view.crop);
+                proj.update_view_ground_overlay(&view.name, view.ground_overlay);
+                if let Some(labels) = &labels {
+                    proj.update_view_hud(&view.name, labels);
+                }
+            }
+            proj.reload_changed_masks();
+            proj.reload_changed_shaders();
 
             timer.mark("setup");
 
-            loader::block_discard_tickets(buf_tickets);
+            let waiting = Instant::now();
+            let loaders = self
+                .cams
+                .iter()
+                .zip(&self.cam_enabled)
+                .filter(|&(_, &enabled)| enabled)
+                .map(|(c, _)| &c.data)
+                .collect::<Vec<_>>();
+            let captures = self.sync.align(&loaders, buf_tickets);
+            if self.latency_compensation {
+                let mut leads = self.sync.leads(&captures).into_iter();
+                let leads = (self.cam_enabled.iter())
+                    .map(|&enabled| {
+                        if enabled {
+                            leads.next().unwrap_or(0.0)
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                proj.set_camera_leads(&leads);
+            }
+            let loaded = Instant::now();
+            let ids = captures.iter().map(|c| c.map(|c| c.id)).collect::<Vec<_>>();
+            span.record("captures", tracing::field::debug(&ids));
 
             timer.mark("frame load");
 
+            // a lowered frame rate still takes every camera frame, but only renders some
+            let quality = self.quality();
+            if !frame_id.is_multiple_of(u64::from(quality.frame_skip)) {
+                if self.pipeline_depth > 1 {
+                    self.next_inputs = Some(self.take_inputs(proj));
+                }
+                continue;
+            }
+
             proj.update_render();
-            proj.block_copy_render_to(&mut self.proj_buf);
+            for view in &mut self.views {
+                view.renders += 1;
+            }
+            // queued writes only land with the next submission, so the cameras can fill the
+            // inputs again while the GPU still reads them
+            if self.pipeline_depth > 1 {
+                self.next_inputs = Some(self.take_inputs(proj));
+            }
+            let times = FrameTimes {
+                id: frame_id,
+                captured: captures
+                    .iter()
+                    .flatten()
+                    .map(|c| c.at)
+                    .min()
+                    .unwrap_or(loaded),
+                loaded,
+                rendered: loaded,
+            };
+            in_flight.push_back((times, self.frame_meta(proj, frame_id, &captures)));
 
-            timer.mark("backward");
+            timer.mark("render");
 
-            self.proj_buf.update_time();
-            timer.mark_from_base("generation");
+            if in_flight.len() > self.readback_lag() {
+                let (mut times, mut metas) = in_flight.pop_front().unwrap();
+                if let Err(err) = self.read_back(proj) {
+                    tracing::error!("dropping frame {}: {err}", times.id);
+                    continue;
+                }
+                times.rendered = Instant::now();
+                times.report(Stage::Render, times.rendered).record();
+                let motion = match proj.take_motion() {
+                    Some(changed) => {
+                        let events = self.motion.push(&self.cfg.motion, &changed, times.rendered);
+                        self.send_motion(events);
+                        self.motion.state(&self.cfg.motion, &changed)
+                    }
+                    None => Vec::new(),
+                };
+                let rendered = utc_timestamp(wall_clock(times.rendered));
+                for meta in &mut metas {
+                    meta.rendered.clone_from(&rendered);
+                    meta.motion.clone_from(&motion);
+                }
+                if let Some(enc) = &self.encoder {
+                    // the main view is always first
+                    let meta = metas.first().and_then(|m| serde_json::to_vec(m).ok());
+                    enc.push_frame(&self.views[0].buf, Some(times), meta.as_deref());
+                }
+                self.send_snapshots();
 
-            let msg = self.proj_buf.take_message();
-            if self.sender.send(msg).is_err() {
-                break;
-            }
+                timer.mark("backward");
+
+                for view in &mut self.views {
+                    view.buf.update_time();
+                }
+                timer.mark_from_base("generation");
+
+                // clients that are still sending the last frame skip this one
+                for view in self.ready_views() {
+                    view.frames.send_replace(Some(ViewFrame {
+                        packet: view.buf.share(metas.iter().find(|m| m.view == view.name)),
+                        times,
+                    }));
+                }
 
-            timer.mark("handoff");
+                timer.mark("handoff");
+            }
             timer.log_iters_per_sec("render");
+
+            let frame_fps = 1. / last_frame.elapsed().as_secs_f32();
+            last_frame = Instant::now();
+            fps = if fps == 0. {
+                frame_fps
+            } else {
+                fps * 0.9 + frame_fps * 0.1
+            };
+            self.stats.send_replace(RenderStats {
+                fps,
+                client_views: self.views.len() - 1,
+                quality,
+            });
+
+            if let Some(ctrl) = &mut self.quality {
+                let took = started.elapsed().saturating_sub(loaded - waiting);
+                if let Some(quality) = ctrl.push(took) {
+                    // frames still in flight were rendered at the old size
+                    in_flight.clear();
+                    self.apply_quality(proj, quality);
+                }
+            }
         }
 
-        tracing::info!("stitching thread exiting");
+        self.discard_next_inputs();
+        // nothing can be in flight when the projector is dropped
+        proj.block_until_idle();
+        self.rebuild.take()
+    }
+
+    fn quality(&self) -> Quality {
+        self.quality
+            .as_ref()
+            .map_or(Quality::FULL, QualityController::quality)
+    }
+
+    /// Resizes every view for `quality`, if its resolution changed.
+    fn apply_quality(&mut self, proj: &mut GpuProjector, quality: Quality) {
+        let (w, h) = quality.scale_size(self.full_size);
+        tracing::info!(
+            scale = quality.scale,
+            frame_skip = quality.frame_skip,
+            "rendering views at {w}x{h}"
+        );
+
+        for view in &mut self.views {
+            if (view.buf.width(), view.buf.height()) == (w, h) {
+                continue;
+            }
+            match VideoPacket::new(w, h, 4) {
+                Ok(buf) => view.buf = buf,
+                Err(err) => {
+                    tracing::error!("failed to resize view {}: {err}", view.name);
+                    continue;
+                }
+            }
+            proj.resize_view(&view.name, w, h);
+            view.renders = 0;
+        }
+    }
+
+    /// Frames rendered after one before it's read back, 1 with a pipeline depth of 3 so the
+    /// GPU renders the next frame during the read back.
+    fn readback_lag(&self) -> usize {
+        usize::from(self.pipeline_depth > 2)
+    }
+
+    /// What every view is rendered from in frame `frame`, in the order of [`Self::views`], with
+    /// `captures` the camera frames lined up for it. The times and motion are left to fill in
+    /// once it's read back.
+    fn frame_meta(
+        &self,
+        proj: &GpuProjector,
+        frame: u64,
+        captures: &[Option<Capture>],
+    ) -> Vec<FrameMeta> {
+        // only enabled cameras are lined up
+        let mut lined_up = captures.iter();
+        let captures = (self.cam_enabled.iter())
+            .map(|&enabled| {
+                let capture = if enabled {
+                    lined_up.next().copied().flatten()
+                } else {
+                    None
+                };
+                capture.map(|c| CaptureMeta {
+                    id: c.id,
+                    at: utc_timestamp(wall_clock(c.at)),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let views = self.cams.iter().map(|c| &c.view).collect::<Vec<_>>();
+        let calibration = serde_json::to_vec(&views)
+            .map(|json| format!("{:016x}", fnv1a(&json)))
+            .unwrap_or_default();
+
+        (self.views.iter())
+            .map(|view| FrameMeta {
+                frame,
+                rendered: String::new(),
+                captures: captures.clone(),
+                view: view.name.clone(),
+                style: view.style,
+                crop: view.crop,
+                view_matrix: proj.view_matrix(&view.name),
+                motion: Vec::new(),
+                calibration: calibration.clone(),
+            })
+            .collect()
+    }
+
+    /// Views that a frame has been read back for.
+    fn ready_views(&self) -> impl Iterator<Item = &RenderView> {
+        let lag = self.readback_lag();
+        self.views.iter().filter(move |v| v.renders > lag)
+    }
+
+    /// Reads back the oldest frame rendered into every view, skipping views opened since.
+    fn read_back(&mut self, proj: &GpuProjector) -> Result<()> {
+        let lag = self.readback_lag();
+        for view in self.views.iter_mut().filter(|v| v.renders > lag) {
+            if lag == 0 {
+                proj.block_copy_view_to(&view.name, &mut view.buf)?;
+            } else {
+                proj.block_copy_prev_view_to(&view.name, &mut view.buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reopens the cameras and builds a new projector for `cfg`, keeping every view.
+    fn rebuild(&mut self, cfg: proj::Config<live::Config>, rt: &Handle) -> Result<GpuProjector> {
+        // the cameras have to be closed before they can be opened again
+        self.health
+            .send_replace(vec![CamHealth::Reconnecting; self.cams.len()]);
+        self.cams.clear();
+        self.controls.clear();
+        self.raw_feeds.clear();
+
+        let main = &self.views[0];
+        let (w, h) = (main.buf.width(), main.buf.height());
+        let mut proj = rt.block_on(build_projector(
+            &cfg,
+            w,
+            h,
+            self.readback_lag() + 1,
+            self.pipeline_cache.as_deref(),
+            self.latency_compensation,
+            self.shared_gpu.as_ref(),
+        ))?;
+
+        self.views[0].style = cfg.style;
+        if cfg.ground_overlay.enabled != self.cfg.ground_overlay.enabled {
+            self.views[0].ground_overlay = cfg.ground_overlay.enabled;
+        }
+        let stopped = self.motion.stop_all(&self.cfg.motion);
+        self.send_motion(stopped);
+        // nothing has been rendered with the new projector
+        for view in &mut self.views {
+            view.renders = 0;
+        }
+        self.cfg = cfg;
+        self.presets.send_replace(self.cfg.views.presets.clone());
+        self.load_cameras()?;
+        self.preprocess_cameras(&mut proj);
+        save_pipeline_cache(&proj);
+
+        for view in &self.views[1..] {
+            proj.add_view(view.name.clone(), w, h, view.style);
+            proj.update_view_overlays(&view.name, &self.cfg.overlays);
+            proj.update_view_watermark(&view.name, self.cfg.watermark.as_ref())?;
+        }
+        for (i, &enabled) in self.cam_enabled.iter().enumerate() {
+            proj.set_camera_enabled(i, enabled);
+        }
+        tracing::info!("rebuilt cameras and projector for the reloaded config");
+        Ok(proj)
+    }
+
+    /// Applies what changed in `cfg` that doesn't need a rebuild, or leaves it in
+    /// [`Self::rebuild`] when something does.
+    fn reload(&mut self, proj: &mut GpuProjector, cfg: proj::Config<live::Config>) {
+        let diff = ConfigDiff::new(&self.cfg, &cfg);
+        // raw feeds are encoded by the position of their camera
+        if diff.rebuild || (diff.swap.is_some() && !self.cam_encoders.is_empty()) {
+            self.rebuild = Some(cfg);
+            return;
+        }
+        if let Some(swap) = &diff.swap {
+            if let Err(err) = self.swap_cameras(proj, &cfg, swap) {
+                tracing::error!("failed to swap cameras, rebuilding instead: {err}");
+                self.rebuild = Some(cfg);
+                return;
+            }
+        }
+
+        for &i in &diff.views {
+            let (w, h, _) = self.cams[i].data.frame_size();
+            self.cams[i].view = cfg.cameras[i].view.with_dims(w as f32, h as f32);
+        }
+        if diff.style {
+            self.views[0].style = cfg.style;
+        }
+        if diff.sampling {
+            proj.set_sampling(cfg.sampling);
+        }
+        if diff.tone_map {
+            proj.set_tone_map(cfg.tone_map);
+        }
+        if diff.color_space {
+            proj.set_color_space(cfg.color_space);
+        }
+        if diff.motion {
+            let stopped = self.motion.stop_all(&self.cfg.motion);
+            self.send_motion(stopped);
+            proj.set_motion(&cfg.motion);
+        }
+        if diff.overlays {
+            for view in &self.views {
+                proj.update_view_overlays(&view.name, &cfg.overlays);
+            }
+        }
+        if diff.watermark {
+            for view in &self.views {
+                if let Err(err) = proj.update_view_watermark(&view.name, cfg.watermark.as_ref()) {
+                    tracing::warn!(
+                        "keeping the previous watermark of view {}: {err}",
+                        view.name
+                    );
+                }
+            }
+        }
+        if diff.stereo || diff.swap.is_some() {
+            if let Err(err) = proj.set_stereo(cfg.stereo.as_ref()) {
+                tracing::warn!("failed to set up stereo depth: {err}");
+            }
+        }
+        if diff.ground_overlay {
+            proj.set_ground_overlay(&cfg.ground_overlay);
+            if cfg.ground_overlay.enabled != self.cfg.ground_overlay.enabled {
+                self.views[0].ground_overlay = cfg.ground_overlay.enabled;
+            }
+        }
+        self.cfg = cfg;
+        if diff.presets {
+            self.presets.send_replace(self.cfg.views.presets.clone());
+        }
+
+        if diff.hud {
+            let labels = self.hud_labels(self.stats.borrow().fps);
+            for view in &self.views {
+                proj.update_view_hud(&view.name, &labels);
+            }
+        }
+
+        if self.cfg.auto_mask_incidence.is_some() {
+            if diff.auto_mask || diff.style || !diff.views.is_empty() || diff.swap.is_some() {
+                self.auto_masks(proj);
+            }
+        } else {
+            // switching auto masks off goes back to the mask files
+            let masks = if diff.auto_mask {
+                (0..self.cams.len()).collect()
+            } else {
+                diff.masks
+            };
+            for i in masks {
+                let path = self.cfg.cameras[i].meta.mask_path.clone();
+                if let Err(err) = proj.replace_mask(i, path) {
+                    tracing::warn!("failed to load mask for camera {i}: {err}");
+                }
+            }
+        }
+
+        if !diff.views.is_empty() || diff.swap.is_some() {
+            self.cameras
+                .send_replace(camera_infos(&self.cfg, &self.cam_enabled));
+        }
+        tracing::info!("applied reloaded config");
+    }
+
+    /// Sets up the preprocessing of every camera, see [`live::Config::preprocess`].
+    fn preprocess_cameras(&mut self, proj: &mut GpuProjector) {
+        let mut failed = false;
+        for (i, (cam, cfg)) in self.cams.iter().zip(&self.cfg.cameras).enumerate() {
+            if let Err(err) = proj.set_preprocess(i, cam, &cfg.meta.preprocess) {
+                tracing::error!("leaving out camera {:?}: {err}", cfg.meta.live_index);
+                // its frames would be rendered undecoded
+                self.health
+                    .send_modify(|health| health[i] = CamHealth::Failed(err.to_string()));
+                self.cam_enabled[i] = false;
+                failed = true;
+            }
+        }
+        if failed {
+            self.cameras
+                .send_replace(camera_infos(&self.cfg, &self.cam_enabled));
+        }
+    }
+
+    /// Closes and opens the cameras in `swap` without rebuilding the projector, so the
+    /// encoder, recorder and every client keep going.
+    fn swap_cameras(
+        &mut self,
+        proj: &mut GpuProjector,
+        cfg: &proj::Config<live::Config>,
+        swap: &CameraSwap,
+    ) -> Result<()> {
+        self.discard_next_inputs();
+
+        for &i in &swap.removed {
+            self.cams.remove(i);
+            self.controls.remove(i);
+            self.cam_enabled.remove(i);
+            self.cam_disabled.remove(i);
+            self.health.send_modify(|health| _ = health.remove(i));
+            proj.remove_camera(i);
+            tracing::info!("closed camera {:?}", self.cfg.cameras[i].meta.live_index);
+        }
+        for &i in &swap.added {
+            let cam_cfg = &cfg.cameras[i];
+            let (cam, controls): (Camera<Loader<GpuDirectBufferWrite>>, _) =
+                self.open_camera(cam_cfg)?;
+            self.health
+                .send_modify(|health| health.insert(i, CamHealth::Open(cam.data.health())));
+            proj.add_camera(
+                i,
+                &cam,
+                cam_cfg.meta.mask_path.clone(),
+                &cam_cfg.meta.preprocess,
+            )?;
+            tracing::info!("opened camera {:?}", cam_cfg.meta.live_index);
+            self.cams.insert(i, cam);
+            self.controls.insert(i, controls);
+            self.cam_enabled.insert(i, true);
+            self.cam_disabled.insert(i, false);
+        }
+        Ok(())
+    }
+
+    /// Gives every enabled camera its upload buffer like [`GpuProjector::take_input_buffers`],
+    /// but disables any camera whose loader is gone instead, so the others keep rendering.
+    fn take_inputs(&mut self, proj: &GpuProjector) -> Vec<loader::Ticket<GpuDirectBufferWrite>> {
+        let mut tickets = Vec::with_capacity(self.cams.len());
+        for i in 0..self.cams.len() {
+            if !self.cam_enabled[i] {
+                continue;
+            }
+            match proj.take_input_buffer(i, &self.cams[i]) {
+                Ok(ticket) => tickets.push(ticket),
+                Err(err) => {
+                    let camera = self.cfg.cameras[i].meta.live_index;
+                    tracing::error!("leaving out camera {camera:?}: {err}");
+                    self.health
+                        .send_modify(|health| health[i] = CamHealth::Failed(err.to_string()));
+                    self.set_camera_enabled(proj, i, false);
+                }
+            }
+        }
+        tickets
+    }
+
+    /// Waits for the prefetched camera frames, which are written straight into the
+    /// projector's buffers.
+    fn discard_next_inputs(&mut self) {
+        if let Some(tickets) = self.next_inputs.take() {
+            loader::block_discard_tickets(tickets);
+        }
+    }
+
+    /// Returns false if there is no camera `i`, or it failed and is asked to be enabled.
+    fn set_camera_enabled(&mut self, proj: &GpuProjector, i: usize, enabled: bool) -> bool {
+        if enabled && matches!(self.health.borrow().get(i), Some(CamHealth::Failed(_))) {
+            tracing::warn!("not enabling camera {i}, it failed");
+            return false;
+        }
+        let Some(flag) = self.cam_enabled.get_mut(i) else {
+            return false;
+        };
+        *flag = enabled;
+        proj.set_camera_enabled(i, enabled);
+        self.cameras
+            .send_replace(camera_infos(&self.cfg, &self.cam_enabled));
+        tracing::info!(
+            "camera {i} {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+        true
+    }
+
+    /// Generates masks from the main view, if the config asks for them.
+    fn auto_masks(&self, proj: &GpuProjector) {
+        if let Some(deg) = self.cfg.auto_mask_incidence {
+            if let Err(err) = proj.update_cam_specs(&self.cams) {
+                tracing::error!("can't generate masks: {err}");
+                return;
+            }
+            proj.update_proj_view(self.views[0].style);
+            proj.auto_masks(deg.to_radians());
+        }
+    }
+
+    /// Logs `events` and sends them to every subscribed client.
+    fn send_motion(&self, events: Vec<MotionEvent>) {
+        for event in events {
+            tracing::info!(
+                zone = %event.zone,
+                area = event.area,
+                "motion {}",
+                if event.moving { "started" } else { "stopped" }
+            );
+            // no clients are subscribed
+            _ = self.motion_events.send(event);
+        }
+    }
+
+    /// Answers every waiting snapshot request with the frame just read back, leaving the
+    /// requests for views that haven't had one yet.
+    fn send_snapshots(&mut self) {
+        let lag = self.readback_lag();
+        for (name, reply) in std::mem::take(&mut self.snapshots) {
+            match self.views.iter().find(|v| v.name == name) {
+                Some(view) if view.renders <= lag => self.snapshots.push((name, reply)),
+                view => {
+                    _ = reply.send(view.map(|v| Snapshot::new(&v.buf)));
+                }
+            }
+        }
+    }
+
+    /// Adds a client view copying the main one, unless every client view allowed is in use.
+    fn open_view(
+        &mut self,
+        proj: &mut GpuProjector,
+        name: String,
+    ) -> Option<watch::Receiver<Frame>> {
+        if self.views.len() > self.max_client_views {
+            tracing::info!("no client views left, {name} shares the main view");
+            return None;
+        }
+
+        let main = &self.views[0];
+        let (w, h) = (main.buf.width(), main.buf.height());
+        let (style, crop, ground_overlay) = (main.style, main.crop, main.ground_overlay);
+        let buf = VideoPacket::new(w, h, 4).ok()?;
+
+        proj.add_view(name.clone(), w, h, style);
+        proj.update_view_overlays(&name, &self.cfg.overlays);
+        proj.update_view_hud(&name, &self.hud_labels(0.0));
+        // an unmarked view would defeat the watermark, so the client shares the main view
+        if let Err(err) = proj.update_view_watermark(&name, self.cfg.watermark.as_ref()) {
+            tracing::error!("failed to watermark {name}, it shares the main view: {err}");
+            proj.remove_view(&name);
+            return None;
+        }
+
+        let (frames, recv) = watch::channel(None);
+        tracing::info!("opened client view {name}");
+        self.views.push(RenderView {
+            name,
+            style,
+            transition: None,
+            crop,
+            ground_overlay,
+            buf,
+            frames,
+            renders: 0,
+        });
+        Some(recv)
+    }
+
+    /// Configured labels, with the timestamp and frame rate in the top left when enabled.
+    fn hud_labels(&self, fps: f32) -> Vec<HudLabel> {
+        let mut lines = Vec::new();
+        if self.cfg.hud.timestamp {
+            lines.push(utc_timestamp(SystemTime::now()));
+        }
+        if self.cfg.hud.fps {
+            lines.push(format!("{fps:.1} FPS"));
+        }
+
+        let mut labels = self.cfg.hud.labels.clone();
+        if !lines.is_empty() {
+            let mut stats = HudLabel::new(lines.join("\n"), [0.01, 0.01]);
+            stats.background = [0., 0., 0., 0.6];
+            labels.push(stats);
+        }
+        labels
+    }
+
+    #[inline]
+    fn view_mut(&mut self, name: &str) -> Option<&mut RenderView> {
+        self.views.iter_mut().find(|v| v.name == name)
     }
 
     #[inline]
-    fn avail_updates(&mut self) -> bool {
+    fn avail_updates(&mut self, proj: &mut GpuProjector) -> bool {
         loop {
             match self.update_chan.try_recv() {
                 Ok(Some(msg)) => match msg {
-                    UpdateFn::ProjSpec(f) => f(&mut self.proj_style),
+                    UpdateFn::ProjSpec(name, f) => {
+                        if let Some(view) = self.view_mut(&name) {
+                            f(&mut view.style);
+                            view.transition = None;
+                        }
+                    }
+                    UpdateFn::Transition(name, f) => {
+                        if let Some(view) = self.view_mut(&name) {
+                            let transition = f(view.style);
+                            view.style = transition.from;
+                            view.transition = Some((transition, Instant::now()));
+                        }
+                    }
+                    UpdateFn::Crop(name, crop) => {
+                        if let Some(view) = self.view_mut(&name) {
+                            view.crop = crop;
+                        }
+                    }
+                    UpdateFn::GroundOverlay(name, shown) => {
+                        if let Some(view) = self.view_mut(&name) {
+                            view.ground_overlay = shown;
+                        }
+                    }
+                    UpdateFn::Snapshot(view, reply) => self.snapshots.push((view, reply)),
+                    UpdateFn::OpenView(name, reply) => {
+                        _ = reply.send(self.open_view(proj, name));
+                    }
+                    UpdateFn::GetView(name, reply) => {
+                        let state = self
+                            .views
+                            .iter()
+                            .find(|v| v.name == name)
+                            .map(|v| ViewState {
+                                view: v.name.clone(),
+                                style: v.style,
+                                crop: v.crop,
+                                ground_overlay: v.ground_overlay,
+                            });
+                        _ = reply.send(state);
+                    }
+                    UpdateFn::CloseView(name) => {
+                        self.views.retain(|v| v.name != name);
+                        proj.remove_view(&name);
+                        tracing::info!("closed client view {name}");
+                    }
+                    UpdateFn::Reload(cfg) => {
+                        self.reload(proj, *cfg);
+                        if self.rebuild.is_some() {
+                            return false;
+                        }
+                    }
+                    UpdateFn::CameraEnabled(i, enabled, reply) => {
+                        let set = self.set_camera_enabled(proj, i, enabled);
+                        if set {
+                            self.cam_disabled[i] = !enabled;
+                        }
+                        _ = reply.send(set);
+                    }
+                    UpdateFn::CameraControls(i, controls, reply) => {
+                        let handle = self.controls.get(i).and_then(Option::as_ref);
+                        _ = reply.send(handle.map(|h| {
+                            h.set(controls);
+                            h.get()
+                        }));
+                    }
+                    UpdateFn::Shutdown(done) => {
+                        self.shutdown = Some(done);
+                        return false;
+                    }
                 },
                 Ok(None) => return true,
                 Err(_) => return false,
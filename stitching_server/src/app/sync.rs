@@ -0,0 +1,274 @@
+use std::time::{Duration, Instant};
+
+use stitch::loader::{Capture, Loader, OwnedWriteBuffer, Ticket};
+
+use crate::util::Metrics;
+
+/// How the frames of every camera are lined up before they're rendered together.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SyncPolicy {
+    /// Render the frame each camera loaded, however far apart they were captured
+    #[default]
+    Latest,
+    /// Load again every camera more than half a frame behind the newest, so each renders the
+    /// frame nearest to it
+    Nearest,
+    /// Load again every camera further behind the newest than --sync-tolerance until none are
+    WaitAll,
+}
+
+/// Lines up the frames the cameras loaded following a [`SyncPolicy`], giving up once the
+/// timeout passes and rendering what was loaded by then.
+pub struct FrameSync {
+    policy: SyncPolicy,
+    tolerance: Duration,
+    timeout: Duration,
+    /// Last frame of every camera, with the smoothed time between its frames
+    cams: Vec<(Option<Capture>, Option<Duration>)>,
+}
+
+impl FrameSync {
+    pub fn new(policy: SyncPolicy, tolerance: Duration, timeout: Duration) -> Self {
+        Self {
+            policy,
+            tolerance,
+            timeout,
+            cams: Vec::new(),
+        }
+    }
+
+    /// Waits for the frames of `tickets`, given to `loaders` in the same order, loading
+    /// cameras that are behind again as the policy asks. Returns the frame each one ended up
+    /// with.
+    pub fn align<B: OwnedWriteBuffer + 'static>(
+        &mut self,
+        loaders: &[&Loader<B>],
+        tickets: Vec<Ticket<B>>,
+    ) -> Vec<Option<Capture>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut loaded = take(tickets);
+        self.cams.resize(loaded.len(), (None, None));
+
+        while self.policy != SyncPolicy::Latest && Instant::now() < deadline {
+            let behind = self.behind(&loaded);
+            if !behind.contains(&true) {
+                break;
+            }
+
+            let tickets = loaded
+                .iter_mut()
+                .zip(loaders)
+                .enumerate()
+                .filter(|&(i, _)| behind[i])
+                .filter_map(|(i, ((buf, _), loader))| Some((i, loader.give(buf.take()?).ok()?)))
+                .collect::<Vec<_>>();
+            for (i, ticket) in tickets {
+                let (buf, c) = take(vec![ticket]).pop().unwrap_or_default();
+                loaded[i] = (buf, c.or(loaded[i].1));
+            }
+        }
+
+        let captures = loaded.into_iter().map(|(_, c)| c).collect::<Vec<_>>();
+        self.push(&captures);
+        captures
+    }
+
+    /// Which cameras in `loaded` were captured further behind the newest one than the policy
+    /// allows, leaving out the ones without a buffer to load into again.
+    fn behind<B>(&self, loaded: &[(Option<B>, Option<Capture>)]) -> Vec<bool> {
+        let newest = loaded.iter().filter_map(|(_, c)| c.map(|c| c.at)).max();
+        loaded
+            .iter()
+            .zip(&self.cams)
+            .map(|((buf, c), (_, interval))| {
+                let allowed = match self.policy {
+                    SyncPolicy::Nearest => interval.map(|d| d / 2),
+                    _ => Some(self.tolerance),
+                };
+                buf.is_some()
+                    && c.zip(allowed)
+                        .zip(newest)
+                        .is_some_and(|((c, allowed), newest)| c.at + allowed < newest)
+            })
+            .collect()
+    }
+
+    /// How many of its own times between frames every camera in `captures` was captured behind
+    /// the newest one, at most one. Cameras without a frame or a known time between frames
+    /// aren't behind.
+    pub fn leads(&self, captures: &[Option<Capture>]) -> Vec<f32> {
+        let newest = captures.iter().flatten().map(|c| c.at).max();
+        captures
+            .iter()
+            .zip(&self.cams)
+            .map(|(c, (_, interval))| {
+                let Some(((c, newest), interval)) = c.zip(newest).zip(*interval) else {
+                    return 0.0;
+                };
+                let behind = newest.duration_since(c.at).as_secs_f32();
+                (behind / interval.as_secs_f32().max(f32::EPSILON)).min(1.0)
+            })
+            .collect()
+    }
+
+    /// Keeps the time between frames of every camera up to date with `captures`.
+    fn push(&mut self, captures: &[Option<Capture>]) {
+        for (&c, (last, interval)) in captures.iter().zip(&mut self.cams) {
+            let Some(c) = c else { continue };
+            if let Some(prev) = last.filter(|prev| c.id > prev.id) {
+                #[allow(clippy::cast_possible_truncation)]
+                let took = (c.at - prev.at) / (c.id - prev.id) as u32;
+                *interval = Some(interval.map_or(took, |d| d.mul_f32(0.9) + took.mul_f32(0.1)));
+            }
+            *last = Some(c);
+        }
+
+        let ats = captures.iter().flatten().map(|c| c.at);
+        if let Some((first, last)) = ats.clone().min().zip(ats.max()) {
+            Metrics::push("camera-skew", (last - first).as_secs_f64() * 1000.);
+        }
+    }
+}
+
+/// Waits for every ticket, keeping the buffers that came back to load into again.
+fn take<B>(tickets: Vec<Ticket<B>>) -> Vec<(Option<B>, Option<Capture>)> {
+    tickets
+        .into_iter()
+        .map(|t| match t.block_take_captured() {
+            Ok((buf, c)) => (Some(buf), c),
+            Err(_) => (None, None),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn at(start: Instant, id: u64, ms: u32) -> Option<Capture> {
+        Some(Capture {
+            id,
+            at: start + MS * ms,
+        })
+    }
+
+    /// Sync of `n` cameras that have each been capturing a frame every 40ms.
+    fn synced(policy: SyncPolicy, n: usize, start: Instant) -> FrameSync {
+        let mut sync = FrameSync::new(policy, MS * 10, Duration::ZERO);
+        sync.cams.resize(n, (None, None));
+        sync.push(&vec![at(start, 1, 0); n]);
+        sync.push(&vec![at(start, 2, 40); n]);
+        sync
+    }
+
+    #[test]
+    fn interval_follows_captures() {
+        let start = Instant::now();
+        let mut sync = FrameSync::new(SyncPolicy::Latest, Duration::ZERO, Duration::ZERO);
+        sync.cams.resize(1, (None, None));
+
+        sync.push(&[at(start, 1, 0)]);
+        assert_eq!(sync.cams[0].1, None);
+        // a frame skipped in between still counts
+        sync.push(&[at(start, 3, 66)]);
+        assert_eq!(sync.cams[0].1, Some(MS * 33));
+        sync.push(&[at(start, 4, 166)]);
+        // 90% of the 33ms before and 10% of the 100ms since
+        let smoothed = sync.cams[0].1.unwrap();
+        assert!(smoothed.abs_diff(Duration::from_micros(39_700)) < Duration::from_micros(10));
+
+        // the same frame again or none at all leave it be
+        sync.push(&[at(start, 4, 166)]);
+        sync.push(&[None]);
+        assert_eq!(sync.cams[0].1, Some(smoothed));
+        assert_eq!(sync.cams[0].0, at(start, 4, 166));
+    }
+
+    #[test]
+    fn leads_in_frames_behind() {
+        let start = Instant::now();
+        let sync = synced(SyncPolicy::Latest, 3, start);
+        let leads = sync.leads(&[at(start, 3, 100), at(start, 3, 80), at(start, 3, 0)]);
+        assert_eq!(leads, [0.0, 0.5, 1.0]);
+        assert_eq!(sync.leads(&[None, at(start, 3, 80), None]), [0.0; 3]);
+
+        // until the time between frames is known nothing is behind
+        let mut fresh = FrameSync::new(SyncPolicy::Latest, Duration::ZERO, Duration::ZERO);
+        fresh.cams.resize(2, (None, None));
+        assert_eq!(fresh.leads(&[at(start, 3, 100), at(start, 3, 0)]), [0.0; 2]);
+    }
+
+    #[test]
+    fn behind_follows_policy() {
+        let start = Instant::now();
+        let loaded = |ms: [Option<u32>; 4]| {
+            ms.map(|ms| (Some(()), ms.and_then(|ms| at(start, 3, ms))))
+                .to_vec()
+        };
+        let frames = loaded([Some(100), Some(95), Some(75), None]);
+
+        // within 10ms of the newest, the last camera has no frame to be behind with
+        let wait_all = synced(SyncPolicy::WaitAll, 4, start);
+        assert_eq!(wait_all.behind(&frames), [false, false, true, false]);
+        // within half of the 40ms between frames
+        let nearest = synced(SyncPolicy::Nearest, 4, start);
+        assert_eq!(nearest.behind(&frames), [false, false, true, false]);
+        let frames = loaded([Some(100), Some(85), Some(79), None]);
+        assert_eq!(wait_all.behind(&frames), [false, true, true, false]);
+        assert_eq!(nearest.behind(&frames), [false, false, true, false]);
+
+        // cameras without a buffer can't load again, or without a known time between frames
+        let mut frames = frames;
+        frames[2].0 = None;
+        assert_eq!(nearest.behind(&frames), [false; 4]);
+        let mut unknown = FrameSync::new(SyncPolicy::Nearest, MS * 10, Duration::ZERO);
+        unknown.cams.resize(4, (None, None));
+        assert_eq!(
+            unknown.behind(&loaded([Some(100), Some(0), None, None])),
+            [false; 4]
+        );
+        assert_eq!(wait_all.behind(&loaded([None; 4])), [false; 4]);
+    }
+
+    #[test]
+    fn align_loads_once_unless_behind() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _rt = rt.enter();
+        let loads = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let loaders = (0..2)
+            .map(|i| {
+                let loads = loads.clone();
+                Loader::<Box<[u8]>>::new_fallible(1, 1, 1, move |_| {
+                    loads[i].fetch_add(1, Ordering::Relaxed);
+                    if i == 1 {
+                        return Err(stitch::Error::Fault("test failure"));
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        let loaders = loaders.iter().collect::<Vec<_>>();
+        let tickets = || {
+            (loaders.iter())
+                .map(|l| l.give(vec![0; 1].into_boxed_slice()).unwrap())
+                .collect()
+        };
+
+        for policy in [SyncPolicy::Latest, SyncPolicy::Nearest, SyncPolicy::WaitAll] {
+            let mut sync = FrameSync::new(policy, Duration::ZERO, Duration::from_secs(1));
+            let captures = sync.align(&loaders, tickets());
+            assert!(captures[0].is_some());
+            assert_eq!(captures[1], None);
+        }
+        // a camera failing to load isn't behind, so nothing is loaded twice
+        assert_eq!(loads[0].load(Ordering::Relaxed), 3);
+        assert_eq!(loads[1].load(Ordering::Relaxed), 3);
+    }
+}
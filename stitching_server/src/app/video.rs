@@ -1,41 +1,84 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    time::{Duration, Instant},
+};
 
 use axum::extract::ws::{CloseFrame, Message, WebSocket};
 use futures_util::{SinkExt, StreamExt};
+use stitch::{
+    proj::{ViewTransition, MAIN_VIEW},
+    proto::{ClientMessage, MotionEvent, ServerMessage},
+};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        watch,
+    },
+    time::Interval,
+};
 
-use crate::util::{IntervalTimer, Metrics};
+use crate::{
+    latency::Stage,
+    util::{IntervalTimer, Metrics},
+};
 
-use super::{proto::RecvPacket, App};
+use super::{
+    proto::{control_packet, RecvPacket},
+    stitcher::{ClientView, Frame},
+    App,
+};
 
+/// Time between statuses sent to subscribed clients.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Streams frames to a client, starting on the shared main view. The first time the client
+/// changes what it sees it moves to a view of its own, if there is one to spare.
 pub async fn conn_state_machine(state: App, socket: WebSocket) {
-    let (sender, receiver) = socket.split();
-
-    let mut send_task = tokio::spawn(send_loop(state.clone(), sender));
-    let mut recv_task = tokio::spawn(recv_loop(state.clone(), receiver));
-
-    tokio::select! {
-        rv_a = (&mut send_task) => {
-            _ = rv_a.inspect_err(|e| println!("Error sending messages {e:?}"));
-            recv_task.abort();
-        },
-        rv_b = (&mut recv_task) => {
-            _ = rv_b.inspect_err(|e| println!("Error receiving messages {e:?}"));
-            send_task.abort();
-        }
-    }
-}
+    let (mut sender, mut receiver) = socket.split();
+    let mut client = Client::new(state);
+
+    loop {
+        let reply = tokio::select! {
+            changed = client.frames.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                // only the latest frame is kept, so a slow client skips the ones it missed
+                let Some(frame) = client.frames.borrow_and_update().clone() else {
+                    continue;
+                };
+
+                let mut timer = IntervalTimer::new();
+                let res = sender.send(Message::Binary(frame.packet.to_vec())).await;
+                timer.mark("send-frame");
+                if let Err(e) = res {
+                    tracing::debug!("error sending frame {e:?}");
+                    break;
+                }
+                frame.times.report(Stage::Send, Instant::now()).record();
+                None
+            }
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Binary(raw))) => client.handle_packet(&raw).await,
+                Some(Ok(Message::Text(text))) => {
+                    let msg = serde_json::from_str(&text).map_err(|err| err.to_string());
+                    client.handle_control(msg, Encoding::Json).await
+                }
+                Some(Ok(_)) => None,
+                Some(Err(e)) => {
+                    tracing::debug!("error receiving messages {e:?}");
+                    break;
+                }
+                None => break,
+            },
+            enc = status_tick(&mut client.status) => Some((ServerMessage::Status(client.state.status()), enc)),
+            (event, enc) = next_motion(&mut client.motion) => Some((ServerMessage::Motion(event), enc)),
+        };
 
-async fn send_loop<S>(state: App, mut sender: S)
-where
-    S: SinkExt<Message> + Unpin + Send,
-{
-    while let Some(msg) = state.ws_frame().await {
-        let mut timer = IntervalTimer::new();
-        let res = sender.send(msg).await;
-        timer.mark("send-frame");
-
-        if res.is_err() {
-            break;
+        if let Some((msg, enc)) = reply {
+            if sender.send(enc.message(&msg)).await.is_err() {
+                break;
+            }
         }
     }
 
@@ -48,36 +91,244 @@ where
         .await;
 }
 
-async fn recv_loop<R>(state: App, mut receiver: R)
-where
-    R: StreamExt<Item = Result<Message, axum::Error>> + Unpin + Send,
-{
-    while let Some(Ok(msg)) = receiver.next().await {
-        if let Message::Binary(raw) = msg {
-            let Some(p) = RecvPacket::from_raw(&raw) else {
-                tracing::error!(
-                    "failed to parse packet from client starting with {:?}",
-                    &raw[..raw.len().min(8)]
-                );
-                continue;
-            };
-
-            match p {
-                RecvPacket::Nop => {}
-                RecvPacket::SettingsSync(sp) => {
-                    state.update_style(move |proj_spec| {
-                        *proj_spec = sp.view_type(proj_spec.radius());
+/// How a client sent its control messages, and so how it is answered.
+#[derive(Clone, Copy, Debug)]
+enum Encoding {
+    Json,
+    Cbor,
+}
+
+impl Encoding {
+    fn message(self, msg: &ServerMessage) -> Message {
+        match self {
+            Self::Json => {
+                Message::Text(serde_json::to_string(msg).expect("server messages always serialize"))
+            }
+            Self::Cbor => Message::Binary(control_packet(msg)),
+        }
+    }
+}
+
+/// View a websocket client watches and controls.
+struct Client {
+    state: App,
+    frames: watch::Receiver<Frame>,
+    own_view: Option<ClientView>,
+    tried_own_view: bool,
+    status: Option<(Interval, Encoding)>,
+    motion: Option<(broadcast::Receiver<MotionEvent>, Encoding)>,
+}
+
+impl Client {
+    fn new(state: App) -> Self {
+        state.add_client();
+        Self {
+            frames: state.subscribe_frames(),
+            state,
+            own_view: None,
+            tried_own_view: false,
+            status: None,
+            motion: None,
+        }
+    }
+
+    fn view(&self) -> &str {
+        self.own_view.as_ref().map_or(MAIN_VIEW, ClientView::name)
+    }
+
+    /// Moves to a view of its own, only asking once so clients that didn't get one keep
+    /// sharing the main view.
+    async fn claim_view(&mut self) {
+        if self.tried_own_view {
+            return;
+        }
+        self.tried_own_view = true;
+
+        if let Some(view) = self.state.open_view().await {
+            self.frames = view.subscribe_frames();
+            self.own_view = Some(view);
+        }
+    }
+
+    async fn handle_packet(&mut self, raw: &[u8]) -> Option<(ServerMessage, Encoding)> {
+        let Some(p) = RecvPacket::from_raw(raw) else {
+            tracing::error!(
+                "failed to parse packet from client starting with {:?}",
+                &raw[..raw.len().min(8)]
+            );
+            return None;
+        };
+
+        match p {
+            RecvPacket::Nop => {}
+            RecvPacket::SettingsSync(sp) => {
+                self.claim_view().await;
+                self.state.update_style(self.view(), move |proj_spec| {
+                    *proj_spec = sp.view_type(proj_spec.radius());
+                });
+            }
+            RecvPacket::Crop(cp) => {
+                self.claim_view().await;
+                self.state.update_crop(self.view(), cp.crop());
+            }
+            RecvPacket::Timing(timing) => {
+                let (took, delay) = timing.info_now();
+                Metrics::push("client-update", delay.as_secs_f64() * 1000.);
+
+                let took = format!("{took:.1?}");
+                let delay = format!("{delay:.1?}");
+                tracing::info!(delay, took, "client update");
+            }
+            RecvPacket::Control(msg) => return self.handle_control(msg, Encoding::Cbor).await,
+        }
+        None
+    }
+
+    async fn handle_control(
+        &mut self,
+        msg: Result<ClientMessage, String>,
+        enc: Encoding,
+    ) -> Option<(ServerMessage, Encoding)> {
+        let reply = match msg {
+            Ok(ClientMessage::GetView) => self.view_reply().await,
+            Ok(ClientMessage::SetView {
+                style,
+                crop,
+                ground_overlay,
+            }) => {
+                self.claim_view().await;
+                if let Some(style) = style {
+                    self.state.update_style(self.view(), move |s| *s = style);
+                }
+                if let Some(crop) = crop {
+                    self.state.update_crop(self.view(), crop.clamped());
+                }
+                if let Some(shown) = ground_overlay {
+                    self.state.update_ground_overlay(self.view(), shown);
+                }
+                self.view_reply().await
+            }
+            Ok(ClientMessage::TransitionView {
+                from,
+                to,
+                duration,
+                easing,
+            }) => {
+                self.claim_view().await;
+                self.state
+                    .transition_style(self.view(), move |current| ViewTransition {
+                        from: from.unwrap_or(current),
+                        to,
+                        duration,
+                        easing,
                     });
+                self.view_reply().await
+            }
+            Ok(ClientMessage::ListPresets) => {
+                let (presets, last) = self.state.presets();
+                ServerMessage::Presets { presets, last }
+            }
+            Ok(ClientMessage::SelectPreset { name }) => {
+                self.claim_view().await;
+                if self.state.select_preset(self.view(), &name).await {
+                    self.view_reply().await
+                } else {
+                    ServerMessage::Error {
+                        message: format!("no preset {name}"),
+                    }
                 }
-                RecvPacket::Timing(timing) => {
-                    let (took, delay) = timing.info_now();
-                    Metrics::push("client-update", delay.as_secs_f64() * 1000.);
+            }
+            Ok(ClientMessage::ListCameras) => ServerMessage::Cameras {
+                cameras: self.state.cameras(),
+            },
+            Ok(ClientMessage::SetCameraEnabled { index, enabled }) => {
+                if self.state.set_camera_enabled(index, enabled).await {
+                    ServerMessage::Cameras {
+                        cameras: self.state.cameras(),
+                    }
+                } else {
+                    ServerMessage::Error {
+                        message: format!("no camera {index}, or it failed"),
+                    }
+                }
+            }
+            Ok(ClientMessage::GetHealth) => ServerMessage::Health {
+                cameras: self.state.camera_health(),
+            },
+            Ok(ClientMessage::Subscribe { status, motion }) => {
+                self.status = status.then(|| (tokio::time::interval(STATUS_INTERVAL), enc));
+                self.motion = motion.then(|| (self.state.subscribe_motion(), enc));
+                return None;
+            }
+            Err(message) => ServerMessage::Error { message },
+        };
+        Some((reply, enc))
+    }
+
+    async fn view_reply(&self) -> ServerMessage {
+        match self.state.view_state(self.view()).await {
+            Some(state) => ServerMessage::View(state),
+            None => ServerMessage::Error {
+                message: "view is gone".to_string(),
+            },
+        }
+    }
+}
 
-                    let took = format!("{took:.1?}");
-                    let delay = format!("{delay:.1?}");
-                    tracing::info!(delay, took, "client update");
+/// Waits until the next status is due, never finishing if the client isn't subscribed.
+async fn status_tick(status: &mut Option<(Interval, Encoding)>) -> Encoding {
+    match status {
+        Some((interval, enc)) => {
+            interval.tick().await;
+            *enc
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next motion event, never finishing if the client isn't subscribed or the
+/// render loop stopped.
+async fn next_motion(
+    motion: &mut Option<(broadcast::Receiver<MotionEvent>, Encoding)>,
+) -> (MotionEvent, Encoding) {
+    if let Some((events, enc)) = motion {
+        loop {
+            match events.recv().await {
+                Ok(event) => return (event, *enc),
+                Err(RecvError::Lagged(n)) => tracing::debug!("client skipped {n} motion events"),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+    std::future::pending().await
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.state.remove_client();
+    }
+}
+
+/// Streams the encoded output, one NAL unit per binary message.
+pub async fn encoded_state_machine(state: App, mut socket: WebSocket) {
+    if let Some((_, mut nals)) = state.subscribe_encoded() {
+        loop {
+            match nals.recv().await {
+                Ok(nal) => {
+                    if socket.send(Message::Binary(nal.to_vec())).await.is_err() {
+                        return;
+                    }
                 }
+                Err(RecvError::Lagged(n)) => tracing::debug!("encoded client skipped {n} NALs"),
+                Err(RecvError::Closed) => break,
             }
         }
     }
+
+    _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code: axum::extract::ws::close_code::AWAY,
+            reason: Cow::from("No encoded stream"),
+        })))
+        .await;
 }
@@ -0,0 +1,195 @@
+//! WebRTC delivery of the encoded output, negotiated with a single offer and answer over HTTP.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, Json};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Notify,
+};
+use webrtc::{
+    api::{
+        interceptor_registry::register_default_interceptors,
+        media_engine::{MediaEngine, MIME_TYPE_H264},
+        APIBuilder,
+    },
+    ice_transport::ice_server::RTCIceServer,
+    interceptor::registry::Registry,
+    media::Sample,
+    peer_connection::{
+        configuration::RTCConfiguration, peer_connection_state::RTCPeerConnectionState,
+        sdp::session_description::RTCSessionDescription, RTCPeerConnection,
+    },
+    rtp_transceiver::rtp_codec::RTCRtpCodecCapability,
+    track::track_local::{track_local_static_sample::TrackLocalStaticSample, TrackLocal},
+};
+
+use super::App;
+use crate::encode::Codec;
+
+/// Duration given to the first frame, before there is a previous one to measure from.
+const FIRST_FRAME: Duration = Duration::from_millis(33);
+
+/// Answers a browser's offer with a peer that streams the encoded output to it. The answer is
+/// sent once ICE gathering is done, so it already holds every candidate.
+pub async fn offer(
+    State(state): State<App>,
+    Json(offer): Json<RTCSessionDescription>,
+) -> Result<Json<RTCSessionDescription>, (StatusCode, String)> {
+    let Some((Codec::H264, nals)) = state.subscribe_encoded() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "WebRTC needs the server to run with --encode h264".to_string(),
+        ));
+    };
+
+    let ice_servers = state.0.ice_servers.clone();
+    connect(offer, nals, ice_servers)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            tracing::error!("failed to answer WebRTC offer: {err}");
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+        })
+}
+
+async fn connect(
+    offer: RTCSessionDescription,
+    nals: broadcast::Receiver<Arc<[u8]>>,
+    ice_servers: Vec<String>,
+) -> anyhow::Result<RTCSessionDescription> {
+    let mut media = MediaEngine::default();
+    media.register_default_codecs()?;
+    let registry = register_default_interceptors(Registry::new(), &mut media)?;
+    let api = APIBuilder::new()
+        .with_media_engine(media)
+        .with_interceptor_registry(registry)
+        .build();
+
+    let pc = Arc::new(
+        api.new_peer_connection(RTCConfiguration {
+            ice_servers: vec![RTCIceServer {
+                urls: ice_servers,
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .await?,
+    );
+
+    let track = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_string(),
+            ..Default::default()
+        },
+        "video".to_string(),
+        "stitching".to_string(),
+    ));
+    let sender = pc
+        .add_track(Arc::clone(&track) as Arc<dyn TrackLocal + Send + Sync>)
+        .await?;
+    // RTCP has to be read for the interceptors to handle NACKs and reports
+    tokio::spawn(async move {
+        let mut buf = vec![0; 1500];
+        while sender.read(&mut buf).await.is_ok() {}
+    });
+
+    let closed = Arc::new(Notify::new());
+    let on_closed = Arc::clone(&closed);
+    pc.on_peer_connection_state_change(Box::new(move |s| {
+        tracing::info!("WebRTC peer {s}");
+        if matches!(
+            s,
+            RTCPeerConnectionState::Disconnected
+                | RTCPeerConnectionState::Failed
+                | RTCPeerConnectionState::Closed
+        ) {
+            on_closed.notify_one();
+        }
+        Box::pin(async {})
+    }));
+
+    pc.set_remote_description(offer).await?;
+    let answer = pc.create_answer(None).await?;
+    let mut gathered = pc.gathering_complete_promise().await;
+    pc.set_local_description(answer).await?;
+    _ = gathered.recv().await;
+    let answer = pc
+        .local_description()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("peer has no local description"))?;
+
+    tokio::spawn(send_samples(pc, track, nals, closed));
+    Ok(answer)
+}
+
+async fn send_samples(
+    pc: Arc<RTCPeerConnection>,
+    track: Arc<TrackLocalStaticSample>,
+    mut nals: broadcast::Receiver<Arc<[u8]>>,
+    closed: Arc<Notify>,
+) {
+    let mut frame = AccessUnit::default();
+    loop {
+        let nal = tokio::select! {
+            () = closed.notified() => break,
+            nal = nals.recv() => nal,
+        };
+        match nal {
+            Ok(nal) => {
+                let Some(sample) = frame.push(&nal) else {
+                    continue;
+                };
+                if let Err(err) = track.write_sample(&sample).await {
+                    tracing::error!("failed to send WebRTC sample: {err}");
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(n)) => {
+                tracing::debug!("WebRTC peer is behind, dropped {n} NAL units");
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    if let Err(err) = pc.close().await {
+        tracing::error!("failed to close WebRTC peer: {err}");
+    }
+}
+
+/// Groups H.264 NAL units into the access units of whole frames.
+#[derive(Default)]
+struct AccessUnit {
+    data: Vec<u8>,
+    has_slice: bool,
+    started: Option<Instant>,
+}
+
+impl AccessUnit {
+    /// Adds `nal`, returning the previous frame as a sample if `nal` is the start of the next.
+    fn push(&mut self, nal: &[u8]) -> Option<Sample> {
+        let &header = nal.get(4)?;
+        let is_slice = matches!(header & 0x1f, 1..=5);
+        // first_mb_in_slice is 0, coded as a single set bit, for the first slice of a frame
+        let first_slice = is_slice && nal.get(5).is_some_and(|b| b & 0x80 != 0);
+
+        let now = Instant::now();
+        let out = (self.has_slice && (!is_slice || first_slice)).then(|| {
+            let duration = self.started.map_or(FIRST_FRAME, |s| now - s);
+            self.has_slice = false;
+            self.started = Some(now);
+            Sample {
+                data: std::mem::take(&mut self.data).into(),
+                duration,
+                ..Default::default()
+            }
+        });
+
+        self.data.extend_from_slice(nal);
+        self.has_slice |= is_slice;
+        out
+    }
+}
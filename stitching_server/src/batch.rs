@@ -0,0 +1,146 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use stitch::{
+    buf::FrameSize,
+    camera::{
+        live,
+        replay::{self, ReplayClock},
+        Camera,
+    },
+    loader::{self, Loader},
+    proj::{self, GpuDirectBufferWrite},
+};
+
+use crate::{
+    app::build_projector,
+    encode::{encoder_name, Backend, Codec},
+    util::RgbaFrame,
+};
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct BatchArgs {
+    /// Directory recorded by `record`
+    #[arg(long)]
+    pub input: PathBuf,
+    /// Video to write, in a container ffmpeg picks from its extension
+    #[arg(long, default_value = "stitched.mp4")]
+    pub out: PathBuf,
+    /// Config rendered with instead of the one recorded along with the cameras
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    #[arg(long, default_value_t = 1280)]
+    pub width: usize,
+    #[arg(long, default_value_t = 720)]
+    pub height: usize,
+    /// Frames rendered for every second of the recording
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: u32,
+    #[arg(long, default_value = "h264")]
+    pub codec: Codec,
+    #[arg(long, default_value = "auto")]
+    pub encoder: Backend,
+    /// Target bitrate in kbit/s
+    #[arg(long, default_value_t = 8000)]
+    pub bitrate: u32,
+}
+
+/// Renders the recording in `args.input` from start to end at `args.fps`, as fast as the GPU
+/// and encoder allow, and encodes it to `args.out`. Every camera renders the latest frame it
+/// recorded by the time of each rendered frame.
+///
+/// # Errors
+/// the config or recording can't be opened, the projector can't be built, or ffmpeg can't be
+/// started or fails
+pub async fn render(args: &BatchArgs) -> Result<()> {
+    let cfg_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| replay::config_path(&args.input));
+    let cfg = proj::Config::<live::Config>::open(&cfg_path)?;
+
+    let clock = ReplayClock::default();
+    let mut end = Duration::ZERO;
+    let mut cams = Vec::with_capacity(cfg.cameras.len());
+    for cam_cfg in &cfg.cameras {
+        let (data, cam_end): (Loader<GpuDirectBufferWrite>, _) =
+            replay::Config::new(&args.input, cam_cfg.meta.live_index)
+                .load_stepped(clock.clone())?;
+        end = end.max(cam_end);
+        // frames the projector turns are projected with their sides swapped
+        let (w, h, _) = data.frame_size();
+        let [w, h] = cam_cfg.meta.preprocess.rotate.turn([w as u32, h as u32]);
+        cams.push(Camera::new(
+            cam_cfg.view.with_dims(w as f32, h as f32),
+            data,
+        ));
+    }
+
+    let mut proj = build_projector(&cfg, args.width, args.height, 1, None, false, None).await?;
+    for (i, (cam, cam_cfg)) in cams.iter().zip(&cfg.cameras).enumerate() {
+        proj.set_preprocess(i, cam, &cam_cfg.meta.preprocess)?;
+    }
+    proj.update_proj_view(cfg.style);
+
+    let encoder = encoder_name(args.codec, args.encoder);
+    tracing::info!("encoding {:?} with {encoder}", args.out);
+    let mut child = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{}x{}", args.width, args.height)])
+        .args(["-r", &args.fps.to_string(), "-i", "-"])
+        .args(["-c:v", encoder, "-pix_fmt", "yuv420p"])
+        .args(["-b:v", &format!("{}k", args.bitrate)])
+        .arg(&args.out)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+
+    let started = Instant::now();
+    let mut out = RgbaFrame::new((args.width, args.height));
+    let mut frames = 0u32;
+    loop {
+        let at = Duration::from_secs(frames.into()) / args.fps;
+        if at > end {
+            break;
+        }
+        clock.set(at);
+
+        loader::block_discard_tickets(proj.take_input_buffers(&cams)?);
+        proj.update_cam_specs(&cams)?;
+        if frames == 0 {
+            if let Some(deg) = cfg.auto_mask_incidence {
+                proj.auto_masks(deg.to_radians());
+            }
+        }
+        proj.update_render();
+        proj.block_copy_render_to(&mut out)?;
+        stdin.write_all(&out)?;
+
+        frames += 1;
+        if frames.is_multiple_of(args.fps * 10) {
+            tracing::info!("rendered {:.0?} of {end:.0?}", at);
+        }
+    }
+
+    // closing stdin lets ffmpeg finish the video
+    drop(stdin);
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("ffmpeg exited with {status}"));
+    }
+
+    let took = started.elapsed();
+    tracing::info!(
+        "rendered {frames} frames in {took:.1?}, {:.1}x real time",
+        end.as_secs_f32() / took.as_secs_f32()
+    );
+    Ok(())
+}
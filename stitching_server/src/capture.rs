@@ -0,0 +1,208 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::Serialize;
+use stitch::{
+    buf::FrameSize,
+    camera::{self, live},
+    loader::Loader,
+};
+
+use crate::rt;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Warmup {
+    /// Give up waiting for the camera to settle after this many frames
+    pub max_frames: usize,
+    /// Consecutive frames that must stay within `tolerance` of each other
+    pub stable_frames: usize,
+    /// Largest change in any channel's mean (0-255) still considered stable
+    pub tolerance: f32,
+}
+
+/// Saves a frame from every camera in `cfg` along with a JSON sidecar describing it.
+/// When `sync` is set, all cameras are warmed up first and then captured together.
+///
+/// # Errors
+/// a camera fails to load or a capture can't be saved
+pub async fn capture_live(
+    cfg: &stitch::proj::Config<live::Config>,
+    warmup: Warmup,
+    sync: bool,
+) -> Result<()> {
+    let cams = cfg
+        .cameras
+        .iter()
+        .map(|c| {
+            // frames are saved as RGBA
+            let mut c = c.clone();
+            c.meta.preprocess.decode = false;
+            c.load::<Box<[u8]>>()
+        })
+        .collect::<stitch::Result<Vec<_>>>()?;
+
+    let mut captures = Vec::with_capacity(cams.len());
+    if sync {
+        let warmed = futures::future::join_all(cams.iter().map(|c| warm_up(&c.data, warmup)))
+            .await
+            .into_iter()
+            .collect::<stitch::Result<Vec<_>>>()?;
+
+        let frames = futures::future::join_all(
+            cams.iter()
+                .zip(warmed)
+                .map(|(c, w)| async move { w.next_frame(&c.data).await }),
+        )
+        .await;
+
+        for frame in frames {
+            captures.push(frame?);
+        }
+    } else {
+        for c in &cams {
+            captures.push(warm_up(&c.data, warmup).await?);
+        }
+    }
+
+    let saves = captures
+        .into_iter()
+        .zip(&cams)
+        .zip(&cfg.cameras)
+        .enumerate()
+        .map(|(i, ((cap, cam), cam_cfg))| {
+            let (width, height, _) = cam.data.frame_size();
+            let sidecar = Sidecar {
+                index: i,
+                image: format!("capture{i}.png"),
+                width,
+                height,
+                captured_unix_ms: cap.captured_unix_ms,
+                warmup_frames: cap.frames,
+                converged: cap.converged,
+                stats: cap.stats,
+                camera: cam_cfg.clone(),
+            };
+
+            rt::spawn_encode(move || -> Result<()> {
+                image::save_buffer(
+                    &sidecar.image,
+                    &cap.buf,
+                    width.try_into()?,
+                    height.try_into()?,
+                    image::ExtendedColorType::Rgba8,
+                )?;
+                std::fs::write(
+                    format!("capture{i}.json"),
+                    serde_json::to_string_pretty(&sidecar)?,
+                )?;
+                Ok(())
+            })
+        });
+
+    for res in futures::future::join_all(saves).await {
+        res??;
+    }
+
+    Ok(())
+}
+
+async fn warm_up(loader: &Loader<Box<[u8]>>, warmup: Warmup) -> stitch::Result<Capture> {
+    let mut cap = Capture {
+        buf: vec![0u8; loader.num_bytes()].into_boxed_slice(),
+        captured_unix_ms: 0,
+        frames: 0,
+        converged: false,
+        stats: FrameStats::default(),
+    };
+
+    let mut stable = 0;
+    while cap.frames < warmup.max_frames {
+        let last = cap.stats;
+        cap = cap.next_frame(loader).await?;
+
+        stable = if cap.frames > 1 && last.max_diff(cap.stats) <= warmup.tolerance {
+            stable + 1
+        } else {
+            0
+        };
+
+        if stable >= warmup.stable_frames {
+            cap.converged = true;
+            break;
+        }
+    }
+
+    if !cap.converged {
+        tracing::warn!("camera didn't settle within {} frames", warmup.max_frames);
+    }
+
+    Ok(cap)
+}
+
+struct Capture {
+    buf: Box<[u8]>,
+    captured_unix_ms: u128,
+    frames: usize,
+    converged: bool,
+    stats: FrameStats,
+}
+
+impl Capture {
+    async fn next_frame(mut self, loader: &Loader<Box<[u8]>>) -> stitch::Result<Self> {
+        self.buf = loader.give_async(self.buf).await?;
+        self.captured_unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        self.frames += 1;
+        self.stats = FrameStats::from_rgba(&self.buf);
+        Ok(self)
+    }
+}
+
+/// Tracks exposure and white balance through the mean of each color channel.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+struct FrameStats {
+    mean: [f32; 3],
+}
+
+impl FrameStats {
+    fn from_rgba(data: &[u8]) -> Self {
+        // every 16th pixel is plenty to follow exposure and white balance changes
+        let (sum, n) =
+            data.chunks_exact(4)
+                .step_by(16)
+                .fold(([0u64; 3], 0u64), |(mut sum, n), px| {
+                    sum.iter_mut()
+                        .zip(px)
+                        .for_each(|(s, v)| *s += u64::from(*v));
+                    (sum, n + 1)
+                });
+
+        #[allow(clippy::cast_precision_loss)]
+        Self {
+            mean: sum.map(|s| s as f32 / n.max(1) as f32),
+        }
+    }
+
+    fn max_diff(self, other: Self) -> f32 {
+        self.mean
+            .iter()
+            .zip(other.mean)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0., f32::max)
+    }
+}
+
+#[derive(Serialize)]
+struct Sidecar {
+    index: usize,
+    image: String,
+    width: usize,
+    height: usize,
+    captured_unix_ms: u128,
+    warmup_frames: usize,
+    converged: bool,
+    stats: FrameStats,
+    camera: camera::Config<live::Config>,
+}
@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use stitch::{
+    buf::{FrameSize, PixelFormat},
+    camera::{live, replay, Camera},
+    loader::{self, Loader},
+    proj::{self, GpuDirectBufferWrite},
+};
+
+use crate::{app::build_projector, util::RgbaFrame};
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct CompareArgs {
+    /// Config rendered first, live.toml by default, or the one recorded with --replay
+    #[arg(long)]
+    pub a: Option<PathBuf>,
+    /// Config rendered second, from the same camera frames as the first
+    #[arg(long)]
+    pub b: PathBuf,
+    /// Take the camera frames from a recording made by `record` in this directory instead of
+    /// the cameras
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+    /// Frames loaded from every camera before the one compared, so live cameras can settle
+    #[arg(long, default_value_t = 10)]
+    pub skip: usize,
+    #[arg(long, default_value_t = 1280)]
+    pub width: usize,
+    #[arg(long, default_value_t = 720)]
+    pub height: usize,
+    /// Write the difference of the two renders instead of putting them side by side
+    #[arg(long)]
+    pub diff: bool,
+    /// PNG to write
+    #[arg(long, default_value = "compare.png")]
+    pub out: PathBuf,
+}
+
+/// Renders the same frame of every camera with the configs `args.a` and `args.b`, and saves
+/// them side by side or their difference.
+///
+/// # Errors
+/// a config can't be opened, the configs don't have the same cameras, a camera fails to load,
+/// a projector can't be built or the image can't be saved
+pub async fn compare(args: &CompareArgs) -> Result<()> {
+    let a_path = args.a.clone().unwrap_or_else(|| {
+        args.replay
+            .as_deref()
+            .map_or_else(|| PathBuf::from("live.toml"), replay::config_path)
+    });
+    let a = proj::Config::<live::Config>::open(&a_path)?;
+    let b = proj::Config::<live::Config>::open(&args.b)?;
+    if a.cameras.len() != b.cameras.len() {
+        return Err(anyhow!(
+            "{a_path:?} has {} cameras but {:?} has {}",
+            a.cameras.len(),
+            args.b,
+            b.cameras.len()
+        ));
+    }
+
+    let frames = load_frames(&a, args.replay.as_deref(), args.skip).await?;
+    let size = (args.width, args.height);
+    let out_a = render(&a, &frames, size).await?;
+    let out_b = render(&b, &frames, size).await?;
+
+    let diff = out_a
+        .data
+        .chunks_exact(4)
+        .zip(out_b.data.chunks_exact(4))
+        .flat_map(|(pa, pb)| {
+            let d = |i: usize| pa[i].abs_diff(pb[i]);
+            [d(0), d(1), d(2), u8::MAX]
+        })
+        .collect::<Vec<_>>();
+    #[allow(clippy::cast_precision_loss)]
+    let mean = diff
+        .chunks_exact(4)
+        .map(|px| px[..3].iter().map(|&c| f64::from(c)).sum::<f64>() / 3.0)
+        .sum::<f64>()
+        / (size.0 * size.1) as f64;
+    tracing::info!("mean difference {mean:.2} of 255");
+
+    let (data, w) = if args.diff {
+        (diff, size.0)
+    } else {
+        let rows = out_a.data.chunks_exact(size.0 * 4);
+        let side = rows
+            .zip(out_b.data.chunks_exact(size.0 * 4))
+            .flat_map(|(ra, rb)| ra.iter().chain(rb).copied())
+            .collect();
+        (side, size.0 * 2)
+    };
+    image::save_buffer(
+        &args.out,
+        &data,
+        w.try_into()?,
+        size.1.try_into()?,
+        image::ExtendedColorType::Rgba8,
+    )?;
+    tracing::info!("saved comparison to {:?}", args.out);
+    Ok(())
+}
+
+/// Camera frame loaded as it was delivered, to hand to every projector.
+struct Frame {
+    data: Box<[u8]>,
+    size: (u32, u32, u32),
+    format: PixelFormat,
+}
+
+/// Loads a frame from every camera in `cfg`, or its recording in `replay`, after skipping
+/// `skip` of them.
+async fn load_frames(
+    cfg: &proj::Config<live::Config>,
+    replay: Option<&Path>,
+    skip: usize,
+) -> Result<Vec<Frame>> {
+    let mut frames = Vec::with_capacity(cfg.cameras.len());
+    for cam in &cfg.cameras {
+        let loader: Loader<Box<[u8]>> = match replay {
+            Some(dir) => replay::Config::new(dir, cam.meta.live_index).try_into()?,
+            None => cam.meta.clone().open()?.0,
+        };
+        let mut data = vec![0; loader.num_bytes()].into_boxed_slice();
+        for _ in 0..=skip {
+            data = loader.give_async(data).await?;
+        }
+
+        let (w, h, c) = loader.frame_size();
+        frames.push(Frame {
+            data,
+            size: (w.try_into()?, h.try_into()?, c.try_into()?),
+            format: loader.pixel_format(),
+        });
+    }
+    Ok(frames)
+}
+
+/// Renders `frames` with `cfg` into its main view of `size`.
+async fn render(
+    cfg: &proj::Config<live::Config>,
+    frames: &[Frame],
+    size: (usize, usize),
+) -> Result<RgbaFrame> {
+    let mut proj = build_projector(cfg, size.0, size.1, 1, None, false, None).await?;
+
+    let cams = frames
+        .iter()
+        .zip(&cfg.cameras)
+        .map(|(frame, cam_cfg)| {
+            let data = frame.data.clone();
+            let (w, h, c) = frame.size;
+            let loader = Loader::<GpuDirectBufferWrite>::new_blocking(w, h, c, move |buf| {
+                buf.copy_from_slice(&data[..buf.len()]);
+            })
+            .with_pixel_format(frame.format);
+            // frames the projector turns are projected with their sides swapped
+            let [w, h] = cam_cfg.meta.preprocess.rotate.turn([w, h]);
+            Camera::new(cam_cfg.view.with_dims(w as f32, h as f32), loader)
+        })
+        .collect::<Vec<_>>();
+    for (i, (cam, cam_cfg)) in cams.iter().zip(&cfg.cameras).enumerate() {
+        proj.set_preprocess(i, cam, &cam_cfg.meta.preprocess)?;
+    }
+
+    loader::block_discard_tickets(proj.take_input_buffers(&cams)?);
+    proj.update_cam_specs(&cams)?;
+    proj.update_proj_view(cfg.style);
+    if let Some(deg) = cfg.auto_mask_incidence {
+        proj.auto_masks(deg.to_radians());
+    }
+    proj.update_render();
+
+    let mut out = RgbaFrame::new(size);
+    proj.block_copy_render_to(&mut out)?;
+    Ok(out)
+}
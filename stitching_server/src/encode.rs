@@ -0,0 +1,433 @@
+use std::{
+    io::{self, BufReader, Read, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, OnceLock},
+    time::Instant,
+};
+
+use tokio::sync::broadcast;
+
+use crate::latency::{FrameTimes, Stage};
+
+/// Frames waiting to be encoded before new ones are dropped.
+const FRAME_QUEUE: usize = 2;
+/// NAL units a subscriber can fall behind by before it misses some.
+const NAL_QUEUE: usize = 256;
+/// Identifies the SEI messages carrying [`Encoder::push_frame`]'s metadata, see
+/// [`Codec::meta_sei`].
+const META_UUID: &[u8; 16] = b"stitch-framemeta";
+/// payloadType of an SEI message of user data identified by a UUID.
+const USER_DATA_UNREGISTERED: u8 = 5;
+/// Bytes of an FLV file header and the size of the tag before the first, which is 0.
+const FLV_HEADER: usize = 13;
+/// Bytes of an FLV tag header, before its data.
+const FLV_TAG_HEADER: usize = 11;
+/// Type of FLV tags holding video.
+const FLV_VIDEO: u8 = 9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Codec {
+    H264,
+    H265,
+}
+
+impl Codec {
+    /// ffmpeg's NVENC and software encoders of the codec.
+    #[must_use]
+    pub const fn encoders(self) -> [&'static str; 2] {
+        match self {
+            Self::H264 => ["h264_nvenc", "libx264"],
+            Self::H265 => ["hevc_nvenc", "libx265"],
+        }
+    }
+
+    /// Whether `nal`, with its start code, holds the first parameter set sent before each
+    /// keyframe, the SPS for H.264 and VPS for H.265.
+    #[must_use]
+    pub fn is_parameter_set(self, nal: &[u8]) -> bool {
+        let Some(&header) = nal.get(4) else {
+            return false;
+        };
+        match self {
+            Self::H264 => header & 0x1f == 7,
+            Self::H265 => (header >> 1) & 0x3f == 32,
+        }
+    }
+
+    /// Whether `nal`, with its start code, holds the first slice of a picture, so each frame
+    /// that comes out of the encoder is counted once.
+    #[must_use]
+    pub fn starts_picture(self, nal: &[u8]) -> bool {
+        let Some(&header) = nal.get(4) else {
+            return false;
+        };
+        // the first bit after the header is set for the first slice, as first_mb_in_slice = 0
+        // for H.264 and first_slice_segment_in_pic_flag for H.265
+        match self {
+            Self::H264 => {
+                matches!(header & 0x1f, 1 | 5) && nal.get(5).is_some_and(|b| b & 0x80 != 0)
+            }
+            Self::H265 => (header >> 1) & 0x3f < 32 && nal.get(6).is_some_and(|b| b & 0x80 != 0),
+        }
+    }
+
+    /// SEI NAL unit, with its start code, carrying `meta` as user data for the picture it
+    /// comes before. `meta` can't hold two zero bytes in a row, which JSON never does, so it
+    /// needs no emulation prevention.
+    #[must_use]
+    pub fn meta_sei(self, meta: &[u8]) -> Vec<u8> {
+        let header: &[u8] = match self {
+            Self::H264 => &[6],
+            // prefix SEI
+            Self::H265 => &[39 << 1, 1],
+        };
+        let size = META_UUID.len() + meta.len();
+
+        let mut nal = Vec::with_capacity(size + size / 255 + 8);
+        nal.extend_from_slice(&[0, 0, 0, 1]);
+        nal.extend_from_slice(header);
+        nal.push(USER_DATA_UNREGISTERED);
+        nal.extend(std::iter::repeat_n(0xff, size / 255));
+        #[allow(clippy::cast_possible_truncation)]
+        nal.push((size % 255) as u8);
+        nal.extend_from_slice(META_UUID);
+        nal.extend_from_slice(meta);
+        // rbsp_trailing_bits
+        nal.push(0x80);
+        nal
+    }
+
+    /// Metadata of an SEI NAL unit made by [`Self::meta_sei`], `None` for any other NAL unit.
+    #[must_use]
+    pub fn sei_meta(self, nal: &[u8]) -> Option<&[u8]> {
+        let body = match self {
+            Self::H264 => nal.strip_prefix(&[0, 0, 0, 1, 6])?,
+            Self::H265 => nal.strip_prefix(&[0, 0, 0, 1, 39 << 1, 1])?,
+        };
+        let (&USER_DATA_UNREGISTERED, mut body) = body.split_first()? else {
+            return None;
+        };
+
+        let mut size = 0;
+        loop {
+            let (&b, rest) = body.split_first()?;
+            size += usize::from(b);
+            body = rest;
+            if b != 0xff {
+                break;
+            }
+        }
+        body.get(..size)?.strip_prefix(META_UUID.as_slice())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// NVENC when ffmpeg was built with it, otherwise x264/x265
+    #[default]
+    Auto,
+    Nvenc,
+    /// x264 or x265 on the CPU
+    Software,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct EncodeArgs {
+    /// Also encode the output, streamed as NAL units at /video/encoded
+    #[arg(long)]
+    pub encode: Option<Codec>,
+    #[arg(long, default_value = "auto")]
+    pub encoder: Backend,
+    /// Target bitrate in kbit/s
+    #[arg(long, default_value_t = 4000)]
+    pub bitrate: u32,
+    /// Frame rate the stream is encoded for
+    #[arg(long, default_value_t = 30)]
+    pub fps: u32,
+    /// Frames between keyframes
+    #[arg(long, default_value_t = 30)]
+    pub gop: u32,
+    /// STUN server WebRTC peers also gather candidates from, like
+    /// stun:stun.l.google.com:19302, given once for each. Without any they only offer the
+    /// server's own addresses, which is enough on the same network.
+    #[cfg(feature = "webrtc")]
+    #[arg(long = "ice-server")]
+    pub ice_servers: Vec<String>,
+}
+
+/// Frame waiting to be encoded, see [`Encoder::push_frame`].
+struct QueuedFrame {
+    pixels: Box<[u8]>,
+    times: Option<FrameTimes>,
+    meta: Option<Box<[u8]>>,
+}
+
+/// Encodes RGBA frames to an Annex B stream with an ffmpeg child process.
+pub struct Encoder {
+    frames: kanal::Sender<QueuedFrame>,
+    child: Child,
+    size: (usize, usize),
+}
+
+impl Encoder {
+    /// Starts encoding `w` by `h` frames, returning the encoder and a channel of the NAL units
+    /// it produces, each starting with a 4 byte start code.
+    ///
+    /// # Errors
+    /// ffmpeg can't be started
+    pub fn spawn(
+        args: &EncodeArgs,
+        codec: Codec,
+        w: usize,
+        h: usize,
+    ) -> io::Result<(Self, broadcast::Sender<Arc<[u8]>>)> {
+        let encoder = encoder_name(codec, args.encoder);
+        tracing::info!("encoding output with {encoder}");
+
+        let mut cmd = Command::new("ffmpeg");
+        cmd.args(["-hide_banner", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{w}x{h}"), "-r", &args.fps.to_string()])
+            .args(["-i", "-", "-c:v", encoder, "-pix_fmt", "yuv420p"])
+            .args(["-b:v", &format!("{}k", args.bitrate)])
+            .args(["-g", &args.gop.to_string(), "-bf", "0"]);
+        if encoder.ends_with("nvenc") {
+            cmd.args(["-preset", "p1", "-tune", "ull", "-zerolatency", "1"]);
+        } else {
+            cmd.args(["-preset", "ultrafast", "-tune", "zerolatency"]);
+        }
+        // parameter sets on every keyframe, so clients can join mid stream, in FLV tags that
+        // say where every picture ends
+        cmd.args(["-bsf:v", "dump_extra", "-f", "flv", "-flush_packets", "1"])
+            .args(["-flvflags", "no_duration_filesize", "-"]);
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (frames, frame_recv) = kanal::bounded(FRAME_QUEUE);
+        // frames come out of the encoder in the order they went in
+        let (written, written_recv) = kanal::unbounded();
+        let (nal_send, _) = broadcast::channel(NAL_QUEUE);
+        let nals = nal_send.clone();
+
+        std::thread::Builder::new()
+            .name("encode-in".to_string())
+            .spawn(move || write_frames(&frame_recv, stdin, &written))?;
+        std::thread::Builder::new()
+            .name("encode-out".to_string())
+            .spawn(move || read_nals(stdout, codec, &written_recv, &nal_send))?;
+
+        Ok((
+            Self {
+                frames,
+                child,
+                size: (w, h),
+            },
+            nals,
+        ))
+    }
+
+    /// Width and height of the frames it encodes.
+    pub const fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    /// Queues `frame` for encoding, dropping it if the encoder is behind. Its latency is
+    /// reported once encoded when `times` is given, and `meta` is sent in an SEI NAL unit right
+    /// before it, see [`Codec::meta_sei`].
+    pub fn push_frame(&self, frame: &[u8], times: Option<FrameTimes>, meta: Option<&[u8]>) {
+        let queued = QueuedFrame {
+            pixels: frame.into(),
+            times,
+            meta: meta.map(Into::into),
+        };
+        if !matches!(self.frames.try_send(queued), Ok(true)) {
+            tracing::debug!("encoder is behind, dropped a frame");
+        }
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        _ = self.frames.close();
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+/// ffmpeg encoder for `codec`, probing for NVENC when `backend` is [`Backend::Auto`].
+pub fn encoder_name(codec: Codec, backend: Backend) -> &'static str {
+    let [hw, sw] = codec.encoders();
+    match backend {
+        Backend::Nvenc => hw,
+        Backend::Software => sw,
+        Backend::Auto if has_encoder(hw) => hw,
+        Backend::Auto => sw,
+    }
+}
+
+/// Encoders of every [`Codec`] that ffmpeg on the path was built with.
+pub fn available_encoders() -> Vec<&'static str> {
+    [Codec::H264, Codec::H265]
+        .into_iter()
+        .flat_map(Codec::encoders)
+        .filter(|e| has_encoder(e))
+        .collect()
+}
+
+/// Whether `ffmpeg -encoders` lists `name`, which is only run the first time it's asked.
+fn has_encoder(name: &str) -> bool {
+    static LISTED: OnceLock<String> = OnceLock::new();
+    LISTED
+        .get_or_init(|| {
+            Command::new("ffmpeg")
+                .args(["-hide_banner", "-encoders"])
+                .output()
+                .map(|out| String::from_utf8_lossy(&out.stdout).into_owned())
+                .unwrap_or_default()
+        })
+        .lines()
+        .any(|l| l.split_whitespace().nth(1) == Some(name))
+}
+
+fn write_frames(
+    frames: &kanal::Receiver<QueuedFrame>,
+    mut stdin: ChildStdin,
+    written: &kanal::Sender<QueuedFrame>,
+) {
+    while let Ok(frame) = frames.recv() {
+        if let Err(err) = stdin.write_all(&frame.pixels) {
+            tracing::error!("failed to send frame to encoder: {err}");
+            break;
+        }
+        _ = written.send(QueuedFrame {
+            pixels: Box::default(),
+            ..frame
+        });
+    }
+}
+
+fn read_nals(
+    stdout: ChildStdout,
+    codec: Codec,
+    written: &kanal::Receiver<QueuedFrame>,
+    nals: &broadcast::Sender<Arc<[u8]>>,
+) {
+    let mut flv = FlvPictures::new(BufReader::new(stdout));
+    loop {
+        let picture = match flv.read_picture() {
+            Ok(Some(picture)) => picture,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::error!("failed to read from encoder: {err}");
+                break;
+            }
+        };
+
+        let (times, mut meta) = match written.try_recv() {
+            Ok(Some(frame)) => (frame.times, frame.meta),
+            _ => (None, None),
+        };
+        if let Some(times) = times {
+            times.report(Stage::Encode, Instant::now()).record();
+        }
+        for nal in picture {
+            // after the parameter sets of a keyframe, so recordings cut before it
+            if codec.starts_picture(&nal) {
+                if let Some(meta) = meta.take() {
+                    _ = nals.send(codec.meta_sei(&meta).into());
+                }
+            }
+            // fails when nobody is subscribed
+            _ = nals.send(nal.into());
+        }
+    }
+    tracing::info!("encoder exited");
+}
+
+/// Pictures of the FLV stream ffmpeg muxes the encoded video into. Unlike in an Annex B stream,
+/// each is in a tag that says how long it is, so it's passed on as soon as ffmpeg writes it
+/// instead of once the next one starts.
+struct FlvPictures<R> {
+    src: R,
+    header_read: bool,
+}
+
+impl<R: Read> FlvPictures<R> {
+    const fn new(src: R) -> Self {
+        Self {
+            src,
+            header_read: false,
+        }
+    }
+
+    /// NAL units of the next picture, each with a 4 byte start code, or `None` once the stream
+    /// ends. A picture cut off by the end of the stream is left out.
+    fn read_picture(&mut self) -> io::Result<Option<Vec<Vec<u8>>>> {
+        if !self.header_read {
+            // the file header, then the size of the tag before the first one
+            self.src.read_exact(&mut [0; FLV_HEADER])?;
+            self.header_read = true;
+        }
+
+        let mut tag = [0; FLV_TAG_HEADER];
+        loop {
+            match self.src.read_exact(&mut tag) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                res => res?,
+            }
+            let size = u32::from_be_bytes([0, tag[1], tag[2], tag[3]]) as usize;
+            // followed by the size of the whole tag
+            let mut data = vec![0; size + 4];
+            match self.src.read_exact(&mut data) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                res => res?,
+            }
+
+            if tag[0] == FLV_VIDEO {
+                if let Some(nals) = flv_nals(&data[..size]) {
+                    return Ok(Some(nals));
+                }
+            }
+        }
+    }
+}
+
+/// NAL units of the picture in the data of an FLV video tag, each given a 4 byte start code.
+/// `None` for tags holding anything else, like the decoder configuration ffmpeg starts with.
+fn flv_nals(data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let &first = data.first()?;
+    let mut body = if first & 0x80 == 0 {
+        // AVC, then a packet type of 1 for NAL units and a 3 byte composition time
+        if first & 0x0f != 7 || *data.get(1)? != 1 {
+            return None;
+        }
+        data.get(5..)?
+    } else {
+        // enhanced FLV, then the codec's FourCC and, for coded frames, a composition time that
+        // the packet type 3 variant leaves out
+        match first & 0x0f {
+            1 => data.get(8..)?,
+            3 => data.get(5..)?,
+            _ => return None,
+        }
+    };
+
+    // each prefixed by its 4 byte length
+    let mut nals = Vec::new();
+    while let Some((len, rest)) = body.split_first_chunk::<4>() {
+        let len = u32::from_be_bytes(*len) as usize;
+        let nal = rest.get(..len)?;
+        let mut out = Vec::with_capacity(len + 4);
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(nal);
+        nals.push(out);
+        body = &rest[len..];
+    }
+    Some(nals)
+}
@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use crate::util::Metrics;
+
+/// Where a rendered frame has reached when its latency is reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Copied back from the projector.
+    Render,
+    /// Came out of the encoder as NAL units.
+    Encode,
+    /// Written to a client's websocket.
+    Send,
+}
+
+impl Stage {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Render => "render",
+            Self::Encode => "encode",
+            Self::Send => "send",
+        }
+    }
+}
+
+/// When a rendered frame passed each step of the render loop, carried with it to the encoder
+/// and every client.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTimes {
+    /// Counts up with every frame the render loop produces.
+    pub id: u64,
+    /// Oldest capture of the camera frames it was rendered from.
+    pub captured: Instant,
+    /// Every camera frame had been loaded.
+    pub loaded: Instant,
+    /// Every view had been copied back from the projector.
+    pub rendered: Instant,
+}
+
+impl FrameTimes {
+    /// Latency of each stage the frame went through to reach `stage` at `at`.
+    pub fn report(&self, stage: Stage, at: Instant) -> LatencyReport {
+        LatencyReport {
+            id: self.id,
+            stage,
+            load: self.loaded.saturating_duration_since(self.captured),
+            render: self.rendered.saturating_duration_since(self.loaded),
+            since_render: at.saturating_duration_since(self.rendered),
+        }
+    }
+}
+
+/// Time a frame spent in each stage from capture, split so lag can be put on the cameras, the
+/// projector or the encoder and network.
+#[derive(Clone, Copy, Debug)]
+pub struct LatencyReport {
+    pub id: u64,
+    pub stage: Stage,
+    /// Capture of the oldest camera frame until every one was loaded.
+    pub load: Duration,
+    /// Loading until the views were copied back from the projector.
+    pub render: Duration,
+    /// Copied back until reaching `stage`, zero for [`Stage::Render`].
+    pub since_render: Duration,
+}
+
+impl LatencyReport {
+    /// Capture to reaching the stage.
+    pub fn total(&self) -> Duration {
+        self.load + self.render + self.since_render
+    }
+
+    /// Adds the report to [`Metrics`] and logs it.
+    pub fn record(&self) {
+        let stage = self.stage.name();
+        if self.stage == Stage::Render {
+            Metrics::push("latency-load", ms(self.load));
+            Metrics::push("latency-render", ms(self.render));
+        } else {
+            Metrics::push(&format!("latency-{stage}"), ms(self.since_render));
+        }
+        Metrics::push(&format!("latency-total-{stage}"), ms(self.total()));
+
+        tracing::debug!(
+            frame = self.id,
+            stage,
+            load = ?self.load,
+            render = ?self.render,
+            since_render = ?self.since_render,
+            total = ?self.total(),
+            "frame latency"
+        );
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.
+}
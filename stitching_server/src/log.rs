@@ -1,13 +1,60 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Debug,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+use serde_json::Value;
 use tower_http::trace::TraceLayer;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::util::utc_timestamp;
+
+/// Most recent events kept for [`dump_events`], older ones are dropped.
+const EVENT_CAPACITY: usize = 4096;
+
+static EVENTS: LazyLock<Mutex<VecDeque<LoggedEvent>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(EVENT_CAPACITY)));
 
-pub fn initialize(filter: impl Into<EnvFilter>) {
+/// Logs events passing `filter`, and keeps the last [`EVENT_CAPACITY`] events passing
+/// `kept` in memory whatever the first lets through, to dump when something goes wrong.
+pub fn initialize(filter: impl Into<EnvFilter>, kept: impl Into<EnvFilter>) {
     tracing_subscriber::registry()
-        .with(
+        .with(tracing_subscriber::fmt::layer().with_filter(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| filter.into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
+        ))
+        .with(EventRing.with_filter(kept.into()))
         .init();
+
+    let prev = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        push_event(LoggedEvent {
+            at: utc_timestamp(SystemTime::now()),
+            level: Level::ERROR.to_string(),
+            target: "panic".to_string(),
+            message: info.to_string(),
+            fields: BTreeMap::new(),
+            spans: Vec::new(),
+        });
+        match dump_events(Path::new(".")) {
+            Ok(path) => eprintln!("dumped recent events to {path:?}"),
+            Err(err) => eprintln!("failed to dump recent events: {err}"),
+        }
+        prev(info);
+    }));
 }
 
 pub fn http_trace_layer(
@@ -15,3 +62,147 @@ pub fn http_trace_layer(
 {
     TraceLayer::new_for_http()
 }
+
+/// Event kept in memory, along with the spans it happened in, outermost first.
+#[derive(Clone, Debug, Serialize)]
+pub struct LoggedEvent {
+    pub at: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    pub fields: BTreeMap<String, Value>,
+    pub spans: Vec<LoggedSpan>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LoggedSpan {
+    pub name: &'static str,
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Every event kept in memory, oldest first.
+pub fn recent_events() -> Vec<LoggedEvent> {
+    events().iter().cloned().collect()
+}
+
+/// Writes [`recent_events`] as JSON to a file in `dir` named after the time, returning its
+/// path.
+///
+/// # Errors
+/// the file can't be written
+pub fn dump_events(dir: &Path) -> io::Result<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = dir.join(format!("events-{secs}.json"));
+    let file = io::BufWriter::new(fs::File::create(&path)?);
+    serde_json::to_writer_pretty(file, &recent_events())?;
+    Ok(path)
+}
+
+fn events() -> MutexGuard<'static, VecDeque<LoggedEvent>> {
+    // a panic while the lock is held shouldn't lose the events before it
+    EVENTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn push_event(event: LoggedEvent) {
+    let mut events = events();
+    if events.len() == EVENT_CAPACITY {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+/// Layer keeping every event it sees in [`EVENTS`].
+struct EventRing;
+
+/// Fields recorded on a span so far, kept in its extensions.
+struct SpanFields(BTreeMap<String, Value>);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for EventRing {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = FieldMap::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanFields(fields.0));
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = FieldMap::default();
+        values.record(&mut fields);
+        let mut ext = span.extensions_mut();
+        if let Some(SpanFields(recorded)) = ext.get_mut::<SpanFields>() {
+            recorded.extend(fields.0);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = FieldMap::default();
+        event.record(&mut fields);
+        let message = match fields.0.remove("message") {
+            Some(Value::String(msg)) => msg,
+            Some(other) => other.to_string(),
+            None => String::new(),
+        };
+        let spans = ctx
+            .event_scope(event)
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| LoggedSpan {
+                name: span.name(),
+                fields: span
+                    .extensions()
+                    .get::<SpanFields>()
+                    .map(|f| f.0.clone())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        let meta = event.metadata();
+        push_event(LoggedEvent {
+            at: utc_timestamp(SystemTime::now()),
+            level: meta.level().to_string(),
+            target: meta.target().to_string(),
+            message,
+            fields: fields.0,
+            spans,
+        });
+    }
+}
+
+/// Fields of an event or span as JSON values.
+#[derive(Default)]
+struct FieldMap(BTreeMap<String, Value>);
+
+impl Visit for FieldMap {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
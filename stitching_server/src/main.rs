@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
 use anyhow::{anyhow, Result};
 use app::App;
@@ -6,22 +6,39 @@ use clap::{Parser, Subcommand};
 use util::Metrics;
 
 mod app;
+mod batch;
+#[cfg(feature = "capture")]
+mod capture;
+mod compare;
+mod encode;
+mod latency;
+mod probe;
+mod recorder;
+mod replay;
 mod util;
 
 mod log;
+#[cfg(feature = "panel")]
+mod panel;
+mod rt;
 
-#[tokio::main]
-pub async fn main() {
-    log::initialize(format!(
-        "{}=debug,tower_http=debug,stitch=debug,smpgpu=debug",
-        env!("CARGO_CRATE_NAME")
-    ));
+pub fn main() {
+    log::initialize(
+        format!(
+            "{}=debug,tower_http=debug,stitch=debug,smpgpu=debug",
+            env!("CARGO_CRATE_NAME")
+        ),
+        format!("info,{}=debug,stitch=debug", env!("CARGO_CRATE_NAME")),
+    );
 
-    Args::try_parse().unwrap().run().await.unwrap();
+    let args = Args::try_parse().unwrap();
+    args.runtime.build().unwrap().block_on(args.run()).unwrap();
 }
 
 #[derive(Clone, Debug, Parser)]
 pub struct Args {
+    #[clap(flatten)]
+    pub runtime: rt::RuntimeArgs,
     #[clap(subcommand)]
     pub cmd: ArgCommand,
 }
@@ -31,22 +48,49 @@ impl Args {
     /// errors can occur if the [App] cannot be loaded, or the server fails.
     pub async fn run(self) -> Result<()> {
         match self.cmd {
-            ArgCommand::Serve { timeout } => {
-                let app = App::from_toml_cfg("live.toml", 1280, 720).await?;
-
-                match timeout {
-                    Some(n) => {
-                        app.listen_and_serve_until(
-                            "0.0.0.0:2780",
-                            tokio::time::sleep(Duration::from_secs(n)),
-                        )
+            ArgCommand::Serve {
+                timeout,
+                render,
+                encode,
+                record,
+                watch_config,
+            } => {
+                let cfg_path = render.replay.as_deref().map_or_else(
+                    || PathBuf::from("live.toml"),
+                    stitch::camera::replay::config_path,
+                );
+                let app =
+                    App::from_toml_cfg(cfg_path, 1280, 720, render, encode, record, watch_config)
                         .await?;
 
-                        Metrics::save_csv("metrics.csv")?;
+                let stop = async move {
+                    match timeout {
+                        Some(n) => {
+                            tokio::select! {
+                                () = tokio::time::sleep(Duration::from_secs(n)) => {}
+                                () = shutdown_signal() => {}
+                            }
+                        }
+                        None => shutdown_signal().await,
                     }
-                    None => app.listen_and_serve("0.0.0.0:2780").await?,
                 };
+                app.clone()
+                    .listen_and_serve_until("0.0.0.0:2780", stop)
+                    .await?;
+                app.shutdown().await;
+
+                if timeout.is_some() {
+                    Metrics::save_csv("metrics.csv")?;
+                }
             }
+            ArgCommand::Record { duration, dir } => {
+                replay::record("live.toml".as_ref(), &dir, Duration::from_secs(duration)).await?;
+            }
+            ArgCommand::Compare { args } => compare::compare(&args).await?,
+            ArgCommand::Render { args } => batch::render(&args).await?,
+            #[cfg(feature = "panel")]
+            ArgCommand::Panel { server } => panel::run(&server)?,
+            ArgCommand::Probe { config } => probe::probe(config.as_deref())?,
             ArgCommand::ListLive => {
                 let cams = nokhwa::query(
                     nokhwa::native_api_backend()
@@ -59,40 +103,126 @@ impl Args {
                         c.human_name(),
                         c.description()
                     );
+                    if let Ok(index) = c.index().as_index() {
+                        for p in stitch::camera::live::stable_paths(index) {
+                            println!("    {}", p.display());
+                        }
+                    }
                 }
             }
             #[cfg(feature = "capture")]
-            ArgCommand::CaptureLive => {
-                let width = 1920;
-                let height = 1080;
-
+            ArgCommand::CaptureLive {
+                max_frames,
+                stable_frames,
+                tolerance,
+                sync,
+            } => {
                 let cfg = stitch::proj::Config::open("live.toml")?;
-                let mut buf = vec![0u8; (width * height * 4) as usize].into_boxed_slice();
-                for (i, c) in cfg.cameras.into_iter().enumerate() {
-                    let c = c.load::<Box<[u8]>>()?;
-                    let ticket = c.data.give(buf)?;
-                    buf = ticket.block_take()?;
-                    image::save_buffer(
-                        format!("capture{i}.png"),
-                        &buf,
-                        width,
-                        height,
-                        image::ExtendedColorType::Rgba8,
-                    )?;
-                }
+                let warmup = capture::Warmup {
+                    max_frames,
+                    stable_frames,
+                    tolerance,
+                };
+                capture::capture_live(&cfg, warmup, sync).await?;
             }
         }
         Ok(())
     }
 }
 
+/// Resolves on Ctrl-C, or on SIGTERM like when the service is stopped.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!("failed to listen for Ctrl-C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sig) => _ = sig.recv().await,
+            Err(err) => {
+                tracing::error!("failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+    tracing::info!("shutting down");
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum ArgCommand {
     Serve {
         #[arg(short, long)]
         timeout: Option<u64>,
+        #[clap(flatten)]
+        render: app::RenderArgs,
+        #[clap(flatten)]
+        encode: encode::EncodeArgs,
+        #[clap(flatten)]
+        record: recorder::RecordArgs,
+        /// Apply changes to live.toml while running, only reopening the cameras when needed
+        #[arg(long)]
+        watch_config: bool,
+    },
+    /// Record the cameras in live.toml as they are loaded, to stitch later with
+    /// `serve --replay`
+    Record {
+        /// Seconds to record for
+        #[arg(long, default_value_t = 60)]
+        duration: u64,
+        /// Directory to record into, along with a copy of live.toml
+        #[arg(long, default_value = "capture")]
+        dir: PathBuf,
+    },
+    /// Render the same camera frames with two configs, like with and without blending or with
+    /// two calibrations, and write them side by side or their difference
+    Compare {
+        #[clap(flatten)]
+        args: compare::CompareArgs,
+    },
+    /// Stitch a recording made by `record` into a video, as fast as the GPU allows rather than
+    /// at the pace it was recorded
+    Render {
+        #[clap(flatten)]
+        args: batch::BatchArgs,
+    },
+    /// Open a window with the view, camera controls and timings of a running server
+    #[cfg(feature = "panel")]
+    Panel {
+        /// Server to control, as host:port
+        #[arg(long, default_value = "127.0.0.1:2780")]
+        server: String,
     },
     ListLive,
+    /// List every camera with the modes it captures in, and write a config to start from
+    Probe {
+        /// Write a config with an entry for every camera found here, if it doesn't exist yet
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
     #[cfg(feature = "capture")]
-    CaptureLive,
+    CaptureLive {
+        /// Give up waiting for exposure to settle after this many frames
+        #[arg(long, default_value_t = 120)]
+        max_frames: usize,
+        /// Consecutive frames with stable exposure before capturing
+        #[arg(long, default_value_t = 5)]
+        stable_frames: usize,
+        /// Largest change in any channel's mean (0-255) considered stable
+        #[arg(long, default_value_t = 1.0)]
+        tolerance: f32,
+        /// Capture all cameras at the same time once they have all settled
+        #[arg(long)]
+        sync: bool,
+    },
 }
@@ -0,0 +1,470 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use eframe::egui::{self, ColorImage, TextureHandle, TextureOptions};
+use stitch::{
+    camera::live::Controls,
+    proj::{DewarpLens, ProjectionStyle, ViewCrop, ViewPreset},
+    proto::{CameraInfo, ClientMessage, ServerMessage, Status, ViewState},
+};
+use tungstenite::Message;
+
+use crate::util::MetricSummary;
+
+/// Time the connection waits for a message before sending the ones queued by the panel.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Time between fetches of the server's metrics.
+const METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Opens a window controlling the server at `server`, given as `host:port`, until it's
+/// closed.
+///
+/// # Errors
+/// the window can't be opened
+pub fn run(server: &str) -> Result<()> {
+    let server = server.to_string();
+    eframe::run_native(
+        "Stitching Panel",
+        eframe::NativeOptions::default(),
+        Box::new(move |cc| Ok(Box::new(Panel::spawn(server, cc.egui_ctx.clone())))),
+    )
+    .map_err(|err| anyhow!("running the panel: {err}"))
+}
+
+/// What the server last told the panel, filled in by the connection threads.
+#[derive(Default)]
+struct Shared {
+    view: Option<ViewState>,
+    cameras: Vec<CameraInfo>,
+    presets: Vec<ViewPreset>,
+    status: Option<Status>,
+    /// Latest frame of the view, taken by the panel to upload
+    frame: Option<ColorImage>,
+    metrics: BTreeMap<String, MetricSummary>,
+    controls: BTreeMap<usize, Controls>,
+    error: Option<String>,
+}
+
+/// Request for the HTTP thread, see [`http_loop`].
+enum HttpRequest {
+    /// Sets the controls that are set on a camera, or only reads them back with
+    /// [`Controls::NONE`].
+    Controls(usize, Controls),
+}
+
+struct Panel {
+    shared: Arc<Mutex<Shared>>,
+    ws: kanal::Sender<ClientMessage>,
+    http: kanal::Sender<HttpRequest>,
+    preview: Option<TextureHandle>,
+}
+
+impl Panel {
+    fn spawn(server: String, ctx: egui::Context) -> Self {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (ws, ws_recv) = kanal::unbounded();
+        let (http, http_recv) = kanal::unbounded();
+
+        for msg in [
+            ClientMessage::Subscribe {
+                status: true,
+                motion: false,
+            },
+            ClientMessage::GetView,
+            ClientMessage::ListCameras,
+            ClientMessage::ListPresets,
+        ] {
+            _ = ws.send(msg);
+        }
+
+        let (ws_shared, ws_ctx, ws_server) = (shared.clone(), ctx.clone(), server.clone());
+        std::thread::spawn(move || {
+            if let Err(err) = ws_loop(&ws_server, &ws_recv, &ws_shared, &ws_ctx) {
+                ws_shared.lock().unwrap().error = Some(format!("connection closed: {err}"));
+                ws_ctx.request_repaint();
+            }
+        });
+        let http_shared = shared.clone();
+        std::thread::spawn(move || http_loop(&server, &http_recv, &http_shared, &ctx));
+
+        Self {
+            shared,
+            ws,
+            http,
+            preview: None,
+        }
+    }
+
+    fn status_bar(&self, ui: &mut egui::Ui, shared: &Shared) {
+        ui.horizontal(|ui| {
+            if let Some(err) = &shared.error {
+                ui.colored_label(egui::Color32::RED, err);
+            }
+            let Some(status) = shared.status else {
+                ui.label("waiting for the server");
+                return;
+            };
+            ui.label(format!("{:.1} fps", status.fps));
+            ui.separator();
+            ui.label(format!(
+                "{} clients, {} with views",
+                status.clients, status.client_views
+            ));
+            ui.separator();
+            ui.label(format!(
+                "scale {:.2}, skip {}",
+                status.render_scale, status.frame_skip
+            ));
+            if status.encoding {
+                ui.separator();
+                ui.label("encoding");
+            }
+            if status.recording {
+                ui.separator();
+                ui.label("recording");
+            }
+        });
+    }
+
+    fn view_controls(&self, ui: &mut egui::Ui, shared: &mut Shared) {
+        let Some(view) = &mut shared.view else {
+            return;
+        };
+        let (mut style, mut crop, mut ground) = (view.style, view.crop, view.ground_overlay);
+
+        ui.heading(format!("View {}", view.view));
+        if !shared.presets.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                for preset in &shared.presets {
+                    if ui.button(&preset.name).clicked() {
+                        _ = self.ws.send(ClientMessage::SelectPreset {
+                            name: preset.name.clone(),
+                        });
+                    }
+                }
+            });
+        }
+        egui::ComboBox::from_label("Style")
+            .selected_text(style_name(style))
+            .show_ui(ui, |ui| {
+                for other in default_styles(style) {
+                    ui.selectable_value(&mut style, other, style_name(other));
+                }
+            });
+        style_controls(ui, &mut style);
+
+        // crops are set as a zoom around a center, like the web client does
+        let mut zoom = 1.0 / crop.w.max(crop.h);
+        let mut center = [crop.x + crop.w / 2.0, crop.y + crop.h / 2.0];
+        let zoomed = [
+            ui.add(egui::Slider::new(&mut zoom, 1.0..=8.0).text("Zoom")),
+            ui.add(egui::Slider::new(&mut center[0], 0.0..=1.0).text("Center x")),
+            ui.add(egui::Slider::new(&mut center[1], 0.0..=1.0).text("Center y")),
+        ];
+        if zoomed.iter().any(egui::Response::changed) {
+            crop = ViewCrop::zoomed(center, zoom).clamped();
+        }
+        ui.checkbox(&mut ground, "Ground overlay");
+
+        let set = ClientMessage::SetView {
+            style: changed(style, view.style),
+            crop: changed(crop, view.crop),
+            ground_overlay: changed(ground, view.ground_overlay),
+        };
+        if !matches!(
+            set,
+            ClientMessage::SetView {
+                style: None,
+                crop: None,
+                ground_overlay: None
+            }
+        ) {
+            _ = self.ws.send(set);
+            // shown right away rather than once the server answers, so sliders don't jump back
+            (view.style, view.crop, view.ground_overlay) = (style, crop, ground);
+        }
+    }
+
+    fn camera_controls(&self, ui: &mut egui::Ui, shared: &mut Shared) {
+        ui.heading("Cameras");
+        for cam in &shared.cameras {
+            let mut enabled = cam.enabled;
+            ui.collapsing(format!("Camera {}", cam.index), |ui| {
+                if ui.checkbox(&mut enabled, "Enabled").changed() {
+                    _ = self.ws.send(ClientMessage::SetCameraEnabled {
+                        index: cam.index,
+                        enabled,
+                    });
+                }
+
+                let Some(controls) = shared.controls.get_mut(&cam.index) else {
+                    if ui.button("Read controls").clicked() {
+                        _ = self
+                            .http
+                            .send(HttpRequest::Controls(cam.index, Controls::NONE));
+                    }
+                    return;
+                };
+                let mut set = Controls::NONE;
+                for (name, value, out) in [
+                    ("Exposure", controls.exposure, &mut set.exposure),
+                    ("Gain", controls.gain, &mut set.gain),
+                    (
+                        "White balance",
+                        controls.white_balance,
+                        &mut set.white_balance,
+                    ),
+                    ("Saturation", controls.saturation, &mut set.saturation),
+                    ("Brightness", controls.brightness, &mut set.brightness),
+                    ("Contrast", controls.contrast, &mut set.contrast),
+                ] {
+                    ui.horizontal(|ui| {
+                        let mut v = value.unwrap_or_default();
+                        if ui.add(egui::DragValue::new(&mut v)).changed() {
+                            *out = Some(v);
+                        }
+                        ui.label(if value.is_some() {
+                            name.to_string()
+                        } else {
+                            format!("{name} (not set)")
+                        });
+                    });
+                }
+                if set != Controls::NONE {
+                    _ = self.http.send(HttpRequest::Controls(cam.index, set));
+                    *controls = controls.merge(set);
+                }
+            });
+        }
+    }
+
+    fn timings(ui: &mut egui::Ui, shared: &Shared) {
+        ui.heading("Metrics, times in ms");
+        egui::Grid::new("timings").striped(true).show(ui, |ui| {
+            for (name, m) in &shared.metrics {
+                ui.label(name);
+                ui.label(format!("{:.2} ± {:.2}", m.mean, m.std_dev));
+                ui.end_row();
+            }
+        });
+    }
+}
+
+impl eframe::App for Panel {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let shared = self.shared.clone();
+        let mut shared = shared.lock().unwrap();
+
+        if let Some(frame) = shared.frame.take() {
+            match &mut self.preview {
+                Some(tex) => tex.set(frame, TextureOptions::LINEAR),
+                None => {
+                    self.preview = Some(ctx.load_texture("preview", frame, TextureOptions::LINEAR));
+                }
+            }
+        }
+
+        egui::TopBottomPanel::top("status").show(ctx, |ui| self.status_bar(ui, &shared));
+        egui::SidePanel::left("controls").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                self.view_controls(ui, &mut shared);
+                ui.separator();
+                self.camera_controls(ui, &mut shared);
+                ui.separator();
+                Self::timings(ui, &shared);
+            });
+        });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(tex) = &self.preview {
+                ui.add(egui::Image::new(tex).shrink_to_fit());
+            }
+        });
+    }
+}
+
+/// Sliders for the fields of `style`.
+fn style_controls(ui: &mut egui::Ui, style: &mut ProjectionStyle) {
+    let pos_sliders = |ui: &mut egui::Ui, pos: &mut [f32; 3], radius: &mut f32| {
+        for (v, name) in pos.iter_mut().zip(["x", "y", "z"]) {
+            ui.add(egui::Slider::new(v, -100.0..=100.0).text(name));
+        }
+        ui.add(egui::Slider::new(radius, 1.0..=500.0).text("Radius"));
+    };
+    match style {
+        ProjectionStyle::RawCamera(index) => {
+            ui.add(egui::Slider::new(index, 0..=15).text("Camera"));
+        }
+        ProjectionStyle::Hemisphere { pos, radius }
+        | ProjectionStyle::Equirect { pos, radius }
+        | ProjectionStyle::CubeMap { pos, radius } => pos_sliders(ui, pos, radius),
+        ProjectionStyle::SingleCamera {
+            index,
+            lens,
+            fov,
+            yaw,
+            pitch,
+        } => {
+            ui.add(egui::Slider::new(index, 0..=15).text("Camera"));
+            egui::ComboBox::from_label("Lens")
+                .selected_text(format!("{lens:?}"))
+                .show_ui(ui, |ui| {
+                    for other in [DewarpLens::Rectilinear, DewarpLens::Cylindrical] {
+                        ui.selectable_value(lens, other, format!("{other:?}"));
+                    }
+                });
+            ui.add(egui::Slider::new(fov, 10.0..=180.0).text("Fov"));
+            ui.add(egui::Slider::new(yaw, -180.0..=180.0).text("Yaw"));
+            ui.add(egui::Slider::new(pitch, -90.0..=90.0).text("Pitch"));
+        }
+    }
+}
+
+/// Every kind of style to switch to, keeping what `current` has in common with each.
+fn default_styles(current: ProjectionStyle) -> [ProjectionStyle; 5] {
+    let (pos, radius) = match current {
+        ProjectionStyle::Hemisphere { pos, radius }
+        | ProjectionStyle::Equirect { pos, radius }
+        | ProjectionStyle::CubeMap { pos, radius } => (pos, radius),
+        _ => ([0.0, 0.0, 4.0], current.radius()),
+    };
+    let single = match current {
+        ProjectionStyle::SingleCamera { .. } => current,
+        _ => ProjectionStyle::SingleCamera {
+            index: 0,
+            lens: DewarpLens::default(),
+            fov: 120.0,
+            yaw: 0.0,
+            pitch: 0.0,
+        },
+    };
+    let raw = match current {
+        ProjectionStyle::RawCamera(_) => current,
+        _ => ProjectionStyle::RawCamera(0),
+    };
+    [
+        ProjectionStyle::Hemisphere { pos, radius },
+        ProjectionStyle::Equirect { pos, radius },
+        ProjectionStyle::CubeMap { pos, radius },
+        single,
+        raw,
+    ]
+}
+
+/// `new` if it isn't `old`.
+fn changed<T: PartialEq>(new: T, old: T) -> Option<T> {
+    (new != old).then_some(new)
+}
+
+const fn style_name(style: ProjectionStyle) -> &'static str {
+    match style {
+        ProjectionStyle::RawCamera(_) => "Raw camera",
+        ProjectionStyle::Hemisphere { .. } => "Hemisphere",
+        ProjectionStyle::Equirect { .. } => "Equirect",
+        ProjectionStyle::CubeMap { .. } => "Cube map",
+        ProjectionStyle::SingleCamera { .. } => "Single camera",
+    }
+}
+
+/// Talks to the server's video websocket, sending the messages from `outgoing` and keeping
+/// `shared` up to date with its replies and frames, until the connection fails.
+fn ws_loop(
+    server: &str,
+    outgoing: &kanal::Receiver<ClientMessage>,
+    shared: &Mutex<Shared>,
+    ctx: &egui::Context,
+) -> Result<()> {
+    let stream = TcpStream::connect(server)?;
+    let (mut socket, _) = tungstenite::client(format!("ws://{server}/video"), stream)
+        .map_err(|err| anyhow!("connecting to {server}: {err}"))?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    shared.lock().unwrap().error = None;
+
+    loop {
+        while let Ok(Some(msg)) = outgoing.try_recv() {
+            socket.send(Message::Text(serde_json::to_string(&msg)?))?;
+        }
+
+        let msg = match socket.read() {
+            Ok(msg) => msg,
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let mut shared = shared.lock().unwrap();
+        match msg {
+            Message::Text(text) => match serde_json::from_str(&text)? {
+                ServerMessage::View(view) => shared.view = Some(view),
+                ServerMessage::Cameras { cameras } => shared.cameras = cameras,
+                ServerMessage::Status(status) => shared.status = Some(status),
+                ServerMessage::Presets { presets, .. } => shared.presets = presets,
+                ServerMessage::Health { .. } | ServerMessage::Motion(_) => {}
+                ServerMessage::Error { message } => shared.error = Some(message),
+            },
+            Message::Binary(packet) => {
+                shared.frame = preview_frame(&packet).or(shared.frame.take())
+            }
+            Message::Close(_) => return Err(anyhow!("the server closed the connection")),
+            _ => continue,
+        }
+        ctx.request_repaint();
+    }
+}
+
+/// Image of a frame packet, laid out like the server's `VideoPacket`.
+fn preview_frame(packet: &[u8]) -> Option<ColorImage> {
+    let header = packet.get(..16)?;
+    let w = u16::from_le_bytes([header[1], header[2]]) as usize;
+    let h = u16::from_le_bytes([header[3], header[4]]) as usize;
+    let pixels = packet.get(16..16 + w * h * 4)?;
+    (header[5] == 4).then(|| ColorImage::from_rgba_unmultiplied([w, h], pixels))
+}
+
+/// Fetches the server's metrics every [`METRICS_INTERVAL`] and answers the requests from
+/// `requests` in between, until the panel closes.
+fn http_loop(
+    server: &str,
+    requests: &kanal::Receiver<HttpRequest>,
+    shared: &Mutex<Shared>,
+    ctx: &egui::Context,
+) {
+    loop {
+        match requests.recv_timeout(METRICS_INTERVAL) {
+            Ok(HttpRequest::Controls(idx, controls)) => {
+                let url = format!("http://{server}/cameras/{idx}/controls");
+                let res = ureq::post(&url)
+                    .send_json(controls)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|res| Ok(res.into_json::<Controls>()?));
+                let mut shared = shared.lock().unwrap();
+                match res {
+                    Ok(controls) => _ = shared.controls.insert(idx, controls),
+                    Err(err) => shared.error = Some(format!("camera {idx} controls: {err}")),
+                }
+            }
+            Err(kanal::ReceiveErrorTimeout::Timeout) => {
+                let res = ureq::get(&format!("http://{server}/metrics"))
+                    .call()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|res| Ok(res.into_json()?));
+                match res {
+                    Ok(metrics) => shared.lock().unwrap().metrics = metrics,
+                    Err(err) => tracing::debug!("failed to fetch metrics: {err}"),
+                }
+            }
+            Err(_) => break,
+        }
+        ctx.request_repaint();
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fmt::Write as _, fs::File, io::Write as _, path::Path};
+
+use anyhow::{anyhow, Result};
+use stitch::camera::live::{self, Probed};
+
+/// Prints every camera found along with the modes it captures in, and writes a config with an
+/// entry for each one to `out` if it's given and doesn't exist yet.
+///
+/// # Errors
+/// the cameras can't be listed, or the config can't be written
+pub fn probe(out: Option<&Path>) -> Result<()> {
+    let backend = nokhwa::native_api_backend().ok_or_else(|| anyhow!("no camera backend found"))?;
+    let found = live::probe(backend)?;
+    if found.is_empty() {
+        println!("no cameras found");
+    }
+
+    for cam in &found {
+        println!("{} -> {:?}", cam.index, cam.name);
+        for p in &cam.paths {
+            println!("    {}", p.display());
+        }
+        for m in &cam.modes {
+            let [w, h] = m.resolution;
+            println!("    {w}x{h} @ {} fps, {:?}", m.frame_rate, m.format);
+        }
+    }
+
+    if let Some(out) = out {
+        File::create_new(out)
+            .and_then(|mut f| f.write_all(skeleton_config(&found).as_bytes()))
+            .map_err(|err| anyhow!("writing {}: {err}", out.display()))?;
+        println!(
+            "wrote a config for {} cameras to {}",
+            found.len(),
+            out.display()
+        );
+    }
+    Ok(())
+}
+
+/// Config opening every camera of `found` at its largest mode, facing evenly around the
+/// origin until they are placed.
+fn skeleton_config(found: &[Probed]) -> String {
+    let mut cfg = String::from("[style.hemisphere]\nradius = 100\npos = [0, 0, 100]\n");
+    for (i, cam) in found.iter().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let azimuth = 360.0 * i as f32 / found.len() as f32;
+        _ = write!(
+            cfg,
+            "\n# {}, place it, aim it and set its lens\n\
+             [[cameras]]\n\
+             pos = [0, 0, 10]\n\
+             pitch = 0\n\
+             azimuth = {azimuth}\n\
+             roll = 0\n\
+             sensor = {{ img_off = [0, 0], fov.W = 90 }}\n\
+             lens = \"rectilinear\"\n\
+             live_index = {}\n",
+            cam.name, cam.index,
+        );
+        if let Some(p) = cam.paths.first() {
+            _ = writeln!(cfg, "device = {:?}", p.display().to_string());
+        }
+        if let Some(m) = cam.modes.first() {
+            let [w, h] = m.resolution;
+            _ = writeln!(cfg, "resolution = [{w}, {h}]");
+        }
+    }
+    cfg
+}
@@ -0,0 +1,504 @@
+//! Recording of encoded streams to disk as rotating segments.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::encode::Codec;
+
+/// Time between checks of the retention limits while recording.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    /// Fragmented MP4, playable even if the server stops mid segment
+    #[default]
+    Mp4,
+    Mkv,
+}
+
+impl Container {
+    const fn muxer(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Mkv => "matroska",
+        }
+    }
+
+    const fn ext(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Mkv => "mkv",
+        }
+    }
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct RecordArgs {
+    /// Start recording as soon as the server is up, needs --encode
+    #[arg(long)]
+    pub record: bool,
+    /// Directory segments are written to
+    #[arg(long, default_value = "recordings")]
+    pub record_dir: PathBuf,
+    /// Also encode and record every camera's raw feed
+    #[arg(long)]
+    pub record_cameras: bool,
+    #[clap(flatten)]
+    pub settings: RecordSettings,
+}
+
+/// Settings that can also be changed while the server is running, see [`Recorder::configure`].
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, clap::Args)]
+pub struct RecordSettings {
+    #[arg(long = "record-format", default_value = "mp4")]
+    pub format: Container,
+    /// Length of each segment in seconds, cut at the next keyframe
+    #[arg(long, default_value_t = 300)]
+    pub segment_secs: u64,
+    /// Oldest segments of a stream are deleted once it has more than this many
+    #[arg(long)]
+    pub keep_segments: Option<usize>,
+    /// Oldest segments of a stream are deleted once they take up more than this many MiB
+    #[arg(long)]
+    pub keep_mib: Option<u64>,
+}
+
+/// Encoded stream that can be recorded.
+pub struct Source {
+    pub name: String,
+    pub codec: Codec,
+    pub nals: broadcast::Sender<Arc<[u8]>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RecordStatus {
+    pub recording: bool,
+    pub dir: PathBuf,
+    pub settings: RecordSettings,
+    pub streams: Vec<StreamStatus>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StreamStatus {
+    pub name: String,
+    pub segments: usize,
+    pub bytes: u64,
+}
+
+/// Records every [`Source`] to its own series of segments while started.
+pub struct Recorder {
+    dir: PathBuf,
+    sources: Vec<Source>,
+    state: Mutex<RecorderState>,
+}
+
+struct RecorderState {
+    settings: RecordSettings,
+    segmenters: Vec<Segmenter>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new(args: &RecordArgs, sources: Vec<Source>) -> Self {
+        Self {
+            dir: args.record_dir.clone(),
+            sources,
+            state: Mutex::new(RecorderState {
+                settings: args.settings,
+                segmenters: Vec::new(),
+            }),
+        }
+    }
+
+    /// Whether there is anything to record, which needs the server to be encoding.
+    #[must_use]
+    pub fn has_sources(&self) -> bool {
+        !self.sources.is_empty()
+    }
+
+    /// Starts recording every source, doing nothing if already recording.
+    ///
+    /// # Errors
+    /// the directory can't be created or ffmpeg can't be started
+    pub fn start(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.segmenters.is_empty() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let segmenters = self
+            .sources
+            .iter()
+            .map(|src| Segmenter::spawn(&self.dir, src, state.settings))
+            .collect::<io::Result<Vec<_>>>()?;
+        state.segmenters = segmenters;
+
+        tracing::info!("recording {} streams to {:?}", self.sources.len(), self.dir);
+        Ok(())
+    }
+
+    /// Stops recording, finishing the current segments.
+    pub fn stop(&self) {
+        let segmenters = std::mem::take(&mut self.state.lock().unwrap().segmenters);
+        if !segmenters.is_empty() {
+            tracing::info!("stopped recording");
+        }
+    }
+
+    /// Like [`Self::stop`], but waits for ffmpeg to write out the last segments, which takes
+    /// the next NAL unit of every stream, so the server has to still be encoding.
+    pub fn finish(&self) {
+        let segmenters = std::mem::take(&mut self.state.lock().unwrap().segmenters);
+        if segmenters.is_empty() {
+            return;
+        }
+        for seg in segmenters {
+            seg.finish();
+        }
+        tracing::info!("finished recording");
+    }
+
+    /// Replaces the settings, restarting with them if currently recording.
+    ///
+    /// # Errors
+    /// recording couldn't be restarted
+    pub fn configure(&self, settings: RecordSettings) -> io::Result<()> {
+        let restart = {
+            let mut state = self.state.lock().unwrap();
+            state.settings = settings;
+            !state.segmenters.is_empty()
+        };
+
+        if restart {
+            self.stop();
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn is_recording(&self) -> bool {
+        !self.state.lock().unwrap().segmenters.is_empty()
+    }
+
+    #[must_use]
+    pub fn status(&self) -> RecordStatus {
+        let state = self.state.lock().unwrap();
+        let ext = state.settings.format.ext();
+
+        RecordStatus {
+            recording: !state.segmenters.is_empty(),
+            dir: self.dir.clone(),
+            settings: state.settings,
+            streams: self
+                .sources
+                .iter()
+                .map(|src| {
+                    let segs = segments(&self.dir, &src.name, ext).unwrap_or_default();
+                    StreamStatus {
+                        name: src.name.clone(),
+                        segments: segs.len(),
+                        bytes: segs.iter().map(|(_, len)| len).sum(),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes one source to segments with an ffmpeg child process, stopping when dropped.
+struct Segmenter {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Segmenter {
+    fn spawn(dir: &Path, src: &Source, settings: RecordSettings) -> io::Result<Self> {
+        let input = match src.codec {
+            Codec::H264 => "h264",
+            Codec::H265 => "hevc",
+        };
+        let ext = settings.format.ext();
+        let pattern = dir.join(format!("{}-%Y%m%d-%H%M%S.{ext}", src.name));
+
+        let mut cmd = Command::new("ffmpeg");
+        cmd.args(["-hide_banner", "-loglevel", "error"])
+            // frames can be dropped before encoding, so time them as they arrive
+            .args(["-use_wallclock_as_timestamps", "1", "-f", input, "-i", "-"])
+            .args(["-c", "copy", "-f", "segment", "-reset_timestamps", "1"])
+            .args(["-segment_time", &settings.segment_secs.to_string()])
+            .args(["-segment_format", settings.format.muxer()]);
+        if settings.format == Container::Mp4 {
+            cmd.args([
+                "-segment_format_options",
+                "movflags=+frag_keyframe+empty_moov+default_base_moof",
+            ]);
+        }
+        cmd.args(["-strftime", "1"]).arg(&pattern);
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = SegmentWriter {
+            codec: src.codec,
+            retention: Retention {
+                dir: dir.to_path_buf(),
+                stream: src.name.clone(),
+                ext,
+                settings,
+            },
+            sidecar: Sidecar {
+                // a restarted recording's writer can still be finishing the last one
+                part: dir.join(format!("{}-{}.jsonl.part", src.name, started.as_millis())),
+                file: None,
+                segment_len: Duration::from_secs(settings.segment_secs),
+                started: None,
+                cuts: 0,
+            },
+            stop: Arc::clone(&stop),
+        };
+        let nals = src.nals.subscribe();
+        let thread = std::thread::Builder::new()
+            .name(format!("record-{}", src.name))
+            .spawn(move || writer.run(nals, child, stdin))?;
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stops and waits for ffmpeg to finish the last segment.
+    fn finish(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Segmenter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct SegmentWriter {
+    codec: Codec,
+    retention: Retention,
+    sidecar: Sidecar,
+    stop: Arc<AtomicBool>,
+}
+
+impl SegmentWriter {
+    fn run(
+        mut self,
+        mut nals: broadcast::Receiver<Arc<[u8]>>,
+        mut child: Child,
+        mut stdin: ChildStdin,
+    ) {
+        // ffmpeg needs the parameter sets before anything else
+        let mut synced = false;
+        let mut last_prune = Instant::now();
+
+        while !self.stop.load(Ordering::Relaxed) {
+            let nal = match nals.blocking_recv() {
+                Ok(nal) => nal,
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("recording fell behind, skipped {n} NAL units");
+                    synced = false;
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let keyframe = self.codec.is_parameter_set(&nal);
+            synced |= keyframe;
+            if !synced {
+                continue;
+            }
+            if keyframe {
+                self.sidecar.keyframe(&self.retention);
+            }
+            if let Some(meta) = self.codec.sei_meta(&nal) {
+                if let Err(err) = self.sidecar.push(meta) {
+                    tracing::error!("failed to write frame metadata: {err}");
+                }
+            }
+            if let Err(err) = stdin.write_all(&nal) {
+                tracing::error!("failed to send NAL unit to recorder: {err}");
+                break;
+            }
+
+            if last_prune.elapsed() >= PRUNE_INTERVAL {
+                self.retention.prune();
+                last_prune = Instant::now();
+            }
+        }
+
+        // closing stdin lets ffmpeg finish the last segment
+        drop(stdin);
+        _ = child.wait();
+        self.sidecar.finish(&self.retention);
+        self.retention.prune();
+    }
+}
+
+/// Metadata the encoder sent along with every frame of a stream, see [`Codec::meta_sei`],
+/// written next to each segment with the same name ending in `.jsonl`, a line per frame.
+struct Sidecar {
+    /// Lines of the segment being written, moved next to it once ffmpeg moves on
+    part: PathBuf,
+    file: Option<BufWriter<fs::File>>,
+    segment_len: Duration,
+    /// When the first keyframe was written, which ffmpeg times the segments from
+    started: Option<Instant>,
+    cuts: u32,
+}
+
+impl Sidecar {
+    fn push(&mut self, meta: &[u8]) -> io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(BufWriter::new(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.part)?,
+            )),
+        };
+        file.write_all(meta)?;
+        file.write_all(b"\n")
+    }
+
+    /// Moves on to the next segment if ffmpeg cuts one at the keyframe about to be written,
+    /// which it does once the recording is another `segment_len` in.
+    fn keyframe(&mut self, retention: &Retention) {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        if started.elapsed() >= self.segment_len * (self.cuts + 1) {
+            self.cuts += 1;
+            self.finish(retention);
+        }
+    }
+
+    /// Moves the lines written so far next to the newest segment, which ffmpeg is still
+    /// writing the frames of.
+    fn finish(&mut self, retention: &Retention) {
+        if let Some(mut file) = self.file.take() {
+            if let Err(err) = file.flush() {
+                tracing::error!("failed to write frame metadata: {err}");
+            }
+        }
+        if !self.part.exists() {
+            return;
+        }
+        let newest = segments(&retention.dir, &retention.stream, retention.ext)
+            .ok()
+            .and_then(|mut segs| segs.pop());
+        let Some((segment, _)) = newest else {
+            return;
+        };
+
+        let path = segment.with_extension("jsonl");
+        let moved = fs::File::open(&self.part).and_then(|mut part| {
+            let mut out = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?;
+            io::copy(&mut part, &mut out)?;
+            fs::remove_file(&self.part)
+        });
+        if let Err(err) = moved {
+            tracing::error!("failed to move frame metadata to {path:?}: {err}");
+        }
+    }
+}
+
+/// Limits on the segments kept for one stream.
+struct Retention {
+    dir: PathBuf,
+    stream: String,
+    ext: &'static str,
+    settings: RecordSettings,
+}
+
+impl Retention {
+    /// Deletes the oldest segments until the stream is within its limits, never deleting the
+    /// newest one as it may still be written to.
+    fn prune(&self) {
+        let max_count = self.settings.keep_segments.unwrap_or(usize::MAX);
+        let max_bytes = self
+            .settings
+            .keep_mib
+            .map_or(u64::MAX, |mib| mib.saturating_mul(1024 * 1024));
+
+        let segs = match segments(&self.dir, &self.stream, self.ext) {
+            Ok(segs) => segs,
+            Err(err) => {
+                tracing::error!("failed to list recorded segments: {err}");
+                return;
+            }
+        };
+
+        let mut count = segs.len();
+        let mut bytes = segs.iter().map(|(_, len)| len).sum::<u64>();
+        for (path, len) in segs.iter().take(count.saturating_sub(1)) {
+            if count <= max_count && bytes <= max_bytes {
+                break;
+            }
+
+            match fs::remove_file(path) {
+                Ok(()) => tracing::info!("deleted old segment {path:?}"),
+                Err(err) => tracing::error!("failed to delete segment {path:?}: {err}"),
+            }
+            match fs::remove_file(path.with_extension("jsonl")) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => tracing::error!("failed to delete frame metadata of {path:?}: {err}"),
+            }
+            count -= 1;
+            bytes -= len;
+        }
+    }
+}
+
+/// Paths and sizes of the segments of `stream`, oldest first.
+fn segments(dir: &Path, stream: &str, ext: &str) -> io::Result<Vec<(PathBuf, u64)>> {
+    let prefix = format!("{stream}-");
+    let mut segs = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|e| {
+            let path = e.path();
+            path.extension().is_some_and(|e| e == ext)
+                && e.file_name().to_string_lossy().starts_with(&prefix)
+        })
+        .filter_map(|e| Some((e.path(), e.metadata().ok()?.len())))
+        .collect::<Vec<_>>();
+
+    // names end in the time the segment started
+    segs.sort_unstable();
+    Ok(segs)
+}
@@ -0,0 +1,74 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use stitch::{
+    buf::FrameSize,
+    camera::{live, replay},
+    loader::Loader,
+};
+
+/// Records every camera in the config at `cfg_path` into `dir` for `duration`, along with a
+/// copy of the config, so the recording can be stitched later with `serve --replay`.
+///
+/// # Errors
+/// a camera fails to load or the recording can't be written
+pub async fn record(cfg_path: &Path, dir: &Path, duration: Duration) -> Result<()> {
+    let cfg = stitch::proj::Config::<live::Config>::open(cfg_path)?;
+    std::fs::create_dir_all(dir)?;
+    std::fs::copy(cfg_path, replay::config_path(dir))?;
+
+    let cams = cfg
+        .cameras
+        .iter()
+        .map(|c| {
+            // frames are saved as RGBA
+            let mut c = c.clone();
+            c.meta.preprocess.decode = false;
+            c.load::<Box<[u8]>>()
+        })
+        .collect::<stitch::Result<Vec<_>>>()?;
+
+    let started = Instant::now();
+    let until = started + duration;
+    let recordings = cams.into_iter().zip(&cfg.cameras).map(|(cam, cam_cfg)| {
+        let out = replay::Config::new(dir, cam_cfg.meta.live_index);
+        tokio::task::spawn_blocking(move || record_camera(&cam.data, &out, started, until))
+    });
+
+    for (res, cam_cfg) in futures::future::join_all(recordings)
+        .await
+        .into_iter()
+        .zip(&cfg.cameras)
+    {
+        let frames = res??;
+        tracing::info!(
+            "recorded {frames} frames of camera {}",
+            cam_cfg.meta.live_index
+        );
+    }
+    Ok(())
+}
+
+/// Writes every frame `loader` loads until `until`, returning how many there were.
+fn record_camera(
+    loader: &Loader<Box<[u8]>>,
+    out: &replay::Config,
+    started: Instant,
+    until: Instant,
+) -> stitch::Result<usize> {
+    let mut writer = replay::StreamWriter::create(out, loader.frame_size(), started)?;
+    let mut buf = vec![0u8; loader.num_bytes()].into_boxed_slice();
+
+    let mut frames = 0;
+    while Instant::now() < until {
+        let (loaded, capture) = loader.give(buf)?.block_take_captured()?;
+        buf = loaded;
+        writer.push(&buf, capture.map_or_else(Instant::now, |c| c.at))?;
+        frames += 1;
+    }
+    writer.finish()?;
+    Ok(frames)
+}
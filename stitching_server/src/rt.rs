@@ -0,0 +1,99 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::Instant,
+};
+
+use tokio::{
+    runtime::{Builder, Handle, Runtime},
+    task::JoinError,
+};
+
+use crate::util::Metrics;
+
+static ENCODE_RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static ENCODE_INFLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct RuntimeArgs {
+    /// Number of async worker threads, defaults to the number of cores
+    #[arg(long, global = true)]
+    pub worker_threads: Option<usize>,
+
+    /// Limit on blocking threads, every camera and the stitch loop each hold one permanently
+    #[arg(long, global = true)]
+    pub blocking_threads: Option<usize>,
+
+    /// Run image encoding on its own runtime with this many threads
+    #[arg(long, global = true)]
+    pub encode_threads: Option<usize>,
+}
+
+impl RuntimeArgs {
+    /// # Errors
+    /// either runtime fails to start
+    pub fn build(&self) -> io::Result<Runtime> {
+        if let Some(n) = self.encode_threads {
+            let encode_rt = Builder::new_multi_thread()
+                .thread_name("encode")
+                .worker_threads(1)
+                .max_blocking_threads(n)
+                .build()?;
+            _ = ENCODE_RUNTIME.set(encode_rt);
+        }
+
+        let mut builder = Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(n) = self.worker_threads {
+            builder.worker_threads(n);
+        }
+        if let Some(n) = self.blocking_threads {
+            builder.max_blocking_threads(n);
+        }
+        builder.build()
+    }
+}
+
+/// Runs CPU heavy encoding work off of the async workers, using the dedicated encode runtime
+/// when one was configured.
+///
+/// # Errors
+/// `f` panicked
+pub async fn spawn_encode<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, JoinError> {
+    let handle = ENCODE_RUNTIME
+        .get()
+        .map_or_else(Handle::current, |rt| rt.handle().clone());
+
+    let queued = Instant::now();
+    let inflight = Inflight::start();
+    handle
+        .spawn_blocking(move || {
+            let _inflight = inflight;
+            Metrics::push("encode-wait", queued.elapsed().as_secs_f64() * 1000.);
+            f()
+        })
+        .await
+}
+
+/// Counts an encode job in flight until it's dropped along with the job, whether the job
+/// finished, panicked or never ran.
+struct Inflight;
+
+impl Inflight {
+    fn start() -> Self {
+        let inflight = ENCODE_INFLIGHT.fetch_add(1, Ordering::Relaxed) + 1;
+        Metrics::push("encode-inflight", inflight as f64);
+        Self
+    }
+}
+
+impl Drop for Inflight {
+    fn drop(&mut self) {
+        ENCODE_INFLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
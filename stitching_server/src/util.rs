@@ -1,17 +1,20 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     future::Future,
     io::{self, Write},
+    ops::{Deref, DerefMut},
     path,
     sync::{LazyLock, Mutex},
-    time::Instant,
+    time::{Instant, SystemTime},
 };
 
 use axum::{
     extract::{ws::WebSocket, FromRequest, State, WebSocketUpgrade},
     handler::Handler,
 };
+use serde::{Deserialize, Serialize};
+use stitch::buf::FrameSize;
 
 pub fn ws_upgrader<M, S: Send + Sync + Clone + 'static, Fut>(
     cb: impl FnOnce(S, WebSocket) -> Fut + Send + Clone + 'static,
@@ -80,6 +83,14 @@ impl IntervalTimer {
     }
 }
 
+/// Summary of a metric, as served at */metrics*.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MetricSummary {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub samples: usize,
+}
+
 static GLOBAL_METRICS: LazyLock<Mutex<Metrics>> = LazyLock::new(|| Mutex::new(Metrics::new()));
 
 pub struct Metrics {
@@ -113,6 +124,23 @@ impl Metrics {
             .collect()
     }
 
+    /// Every metric by name, see [`MetricSummary`].
+    pub fn summaries() -> BTreeMap<String, MetricSummary> {
+        Self::current_marks()
+            .into_iter()
+            .map(|(name, (mean, std_dev, samples))| {
+                // rounding can take the variance of a constant metric just below zero
+                let std_dev = if std_dev.is_nan() { 0.0 } else { std_dev };
+                let summary = MetricSummary {
+                    mean,
+                    std_dev,
+                    samples,
+                };
+                (name, summary)
+            })
+            .collect()
+    }
+
     pub fn save_csv(out_path: impl AsRef<path::Path>) -> io::Result<()> {
         let mut out = fs::File::create(out_path)?;
 
@@ -161,3 +189,83 @@ impl Metric {
         self.count as _
     }
 }
+
+/// `t` as `YYYY-MM-DD HH:MM:SS.mmm UTC`, with the date from
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+pub fn utc_timestamp(t: SystemTime) -> String {
+    let since = t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+
+    let z = secs / 86_400 + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}.{:03} UTC",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        since.subsec_millis()
+    )
+}
+
+/// Time on the wall clock `at` was, for timestamps other processes can line up with.
+pub fn wall_clock(at: Instant) -> SystemTime {
+    SystemTime::now() - at.elapsed()
+}
+
+/// 64-bit FNV-1a hash of `data`, which unlike the std hashers is the same for every build.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// RGBA frame of a view, to read one back from the GPU into.
+pub struct RgbaFrame {
+    pub data: Box<[u8]>,
+    size: (usize, usize),
+}
+
+impl RgbaFrame {
+    pub fn new(size: (usize, usize)) -> Self {
+        Self {
+            data: vec![0; size.0 * size.1 * 4].into_boxed_slice(),
+            size,
+        }
+    }
+}
+
+impl FrameSize for RgbaFrame {
+    fn width(&self) -> usize {
+        self.size.0
+    }
+
+    fn height(&self) -> usize {
+        self.size.1
+    }
+
+    fn chans(&self) -> usize {
+        4
+    }
+}
+
+impl Deref for RgbaFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl DerefMut for RgbaFrame {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}